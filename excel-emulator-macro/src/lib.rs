@@ -1,5 +1,5 @@
 use proc_macro::{TokenStream, Span};
-use syn::{self, ItemFn, Ident, FnArg}; 
+use syn::{self, ItemFn, Ident, FnArg, AttributeArgs, NestedMeta, Lit, parse_macro_input}; 
 use quote::quote; 
 
 fn make_ascii_titlecase(s: &mut str) {
@@ -8,13 +8,42 @@ fn make_ascii_titlecase(s: &mut str) {
     }
 }
 
+/*
+ * #[function] or #[function("STDEV.S", "STDEV")] when the Excel names are not the 
+ * function's name in capitals. The function is registered under each of them. 
+ */
 #[proc_macro_attribute]
-pub fn function(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn function(attr: TokenStream, item: TokenStream) -> TokenStream {
     let ast: ItemFn = syn::parse(item).unwrap();
-    create_excel_function(ast)
+    let attr_args = parse_macro_input!(attr as AttributeArgs); 
+    let names: Vec<String> = attr_args.iter().map(|arg| match arg {
+        NestedMeta::Lit(Lit::Str(s)) => s.value(), 
+        _ => panic!("#[function] takes the Excel names of the function as strings")
+    }).collect(); 
+    create_excel_function(ast, names)
 }
 
-fn create_excel_function(ast: ItemFn) -> TokenStream {
+fn is_optional(fnarg: &FnArg) -> bool {
+    match fnarg {
+        FnArg::Typed(pat_type) => match &*pat_type.ty {
+            syn::Type::Path(typepath) => typepath.path.segments.len() == 1 && typepath.path.segments[0].ident.to_string().as_str() == "Option",
+            _ => false
+        }, 
+        _ => false
+    }
+}
+
+fn arg_name(fnarg: &FnArg) -> String {
+    match fnarg {
+        FnArg::Typed(pat_type) => match &*pat_type.pat {
+            syn::Pat::Ident(pat_ident) => pat_ident.ident.to_string(), 
+            _ => String::new()
+        }, 
+        _ => String::new()
+    }
+}
+
+fn create_excel_function(ast: ItemFn, mut names: Vec<String>) -> TokenStream {
     let function_name = ast.sig.ident.clone(); 
     let mut struct_name: String = ast.sig.ident.to_string(); 
     make_ascii_titlecase(&mut struct_name); 
@@ -23,6 +52,24 @@ fn create_excel_function(ast: ItemFn) -> TokenStream {
         .iter()
         .filter(|fnarg| { matches!(fnarg, FnArg::Typed(_)) })
         .collect::<Vec<&FnArg>>(); 
+    if names.is_empty() {
+        names.push(function_name.to_string().to_uppercase()); 
+    }
+
+    // What the registry knows of the arguments: args collects the rest, Option ones may be left out 
+    let params = fn_args.iter().map(|fnarg| {
+        let name = arg_name(fnarg); 
+        let kind = if name == "args" {
+            quote! { crate::function::registry::ParamKind::Variadic }
+        } else if is_optional(fnarg) {
+            quote! { crate::function::registry::ParamKind::Optional }
+        } else {
+            quote! { crate::function::registry::ParamKind::Required }
+        }; 
+        quote! {
+            crate::function::registry::Param { name: #name, kind: #kind }
+        }
+    }).collect::<Vec<_>>(); 
 
     let struct_fields = fn_args.clone().into_iter().map(|fnarg| {
         quote! {
@@ -33,19 +80,17 @@ fn create_excel_function(ast: ItemFn) -> TokenStream {
     let field_declarations = fn_args.clone().into_iter().map(|fnarg| {
         if let FnArg::Typed(pat_type) = fnarg {
             let arg_name = *pat_type.pat.clone(); 
-            let is_optional: bool = match &*pat_type.ty {
-                syn::Type::Path(typepath) => typepath.path.segments.len() == 1 && typepath.path.segments[0].ident.to_string().as_str() == "Option",
-                _ => false
-            }; 
+            let is_optional: bool = is_optional(fnarg); 
             if let syn::Pat::Ident(pat_ident) = arg_name {
                if pat_ident.ident.to_string() == "args" {
                     quote! {
                         let args = v; 
                     }
                 } else if is_optional {
+                    // An empty argument, as in XLOOKUP(3,A1:A3,B1:B3,,0,2), is left out too 
                     quote! {
                         let #fnarg = if v.len() > 0 {
-                            Some(v.remove(0))
+                            Some(v.remove(0)).filter(|x| !x.is_empty())
                         } else {
                             None
                         }; 
@@ -77,10 +122,7 @@ fn create_excel_function(ast: ItemFn) -> TokenStream {
     let error_handling = fn_args.clone().into_iter().map(|fnarg| {
         if let FnArg::Typed(pat_type) = fnarg {
             let arg_name = *pat_type.pat.clone(); 
-            let is_optional: bool = match &*pat_type.ty {
-                syn::Type::Path(typepath) => typepath.path.segments.len() == 1 && typepath.path.segments[0].ident.to_string().as_str() == "Option",
-                _ => false
-            }; 
+            let is_optional: bool = is_optional(fnarg); 
             if let syn::Pat::Ident(ref pat_ident) = arg_name {
                if pat_ident.ident.to_string() == "args" {
                     quote! {
@@ -142,6 +184,21 @@ fn create_excel_function(ast: ItemFn) -> TokenStream {
             }
         }
 
+        impl #struct_name_ident {
+            pub const INFO: crate::function::registry::FunctionInfo = crate::function::registry::FunctionInfo {
+                names: &[#(#names),*], 
+                params: &[#(#params),*], 
+            }; 
+
+            pub fn evaluate_args(args: Vec<Value>) -> Value {
+                Function::evaluate(#struct_name_ident::from(args))
+            }
+        }
+
+        ::inventory::submit! {
+            crate::function::registry::Builtin { info: #struct_name_ident::INFO, evaluate: #struct_name_ident::evaluate_args }
+        }
+
         impl From<Vec<Value>> for #struct_name_ident { 
             fn from(mut v: Vec<Value>) -> #struct_name_ident {
                 #(#field_declarations)*; 
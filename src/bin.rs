@@ -1,11 +1,11 @@
 use clap::{Parser, Subcommand};
 use excel_lib::{
     workbook::Book, 
+    coverage::function_coverage, 
     parser::{
         ast::Expr, 
         parse_str
     }, 
-    errors::Error
 }; 
 
 #[derive(Parser)]
@@ -38,15 +38,42 @@ enum Commands {
         #[clap(value_parser)]
         range: String 
     }, 
+    // Functions the formulas use, exiting with 1 when any cannot be calculated
+    Functions {
+        #[clap(long)]
+        json: bool 
+    }, 
 }
 
 // 16355
 // 9154
 // 9155
 
-fn main() -> Result<(), Error> {
+// One line per row, cells separated by tabs, as Excel displays them. 
+fn print_formatted(book: &Book, expr: Expr) -> anyhow::Result<()> {
+    for row in book.resolve_formatted_ref(expr)?.rows() {
+        println!("{}", row.iter().cloned().collect::<Vec<String>>().join("\t")); 
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     let mut book: Book = Book::from(cli.path); 
+    if let Some(Commands::Functions { json }) = &cli.command {
+        // Formulas that do not parse are reported rather than stopping the load
+        book.load_cells(cli.progress)?; 
+        let coverage = function_coverage(&book); 
+        if *json {
+            println!("{}", coverage.to_json()); 
+        } else {
+            print!("{}", coverage); 
+        }
+        if !coverage.is_complete() {
+            std::process::exit(1); 
+        }
+        return Ok(()); 
+    }
     book.load(cli.progress)?; 
     match &cli.command {
         Some(Commands::Load) => { book.load(cli.progress)?}, 
@@ -64,14 +91,14 @@ fn main() -> Result<(), Error> {
         Some(Commands::Get {range}) => {
             let expr: Expr = parse_str(range)?;
             if matches!(expr, Expr::Reference { sheet: _, reference: _} ) {
-				println!("{}", &book.resolve_ref(expr)?); 
+				print_formatted(&book, expr)?; 
             } else {
                 panic!("Could not resolve {} to a reference.", range); 
             }
         }, 
         Some(Commands::Calculate {range}) => {
             book.calculate(cli.debug, cli.progress)?; 
-            println!("{:?}", book.resolve_str_ref(range)); 
+            print_formatted(&book, parse_str(range)?)?; 
         }
         _ => {}
     }
@@ -88,13 +88,13 @@ impl From<String> for Cell {
                 .filter(|c| c.is_alphabetic())
                 .collect::<String>();
             let mut col = 0;
-            for (_, c) in col_str.to_lowercase().chars().enumerate() {
+            for c in col_str.to_lowercase().chars() {
                 let c_i = alpha.chars().position(|r| r == c).unwrap();
                 col = col * 26 + c_i + 1;
             }
             Cell {
                 row: CellIndex::from((0, false)), 
-                column: CellIndex::from((col as usize, col_anchor))
+                column: CellIndex::from((col, col_anchor))
             }
         // Check if hrange
         } else if range.chars().filter(|c| c.is_alphabetic()).count() == 0 {
@@ -122,13 +122,13 @@ impl From<String> for Cell {
             let row_num_str = range.chars().filter(|c| c.is_ascii_digit()).collect::<String>();
             let row: usize = row_num_str.parse().unwrap();
             let mut col = 0;
-            for (_, c) in col_str.to_lowercase().chars().enumerate() {
+            for c in col_str.to_lowercase().chars() {
                 let c_i = alpha.chars().position(|r| r == c).unwrap();
                 col = col * 26 + c_i + 1;
             }
             Cell {
                 row : CellIndex::from((row, row_anchor)),
-                column : CellIndex::from((col as usize, col_anchor))
+                column : CellIndex::from((col, col_anchor))
             }
         }
     }
@@ -179,6 +179,59 @@ impl Cell {
         }
         col_name.into_iter().rev().collect::<String>()
     }
+
+    /*
+     * R1C1 notation relative to `anchor`, the cell the formula lives in. 
+     * Anchored ($) indexes print absolute (R2), the rest as offsets (R[-1], or R for no offset). 
+     * An hrange or vrange cell only prints its row or column part. 
+     */
+    pub fn to_r1c1(&self, anchor: Cell) -> String {
+        let part = |letter: char, index: CellIndex, base: usize| -> String {
+            if index.index == 0 {
+                String::new()
+            } else if index.anchor {
+                format!("{}{}", letter, index.index)
+            } else if index.index == base {
+                letter.to_string()
+            } else {
+                format!("{}[{}]", letter, index.index as i64 - base as i64)
+            }
+        }; 
+        format!("{}{}", part('R', self.row, anchor.row.index), part('C', self.column, anchor.column.index))
+    }
+
+    // Inverse of to_r1c1, None when the text is not R1C1 or points off the sheet. 
+    pub fn from_r1c1(s: &str, anchor: Cell) -> Option<Cell> {
+        fn part(s: &str, letter: char, base: usize) -> Option<(CellIndex, &str)> {
+            let rest = match s.strip_prefix(letter) {
+                Some(rest) => rest, 
+                None => return Some((CellIndex::from(0), s))
+            }; 
+            if let Some(offset) = rest.strip_prefix('[') {
+                let end = offset.find(']')?; 
+                let index = base as i64 + offset[..end].parse::<i64>().ok()?; 
+                if index < 1 {
+                    return None; 
+                }
+                Some((CellIndex::from((index as usize, false)), &offset[end + 1..]))
+            } else {
+                let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len(); 
+                if digits == 0 {
+                    Some((CellIndex::from((base, false)), rest))
+                } else {
+                    let index = rest[..digits].parse::<usize>().ok().filter(|i| *i > 0)?; 
+                    Some((CellIndex::from((index, true)), &rest[digits..]))
+                }
+            }
+        }
+        let (row, rest) = part(s, 'R', anchor.row.index)?; 
+        let (column, rest) = part(rest, 'C', anchor.column.index)?; 
+        if !rest.is_empty() || (row.index == 0 && column.index == 0) {
+            None
+        } else {
+            Some(Cell { row, column })
+        }
+    }
 }
 
 impl fmt::Display for Cell {
@@ -228,4 +281,23 @@ mod tests {
         assert_eq!(Cell::from("A").to_string(), String::from("A"));
         assert_eq!(Cell::from("12").to_string(), String::from("12"));
     }
+
+    #[test]
+    fn r1c1() {
+        let anchor = Cell::from("C3"); 
+        assert_eq!(Cell::from("B2").to_r1c1(anchor), String::from("R[-1]C[-1]"));
+        assert_eq!(Cell::from("C5").to_r1c1(anchor), String::from("R[2]C"));
+        assert_eq!(Cell::from("$A$1").to_r1c1(anchor), String::from("R1C1"));
+        assert_eq!(Cell::from("D$7").to_r1c1(anchor), String::from("R7C[1]"));
+        assert_eq!(Cell::from("$B").to_r1c1(anchor), String::from("C2"));
+        assert_eq!(Cell::from("4").to_r1c1(anchor), String::from("R[1]"));
+        assert_eq!(Cell::from_r1c1("R[-1]C[-1]", anchor).unwrap().to_string(), String::from("B2"));
+        assert_eq!(Cell::from_r1c1("RC", anchor).unwrap().to_string(), String::from("C3"));
+        assert_eq!(Cell::from_r1c1("R1C1", anchor).unwrap().to_string(), String::from("$A$1"));
+        assert_eq!(Cell::from_r1c1("R7C[1]", anchor).unwrap().to_string(), String::from("D$7"));
+        assert_eq!(Cell::from_r1c1("C2", anchor).unwrap().to_string(), String::from("$B"));
+        assert!(Cell::from_r1c1("R[-3]C", anchor).is_none());
+        assert!(Cell::from_r1c1("R0C1", anchor).is_none());
+        assert!(Cell::from_r1c1("A1", anchor).is_none());
+    }
 }
@@ -0,0 +1,175 @@
+use std::{fmt, collections::{HashMap, HashSet}};
+use serde::Serialize;
+use crate::{
+    workbook::Book,
+    parser::{parse_str, ast::Expr},
+    function::{is_supported, registry::check_arities},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionCount {
+    pub name: String,
+    pub count: usize, // Calls across every formula
+    pub supported: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnsupportedCell {
+    pub cell: String,
+    pub formula: String,
+    pub functions: Vec<String>,
+}
+
+// A formula that does not load, because it does not parse or calls a function with the wrong number of arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FormulaError {
+    pub cell: String,
+    pub formula: String,
+    pub error: String,
+}
+
+/*
+ * Which functions the formulas in a workbook use and whether they can be calculated,
+ * to check a model before relying on the emulator for it.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Coverage {
+    pub formulas: usize,
+    pub functions: Vec<FunctionCount>, // Most used first
+    pub unsupported: Vec<UnsupportedCell>,
+    pub errors: Vec<FormulaError>,
+}
+
+impl Coverage {
+    pub fn is_complete(&self) -> bool {
+        self.unsupported.is_empty() && self.errors.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} formulas, {} with unsupported functions, {} not loading", self.formulas, self.unsupported.len(), self.errors.len())?;
+        let width = self.functions.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        for c in self.functions.iter() {
+            writeln!(f, "{:width$} {:>6}{}", c.name, c.count, if c.supported { "" } else { "  unsupported" }, width = width)?;
+        }
+        for cell in self.unsupported.iter() {
+            writeln!(f, "{}: {} uses {}", cell.cell, cell.formula, cell.functions.join(", "))?;
+        }
+        for cell in self.errors.iter() {
+            writeln!(f, "{}: {}\n{}", cell.cell, cell.formula, cell.error)?;
+        }
+        Ok(())
+    }
+}
+
+// Function calls in expr, leaving out LAMBDAs that LET or a LAMBDA parameter names.
+fn calls(expr: &Expr, names: &mut Vec<String>, local: &mut HashSet<String>) {
+    match expr {
+        Expr::Func { name, args } => {
+            let name = name.to_uppercase();
+            if name == "LET" || name == "LAMBDA" {
+                local.extend(args.iter().filter_map(|a| match a {
+                    Expr::Name(n) => Some(n.to_uppercase()),
+                    _ => None
+                }));
+            }
+            names.push(name);
+            args.iter().for_each(|a| calls(a, names, local));
+        },
+        Expr::Call(f, args) => {
+            calls(f, names, local);
+            args.iter().for_each(|a| calls(a, names, local));
+        },
+        Expr::Prefix(_, a) => calls(a, names, local),
+        Expr::Infix(_, a, b) => {
+            calls(a, names, local);
+            calls(b, names, local);
+        },
+        Expr::Array(rows) => rows.iter().flatten().for_each(|a| calls(a, names, local)),
+        _ => {}
+    }
+}
+
+/*
+ * Counts the functions every formula in the book calls. Functions registered on the
+ * book and LAMBDAs given a defined name count as supported.
+ */
+pub fn function_coverage(book: &Book) -> Coverage {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut unsupported: Vec<UnsupportedCell> = vec![];
+    let mut errors: Vec<FormulaError> = vec![];
+    let defined: HashSet<&String> = book.defined_names.keys().map(|(_, name)| name).collect();
+    for (cell_id, formula) in book.formulas().iter() {
+        let cell = book.cell_name(cell_id.sheet, cell_id.row, cell_id.column);
+        let expr = match parse_str(&formula[1..]) {
+            Ok(expr) => expr,
+            Err(e) => {
+                errors.push(FormulaError { cell, formula: formula.clone(), error: e.to_string() });
+                continue;
+            }
+        };
+        let (mut names, mut local) = (vec![], HashSet::new());
+        calls(&expr, &mut names, &mut local);
+        names.retain(|n| !local.contains(n) && !defined.contains(n));
+        let mut missing: Vec<String> = vec![];
+        for name in names.into_iter() {
+            if !is_supported(&name) && !book.functions.contains(&name) && !missing.contains(&name) {
+                missing.push(name.clone());
+            }
+            *counts.entry(name).or_insert(0) += 1;
+        }
+        if !missing.is_empty() {
+            unsupported.push(UnsupportedCell { cell: cell.clone(), formula: formula.clone(), functions: missing });
+        }
+        if let Err(e) = check_arities(&expr, &book.functions) {
+            errors.push(FormulaError { cell, formula: formula.clone(), error: e.to_string() });
+        }
+    }
+    let mut functions: Vec<FunctionCount> = counts.into_iter()
+        .map(|(name, count)| FunctionCount { supported: is_supported(&name) || book.functions.contains(&name), name, count })
+        .collect();
+    functions.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    Coverage { formulas: book.formulas().len(), functions, unsupported, errors }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        coverage::{function_coverage, FunctionCount},
+        workbook::Book,
+        function::registry::Arity,
+    };
+
+    #[test]
+    fn test_function_coverage() {
+        let mut book = Book::from("assets/coverage.xlsx");
+        book.load_cells(false).unwrap();
+        let coverage = function_coverage(&book);
+        assert_eq!(coverage.formulas, 6);
+        let count = |name: &str| coverage.functions.iter().find(|c| c.name == name).cloned();
+        assert_eq!(count("SUM"), Some(FunctionCount { name: "SUM".to_string(), count: 3, supported: true }));
+        assert_eq!(count("FOO"), Some(FunctionCount { name: "FOO".to_string(), count: 1, supported: false }));
+        // LET names and defined LAMBDAs are not functions
+        assert_eq!(count("F"), None);
+        assert_eq!(count("TWICE"), None);
+        assert_eq!(coverage.unsupported.len(), 1);
+        assert_eq!(coverage.unsupported[0].cell, "'Sheet1'!B2");
+        assert_eq!(coverage.unsupported[0].functions, vec!["FOO".to_string()]);
+        assert_eq!(coverage.errors.iter().map(|e| e.cell.as_str()).collect::<Vec<&str>>(), vec!["'Sheet1'!B4", "'Sheet1'!B5"]);
+        assert_eq!(coverage.errors[1].error, "ROUND takes 2 arguments, found 1");
+        assert!(!coverage.is_complete());
+        let json: serde_json::Value = serde_json::from_str(&coverage.to_json()).unwrap();
+        assert_eq!(json["functions"][0]["name"], "SUM");
+        assert_eq!(json["unsupported"][0]["functions"][0], "FOO");
+        // Functions registered on the book are supported
+        book.functions.register("FOO", Arity::exactly(1), |args| args[0].clone());
+        let coverage = function_coverage(&book);
+        assert!(coverage.unsupported.is_empty());
+        assert!(coverage.functions.iter().all(|c| c.supported));
+    }
+}
@@ -2,9 +2,9 @@ use petgraph::{
     graphmap::DiGraphMap, 
     algo::toposort, 
     dot::{Dot, Config}, 
-    visit::Dfs 
+    visit::Bfs, 
 }; 
-use std::{fmt, cmp::Ordering}; 
+use std::{fmt, cmp::Ordering, collections::HashSet}; 
 use crate::{
     workbook::Sheet,
     parser::{
@@ -13,6 +13,8 @@ use crate::{
     }, 
     reference::Reference, 
     errors::Error,
+    utils::split_external_sheet, 
+    function::volatile::VOLATILE_FUNCTIONS, 
 }; 
 
 #[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
@@ -59,7 +61,8 @@ impl fmt::Display for CellId {
 
 pub struct DependencyTree {
     tree: DiGraphMap<CellId, u8>, 
-    pub offsets: Vec<CellId>
+    pub offsets: Vec<CellId>, 
+    volatile: HashSet<CellId>, // Recalculated on every calculation 
 }
 
 /*
@@ -76,7 +79,7 @@ impl Default for DependencyTree {
 
 impl DependencyTree {
     pub fn new() -> DependencyTree {
-        DependencyTree { tree: DiGraphMap::new(), offsets: vec![] }
+        DependencyTree { tree: DiGraphMap::new(), offsets: vec![], volatile: HashSet::new() }
     }
 
     pub fn add_formula(&mut self, cell: CellId, formula_text: &str, sheets: &Vec<Sheet>) -> Result<(), Error> {
@@ -91,6 +94,13 @@ impl DependencyTree {
         match expression {
             Expr::Reference { sheet, reference } => {
                 let sheet_id = match sheet {
+                    // Other workbooks are calculated on their own, only the formula cell needs a node. 
+                    // Their values can change between calculations, like volatile functions. 
+                    Some(s) if split_external_sheet(&s).is_some() => {
+                        self.add_cell(cell); 
+                        self.volatile.insert(cell); 
+                        return Ok(()); 
+                    }, 
                     Some(s) => {
                         sheets.iter().position(|x|  {
                             x.name == s
@@ -123,6 +133,21 @@ impl DependencyTree {
                     self.add_precedent(&pre_cell, &cell); 
                 }
             },
+            // Only the anchor's formula decides what is in the spill range 
+            Expr::Spill { sheet, anchor } => {
+                let sheet_id = match sheet {
+                    Some(s) => match sheets.iter().position(|x| x.name == s) {
+                        Some(idx) => idx, 
+                        None => return Ok(())
+                    }, 
+                    None => cell.sheet
+                }; 
+                let (row, column) = Reference::from(anchor).start_cell.as_tuple(); 
+                let pre_cell = CellId::from((sheet_id, row, column, 1, 1, true)); 
+                if pre_cell != cell {
+                    self.add_precedent(&pre_cell, &cell); 
+                }
+            }, 
             Expr::Infix(_, a, b) => {
                 self.add_expression(cell, *a, sheets)?; 
                 self.add_expression(cell, *b, sheets)?; 
@@ -131,20 +156,37 @@ impl DependencyTree {
                 self.add_expression(cell, *a, sheets)?; 
             }, 
             Expr::Func { name, args } => {
+                // OFFSET and INDIRECT can point somewhere else without their arguments changing 
+                if VOLATILE_FUNCTIONS.contains(&name.as_str()) || matches!(name.as_str(), "OFFSET" | "INDIRECT") {
+                    self.add_cell(cell); 
+                    self.volatile.insert(cell); 
+                }
                 if name.as_str() == "OFFSET" {
                     let mut offset_args = args.clone(); 
                     offset_args.remove(0); 
-                    self.add_expression(cell, Expr::Array(offset_args), sheets)?; 
+                    self.add_expression(cell, Expr::Array(vec![offset_args]), sheets)?; 
                 }
+                // These only look at where a reference points, so ROWS($A$1:A5) in A5 is not circular 
+                let reference_only = matches!(name.as_str(), "ROW" | "COLUMN" | "ROWS" | "COLUMNS" | "SHEET" | "SHEETS" | "ISREF" | "ISFORMULA" | "FORMULATEXT"); 
                 for arg in args.into_iter() {
-                    self.add_expression(cell, arg, sheets)?; 
+                    if !(reference_only && matches!(arg, Expr::Reference { .. })) {
+                        self.add_expression(cell, arg, sheets)?; 
+                    }
                 }
             }, 
-            Expr::Array(arr) => {
-                for a in arr.into_iter() {
+            Expr::Array(rows) => {
+                for a in rows.into_iter().flatten() {
                     self.add_expression(cell, a, sheets)?; 
                 }
             }, 
+            Expr::Call(f, args) => {
+                self.add_expression(cell, *f, sheets)?; 
+                for arg in args.into_iter() {
+                    self.add_expression(cell, arg, sheets)?; 
+                }
+            }, 
+            // LET variables and LAMBDA parameters are not cells, defined names are resolved before this 
+            Expr::Name(_) => {}, 
             _ => {}
             
         }
@@ -155,6 +197,13 @@ impl DependencyTree {
         self.tree.add_node(cell); 
     }
 
+    // An array formula block calculates before each of its cells is read. 
+    pub fn add_array(&mut self, block: CellId) {
+        for (row, column) in Reference::get_cells_from_dim(block.row, block.column, block.num_row, block.num_col) {
+            self.add_precedent(&block, &CellId::from((block.sheet, row, column, 1, 1, true))); 
+        }
+    }
+
     pub fn cell_exists(&self, cell: &CellId) -> bool {
         self.tree.contains_node(*cell)
     }
@@ -191,11 +240,24 @@ impl DependencyTree {
         } 
     } 
 
-    pub fn mark_for_recalculation(&mut self, root: &CellId) {
-        let mut dfs = Dfs::new(&self.tree, root.clone());
-        while let Some(mut node_id) = dfs.next(&self.tree) {
-            node_id.dirty = true; 
+    pub fn is_volatile(&self, cell: &CellId) -> bool {
+        self.volatile.contains(cell)
+    }
+
+    pub fn volatile_cells(&self) -> Vec<CellId> {
+        self.volatile.iter().copied().collect()
+    }
+
+    // The cells and everything depending on them, directly or through other cells. 
+    pub fn dependents(&self, cells: Vec<CellId>) -> Vec<CellId> {
+        let mut output: HashSet<CellId> = HashSet::new(); 
+        for cell in cells.into_iter().filter(|c| self.cell_exists(c)) {
+            let mut bfs = Bfs::new(&self.tree, cell); 
+            while let Some(c) = bfs.next(&self.tree) {
+                output.insert(c); 
+            }
         }
+        output.into_iter().collect()
     }
 }
 
@@ -218,7 +280,7 @@ mod tests {
         tree.add_precedent(&a, &b); // A must calculate before B 
         tree.add_precedent(&c, &b); // C must calculate before B 
         assert!(tree.is_dependent_of(&b, &a)); 
-        assert_eq!(tree.is_dependent_of(&a, &b), false); 
+        assert!(!tree.is_dependent_of(&a, &b)); 
     }
 
     #[test]
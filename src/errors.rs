@@ -1,21 +1,71 @@
+use std::fmt; 
 use thiserror::Error; 
 use crate::dependency::CellId; 
 use crate::parser::ast::Expr; 
+use crate::function::registry::Arity; 
+use crate::lexer::token::Span; 
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Unable to calculate cell {0} with error {1}")]
     Calculation(CellId, Box<Error>), 
 
+    #[error("Unable to load formula in {0}: {1}")]
+    Formula(String, Box<Error>), 
+
     #[error("Function {0} is not supported")]
     FunctionNotSupport(String),
 
-    #[error("Unable to parse str {0}")]
-    UnableToParse(String), 
+    #[error("{name} takes {arity} arguments, found {found}")]
+    ArgumentCount { name: String, arity: Arity, found: usize },
+
+    #[error("Unable to parse formula: {0}")]
+    UnableToParse(SyntaxError), 
 
-    #[error("Unable to lex str {0}")]
-    UnableToLex(String), 
+    #[error("Unable to lex formula: {0}")]
+    UnableToLex(SyntaxError), 
 
     #[error("Dependency tree changed.")]
     Volatile(Box<Expr>)
 }
+
+/*
+ * Where a formula stopped lexing or parsing.
+ * The span is in bytes of `formula`; `expected` lists the token kinds
+ * that would have been accepted at that position.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub formula: String,
+    pub span: Span,
+    pub expected: Vec<String>,
+    pub found: String,
+}
+
+impl SyntaxError {
+    pub fn new(formula: &str, span: Span, expected: Vec<String>) -> SyntaxError {
+        let found = match formula.get(span.start..span.end) {
+            Some(s) if !s.is_empty() => format!("'{}'", s),
+            _ => String::from("end of formula")
+        };
+        SyntaxError { formula: formula.to_string(), span, expected, found }
+    }
+
+    // The formula with a caret line underneath the offending span.
+    pub fn render(&self) -> String {
+        let start = self.formula.get(..self.span.start).map(|s| s.chars().count()).unwrap_or(0);
+        let width = self.formula.get(self.span.start..self.span.end).map(|s| s.chars().count()).unwrap_or(0).max(1);
+        format!("{}\n{}{}", self.formula, " ".repeat(start), "^".repeat(width))
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.expected.len() {
+            0 => write!(f, "unexpected {} at {}", self.found, self.span)?,
+            1 => write!(f, "expected {}, found {} at {}", self.expected[0], self.found, self.span)?,
+            n => write!(f, "expected {} or {}, found {} at {}", self.expected[..n-1].join(", "), self.expected[n-1], self.found, self.span)?
+        }
+        write!(f, "\n{}", self.render())
+    }
+}
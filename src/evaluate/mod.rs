@@ -1,6 +1,7 @@
+use ndarray::Array2; 
 use crate::{
     parser::{
-        ast::{Literal, Prefix, Infix, Expr}, 
+        ast::{Literal, Prefix, Infix, Expr, Error as ExcelError}, 
         parse_str
     }, 
     function::*, 
@@ -33,7 +34,8 @@ pub fn evaluate_expr(expr: Expr) -> Result<Value, Error> {
                     }
                 }
 				Literal::Boolean(b) => Value::from(b), 
-				Literal::Text(s) => Value::from(s)
+				Literal::Text(s) => Value::from(s),
+				Literal::Empty => Value::Empty
 			}
 		},
 		Expr::Prefix(p, box_expr) => { 
@@ -75,41 +77,90 @@ pub fn evaluate_expr(expr: Expr) -> Result<Value, Error> {
                 }
             }
         }, 
-        Expr::Array(x) => Value::Array(x.into_iter().map(|x| evaluate_expr(x).unwrap()).collect::<Vec<Value>>()), 
+        Expr::Array(rows) => array_value(rows.into_iter().map(|row| row.into_iter().map(|x| evaluate_expr(x).unwrap()).collect()).collect()), 
         Expr::Error(err) => Value::Error(err), 
+        // Structured and spill references need a workbook to resolve against
+        Expr::Table(_) | Expr::Spill { .. } => Value::Error(ExcelError::Ref), 
+        // So do names and the LAMBDAs they are bound to
+        Expr::Name(_) | Expr::Call(..) => Value::Error(ExcelError::Name), 
         _ => panic!("Expression {:?} does not convert to a value.", expr)  
 	}; 
     Ok(value)
 }
 
 pub fn offset_expr(args: Vec<Expr>, book: &Book, debug: bool) -> Result<Expr, Error> {
-    if let Expr::Reference { sheet, reference } = args.get(0).unwrap() { 
-        let rows = evaluate_expr_with_context(args.get(1).unwrap().clone(), book, debug)?;
-        let cols = evaluate_expr_with_context(args.get(2).unwrap().clone(), book, debug)?; 
-        let height = args.get(3); 
-        let height_opt: Option<i32> = height.map(|h| {
-            evaluate_expr_with_context(h.clone(), book, debug).unwrap().as_num() as i32 
-        }); 
-        let width = args.get(4); 
-        let width_opt: Option<i32> = width.map(|w| {
-            evaluate_expr_with_context(w.clone(), book, debug).unwrap().as_num() as i32 
-        }); 
-        let new_reference = offset_reference(&mut Reference::from(reference.as_str()), rows.as_num() as i32, cols.as_num() as i32, height_opt, width_opt); 
-        Ok(Expr::Reference { sheet: sheet.clone(), reference: new_reference.to_string() })
-    } else {
-        panic!("Offset must have a reference.")
-    }
+    let (Some(Expr::Reference { sheet, reference }), Some(rows), Some(cols)) = (args.first(), args.get(1), args.get(2)) else {
+        return Ok(Expr::Error(ExcelError::Value)); 
+    }; 
+    let rows = evaluate_expr_with_context(rows.clone(), book, debug)?;
+    let cols = evaluate_expr_with_context(cols.clone(), book, debug)?; 
+    let height = args.get(3); 
+    let height_opt: Option<i32> = height.map(|h| {
+        evaluate_expr_with_context(h.clone(), book, debug).map(|v| v.as_num() as i32)
+    }).transpose()?; 
+    let width = args.get(4); 
+    let width_opt: Option<i32> = width.map(|w| {
+        evaluate_expr_with_context(w.clone(), book, debug).map(|v| v.as_num() as i32)
+    }).transpose()?; 
+    let new_reference = offset_reference(&mut Reference::from(reference.as_str()), rows.as_num() as i32, cols.as_num() as i32, height_opt, width_opt); 
+    Ok(Expr::Reference { sheet: sheet.clone(), reference: new_reference.to_string() })
 }
 
 pub fn ensure_non_range(value: Value) -> Value {
     if let Value::Range { sheet: _, reference: _, value } = value {
         if let Some(value) = value {
-            return *value; 
+            *value
         } else {
             panic!("Value::Range is missing a value to return")
         }
     } else {
-        return value; 
+        value
+    }
+}
+
+// A one row array literal stays a list, {1,2;3,4} is a grid. 
+fn array_value(rows: Vec<Vec<Value>>) -> Value {
+    match rows.len() {
+        0 | 1 => Value::Array(rows.into_iter().flatten().collect()), 
+        n => Value::from(Array2::from_shape_vec((n, rows[0].len()), rows.into_iter().flatten().collect()).unwrap())
+    }
+}
+
+fn infix(i: &Infix, a: Value, b: Value) -> Value {
+    match i {
+        Infix::Plus => a + b, 
+        Infix::Minus => a - b, 
+        Infix::Multiply => a * b, 
+        Infix::Divide => a / b, 
+        Infix::Exponent => Exponent {a, b}.evaluate(), 
+        Infix::NotEqual => Value::from(a != b), 
+        Infix::Equal => Value::from(a == b), 
+        Infix::LessThan => Value::from(a < b), 
+        Infix::LessThanEqual => Value::from(a <= b), 
+        Infix::GreaterThan => Value::from(a > b), 
+        Infix::GreaterThanEqual => Value::from(a >= b), 
+        Infix::Ampersand => Value::from(format!("{}{}", a.as_text(), b.as_text())), 
+    }
+}
+
+// IF only evaluates the branch it takes, so a LAMBDA can call itself until it stops. 
+fn if_expr(args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
+    let condition = ensure_non_range(evaluate_expr_with_context(args[0].clone(), book, debug)?); 
+    match condition {
+        Value::Error(_) => Ok(condition), 
+        // Both branches are needed to pick element by element 
+        Value::Array(_) | Value::Array2(_) => {
+            let a = ensure_non_range(evaluate_expr_with_context(args[1].clone(), book, debug)?); 
+            let b = match args.get(2) {
+                Some(b) => ensure_non_range(evaluate_expr_with_context(b.clone(), book, debug)?), 
+                None => Value::from(false)
+            }; 
+            Ok(if_values(condition, a, b))
+        }, 
+        _ => match args.get(if condition.as_bool() { 1 } else { 2 }) {
+            Some(branch) => evaluate_expr_with_context(branch.clone(), book, debug), 
+            None => Ok(Value::from(false))
+        }
     }
 }
 
@@ -124,6 +175,9 @@ pub fn evaluate_expr_with_context(expr: Expr, book: &Book, debug: bool) -> Resul
 		}, 
         Expr::Func {name, args} => {
             match name.as_str() {
+                c if book.functions.contains(c) => {
+                    registry::call(book.functions.get(c).unwrap(), args, book, debug)?
+                }, 
                 "OFFSET" => {
                     let offset_value: Value = offset(args, book, debug)?;  
                     match offset_value {
@@ -136,11 +190,40 @@ pub fn evaluate_expr_with_context(expr: Expr, book: &Book, debug: bool) -> Resul
                         _ => unreachable!()
                     }
                 }, 
+                "IF" if (2..=3).contains(&args.len()) => {
+                    if_expr(args, book, debug)?
+                }, 
                 "INDEX" => {
                     index(args, book, debug)?
                 }, 
+                "INDIRECT" => {
+                    indirect(args, book, debug)?
+                }, 
+                "ROW" | "COLUMN" | "ROWS" | "COLUMNS" | "SHEET" | "SHEETS" => {
+                    reference_info(name.as_str(), args, book, debug)?
+                }, 
+                "SUBTOTAL" | "AGGREGATE" => {
+                    subtotal(name.as_str(), args, book, debug)?
+                }, 
+                "ISREF" | "ISFORMULA" | "FORMULATEXT" => {
+                    logical::formula_info(name.as_str(), args, book, debug)?
+                }, 
+                c if lambda::LAMBDA_FUNCTIONS.contains(&c) => {
+                    lambda::evaluate(c, args, book, debug)?
+                }, 
+                // A LAMBDA bound with LET or to a defined name 
+                c if lambda::is_name(c, book) => {
+                    let f = lambda::name(c, book, debug)?; 
+                    let arg_values: Vec<Value> = args.into_iter().map(|x| evaluate_expr_with_context(x, book, debug)).collect::<Result<Vec<Value>, Error>>()?; 
+                    lambda::call(&f, arg_values, book, debug)?
+                }, 
+                c if volatile::VOLATILE_FUNCTIONS.contains(&c) => {
+                    let arg_values: Vec<Value> = args.into_iter().map(|x| evaluate_expr_with_context(x, book, debug).map(ensure_non_range)).collect::<Result<Vec<Value>, Error>>()?; 
+                    volatile::evaluate(c, arg_values, &book.context)
+                }, 
                 c => {
-                    let arg_values: Vec<Value> = args.into_iter().map(|x| ensure_non_range(evaluate_expr_with_context(x, book, debug).unwrap())).collect::<Vec<Value>>(); 
+                    // Error::Volatile from a nested OFFSET or INDIRECT restarts the calculation 
+                    let arg_values: Vec<Value> = args.into_iter().map(|x| evaluate_expr_with_context(x, book, debug).map(ensure_non_range)).collect::<Result<Vec<Value>, Error>>()?; 
                     get_function_value(c, arg_values)?
                 }
             }
@@ -149,7 +232,8 @@ pub fn evaluate_expr_with_context(expr: Expr, book: &Book, debug: bool) -> Resul
 			match lit {
 				Literal::Number(f) => Value::from(f), 
 				Literal::Boolean(b) => Value::from(b), 
-				Literal::Text(s) => Value::from(s)
+				Literal::Text(s) => Value::from(s),
+				Literal::Empty => Value::Empty
 			}
 		},
 		Expr::Prefix(p, box_expr) => { 
@@ -162,38 +246,24 @@ pub fn evaluate_expr_with_context(expr: Expr, book: &Book, debug: bool) -> Resul
 		Expr::Infix(i, a, b) => {
             let a = ensure_non_range(evaluate_expr_with_context(*a, book, debug)?); 
             let b = ensure_non_range(evaluate_expr_with_context(*b, book, debug)?); 
-            if a.is_err() {
-                return Ok(a); 
-            } else if b.is_err() {
-                return Ok(b); 
-            } else {
-                match i {
-                    Infix::Plus => a.ensure_single() + b.ensure_single(), 
-                    Infix::Minus => a.ensure_single() - b.ensure_single(), 
-                    Infix::Multiply => a.ensure_single() * b.ensure_single(), 
-                    Infix::Divide => a.ensure_single() / b.ensure_single(), 
-                    Infix::Exponent => Exponent {a, b}.evaluate(), 
-                    Infix::NotEqual => Value::from(a.ensure_single() != b.ensure_single()), 
-                    Infix::Equal => Value::from(a.ensure_single() == b.ensure_single()), 
-                    Infix::LessThan => Value::from(a.ensure_single() < b.ensure_single()), 
-                    Infix::LessThanEqual => Value::from(a.ensure_single() <= b.ensure_single()), 
-                    Infix::GreaterThan => Value::from(a.ensure_single() > b.ensure_single()), 
-                    Infix::GreaterThanEqual => Value::from(a.ensure_single() >= b.ensure_single()), 
-                    Infix::Ampersand => {
-                        let value = if a.is_array() {
-                            Value::from(a.as_array().into_iter().map(|x| Value::from(format!("{}{}", x.as_text(), b.as_text()))).collect::<Vec<Value>>())
-                        } else if b.is_array() {
-                            Value::from(b.as_array().into_iter().map(|x| Value::from(format!("{}{}", a.as_text(), x.as_text()))).collect::<Vec<Value>>())
-                        } else {
-                            Value::from(format!("{}{}", a.as_text(), b.as_text()))
-                        }; 
-                        value
-                    },
-                }
-            } 
+            // Arrays combine element by element, as in {=A1:A3*B1:B3} 
+            broadcast(vec![a, b], |v| infix(&i, v[0].clone(), v[1].clone()))
+        }, 
+		Expr::Array(rows) => {
+            let rows: Vec<Vec<Value>> = rows.into_iter()
+                .map(|row| row.into_iter().map(|e| evaluate_expr_with_context(e, book, debug).map(ensure_non_range)).collect::<Result<Vec<Value>, Error>>())
+                .collect::<Result<Vec<Vec<Value>>, Error>>()?; 
+            array_value(rows)
+        }, 
+        Expr::Error(err) => Value::Error(err), 
+        // Left unresolved when the table does not exist in the book, or the anchor does not spill 
+        Expr::Table(_) | Expr::Spill { .. } => Value::Error(ExcelError::Ref), 
+        Expr::Name(name) => lambda::name(&name, book, debug)?, 
+        Expr::Call(f, args) => {
+            let f = evaluate_expr_with_context(*f, book, debug)?; 
+            let arg_values: Vec<Value> = args.into_iter().map(|x| evaluate_expr_with_context(x, book, debug)).collect::<Result<Vec<Value>, Error>>()?; 
+            lambda::call(&f, arg_values, book, debug)?
         }, 
-		Expr::Array(x) => Value::Array(x.into_iter().map(|e| ensure_non_range(evaluate_expr_with_context(e, book, debug).unwrap())).collect::<Vec<Value>>()), 
-        _ => panic!("Expression {:?} does not convert to a value.", expr)  
 	}; 
     if debug {
         match expr.clone() {
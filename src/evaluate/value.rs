@@ -1,19 +1,20 @@
-use chrono::{Duration, NaiveDate}; 
+use chrono::{NaiveDate, NaiveDateTime, Timelike}; 
 use std::fmt; 
+use std::collections::HashMap; 
 use std::cmp::{Eq, PartialEq, PartialOrd, Ordering};
 use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign};  
 use ndarray::Array2; 
 
 use crate::reference::Reference;
-use crate::parser::ast::Error;
-use crate::utils::excel_to_date; 
+use crate::parser::ast::{Expr, Error};
+use crate::utils::{excel_to_date, date_time_to_excel}; 
 
 type NumType = f64;
 type BoolType = bool;
 type TextType = String; 
 type ArrayType = Vec<Value>;
 type Array2Type = Array2<Value>;
-type DateType = NaiveDate; 
+type DateType = NaiveDateTime; 
 type ErrorType = Error; 
 
 #[derive(Clone, PartialEq, Debug)]
@@ -27,6 +28,8 @@ pub enum Value {
     Formula(TextType), 
     Error(ErrorType), 
     Range { sheet: Option<String>, reference: Reference, value: Option<Box<Value>> }, 
+    // What LAMBDA evaluates to, with the LET names it saw when it was made 
+    Lambda { params: Vec<String>, body: Box<Expr>, scope: HashMap<String, Value> }, 
     Empty
 }
 
@@ -38,7 +41,8 @@ impl From<String> for Value { fn from(s: TextType) -> Value { Value::Text(s) }}
 impl From<&str> for Value { fn from(s: &str) -> Value { Value::Text(s.to_string()) }}
 impl From<Vec<Value>> for Value { fn from(v: ArrayType) -> Value { Value::Array(v) }}
 impl From<Array2<Value>> for Value { fn from(v: Array2Type) -> Value { Value::Array2(v) }}
-impl From<NaiveDate> for Value { fn from(d: DateType) -> Value { Value::Date(d) }}
+impl From<NaiveDate> for Value { fn from(d: NaiveDate) -> Value { Value::Date(d.and_hms_opt(0, 0, 0).unwrap()) }}
+impl From<NaiveDateTime> for Value { fn from(d: DateType) -> Value { Value::Date(d) }}
 
 impl Value {
     pub fn is_num(&self) -> bool { matches!(self, Value::Num(_)) }
@@ -51,11 +55,12 @@ impl Value {
     pub fn is_formula(&self) -> bool { matches!(self, Value::Formula(_)) }
     pub fn is_range(&self) -> bool { matches!(self, Value::Range {sheet: _, reference: _, value: _}) }
     pub fn is_err(&self) -> bool { matches!(self, Value::Error(_)) }
+    pub fn is_lambda(&self) -> bool { matches!(self, Value::Lambda { .. }) }
 
     pub fn ensure_single(&self) -> Value {
         match self {
             Value::Array2(arr2) => arr2[[0,0]].ensure_single().clone(), // assume single
-            Value::Array(arr) => arr.get(0).unwrap().ensure_single().clone(), // assume single
+            Value::Array(arr) => arr.first().unwrap().ensure_single().clone(), // assume single
             c => c.clone() // TODO
         }
     }
@@ -111,11 +116,15 @@ impl Value {
         } 
     }
 
-    pub fn as_date(&self) -> DateType {
+    pub fn as_date(&self) -> NaiveDate {
+        self.as_date_time().date()
+    }
+
+    pub fn as_date_time(&self) -> DateType {
         match self { 
             Value::Date(x) => *x,
             Value::Array2(arr2) => {
-                arr2[[0,0]].as_date()
+                arr2[[0,0]].as_date_time()
             }, 
             Value::Num(n) => excel_to_date(*n), 
             _ => panic!("{} cannot be converted to a date.", self)
@@ -151,7 +160,7 @@ impl fmt::Display for Value {
         match self {
             Value::Num(x) => { 
                 if x.is_nan() {
-                    write!(f, "{}", "0") 
+                    write!(f, "0") 
                 } else {
                     write!(f, "{}", x) 
                 } 
@@ -159,11 +168,15 @@ impl fmt::Display for Value {
             Value::Bool(x) => { write!(f, "{}", if *x { "TRUE" } else { "FALSE" }) }, 
             Value::Text(x) => { write!(f, "\"{}\"", x) },
             Value::Formula(x) => { write!(f, "{}", x) }, 
-            Value::Date(x) => { write!(f, "{}", x) }, 
+            Value::Date(x) => { 
+                if x.num_seconds_from_midnight() == 0 {
+                    write!(f, "{}", x.date()) 
+                } else {
+                    write!(f, "{}", x) 
+                }
+            }, 
             Value::Array(x) => {
-                x.iter().fold(Ok(()), |result, output| {
-                    result.and_then(|_| writeln!(f, "{}", output)) 
-                })
+                x.iter().try_for_each(|output| writeln!(f, "{}", output))
             }, 
             Value::Empty => { write!(f, "\"\"") }
             Value::Range {sheet, reference, value: _} => { 
@@ -173,7 +186,8 @@ impl fmt::Display for Value {
                 }
             }, 
             Value::Array2(arr2) => write!(f, "{}", arr2), 
-            Value::Error(err) => write!(f, "{}", err)
+            Value::Error(err) => write!(f, "{}", err), 
+            Value::Lambda { .. } => write!(f, "{}", Error::Calc)
         }
     }
 }
@@ -198,6 +212,9 @@ fn variant_ord(v : &Value) -> usize {
     }
 }
 
+// Values of different kinds (arrays, errors) have no ordering, so 
+// partial_cmp stays the source of truth. 
+#[allow(clippy::non_canonical_partial_ord_impl)]
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         let self_rank : usize = variant_ord(self);
@@ -225,7 +242,7 @@ impl PartialOrd for Value {
                         Some(Ordering::Equal)
                     }
                 } else if self.is_date() {
-                    Some(self.as_date().cmp(&other.as_date()))
+                    Some(self.as_date_time().cmp(&other.as_date_time()))
                 } else {
                     None
                 }
@@ -249,7 +266,7 @@ impl Add for Value {
             Value::Bool(_) => Value::from(self.as_num() + other.ensure_single().as_num()), 
             Value::Empty => Value::from(0.0 + other.ensure_single().as_num()), 
             Value::Date(dt) => {
-                Value::from(dt.checked_add_signed(Duration::days(other.ensure_single().as_num() as i64)).unwrap())
+                Value::from(excel_to_date(date_time_to_excel(dt) + other.ensure_single().as_num()))
             }, 
             Value::Error(_) => self, 
             _ => panic!("{} cannot be added to {}.", other, self)
@@ -277,9 +294,9 @@ impl Sub for Value {
             Value::Date(dt) => {
                 let other_single = other.ensure_single(); 
                 if other_single.is_date() {
-                        Value::from(NaiveDate::signed_duration_since(dt, other_single.as_date()).num_days() as f64)
+                    Value::from(date_time_to_excel(dt) - date_time_to_excel(other_single.as_date_time()))
                 } else {
-                    Value::from(dt.checked_sub_signed(Duration::days(other_single.as_num() as i64)).unwrap())
+                    Value::from(excel_to_date(date_time_to_excel(dt) - other_single.as_num()))
                 }
             }, 
             Value::Error(_) => self, 
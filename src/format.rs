@@ -0,0 +1,765 @@
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    function::text::general_number,
+    utils::{date_time_to_excel, serial_to_ymd},
+};
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+const DAYS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+// Largest serial a date format can show, 9999-12-31.
+const MAX_DATE: f64 = 2958466.0;
+
+// Format codes for the built-in numFmtId values, as shown in an en-US Excel.
+pub fn builtin_format(id: usize) -> Option<&'static str> {
+    Some(match id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        5 => "$#,##0_);($#,##0)",
+        6 => "$#,##0_);[Red]($#,##0)",
+        7 => "$#,##0.00_);($#,##0.00)",
+        8 => "$#,##0.00_);[Red]($#,##0.00)",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "m/d/yyyy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yyyy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        _ => return None
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Digit(char), // 0, # or ?
+    Point,
+    Percent,
+    Exponent(bool, Vec<char>), // E+ shows the sign of positive exponents too
+    Text,
+    General,
+    Year(usize),
+    Month(usize),
+    Day(usize),
+    Hour(usize),
+    Minute(usize),
+    Second(usize),
+    SubSecond(usize),
+    AmPm(bool), // AM/PM or A/P
+    Elapsed(char, usize), // [h], [mm], [ss]
+}
+
+impl Token {
+    fn is_date(&self) -> bool {
+        matches!(self, Token::Year(_) | Token::Month(_) | Token::Day(_) | Token::Hour(_) | Token::Minute(_)
+            | Token::Second(_) | Token::SubSecond(_) | Token::AmPm(_) | Token::Elapsed(_, _))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Section {
+    tokens: Vec<Token>,
+    condition: Option<(String, f64)>,
+    thousands: bool,
+    scale: i32, // Commas after the last digit, each one divides by 1000
+}
+
+impl Section {
+    fn parse(code: &str) -> Section {
+        let chars: Vec<char> = code.chars().collect();
+        let mut section = Section::default();
+        let mut tokens: Vec<Token> = vec![];
+        let mut i = 0;
+        let is_digit = |c: Option<&char>| matches!(c, Some('0') | Some('#') | Some('?'));
+        while i < chars.len() {
+            let c = chars[i];
+            let run = chars[i..].iter().take_while(|x| x.eq_ignore_ascii_case(&c)).count();
+            match c {
+                '"' => {
+                    let end = chars[i + 1..].iter().position(|x| *x == '"').map(|p| i + 1 + p).unwrap_or(chars.len());
+                    tokens.push(Token::Literal(chars[i + 1..end].iter().collect()));
+                    i = end + 1;
+                },
+                '\\' => {
+                    if let Some(x) = chars.get(i + 1) {
+                        tokens.push(Token::Literal(x.to_string()));
+                    }
+                    i += 2;
+                },
+                '_' => {
+                    // Space as wide as the next character
+                    tokens.push(Token::Literal(String::from(" ")));
+                    i += 2;
+                },
+                // Repeat fill, there is no column width to fill
+                '*' => i += 2,
+                '[' => {
+                    let end = chars[i..].iter().position(|x| *x == ']').map(|p| i + p).unwrap_or(chars.len());
+                    let content: String = chars[i + 1..end].iter().collect();
+                    let first = content.chars().next().unwrap_or(' ');
+                    if let Some(currency) = content.strip_prefix('$') {
+                        let symbol = currency.split('-').next().unwrap_or("");
+                        if !symbol.is_empty() {
+                            tokens.push(Token::Literal(symbol.to_string()));
+                        }
+                    } else if !content.is_empty() && "hms".contains(first.to_ascii_lowercase()) && content.chars().all(|x| x.eq_ignore_ascii_case(&first)) {
+                        tokens.push(Token::Elapsed(first.to_ascii_lowercase(), content.len()));
+                    } else if "<>=".contains(first) {
+                        let operator: String = content.chars().take_while(|x| "<>=".contains(*x)).collect();
+                        if let Ok(n) = content[operator.len()..].trim().parse::<f64>() {
+                            section.condition = Some((operator, n));
+                        }
+                    } // Colors are ignored
+                    i = end + 1;
+                },
+                '0' | '#' | '?' => {
+                    tokens.push(Token::Digit(c));
+                    i += 1;
+                },
+                '.' => {
+                    let after_seconds = matches!(tokens.iter().rev().find(|t| t.is_date()), Some(Token::Second(_)) | Some(Token::Elapsed('s', _)));
+                    let zeros = chars[i + 1..].iter().take_while(|x| **x == '0').count();
+                    if after_seconds && zeros > 0 {
+                        tokens.push(Token::SubSecond(zeros.min(3)));
+                        i += 1 + zeros;
+                    } else {
+                        tokens.push(Token::Point);
+                        i += 1;
+                    }
+                },
+                ',' => {
+                    if matches!(tokens.last(), Some(Token::Digit(_))) && is_digit(chars.get(i + 1)) {
+                        section.thousands = true;
+                    } else if matches!(tokens.last(), Some(Token::Digit(_))) || (section.scale > 0 && chars[i - 1] == ',') {
+                        section.scale += 1;
+                    } else {
+                        tokens.push(Token::Literal(String::from(",")));
+                    }
+                    i += 1;
+                },
+                '%' => {
+                    tokens.push(Token::Percent);
+                    i += 1;
+                },
+                '@' => {
+                    tokens.push(Token::Text);
+                    i += 1;
+                },
+                'E' | 'e' if matches!(chars.get(i + 1), Some('+') | Some('-')) => {
+                    let digits: Vec<char> = chars[i + 2..].iter().take_while(|x| is_digit(Some(x))).copied().collect();
+                    i += 2 + digits.len();
+                    tokens.push(Token::Exponent(chars[i - 1 - digits.len()] == '+', digits));
+                },
+                'y' | 'Y' | 'e' => {
+                    tokens.push(Token::Year(run));
+                    i += run;
+                },
+                'm' | 'M' => {
+                    tokens.push(Token::Month(run));
+                    i += run;
+                },
+                'd' | 'D' => {
+                    tokens.push(Token::Day(run));
+                    i += run;
+                },
+                'h' | 'H' => {
+                    tokens.push(Token::Hour(run));
+                    i += run;
+                },
+                's' | 'S' => {
+                    tokens.push(Token::Second(run));
+                    i += run;
+                },
+                'A' | 'a' if code[code.char_indices().nth(i).unwrap().0..].to_uppercase().starts_with("AM/PM") => {
+                    tokens.push(Token::AmPm(true));
+                    i += 5;
+                },
+                'A' | 'a' if chars.get(i + 1) == Some(&'/') && matches!(chars.get(i + 2), Some('P') | Some('p')) => {
+                    tokens.push(Token::AmPm(false));
+                    tokens.push(Token::Literal(String::from(if c == 'A' { "A" } else { "a" })));
+                    i += 3;
+                },
+                'G' | 'g' if chars[i..].iter().take(7).collect::<String>().eq_ignore_ascii_case("General") => {
+                    tokens.push(Token::General);
+                    i += 7;
+                },
+                c => {
+                    tokens.push(Token::Literal(c.to_string()));
+                    i += 1;
+                }
+            }
+        }
+        // m and mm next to hours or seconds are minutes
+        for k in 0..tokens.len() {
+            if let Token::Month(n) = tokens[k] {
+                let previous = tokens[..k].iter().rev().find(|t| t.is_date());
+                let next = tokens[k + 1..].iter().find(|t| t.is_date());
+                let after_hours = matches!(previous, Some(Token::Hour(_)) | Some(Token::Elapsed('h', _)));
+                let before_seconds = matches!(next, Some(Token::Second(_)) | Some(Token::Elapsed('s', _)));
+                if n <= 2 && (after_hours || before_seconds) {
+                    tokens[k] = Token::Minute(n);
+                }
+            }
+        }
+        section.tokens = tokens;
+        section
+    }
+
+    fn is_date(&self) -> bool {
+        self.tokens.iter().any(|t| t.is_date())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn matches(&self, x: f64) -> bool {
+        match &self.condition {
+            Some((operator, n)) => match operator.as_str() {
+                "<" => x < *n,
+                "<=" => x <= *n,
+                ">" => x > *n,
+                ">=" => x >= *n,
+                "<>" => x != *n,
+                _ => x == *n
+            },
+            None => true
+        }
+    }
+
+    fn render_text(&self, text: &str) -> String {
+        self.tokens.iter().map(|t| match t {
+            Token::Text => text.to_string(),
+            Token::Literal(s) => s.clone(),
+            _ => String::new()
+        }).collect()
+    }
+
+    // Position of the / in a fraction such as # ?/? or ?/8, digit placeholders before it and a placeholder or number after.
+    fn fraction(&self) -> Option<usize> {
+        let slash = self.tokens.iter().position(|t| *t == Token::Literal(String::from("/")))?;
+        let before = slash > 0 && matches!(self.tokens[slash - 1], Token::Digit(_));
+        let after = match self.tokens.get(slash + 1) {
+            Some(Token::Digit(_)) => true,
+            Some(Token::Literal(l)) => l.chars().all(|c| c.is_ascii_digit()),
+            _ => false
+        };
+        (before && after).then_some(slash)
+    }
+
+    /*
+     * A fraction with the closest denominator that fits its placeholders, or the fixed one
+     * written after the /. With a whole number part in front only the remainder is a fraction,
+     * and a remainder of 0 leaves spaces where the fraction would be.
+     */
+    fn render_fraction(&self, x: f64, slash: usize) -> String {
+        let numerator_start = self.tokens[..slash].iter().rposition(|t| !matches!(t, Token::Digit(_))).map(|p| p + 1).unwrap_or(0);
+        let has_integer = self.tokens[..numerator_start].iter().any(|t| matches!(t, Token::Digit(_)));
+        let denominator_end = self.tokens[slash + 1..].iter()
+            .position(|t| !matches!(t, Token::Digit(_)) && !matches!(t, Token::Literal(l) if l.chars().all(|c| c.is_ascii_digit())))
+            .map(|p| slash + 1 + p)
+            .unwrap_or(self.tokens.len());
+        let denominator_tokens = &self.tokens[slash + 1..denominator_end];
+        let fixed: Option<u64> = match denominator_tokens.iter().all(|t| matches!(t, Token::Literal(_))) {
+            true => denominator_tokens.iter().map(|t| match t { Token::Literal(l) => l.clone(), _ => String::new() }).collect::<String>().parse().ok(),
+            false => None
+        };
+        let (mut integer, remainder) = if has_integer { (x.trunc(), x.fract()) } else { (0.0, x) };
+        let (mut numerator, denominator) = match fixed {
+            Some(d) if d > 0 => ((remainder * d as f64).round() as u64, d),
+            _ => {
+                let max = 10u64.pow(denominator_tokens.len() as u32) - 1;
+                (1..=max)
+                    .map(|d| ((remainder * d as f64).round() as u64, d))
+                    .min_by(|(n1, d1), (n2, d2)| {
+                        let e1 = (remainder - *n1 as f64 / *d1 as f64).abs();
+                        let e2 = (remainder - *n2 as f64 / *d2 as f64).abs();
+                        e1.partial_cmp(&e2).unwrap()
+                    })
+                    .unwrap()
+            }
+        };
+        if has_integer && numerator == denominator {
+            integer += 1.0;
+            numerator = 0;
+        }
+        let blank = has_integer && numerator == 0;
+        let pad = |text: String, placeholders: &[Token], right: bool| -> String {
+            let mut output = text.clone();
+            for t in placeholders.iter().skip(text.len()) {
+                let fill = match t { Token::Digit('0') => "0", Token::Digit('?') => " ", _ => "" };
+                output = if right { format!("{}{}", fill, output) } else { format!("{}{}", output, fill) };
+            }
+            output
+        };
+        // The whole number goes in the first placeholder, the others only pad
+        let integer_start = self.tokens.iter().position(|t| matches!(t, Token::Digit(_))).filter(|p| *p < numerator_start);
+        let mut output = String::new();
+        for (k, token) in self.tokens.iter().enumerate() {
+            match token {
+                _ if Some(k) == integer_start => {
+                    let placeholders: Vec<Token> = self.tokens[k..numerator_start].iter().filter(|t| matches!(t, Token::Digit(_))).cloned().collect();
+                    // 0 still shows when there is no fraction either
+                    let text = if integer > 0.0 || blank { format!("{}", integer) } else { String::new() };
+                    output.push_str(&pad(text, &placeholders, true));
+                },
+                _ if k == numerator_start => {
+                    let text = pad(numerator.to_string(), &self.tokens[numerator_start..slash], true);
+                    output.push_str(&if blank { " ".repeat(text.len()) } else { text });
+                },
+                _ if k == slash => output.push(if blank { ' ' } else { '/' }),
+                _ if k == slash + 1 => {
+                    let text = match fixed {
+                        Some(d) => d.to_string(),
+                        None => pad(denominator.to_string(), denominator_tokens, false)
+                    };
+                    output.push_str(&if blank { " ".repeat(text.len()) } else { text });
+                },
+                _ if k > slash && k < denominator_end => {},
+                Token::Literal(l) => output.push_str(l),
+                Token::Percent => output.push('%'),
+                _ => {}
+            }
+        }
+        output
+    }
+
+    fn render_number(&self, x: f64) -> String {
+        let mut x = x * 100f64.powi(self.tokens.iter().filter(|t| **t == Token::Percent).count() as i32) / 1000f64.powi(self.scale);
+        if let Some(slash) = self.fraction() {
+            return self.render_fraction(x, slash);
+        }
+        let digits_before = |tokens: &[Token]| tokens.iter().filter(|t| matches!(t, Token::Digit(_))).count();
+        let point = self.tokens.iter().position(|t| *t == Token::Point);
+        let exponent = self.tokens.iter().position(|t| matches!(t, Token::Exponent(_, _)));
+        let number_end = exponent.unwrap_or(self.tokens.len());
+        let integer_end = point.unwrap_or(number_end).min(number_end);
+        let integer_places = digits_before(&self.tokens[..integer_end]);
+        let decimal_places = digits_before(&self.tokens[integer_end..number_end]);
+
+        // Scientific: ##0.0E+0 keeps the exponent a multiple of three
+        let mut power = 0;
+        if exponent.is_some() && x != 0.0 {
+            let step = if integer_places > 1 && self.tokens[..integer_end].iter().find(|t| matches!(t, Token::Digit(_))) == Some(&Token::Digit('#')) { integer_places as i32 } else { 1 };
+            let magnitude = x.log10().floor() as i32;
+            power = if step > 1 { magnitude.div_euclid(step) * step } else { magnitude - (integer_places.max(1) as i32 - 1) };
+            let (integer, _) = round_digits(x / 10f64.powi(power), decimal_places);
+            if integer.len() > integer_places.max(1) && step == 1 {
+                power += 1;
+            } else if integer.len() > step as usize && step > 1 {
+                power += step;
+            }
+            x /= 10f64.powi(power);
+        }
+        let (integer, decimals) = round_digits(x, decimal_places);
+        let integer: Vec<char> = integer.chars().collect();
+        let decimals: Vec<char> = decimals.chars().collect();
+        let significant_decimals = decimals.iter().rposition(|d| *d != '0').map(|p| p + 1).unwrap_or(0);
+
+        let mut output = String::new();
+        let mut placeholder = 0;
+        for (k, token) in self.tokens.iter().enumerate() {
+            match token {
+                Token::Digit(d) if k < integer_end => {
+                    // Right-align the integer digits, extra leading digits go to the first placeholder
+                    let position = integer_places - 1 - placeholder;
+                    let from = if placeholder == 0 { integer.len().max(position + 1) } else { position + 1 };
+                    for p in (position..from).rev() {
+                        let c = if p < integer.len() {
+                            Some(integer[integer.len() - 1 - p])
+                        } else {
+                            match d { '0' => Some('0'), '?' => Some(' '), _ => None }
+                        };
+                        if let Some(c) = c {
+                            output.push(c);
+                            if self.thousands && p > 0 && p % 3 == 0 && c != ' ' {
+                                output.push(',');
+                            }
+                        }
+                    }
+                    placeholder += 1;
+                },
+                Token::Digit(d) if k < number_end => {
+                    let index = placeholder - integer_places;
+                    match d {
+                        _ if index < significant_decimals || *d == '0' => output.push(decimals[index]),
+                        '?' => output.push(' '),
+                        _ => {}
+                    }
+                    placeholder += 1;
+                },
+                Token::Point => output.push('.'),
+                Token::Percent => output.push('%'),
+                Token::General => output.push_str(&general_number(x)),
+                Token::Exponent(plus, places) => {
+                    output.push('E');
+                    if power < 0 {
+                        output.push('-');
+                    } else if *plus {
+                        output.push('+');
+                    }
+                    let width = places.iter().filter(|p| **p == '0').count();
+                    output.push_str(&format!("{:0width$}", power.abs(), width = width));
+                },
+                Token::Literal(s) => output.push_str(s),
+                Token::Digit(d) => output.push(*d),
+                _ => {}
+            }
+        }
+        output
+    }
+
+    fn render_date(&self, serial: f64) -> Result<String, ExcelError> {
+        if !(0.0..MAX_DATE).contains(&serial) {
+            return Err(ExcelError::Value);
+        }
+        let precision = self.tokens.iter().filter_map(|t| match t { Token::SubSecond(n) => Some(*n), _ => None }).max().unwrap_or(0);
+        let per_second = 10i64.pow(precision as u32);
+        let units = (serial * 86400.0 * per_second as f64).round() as i64;
+        let days = units / (86400 * per_second);
+        let seconds = units % (86400 * per_second) / per_second;
+        let fraction = units % per_second;
+        let (year, month, day) = serial_to_ymd(days);
+        let month = month as usize;
+        let weekday = ((days + 6) % 7) as usize;
+        let twelve_hour = self.tokens.iter().any(|t| matches!(t, Token::AmPm(_)));
+        let hour = seconds / 3600;
+        let pad = |n: i64, width: usize| format!("{:0width$}", n, width = width);
+        let mut output = String::new();
+        let mut tokens = self.tokens.iter().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Year(n) if *n <= 2 => output.push_str(&pad(year as i64 % 100, 2)),
+                Token::Year(_) => output.push_str(&pad(year as i64, 4)),
+                Token::Month(1) => output.push_str(&month.to_string()),
+                Token::Month(2) => output.push_str(&pad(month as i64, 2)),
+                Token::Month(3) => output.push_str(&MONTHS[month - 1][..3]),
+                Token::Month(5) => output.push_str(&MONTHS[month - 1][..1]),
+                Token::Month(_) => output.push_str(MONTHS[month - 1]),
+                Token::Day(1) => output.push_str(&day.to_string()),
+                Token::Day(2) => output.push_str(&pad(day as i64, 2)),
+                Token::Day(3) => output.push_str(&DAYS[weekday][..3]),
+                Token::Day(_) => output.push_str(DAYS[weekday]),
+                Token::Hour(n) => {
+                    let h = if twelve_hour { (hour + 11) % 12 + 1 } else { hour };
+                    output.push_str(&pad(h, (*n).min(2)));
+                },
+                Token::Minute(n) => output.push_str(&pad(seconds / 60 % 60, (*n).min(2))),
+                Token::Second(n) => output.push_str(&pad(seconds % 60, (*n).min(2))),
+                Token::SubSecond(n) => {
+                    output.push('.');
+                    output.push_str(&pad(fraction, precision)[..*n]);
+                },
+                Token::Elapsed(unit, n) => {
+                    let total = units / per_second / match unit { 'h' => 3600, 'm' => 60, _ => 1 };
+                    output.push_str(&pad(total, *n));
+                },
+                Token::AmPm(true) => output.push_str(if hour < 12 { "AM" } else { "PM" }),
+                Token::AmPm(false) => {
+                    // A/P keeps the case it was written in
+                    if let Some(Token::Literal(case)) = tokens.next() {
+                        let letter = if hour < 12 { "A" } else { "P" };
+                        output.push_str(&if case == "a" { letter.to_lowercase() } else { letter.to_string() });
+                    }
+                },
+                Token::Literal(s) => output.push_str(s),
+                Token::Point => output.push('.'),
+                Token::Percent => output.push('%'),
+                Token::Digit(d) => output.push(*d),
+                _ => {}
+            }
+        }
+        Ok(output)
+    }
+}
+
+// Integer and decimal digits of x rounded half away from zero to `places`, using the
+// 15 significant digits Excel keeps. A zero integer part is "".
+fn round_digits(x: f64, places: usize) -> (String, String) {
+    let x = x.abs();
+    if x == 0.0 {
+        return (String::new(), "0".repeat(places));
+    }
+    let scientific = format!("{:.14e}", x);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let mut digits: Vec<u8> = mantissa.bytes().filter(|b| b.is_ascii_digit()).map(|b| b - b'0').collect();
+    let mut exponent: i64 = exponent.parse().unwrap();
+    let keep = exponent + 1 + places as i64;
+    if keep < 0 {
+        return (String::new(), "0".repeat(places));
+    }
+    let keep = keep as usize;
+    if keep < digits.len() {
+        let round_up = digits[keep] >= 5;
+        digits.truncate(keep);
+        if round_up {
+            let mut k = keep;
+            loop {
+                if k == 0 {
+                    digits.insert(0, 1);
+                    exponent += 1;
+                    break;
+                }
+                k -= 1;
+                if digits[k] == 9 {
+                    digits[k] = 0;
+                } else {
+                    digits[k] += 1;
+                    break;
+                }
+            }
+        }
+    }
+    // Digits as a string starting at 10^exponent
+    let integer_len = exponent + 1;
+    let text: String = digits.iter().map(|d| (b'0' + d) as char).collect();
+    let (integer, decimals) = if integer_len <= 0 {
+        (String::new(), format!("{}{}", "0".repeat((-integer_len) as usize), text))
+    } else if integer_len as usize >= text.len() {
+        (format!("{}{}", text, "0".repeat(integer_len as usize - text.len())), String::new())
+    } else {
+        (text[..integer_len as usize].to_string(), text[integer_len as usize..].to_string())
+    };
+    let mut decimals: String = decimals.chars().take(places).collect();
+    while decimals.len() < places {
+        decimals.push('0');
+    }
+    (integer.trim_start_matches('0').to_string(), decimals)
+}
+
+/*
+ * A number format code such as #,##0.00;[Red](#,##0.00) or d-mmm-yy, with up to
+ * four sections for positive, negative, zero and text values.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFormat {
+    sections: Vec<Section>,
+}
+
+impl From<&str> for NumberFormat {
+    fn from(code: &str) -> NumberFormat {
+        let mut sections = vec![];
+        let mut current = String::new();
+        let mut quoted = false;
+        let mut chars = code.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' | '_' | '*' if !quoted => {
+                    current.push(c);
+                    if let Some(next) = chars.next() {
+                        current.push(next);
+                    }
+                    continue;
+                },
+                ';' if !quoted => {
+                    sections.push(Section::parse(&current));
+                    current.clear();
+                    continue;
+                },
+                _ => {}
+            }
+            current.push(c);
+        }
+        sections.push(Section::parse(&current));
+        NumberFormat { sections }
+    }
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat::from("General")
+    }
+}
+
+impl NumberFormat {
+    // Formats with a day, month or year show numbers as dates.
+    pub fn is_date(&self) -> bool {
+        self.sections.first().is_some_and(|s| s.tokens.iter().any(|t| matches!(t, Token::Year(_) | Token::Month(_) | Token::Day(_))))
+    }
+
+    pub fn format(&self, v: &Value) -> Result<String, ExcelError> {
+        match v {
+            Value::Num(x) => self.format_number(*x),
+            Value::Date(d) => self.format_number(date_time_to_excel(*d)),
+            Value::Text(t) => Ok(self.format_text(t)),
+            Value::Bool(b) => Ok(String::from(if *b { "TRUE" } else { "FALSE" })),
+            Value::Empty => Ok(String::new()),
+            Value::Error(e) => Err(e.clone()),
+            Value::Array(_) | Value::Array2(_) | Value::Range { .. } => self.format(&v.ensure_single()),
+            Value::Formula(f) => Ok(f.clone()),
+            Value::Lambda { .. } => Err(ExcelError::Calc)
+        }
+    }
+
+    pub fn format_text(&self, text: &str) -> String {
+        match self.sections.get(3) {
+            Some(section) => section.render_text(text),
+            None if self.sections.len() == 1 && self.sections[0].tokens.contains(&Token::Text) => self.sections[0].render_text(text),
+            None => text.to_string()
+        }
+    }
+
+    pub fn format_number(&self, x: f64) -> Result<String, ExcelError> {
+        let (section, negative) = self.section(x);
+        if section.is_empty() {
+            return Ok(String::new());
+        }
+        if section.is_date() {
+            return section.render_date(if negative { x } else { x.abs() });
+        }
+        let minus = if negative && x < 0.0 { "-" } else { "" };
+        Ok(format!("{}{}", minus, section.render_number(x.abs())))
+    }
+
+    // The section for x, and whether a minus sign is shown in front of it.
+    fn section(&self, x: f64) -> (&Section, bool) {
+        let numeric: Vec<&Section> = self.sections.iter().take(3).collect();
+        if numeric.iter().any(|s| s.condition.is_some()) {
+            let found = numeric.iter().take(2).find(|s| s.condition.is_some() && s.matches(x));
+            return match found {
+                Some(s) => (s, true),
+                None => (numeric.iter().find(|s| s.condition.is_none()).unwrap_or(numeric.last().unwrap()), true)
+            };
+        }
+        match numeric.len() {
+            _ if x > 0.0 => (numeric[0], false),
+            1 => (numeric[0], true),
+            2 if x == 0.0 => (numeric[0], false),
+            _ if x < 0.0 => (numeric[1], false),
+            _ => (numeric[2], false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{NumberFormat, builtin_format};
+    use crate::evaluate::value::Value;
+    use crate::parser::ast::Error as ExcelError;
+    use chrono::NaiveDate;
+
+    fn format(x: f64, code: &str) -> String {
+        NumberFormat::from(code).format_number(x).unwrap()
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(format(1234.567, "General"), "1234.567");
+        assert_eq!(format(1234.567, "0"), "1235");
+        assert_eq!(format(1234.567, "#,##0.00"), "1,234.57");
+        assert_eq!(format(1234567.891, "#,##0"), "1,234,568");
+        assert_eq!(format(0.5, "#.##"), ".5");
+        assert_eq!(format(2.675, "0.00"), "2.68");
+        assert_eq!(format(5.0, "000"), "005");
+        assert_eq!(format(12.5, "0.0#"), "12.5");
+        assert_eq!(format(1.5, "0.0?"), "1.5 ");
+        assert_eq!(format(-1234.5, "$#,##0.00"), "-$1,234.50");
+        assert_eq!(format(1234567.0, "#,##0,\"K\""), "1,235K");
+        assert_eq!(format(1234567.0, "0.0,,\" M\""), "1.2 M");
+        assert_eq!(format(123.0, "\"Total: \"0"), "Total: 123");
+        assert_eq!(format(12.0, "0\\%"), "12%");
+    }
+
+    #[test]
+    fn test_percent_scientific() {
+        assert_eq!(format(0.256, "0%"), "26%");
+        assert_eq!(format(0.256, "0.0%"), "25.6%");
+        assert_eq!(format(12345.678, "0.00E+00"), "1.23E+04");
+        assert_eq!(format(0.000123, "0.00E+00"), "1.23E-04");
+        assert_eq!(format(0.000123, "0.0E-0"), "1.2E-4");
+        assert_eq!(format(12345.0, "##0.0E+0"), "12.3E+3");
+        assert_eq!(format(9.999, "0.00E+00"), "1.00E+01");
+    }
+
+    #[test]
+    fn test_fractions() {
+        assert_eq!(format(1.5, builtin_format(12).unwrap()), "1 1/2");
+        assert_eq!(format(0.75, "# ?/?"), " 3/4");
+        assert_eq!(format(-1.5, "# ?/?"), "-1 1/2");
+        assert_eq!(format(std::f64::consts::PI, builtin_format(13).unwrap()), "3 14/99");
+        assert_eq!(format(std::f64::consts::PI, "# ?/?"), "3 1/7");
+        assert_eq!(format(1.0 / 3.0, "# ??/??"), "  1/3 ");
+        assert_eq!(format(2.0, "# ?/?"), "2    ");
+        assert_eq!(format(0.0, "# ?/?"), "0    ");
+        assert_eq!(format(1.25, "?/?"), "5/4");
+        assert_eq!(format(0.3, "# ?/8"), " 2/8");
+        assert_eq!(format(2.99, "# ?/?"), "3    ");
+    }
+
+    #[test]
+    fn test_sections() {
+        let code = "#,##0.00;(#,##0.00);\"zero\";\"text: \"@";
+        assert_eq!(format(1234.5, code), "1,234.50");
+        assert_eq!(format(-1234.5, code), "(1,234.50)");
+        assert_eq!(format(0.0, code), "zero");
+        assert_eq!(NumberFormat::from(code).format(&Value::from("abc")).unwrap(), "text: abc");
+        assert_eq!(format(-5.0, "0;;0"), "");
+        assert_eq!(format(-5.0, "[Red]0;[Blue]-0"), "-5");
+        assert_eq!(format(-5.0, builtin_format(38).unwrap()), "(5)");
+        assert_eq!(format(5.0, builtin_format(37).unwrap()), "5 ");
+        assert_eq!(format(150.0, "[>100]\"big\";[<=100]0"), "big");
+        assert_eq!(format(50.0, "[>100]\"big\";[<=100]0"), "50");
+        assert_eq!(format(1.0, "[$€-407]#,##0.00"), "€1.00");
+    }
+
+    #[test]
+    fn test_dates() {
+        // 2024-03-05 14:07:09
+        let serial = 45356.0 + (14.0 * 3600.0 + 7.0 * 60.0 + 9.0) / 86400.0;
+        assert_eq!(format(serial, "yyyy-mm-dd"), "2024-03-05");
+        assert_eq!(format(serial, "m/d/yy"), "3/5/24");
+        assert_eq!(format(serial, "mmm yyyy"), "Mar 2024");
+        assert_eq!(format(serial, "mmmm d, yyyy"), "March 5, 2024");
+        assert_eq!(format(serial, "mmmmm"), "M");
+        assert_eq!(format(serial, "ddd dddd"), "Tue Tuesday");
+        assert_eq!(format(serial, "hh:mm:ss"), "14:07:09");
+        assert_eq!(format(serial, "h:mm AM/PM"), "2:07 PM");
+        assert_eq!(format(serial, "h:mm a/p"), "2:07 p");
+        assert_eq!(format(serial, builtin_format(22).unwrap()), "3/5/2024 14:07");
+        assert_eq!(format(0.5 + 0.25 / 86400.0, "hh:mm:ss.00"), "12:00:00.25");
+        assert_eq!(format(1.0, "dddd yyyy-mm-dd"), "Sunday 1900-01-01");
+        assert_eq!(format(60.0, "yyyy-mm-dd"), "1900-02-29");
+        assert_eq!(NumberFormat::from("yyyy").format_number(-1.0), Err(ExcelError::Value));
+        assert_eq!(NumberFormat::from("d-mmm-yy").format(&Value::from(NaiveDate::from_ymd_opt(2021, 12, 1).unwrap())).unwrap(), "1-Dec-21");
+    }
+
+    #[test]
+    fn test_elapsed() {
+        assert_eq!(format(1.5 + 90.0 / 86400.0, "[h]:mm:ss"), "36:01:30");
+        assert_eq!(format(0.0625, "[mm]:ss"), "90:00");
+        assert_eq!(format(65.0 / 86400.0, "[ss]"), "65");
+    }
+
+    #[test]
+    fn test_is_date() {
+        assert!(NumberFormat::from("yyyy-mm-dd").is_date());
+        assert!(NumberFormat::from("[$-409]mmmm d, yyyy;@").is_date());
+        assert!(!NumberFormat::from("h:mm").is_date());
+        assert!(!NumberFormat::from("#,##0.00").is_date());
+    }
+}
@@ -0,0 +1,183 @@
+use std::cmp::Ordering;
+use crate::{
+    evaluate::value::Value,
+    parser::{parse_str, ast::{Expr, Error as ExcelError}},
+    utils::date_time_to_excel,
+    function::{lookup::wildcard_match, text::parse_value},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanEqual,
+    GreaterThan,
+    GreaterThanEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Blank,
+    Number(f64),
+    Bool(bool),
+    Error(ExcelError),
+    Text(String), // May hold wildcards
+}
+
+/*
+ * A criteria argument of the *IF and *IFS functions, like 10, ">=10", "<>", "a*" or "<>#N/A".
+ * Numbers only compare with numbers and dates, text with text, and "=" or "" match blank cells.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Criteria {
+    operator: Operator,
+    operand: Operand,
+}
+
+impl From<&Value> for Criteria {
+    fn from(value: &Value) -> Criteria {
+        let operand = match value.ensure_single() {
+            Value::Num(n) => Operand::Number(n),
+            Value::Date(d) => Operand::Number(date_time_to_excel(d)),
+            Value::Bool(b) => Operand::Bool(b),
+            Value::Error(e) => Operand::Error(e),
+            Value::Text(t) => return Criteria::from(t.as_str()),
+            _ => Operand::Blank
+        };
+        Criteria { operator: Operator::Equal, operand }
+    }
+}
+
+impl From<&str> for Criteria {
+    fn from(s: &str) -> Criteria {
+        let operators = [
+            ("<=", Operator::LessThanEqual),
+            (">=", Operator::GreaterThanEqual),
+            ("<>", Operator::NotEqual),
+            ("<", Operator::LessThan),
+            (">", Operator::GreaterThan),
+            ("=", Operator::Equal),
+        ];
+        let (operator, rest) = operators.iter()
+            .find_map(|(prefix, op)| s.strip_prefix(prefix).map(|rest| (*op, rest)))
+            .unwrap_or((Operator::Equal, s));
+        let operand = if rest.is_empty() {
+            Operand::Blank
+        } else if let Some(n) = parse_value(rest) {
+            Operand::Number(n)
+        } else if rest.eq_ignore_ascii_case("TRUE") || rest.eq_ignore_ascii_case("FALSE") {
+            Operand::Bool(rest.eq_ignore_ascii_case("TRUE"))
+        } else if let Ok(Expr::Error(e)) = parse_str(rest) {
+            Operand::Error(e)
+        } else {
+            Operand::Text(rest.to_string())
+        };
+        Criteria { operator, operand }
+    }
+}
+
+impl Criteria {
+    // Text without an operator matches the start of a cell, as criteria ranges read it.
+    pub fn as_prefix(self) -> Criteria {
+        match (self.operator, self.operand) {
+            (Operator::Equal, Operand::Text(p)) => Criteria { operator: Operator::Equal, operand: Operand::Text(format!("{}*", p)) },
+            (operator, operand) => Criteria { operator, operand }
+        }
+    }
+
+    pub fn matches(&self, value: &Value) -> bool {
+        let ordering: Option<Ordering> = match (&self.operand, value) {
+            (Operand::Blank, Value::Empty) => Some(Ordering::Equal),
+            (Operand::Blank, Value::Text(t)) if t.is_empty() => Some(Ordering::Equal),
+            (Operand::Number(n), Value::Num(x)) => x.partial_cmp(n),
+            (Operand::Number(n), Value::Date(d)) => date_time_to_excel(*d).partial_cmp(n),
+            // Text that reads as the number counts as equal to it
+            (Operand::Number(n), Value::Text(t)) if matches!(self.operator, Operator::Equal | Operator::NotEqual) => {
+                parse_value(t).filter(|x| x == n).map(|_| Ordering::Equal)
+            },
+            (Operand::Bool(b), Value::Bool(x)) => Some(x.cmp(b)),
+            (Operand::Error(e), Value::Error(x)) if e == x => Some(Ordering::Equal),
+            (Operand::Text(p), Value::Text(t)) => match self.operator {
+                Operator::Equal | Operator::NotEqual => Some(if wildcard_match(p, t) { Ordering::Equal } else { Ordering::Less }),
+                _ => Some(t.to_lowercase().cmp(&p.to_lowercase()))
+            },
+            _ => None
+        };
+        match self.operator {
+            Operator::Equal => ordering == Some(Ordering::Equal),
+            Operator::NotEqual => ordering != Some(Ordering::Equal),
+            Operator::LessThan => ordering == Some(Ordering::Less),
+            Operator::LessThanEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Operator::GreaterThan => ordering == Some(Ordering::Greater),
+            Operator::GreaterThanEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+/*
+ * Positions matching every (criteria_range, criteria) pair, as the *IFS functions take them.
+ * The ranges must all be the same size.
+ */
+pub fn criteria_mask(args: &[Value]) -> Result<Vec<bool>, ExcelError> {
+    if args.is_empty() || args.len() % 2 != 0 {
+        return Err(ExcelError::Value);
+    }
+    let mut mask: Option<Vec<bool>> = None;
+    for pair in args.chunks(2) {
+        let criteria = Criteria::from(&pair[1]);
+        let matched: Vec<bool> = pair[0].as_array().iter().map(|v| criteria.matches(v)).collect();
+        mask = match mask {
+            None => Some(matched),
+            Some(m) if m.len() == matched.len() => Some(m.into_iter().zip(matched).map(|(a, b)| a && b).collect()),
+            Some(_) => return Err(ExcelError::Value)
+        };
+    }
+    Ok(mask.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::function::criteria::*;
+
+    fn matches(criteria: &str, value: Value) -> bool {
+        Criteria::from(criteria).matches(&value)
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert!(matches(">=10", Value::from(10.0)));
+        assert!(matches(">=10", Value::from(100.0)));
+        assert!(!matches(">=10", Value::from(9.0)));
+        assert!(!matches(">=10", Value::from("100")));
+        assert!(matches("5", Value::from("5")));
+        assert!(Criteria::from(&Value::from(5.0)).matches(&Value::from(5.0)));
+        assert!(matches("<>10", Value::Empty));
+        assert!(!matches("<0", Value::Empty));
+    }
+
+    #[test]
+    fn test_text_and_blanks() {
+        assert!(matches("a*", Value::from("Apple")));
+        assert!(matches("?pple", Value::from("apple")));
+        assert!(matches("<>a*", Value::from("banana")));
+        assert!(matches("*~?", Value::from("why?")));
+        assert!(!matches("*", Value::Empty));
+        assert!(matches(">b", Value::from("Cherry")));
+        assert!(matches("<>", Value::from("x")));
+        assert!(!matches("<>", Value::Empty));
+        assert!(matches("=", Value::Empty));
+        assert!(matches("", Value::from("")));
+        assert!(matches("TRUE", Value::from(true)));
+        assert!(matches("#N/A", Value::Error(ExcelError::NA)));
+        assert!(matches("<>#N/A", Value::from(1.0)));
+    }
+
+    #[test]
+    fn test_mask() {
+        let a = Value::from(vec![Value::from(1.0), Value::from(5.0), Value::from(10.0)]);
+        let b = Value::from(vec![Value::from("x"), Value::from("y"), Value::from("x")]);
+        assert_eq!(criteria_mask(&[a.clone(), Value::from(">1"), b, Value::from("x")]), Ok(vec![false, false, true]));
+        assert_eq!(criteria_mask(&[a, Value::from(">1"), Value::from(vec![Value::from(1.0)]), Value::from(1.0)]), Err(ExcelError::Value));
+    }
+}
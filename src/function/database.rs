@@ -0,0 +1,130 @@
+use ndarray::Array2;
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    function::{Function, collect_numbers, criteria::Criteria, lookup::as_table},
+};
+use excel_emulator_macro::function;
+
+// Column of a header row, by its name (case-insensitive) or its position from 1.
+fn column(headers: &[Value], field: &Value) -> Option<usize> {
+    match field.ensure_single() {
+        Value::Text(name) => headers.iter().position(|h| h.as_text().eq_ignore_ascii_case(&name)),
+        Value::Num(n) if n >= 1.0 && (n as usize) <= headers.len() => Some(n as usize - 1),
+        _ => None
+    }
+}
+
+/*
+ * The values in field of the database records the criteria range selects. Conditions
+ * on one row of the criteria range must all hold and any of its rows may, a blank row
+ * selects every record.
+ */
+fn records(database: &Value, field: &Value, criteria: &Value) -> Result<Vec<Value>, ExcelError> {
+    let (database, criteria): (Array2<Value>, Array2<Value>) = (as_table(database), as_table(criteria));
+    let headers: Vec<Value> = database.row(0).to_vec();
+    let field = column(&headers, field).ok_or(ExcelError::Value)?;
+    if criteria.nrows() < 2 {
+        return Err(ExcelError::Value);
+    }
+    let criteria_columns: Vec<Option<usize>> = criteria.row(0).iter().map(|h| column(&headers, &Value::from(h.as_text()))).collect();
+    let conditions: Vec<Vec<(Option<usize>, Criteria)>> = criteria.rows().into_iter().skip(1).map(|row| {
+        row.iter().zip(criteria_columns.iter())
+            .filter(|(v, _)| !matches!(v, Value::Empty))
+            .map(|(v, c)| {
+                let criteria = Criteria::from(v);
+                // Plain text matches the start of a cell, "=text" all of it
+                match v {
+                    Value::Text(t) if !t.starts_with(['=', '<', '>']) => (*c, criteria.as_prefix()),
+                    _ => (*c, criteria)
+                }
+            })
+            .collect()
+    }).collect();
+    Ok(database.rows().into_iter().skip(1)
+        .filter(|record| conditions.iter().any(|row| row.iter().all(|(c, criteria)| c.is_some_and(|c| criteria.matches(&record[c])))))
+        .map(|record| record[field].clone())
+        .collect())
+}
+
+fn numbers(database: &Value, field: &Value, criteria: &Value) -> Result<Vec<f64>, ExcelError> {
+    collect_numbers(&[Value::Array(records(database, field, criteria)?)])
+}
+
+#[function]
+fn dsum(database: Value, field: Value, criteria: Value) -> Value {
+    Value::from(tryv!(numbers(&database, &field, &criteria)).iter().sum::<f64>())
+}
+
+#[function]
+fn dcount(database: Value, field: Value, criteria: Value) -> Value {
+    Value::from(tryv!(records(&database, &field, &criteria)).iter().filter(|v| matches!(v, Value::Num(_) | Value::Date(_))).count())
+}
+
+#[function]
+fn dcounta(database: Value, field: Value, criteria: Value) -> Value {
+    Value::from(tryv!(records(&database, &field, &criteria)).iter().filter(|v| !matches!(v, Value::Empty)).count())
+}
+
+#[function]
+fn daverage(database: Value, field: Value, criteria: Value) -> Value {
+    let xs = tryv!(numbers(&database, &field, &criteria));
+    if xs.is_empty() {
+        return Value::Error(ExcelError::Div);
+    }
+    Value::from(xs.iter().sum::<f64>() / xs.len() as f64)
+}
+
+#[function]
+fn dmax(database: Value, field: Value, criteria: Value) -> Value {
+    Value::from(tryv!(numbers(&database, &field, &criteria)).into_iter().reduce(f64::max).unwrap_or(0.0))
+}
+
+#[function]
+fn dmin(database: Value, field: Value, criteria: Value) -> Value {
+    Value::from(tryv!(numbers(&database, &field, &criteria)).into_iter().reduce(f64::min).unwrap_or(0.0))
+}
+
+// The one matching record's value: #VALUE! when nothing matches, #NUM! when several do.
+#[function]
+fn dget(database: Value, field: Value, criteria: Value) -> Value {
+    let mut values = tryv!(records(&database, &field, &criteria));
+    match values.len() {
+        0 => Value::Error(ExcelError::Value),
+        1 => values.remove(0),
+        _ => Value::Error(ExcelError::Num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::value::Value,
+        workbook::Book,
+        errors::Error,
+        parser::ast::Error as ExcelError,
+    };
+
+    #[test]
+    fn test_database_functions() -> Result<(), Error> {
+        let mut book = Book::from("assets/database.xlsx");
+        book.load(false).unwrap();
+        book.calculate(false, false)?;
+        let cell = |r: &str| book.resolve_str_ref(r).unwrap()[[0, 0]].clone();
+        // F1:H3 selects apples between 10 and 16 high, or pears
+        assert!((cell("Sheet1!P1").as_num() - 247.8).abs() < 1e-9);
+        assert_eq!(cell("Sheet1!P2"), Value::from(3.0));
+        assert!((cell("Sheet1!P3").as_num() - 82.6).abs() < 1e-9);
+        assert_eq!(cell("Sheet1!P4"), Value::from(96.0));
+        assert_eq!(cell("Sheet1!P5"), Value::from(75.0));
+        assert_eq!(cell("Sheet1!P6"), Value::from(14.0));
+        assert_eq!(cell("Sheet1!P7"), Value::Error(ExcelError::Num));
+        assert_eq!(cell("Sheet1!P8"), Value::from(2.0));
+        assert_eq!(cell("Sheet1!P9"), Value::Error(ExcelError::Value));
+        assert_eq!(cell("Sheet1!P10"), Value::from(6.0));
+        // Text criteria match the start of a cell unless they begin with =
+        assert_eq!(cell("Sheet1!P11"), Value::from(0.0));
+        assert_eq!(cell("Sheet1!P12"), Value::from(3.0));
+        Ok(())
+    }
+}
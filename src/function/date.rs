@@ -0,0 +1,581 @@
+use chrono::{Months, NaiveDate, Datelike};
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    utils::{excel_to_date, date_to_excel, serial_to_ymd},
+    function::{Function, broadcast, number_arg, text::parse_date_time},
+};
+use excel_emulator_macro::function;
+
+// 1 January 10000, the first serial past the dates Excel has.
+const MAX_SERIAL: f64 = 2958466.0;
+
+// A date argument as a serial: numbers, dates and date text. Serials outside Excel's dates are #NUM!
+fn serial_arg(v: &Value) -> Result<f64, ExcelError> {
+    let serial = number_arg(v)?;
+    if (0.0..MAX_SERIAL).contains(&serial) {
+        Ok(serial)
+    } else {
+        Err(ExcelError::Num)
+    }
+}
+
+// The whole day of a date argument.
+fn day_arg(v: &Value) -> Result<i64, ExcelError> {
+    Ok(serial_arg(v)?.floor() as i64)
+}
+
+fn int_arg(v: &Value) -> Result<i64, ExcelError> {
+    Ok(number_arg(v)?.trunc() as i64)
+}
+
+/*
+ * A serial as a date, except for serial 60: Excel's 29 February 1900 has no
+ * date and stays a number.
+ */
+fn date_value(serial: f64) -> Value {
+    if serial.floor() == 60.0 {
+        Value::from(serial)
+    } else {
+        Value::from(excel_to_date(serial))
+    }
+}
+
+// The serial of year, month and day, rolling months and days over like DATE.
+fn serial_from_ymd(year: i64, month: i64, day: i64) -> Result<f64, ExcelError> {
+    let months = year * 12 + month - 1;
+    let first = NaiveDate::from_ymd_opt(months.div_euclid(12) as i32, months.rem_euclid(12) as u32 + 1, 1).ok_or(ExcelError::Num)?;
+    let serial = date_to_excel(first) + (day - 1) as f64;
+    if (0.0..MAX_SERIAL).contains(&serial) {
+        Ok(serial)
+    } else {
+        Err(ExcelError::Num)
+    }
+}
+
+fn ymd(days: i64) -> (i64, i64, i64) {
+    let (year, month, day) = serial_to_ymd(days);
+    (year as i64, month as i64, day as i64)
+}
+
+// Excel's leap years, 1900 included.
+fn is_leap(year: i64) -> bool {
+    year == 1900 || (year % 4 == 0 && (year % 100 != 0 || year % 400 == 0))
+}
+
+fn is_last_of_february(year: i64, month: i64, day: i64) -> bool {
+    month == 2 && day == if is_leap(year) { 29 } else { 28 }
+}
+
+// Day of the week with Sunday as 0. Serial 1 is a Sunday, as Excel counts.
+fn weekday_of(days: i64) -> i64 {
+    (days + 6) % 7
+}
+
+// The date months away, on the same day or the end of a shorter month.
+fn add_months(days: i64, months: i64) -> Result<NaiveDate, ExcelError> {
+    let date = excel_to_date(days as f64).date();
+    let shifted = if months >= 0 {
+        date.checked_add_months(Months::new(months as u32))
+    } else {
+        date.checked_sub_months(Months::new(months.unsigned_abs() as u32))
+    };
+    shifted.ok_or(ExcelError::Num)
+}
+
+fn checked_date(date: NaiveDate) -> Value {
+    let serial = date_to_excel(date);
+    if (0.0..MAX_SERIAL).contains(&serial) {
+        date_value(serial)
+    } else {
+        Value::Error(ExcelError::Num)
+    }
+}
+
+#[function]
+fn date(year: Value, month: Value, day: Value) -> Value {
+    broadcast(vec![year, month, day], |a| {
+        let year = tryv!(int_arg(&a[0]));
+        let year = match year {
+            0..=1899 => year + 1900,
+            1900..=9999 => year,
+            _ => return Value::Error(ExcelError::Num)
+        };
+        date_value(tryv!(serial_from_ymd(year, tryv!(int_arg(&a[1])), tryv!(int_arg(&a[2])))))
+    })
+}
+
+#[function]
+fn day(serial_number: Value) -> Value {
+    broadcast(vec![serial_number], |a| Value::from(ymd(tryv!(day_arg(&a[0]))).2 as f64))
+}
+
+#[function]
+fn month(serial_number: Value) -> Value {
+    broadcast(vec![serial_number], |a| Value::from(ymd(tryv!(day_arg(&a[0]))).1 as f64))
+}
+
+#[function]
+fn year(serial_number: Value) -> Value {
+    broadcast(vec![serial_number], |a| Value::from(ymd(tryv!(day_arg(&a[0]))).0 as f64))
+}
+
+/*
+ * Day of the week counted from 1, starting on Sunday (1, 17), Monday (2, 11)
+ * or Tuesday through Saturday (12 to 16). Type 3 counts Monday as 0.
+ */
+#[function]
+fn weekday(serial_number: Value, return_type: Option<Value>) -> Value {
+    broadcast(vec![serial_number, return_type.unwrap_or(Value::from(1.0))], |a| {
+        let weekday = weekday_of(tryv!(day_arg(&a[0])));
+        let first = match tryv!(int_arg(&a[1])) {
+            1 | 17 => 0,
+            2 | 11 => 1,
+            3 => return Value::from((weekday - 1).rem_euclid(7) as f64),
+            t @ 12..=16 => t - 10,
+            _ => return Value::Error(ExcelError::Num)
+        };
+        Value::from(((weekday - first).rem_euclid(7) + 1) as f64)
+    })
+}
+
+// Week of the year, with week 1 holding 1 January and weeks starting on the return_type's day.
+#[function]
+fn weeknum(serial_number: Value, return_type: Option<Value>) -> Value {
+    broadcast(vec![serial_number, return_type.unwrap_or(Value::from(1.0))], |a| {
+        let days = tryv!(day_arg(&a[0]));
+        let first = match tryv!(int_arg(&a[1])) {
+            1 | 17 => 0,
+            2 | 11 => 1,
+            t @ 12..=16 => t - 10,
+            21 => return Value::from(excel_to_date(days as f64).iso_week().week() as f64),
+            _ => return Value::Error(ExcelError::Num)
+        };
+        let january = tryv!(serial_from_ymd(ymd(days).0, 1, 1)) as i64;
+        let offset = (weekday_of(january) - first).rem_euclid(7);
+        Value::from(((days - january + offset) / 7 + 1) as f64)
+    })
+}
+
+#[function]
+fn isoweeknum(date: Value) -> Value {
+    broadcast(vec![date], |a| Value::from(excel_to_date(tryv!(day_arg(&a[0])) as f64).iso_week().week() as f64))
+}
+
+#[function]
+fn edate(start_date: Value, months: Value) -> Value {
+    broadcast(vec![start_date, months], |a| {
+        checked_date(tryv!(add_months(tryv!(day_arg(&a[0])), tryv!(int_arg(&a[1])))))
+    })
+}
+
+#[function]
+fn eomonth(start_date: Value, months: Value) -> Value {
+    broadcast(vec![start_date, months], |a| {
+        let shifted = tryv!(add_months(tryv!(day_arg(&a[0])), tryv!(int_arg(&a[1]))));
+        let next = shifted.with_day(1).and_then(|d| d.checked_add_months(Months::new(1))).and_then(|d| d.pred_opt());
+        match next {
+            Some(eom) => checked_date(eom),
+            None => Value::Error(ExcelError::Num)
+        }
+    })
+}
+
+#[function]
+fn days(end_date: Value, start_date: Value) -> Value {
+    broadcast(vec![end_date, start_date], |a| Value::from((tryv!(day_arg(&a[0])) - tryv!(day_arg(&a[1]))) as f64))
+}
+
+// Days between two dates in a year of twelve 30-day months, by the US (NASD) or European method.
+fn days360_between(start: i64, end: i64, european: bool) -> i64 {
+    let ((y1, m1, mut d1), (y2, m2, mut d2)) = (ymd(start), ymd(end));
+    if european {
+        d1 = d1.min(30);
+        d2 = d2.min(30);
+    } else {
+        if d1 == 31 || is_last_of_february(y1, m1, d1) {
+            d1 = 30;
+        }
+        if d2 == 31 && d1 == 30 {
+            d2 = 30;
+        }
+    }
+    (y2 - y1) * 360 + (m2 - m1) * 30 + d2 - d1
+}
+
+#[function]
+fn days360(start_date: Value, end_date: Value, method: Option<Value>) -> Value {
+    broadcast(vec![start_date, end_date, method.unwrap_or(Value::from(false))], |a| {
+        let european = tryv!(number_arg(&a[2])) != 0.0;
+        Value::from(days360_between(tryv!(day_arg(&a[0])), tryv!(day_arg(&a[1])), european) as f64)
+    })
+}
+
+// Weekend days, Monday first, from a weekend number or a mask like "0000011".
+fn weekend_arg(v: &Value) -> Result<[bool; 7], ExcelError> {
+    let mut weekend = [false; 7];
+    match v {
+        Value::Text(mask) => {
+            if mask.len() != 7 || !mask.chars().all(|c| c == '0' || c == '1') || mask == "1111111" {
+                return Err(ExcelError::Value);
+            }
+            for (i, c) in mask.chars().enumerate() {
+                weekend[i] = c == '1';
+            }
+        },
+        v => match int_arg(v)? {
+            n @ 1..=7 => {
+                weekend[((n + 4) % 7) as usize] = true;
+                weekend[((n + 5) % 7) as usize] = true;
+            },
+            n @ 11..=17 => weekend[((n - 5) % 7) as usize] = true,
+            _ => return Err(ExcelError::Num)
+        }
+    }
+    Ok(weekend)
+}
+
+fn holidays_arg(v: Option<Value>) -> Result<Vec<i64>, ExcelError> {
+    match v {
+        Some(v) => v.as_array().iter().filter(|h| !h.is_empty()).map(day_arg).collect(),
+        None => Ok(vec![])
+    }
+}
+
+fn is_workday(days: i64, weekend: &[bool; 7], holidays: &[i64]) -> bool {
+    !weekend[((days + 5) % 7) as usize] && !holidays.contains(&days)
+}
+
+// Working days from start to end inclusive, negative when end comes first.
+fn networkdays_between(start: Value, end: Value, weekend: Value, holidays: Option<Value>) -> Value {
+    let holidays = tryv!(holidays_arg(holidays));
+    broadcast(vec![start, end, weekend], |a| {
+        let (start, end) = (tryv!(day_arg(&a[0])), tryv!(day_arg(&a[1])));
+        let weekend = tryv!(weekend_arg(&a[2]));
+        let count = (start.min(end)..=start.max(end)).filter(|d| is_workday(*d, &weekend, &holidays)).count() as f64;
+        Value::from(if end < start { -count } else { count })
+    })
+}
+
+#[function]
+fn networkdays(start_date: Value, end_date: Value, holidays: Option<Value>) -> Value {
+    networkdays_between(start_date, end_date, Value::from(1.0), holidays)
+}
+
+#[function("NETWORKDAYS.INTL")]
+fn networkdaysintl(start_date: Value, end_date: Value, weekend: Option<Value>, holidays: Option<Value>) -> Value {
+    networkdays_between(start_date, end_date, weekend.unwrap_or(Value::from(1.0)), holidays)
+}
+
+// The date days working days after (before, when negative) start.
+fn workday_after(start: Value, days: Value, weekend: Value, holidays: Option<Value>) -> Value {
+    let holidays = tryv!(holidays_arg(holidays));
+    broadcast(vec![start, days, weekend], |a| {
+        let (mut date, days) = (tryv!(day_arg(&a[0])), tryv!(int_arg(&a[1])));
+        let weekend = tryv!(weekend_arg(&a[2]));
+        let mut remaining = days.abs();
+        while remaining > 0 {
+            date += days.signum();
+            if !(0..MAX_SERIAL as i64).contains(&date) {
+                return Value::Error(ExcelError::Num);
+            }
+            if is_workday(date, &weekend, &holidays) {
+                remaining -= 1;
+            }
+        }
+        date_value(date as f64)
+    })
+}
+
+#[function]
+fn workday(start_date: Value, days: Value, holidays: Option<Value>) -> Value {
+    workday_after(start_date, days, Value::from(1.0), holidays)
+}
+
+#[function("WORKDAY.INTL")]
+fn workdayintl(start_date: Value, days: Value, weekend: Option<Value>, holidays: Option<Value>) -> Value {
+    workday_after(start_date, days, weekend.unwrap_or(Value::from(1.0)), holidays)
+}
+
+#[function]
+fn time(hour: Value, minute: Value, second: Value) -> Value {
+    broadcast(vec![hour, minute, second], |a| {
+        let (h, m, s) = (tryv!(int_arg(&a[0])), tryv!(int_arg(&a[1])), tryv!(int_arg(&a[2])));
+        let seconds = h * 3600 + m * 60 + s;
+        if seconds < 0 || h > 32767 {
+            return Value::Error(ExcelError::Num);
+        }
+        Value::from((seconds % 86400) as f64 / 86400.0)
+    })
+}
+
+// Seconds since midnight of a serial, to the nearest second.
+fn seconds_of(v: &Value) -> Result<i64, ExcelError> {
+    let serial = serial_arg(v)?;
+    Ok(((serial - serial.floor()) * 86400.0).round() as i64 % 86400)
+}
+
+#[function]
+fn hour(serial_number: Value) -> Value {
+    broadcast(vec![serial_number], |a| Value::from((tryv!(seconds_of(&a[0])) / 3600) as f64))
+}
+
+#[function]
+fn minute(serial_number: Value) -> Value {
+    broadcast(vec![serial_number], |a| Value::from((tryv!(seconds_of(&a[0])) / 60 % 60) as f64))
+}
+
+#[function]
+fn second(serial_number: Value) -> Value {
+    broadcast(vec![serial_number], |a| Value::from((tryv!(seconds_of(&a[0])) % 60) as f64))
+}
+
+fn parse_text(v: &Value) -> Result<f64, ExcelError> {
+    match v {
+        Value::Text(t) => parse_date_time(t.trim()).ok_or(ExcelError::Value),
+        _ => Err(ExcelError::Value)
+    }
+}
+
+#[function]
+fn timevalue(time_text: Value) -> Value {
+    broadcast(vec![time_text], |a| {
+        let serial = tryv!(parse_text(&a[0]));
+        Value::from(serial - serial.floor())
+    })
+}
+
+#[function]
+fn datevalue(date_text: Value) -> Value {
+    broadcast(vec![date_text], |a| Value::from(tryv!(parse_text(&a[0])).floor()))
+}
+
+/*
+ * Fraction of a year between two dates: 30/360 US (0), actual/actual (1),
+ * actual/360 (2), actual/365 (3) or 30/360 European (4).
+ */
+fn yearfrac_between(start: i64, end: i64, basis: i64) -> Result<f64, ExcelError> {
+    let (start, end) = (start.min(end), start.max(end));
+    let ((y1, m1, mut d1), (y2, m2, mut d2)) = (ymd(start), ymd(end));
+    let actual = (end - start) as f64;
+    match basis {
+        0 => {
+            if d1 == 31 && d2 == 31 {
+                d1 = 30;
+                d2 = 30;
+            } else if d1 == 31 {
+                d1 = 30;
+            } else if d1 == 30 && d2 == 31 {
+                d2 = 30;
+            } else if is_last_of_february(y1, m1, d1) && is_last_of_february(y2, m2, d2) {
+                d1 = 30;
+                d2 = 30;
+            } else if is_last_of_february(y1, m1, d1) {
+                d1 = 30;
+            }
+            Ok(((y2 - y1) * 360 + (m2 - m1) * 30 + d2 - d1) as f64 / 360.0)
+        },
+        1 => {
+            let within_a_year = y1 == y2 || (y2 == y1 + 1 && (m1 > m2 || (m1 == m2 && d1 >= d2)));
+            if within_a_year {
+                let mut leap_day = false;
+                for year in y1..=y2 {
+                    if is_leap(year) {
+                        let day = serial_from_ymd(year, 2, 29)? as i64;
+                        leap_day |= start <= day && day <= end;
+                    }
+                }
+                let length = if (y1 == y2 && is_leap(y1)) || leap_day { 366.0 } else { 365.0 };
+                Ok(actual / length)
+            } else {
+                let years = (y2 - y1 + 1) as f64;
+                let length = serial_from_ymd(y2 + 1, 1, 1)? - serial_from_ymd(y1, 1, 1)?;
+                Ok(actual / (length / years))
+            }
+        },
+        2 => Ok(actual / 360.0),
+        3 => Ok(actual / 365.0),
+        4 => Ok(days360_between(start, end, true) as f64 / 360.0),
+        _ => Err(ExcelError::Num)
+    }
+}
+
+#[function]
+fn yearfrac(start_date: Value, end_date: Value, basis: Option<Value>) -> Value {
+    broadcast(vec![start_date, end_date, basis.unwrap_or(Value::from(0.0))], |a| {
+        let (start, end) = (tryv!(day_arg(&a[0])), tryv!(day_arg(&a[1])));
+        Value::from(tryv!(yearfrac_between(start, end, tryv!(int_arg(&a[2])))))
+    })
+}
+
+// Complete years, months or days between two dates, or the remainder after whole years or months.
+#[function]
+fn datedif(start_date: Value, end_date: Value, unit: Value) -> Value {
+    broadcast(vec![start_date, end_date, unit], |a| {
+        let (start, end) = (tryv!(day_arg(&a[0])), tryv!(day_arg(&a[1])));
+        if start > end {
+            return Value::Error(ExcelError::Num);
+        }
+        let ((y1, m1, d1), (y2, m2, d2)) = (ymd(start), ymd(end));
+        let months = (y2 - y1) * 12 + m2 - m1 - if d2 < d1 { 1 } else { 0 };
+        let result = match a[2].as_text().to_uppercase().as_str() {
+            "Y" => months / 12,
+            "M" => months,
+            "D" => end - start,
+            "YM" => months % 12,
+            "MD" if d2 >= d1 => d2 - d1,
+            "MD" => end - tryv!(serial_from_ymd(y2, m2 - 1, d1)) as i64,
+            "YD" => {
+                let mut anniversary = tryv!(serial_from_ymd(y2, m1, d1)) as i64;
+                if anniversary > end {
+                    anniversary = tryv!(serial_from_ymd(y2 - 1, m1, d1)) as i64;
+                }
+                end - anniversary
+            },
+            _ => return Value::Error(ExcelError::Num)
+        };
+        Value::from(result as f64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        parser::ast::Error as ExcelError,
+        errors::Error,
+    };
+
+    fn date(y: i32, m: u32, d: u32) -> Value {
+        Value::from(NaiveDate::from_ymd_opt(y, m, d).unwrap())
+    }
+
+    fn approx(formula: &str, expected: f64) -> Result<(), Error> {
+        let value = evaluate_str(formula)?.as_num();
+        assert!((value - expected).abs() < 1e-9, "{} = {}, expected {}", formula, value, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_serials() -> Result<(), Error> {
+        assert_eq!(evaluate_str("DATE(2008, 14, 2)")?, date(2009, 2, 2));
+        assert_eq!(evaluate_str("DATE(2008, 1, -15)")?, date(2007, 12, 16));
+        assert_eq!(evaluate_str("DATE(108, 1, 2)")?, date(2008, 1, 2));
+        assert_eq!(evaluate_str("DATE(1900, 2, 29)")?, Value::from(60.0));
+        assert_eq!(evaluate_str("DATE(10000, 1, 1)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("DATE(1900, 1, 1) + 0")?, date(1900, 1, 1));
+        assert_eq!(evaluate_str("VALUE(DATE(1900, 1, 1))")?, Value::from(1.0));
+        assert_eq!(evaluate_str("VALUE(DATE(1900, 3, 1))")?, Value::from(61.0));
+        assert_eq!(evaluate_str("VALUE(DATE(2008, 1, 1))")?, Value::from(39448.0));
+        assert_eq!(evaluate_str("DAY(60)")?, Value::from(29.0));
+        assert_eq!(evaluate_str("MONTH(60)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("DAY(DATE(2011, 4, 15))")?, Value::from(15.0));
+        assert_eq!(evaluate_str("MONTH(\"2011-04-15\")")?, Value::from(4.0));
+        assert_eq!(evaluate_str("YEAR(40000.75)")?, Value::from(2009.0));
+        assert_eq!(evaluate_str("DAY(-1)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("DATE(2008, 1, 1) + TIME(6, 0, 0)")?.to_string(), "2008-01-01 06:00:00");
+        assert_eq!(evaluate_str("(DATE(2008, 1, 2) + 0.5) - DATE(2008, 1, 1)")?, Value::from(1.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_weeks() -> Result<(), Error> {
+        assert_eq!(evaluate_str("WEEKDAY(DATE(2008, 2, 14))")?, Value::from(5.0));
+        assert_eq!(evaluate_str("WEEKDAY(DATE(2008, 2, 14), 2)")?, Value::from(4.0));
+        assert_eq!(evaluate_str("WEEKDAY(DATE(2008, 2, 14), 3)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("WEEKDAY(DATE(2008, 2, 14), 16)")?, Value::from(6.0));
+        assert_eq!(evaluate_str("WEEKDAY(1)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("WEEKDAY(DATE(2008, 2, 14), 4)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("WEEKNUM(DATE(2012, 3, 9))")?, Value::from(10.0));
+        assert_eq!(evaluate_str("WEEKNUM(DATE(2012, 3, 9), 2)")?, Value::from(11.0));
+        assert_eq!(evaluate_str("WEEKNUM(DATE(2012, 1, 1), 21)")?, Value::from(52.0));
+        assert_eq!(evaluate_str("ISOWEEKNUM(DATE(2012, 3, 9))")?, Value::from(10.0));
+        assert_eq!(evaluate_str("ISOWEEKNUM(DATE(2021, 1, 3))")?, Value::from(53.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_months() -> Result<(), Error> {
+        assert_eq!(evaluate_str("EDATE(DATE(2011, 1, 15), 1)")?, date(2011, 2, 15));
+        assert_eq!(evaluate_str("EDATE(DATE(2011, 1, 31), 1)")?, date(2011, 2, 28));
+        assert_eq!(evaluate_str("EDATE(DATE(2011, 1, 15), -2.7)")?, date(2010, 11, 15));
+        assert_eq!(evaluate_str("EOMONTH(DATE(2011, 1, 1), -3)")?, date(2010, 10, 31));
+        assert_eq!(evaluate_str("DAYS(\"2021-03-15\", \"2021-02-01\")")?, Value::from(42.0));
+        assert_eq!(evaluate_str("DAYS(DATE(2021, 1, 1), DATE(2021, 12, 31))")?, Value::from(-364.0));
+        assert_eq!(evaluate_str("DAYS360(DATE(2011, 1, 30), DATE(2011, 12, 31))")?, Value::from(330.0));
+        assert_eq!(evaluate_str("DAYS360(DATE(2011, 1, 1), DATE(2011, 4, 30))")?, Value::from(119.0));
+        assert_eq!(evaluate_str("DAYS360(DATE(2011, 2, 28), DATE(2011, 3, 31))")?, Value::from(30.0));
+        assert_eq!(evaluate_str("DAYS360(DATE(2011, 1, 31), DATE(2011, 3, 31), TRUE)")?, Value::from(60.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_workdays() -> Result<(), Error> {
+        assert_eq!(evaluate_str("NETWORKDAYS(DATE(2012, 10, 1), DATE(2013, 3, 1))")?, Value::from(110.0));
+        assert_eq!(evaluate_str("NETWORKDAYS(DATE(2012, 10, 1), DATE(2013, 3, 1), DATE(2012, 11, 22))")?, Value::from(109.0));
+        assert_eq!(evaluate_str("NETWORKDAYS(DATE(2012, 10, 1), DATE(2013, 3, 1), {41235, 41247, 41295})")?, Value::from(107.0));
+        assert_eq!(evaluate_str("NETWORKDAYS(DATE(2013, 3, 1), DATE(2012, 10, 1))")?, Value::from(-110.0));
+        assert_eq!(evaluate_str("NETWORKDAYS.INTL(DATE(2006, 1, 1), DATE(2006, 1, 31))")?, Value::from(22.0));
+        assert_eq!(evaluate_str("NETWORKDAYS.INTL(DATE(2006, 1, 1), DATE(2006, 2, 1), 7, {\"2006/1/2\", \"2006/1/16\"})")?, Value::from(22.0));
+        assert_eq!(evaluate_str("NETWORKDAYS.INTL(DATE(2006, 1, 1), DATE(2006, 2, 1), \"0010001\", {\"2006/1/2\", \"2006/1/16\"})")?, Value::from(20.0));
+        assert_eq!(evaluate_str("NETWORKDAYS.INTL(DATE(2006, 1, 1), DATE(2006, 2, 1), \"1111111\")")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("WORKDAY(DATE(2008, 10, 1), 151)")?, date(2009, 4, 30));
+        assert_eq!(evaluate_str("WORKDAY(DATE(2008, 10, 1), 151, {39778, 39786, 39834})")?, date(2009, 5, 5));
+        assert_eq!(evaluate_str("WORKDAY(DATE(2008, 10, 6), -1)")?, date(2008, 10, 3));
+        assert_eq!(evaluate_str("WORKDAY.INTL(DATE(2012, 1, 1), 30, 17)")?, date(2012, 2, 5));
+        assert_eq!(evaluate_str("WORKDAY.INTL(DATE(2012, 1, 1), 90, 11)")?, date(2012, 4, 14));
+        assert_eq!(evaluate_str("WORKDAY.INTL(DATE(2012, 1, 1), 30, 0)")?, Value::Error(ExcelError::Num));
+        Ok(())
+    }
+
+    #[test]
+    fn test_times() -> Result<(), Error> {
+        approx("TIME(12, 0, 0)", 0.5)?;
+        approx("TIME(16, 48, 10)", 0.700115741)?;
+        approx("TIME(27, 0, 0)", 0.125)?;
+        assert_eq!(evaluate_str("TIME(0, -30, 0)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("HOUR(0.75)")?, Value::from(18.0));
+        assert_eq!(evaluate_str("HOUR(\"3:30:30 PM\")")?, Value::from(15.0));
+        assert_eq!(evaluate_str("MINUTE(\"12:45:00 PM\")")?, Value::from(45.0));
+        assert_eq!(evaluate_str("SECOND(\"4:48:18 PM\")")?, Value::from(18.0));
+        assert_eq!(evaluate_str("SECOND(TIME(1, 2, 59.9))")?, Value::from(59.0));
+        approx("TIMEVALUE(\"2:24 AM\")", 0.1)?;
+        approx("TIMEVALUE(\"22-Aug-2011 6:35 AM\")", 0.274305556)?;
+        assert_eq!(evaluate_str("TIMEVALUE(0.5)")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("DATEVALUE(\"8/22/2011\")")?, Value::from(40777.0));
+        assert_eq!(evaluate_str("DATEVALUE(\"22-May-2011\")")?, Value::from(40685.0));
+        assert_eq!(evaluate_str("DATEVALUE(\"2011/02/23\")")?, Value::from(40597.0));
+        assert_eq!(evaluate_str("DATEVALUE(\"July 4, 2011 13:00\")")?, Value::from(40728.0));
+        assert_eq!(evaluate_str("DATEVALUE(\"not a date\")")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_yearfrac_bases() -> Result<(), Error> {
+        approx("YEARFRAC(DATE(2012, 1, 1), DATE(2012, 7, 30))", 0.580555556)?;
+        approx("YEARFRAC(DATE(2012, 1, 1), DATE(2012, 7, 30), 1)", 0.576502732)?;
+        approx("YEARFRAC(DATE(2012, 1, 1), DATE(2012, 7, 30), 2)", 0.586111111)?;
+        approx("YEARFRAC(DATE(2012, 1, 1), DATE(2012, 7, 30), 3)", 0.578082192)?;
+        approx("YEARFRAC(DATE(2012, 1, 31), DATE(2012, 7, 31), 4)", 0.5)?;
+        approx("YEARFRAC(DATE(2012, 7, 30), DATE(2012, 1, 1), 3)", 0.578082192)?;
+        approx("YEARFRAC(DATE(2011, 2, 28), DATE(2012, 2, 29), 0)", 1.0)?;
+        approx("YEARFRAC(DATE(2010, 6, 1), DATE(2013, 6, 1), 1)", 3.000684463)?;
+        approx("YEARFRAC(DATE(2011, 6, 1), DATE(2012, 3, 1), 1)", 0.748633880)?;
+        assert_eq!(evaluate_str("YEARFRAC(DATE(2012, 1, 1), DATE(2012, 7, 30), 5)")?, Value::Error(ExcelError::Num));
+        Ok(())
+    }
+
+    #[test]
+    fn test_datedif_units() -> Result<(), Error> {
+        assert_eq!(evaluate_str("DATEDIF(DATE(2001, 6, 1), DATE(2002, 8, 15), \"Y\")")?, Value::from(1.0));
+        assert_eq!(evaluate_str("DATEDIF(DATE(2001, 6, 15), DATE(2002, 6, 14), \"Y\")")?, Value::from(0.0));
+        assert_eq!(evaluate_str("DATEDIF(DATE(2001, 1, 31), DATE(2001, 3, 30), \"M\")")?, Value::from(1.0));
+        assert_eq!(evaluate_str("DATEDIF(DATE(2001, 6, 1), DATE(2002, 8, 15), \"yd\")")?, Value::from(75.0));
+        assert_eq!(evaluate_str("DATEDIF(DATE(2001, 6, 20), DATE(2002, 8, 15), \"MD\")")?, Value::from(26.0));
+        assert_eq!(evaluate_str("DATEDIF(DATE(2001, 6, 20), DATE(2002, 8, 15), \"YM\")")?, Value::from(1.0));
+        assert_eq!(evaluate_str("DATEDIF(DATE(2002, 8, 15), DATE(2001, 6, 1), \"D\")")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("DATEDIF(DATE(2001, 6, 1), DATE(2002, 8, 15), \"W\")")?, Value::Error(ExcelError::Num));
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+use std::cmp::Ordering;
+use ndarray::{Array2, Axis, concatenate};
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    function::{Function, number_arg, lookup::{as_table, compare}},
+};
+use excel_emulator_macro::function;
+
+// The functions here return arrays that spill over the cells next to the formula.
+
+fn int_arg(v: &Value) -> Result<i64, ExcelError> {
+    Ok(number_arg(&v.ensure_single())?.trunc() as i64)
+}
+
+fn flag(v: Option<Value>) -> Result<bool, ExcelError> {
+    match v {
+        Some(v) => Ok(number_arg(&v.ensure_single())? != 0.0),
+        None => Ok(false)
+    }
+}
+
+// A TRUE/FALSE out of a FILTER condition, blanks are FALSE and text is #VALUE!
+fn include_arg(v: &Value) -> Result<bool, ExcelError> {
+    match v {
+        Value::Bool(b) => Ok(*b),
+        Value::Num(n) => Ok(*n != 0.0),
+        Value::Empty => Ok(false),
+        Value::Error(e) => Err(e.clone()),
+        _ => Err(ExcelError::Value)
+    }
+}
+
+fn rank(v: &Value) -> u8 {
+    match v {
+        Value::Num(_) | Value::Date(_) => 0,
+        Value::Text(_) => 1,
+        Value::Bool(_) => 2,
+        Value::Error(_) => 3,
+        _ => 4
+    }
+}
+
+// Numbers, then text, logicals and errors, and blanks last whichever way the sort goes.
+fn collate(a: &Value, b: &Value, descending: bool) -> Ordering {
+    let order = rank(a).cmp(&rank(b)).then_with(|| compare(a, b).unwrap_or(Ordering::Equal));
+    match (a, b) {
+        (Value::Empty, _) | (_, Value::Empty) => order,
+        _ if descending => order.reverse(),
+        _ => order
+    }
+}
+
+// Values UNIQUE sees as the same: text ignores case.
+fn same(a: &Value, b: &Value) -> bool {
+    compare(a, b) == Some(Ordering::Equal) || a == b
+}
+
+fn lines(table: &Array2<Value>, by_col: bool) -> Vec<Vec<Value>> {
+    let axis = if by_col { Axis(1) } else { Axis(0) };
+    table.axis_iter(axis).map(|l| l.to_vec()).collect()
+}
+
+fn select(table: &Array2<Value>, indices: &[usize], by_col: bool) -> Value {
+    let axis = if by_col { Axis(1) } else { Axis(0) };
+    Value::from(table.select(axis, indices))
+}
+
+fn sort_direction(v: Option<&Value>) -> Result<bool, ExcelError> {
+    match v {
+        Some(v) => match int_arg(v)? {
+            1 => Ok(false),
+            -1 => Ok(true),
+            _ => Err(ExcelError::Value)
+        },
+        None => Ok(false)
+    }
+}
+
+// FILTER keeps rows (or columns) where include is TRUE, include being a column (or row) as long.
+#[function]
+fn filter(array: Value, include: Value, if_empty: Option<Value>) -> Value {
+    let table = as_table(&array);
+    let include = as_table(&include);
+    let by_col = match include.dim() {
+        (rows, 1) if rows == table.nrows() => false,
+        (1, cols) if cols == table.ncols() => true,
+        _ => return Value::Error(ExcelError::Value)
+    };
+    let mut keep = vec![];
+    for (i, v) in include.iter().enumerate() {
+        if tryv!(include_arg(v)) {
+            keep.push(i);
+        }
+    }
+    if keep.is_empty() {
+        return if_empty.unwrap_or(Value::Error(ExcelError::Calc));
+    }
+    select(&table, &keep, by_col)
+}
+
+// SORT(array, [sort_index], [sort_order], [by_col])
+#[function]
+fn sort(array: Value, sort_index: Option<Value>, sort_order: Option<Value>, by_col: Option<Value>) -> Value {
+    let table = as_table(&array);
+    let by_col = tryv!(flag(by_col));
+    let descending = tryv!(sort_direction(sort_order.as_ref()));
+    let lines = lines(&table, by_col);
+    let index = match sort_index {
+        Some(i) => tryv!(int_arg(&i)),
+        None => 1
+    };
+    if index < 1 || index as usize > lines[0].len() {
+        return Value::Error(ExcelError::Value);
+    }
+    let mut order: Vec<usize> = (0..lines.len()).collect();
+    order.sort_by(|a, b| collate(&lines[*a][index as usize - 1], &lines[*b][index as usize - 1], descending));
+    select(&table, &order, by_col)
+}
+
+// SORTBY(array, by_array1, [sort_order1], ...), each by_array a single row or column.
+#[function]
+fn sortby(array: Value, args: Vec<Value>) -> Value {
+    let table = as_table(&array);
+    let mut keys: Vec<(Vec<Value>, bool)> = vec![];
+    let mut by_col: Option<bool> = None;
+    let mut rest = args.iter().peekable();
+    while let Some(by) = rest.next() {
+        let by = as_table(by);
+        let by_columns = match by.dim() {
+            (rows, 1) if rows == table.nrows() => false,
+            (1, cols) if cols == table.ncols() => true,
+            _ => return Value::Error(ExcelError::Value)
+        };
+        if by_col.is_some_and(|b| b != by_columns) {
+            return Value::Error(ExcelError::Value);
+        }
+        by_col = Some(by_columns);
+        let order = if rest.peek().is_some_and(|v| !v.is_array2() && !v.is_array()) { rest.next() } else { None };
+        keys.push((by.into_raw_vec(), tryv!(sort_direction(order))));
+    }
+    let by_col = match by_col {
+        Some(b) => b,
+        None => return Value::Error(ExcelError::Value)
+    };
+    let mut order: Vec<usize> = (0..if by_col { table.ncols() } else { table.nrows() }).collect();
+    order.sort_by(|a, b| {
+        keys.iter().fold(Ordering::Equal, |o, (key, descending)| o.then_with(|| collate(&key[*a], &key[*b], *descending)))
+    });
+    select(&table, &order, by_col)
+}
+
+// UNIQUE(array, [by_col], [exactly_once])
+#[function]
+fn unique(array: Value, by_col: Option<Value>, exactly_once: Option<Value>) -> Value {
+    let table = as_table(&array);
+    let by_col = tryv!(flag(by_col));
+    let exactly_once = tryv!(flag(exactly_once));
+    let lines = lines(&table, by_col);
+    let matches = |a: &Vec<Value>, b: &Vec<Value>| a.iter().zip(b.iter()).all(|(x, y)| same(x, y));
+    let keep: Vec<usize> = (0..lines.len()).filter(|i| {
+        let first = (0..*i).all(|j| !matches(&lines[j], &lines[*i]));
+        let count = lines.iter().filter(|l| matches(l, &lines[*i])).count();
+        first && (!exactly_once || count == 1)
+    }).collect();
+    if keep.is_empty() {
+        return Value::Error(ExcelError::Calc);
+    }
+    select(&table, &keep, by_col)
+}
+
+// SEQUENCE(rows, [columns], [start], [step]), filled row by row.
+#[function]
+fn sequence(rows: Value, columns: Option<Value>, start: Option<Value>, step: Option<Value>) -> Value {
+    let rows = tryv!(int_arg(&rows));
+    let columns = match columns {
+        Some(c) => tryv!(int_arg(&c)),
+        None => 1
+    };
+    let start = tryv!(number_arg(&start.unwrap_or(Value::from(1.0)).ensure_single()));
+    let step = tryv!(number_arg(&step.unwrap_or(Value::from(1.0)).ensure_single()));
+    if rows < 1 || columns < 1 {
+        return Value::Error(ExcelError::Calc);
+    }
+    if rows > 1048576 || columns > 16384 {
+        return Value::Error(ExcelError::Value);
+    }
+    let columns = columns as usize;
+    Value::from(Array2::from_shape_fn((rows as usize, columns), |(i, j)| Value::from(start + step * (i * columns + j) as f64)))
+}
+
+#[function]
+fn transpose(array: Value) -> Value {
+    Value::from(as_table(&array).reversed_axes())
+}
+
+// Arrays one after the other, the narrower ones padded with #N/A.
+fn stack(args: Vec<Value>, axis: Axis) -> Value {
+    let tables: Vec<Array2<Value>> = args.iter().map(as_table).collect();
+    let other = Axis(1 - axis.index());
+    let width = tables.iter().map(|t| t.len_of(other)).max().unwrap_or(0);
+    let padded: Vec<Array2<Value>> = tables.into_iter().map(|t| {
+        let shape = if axis == Axis(0) { (t.nrows(), width) } else { (width, t.ncols()) };
+        Array2::from_shape_fn(shape, |(i, j)| t.get((i, j)).cloned().unwrap_or(Value::Error(ExcelError::NA)))
+    }).collect();
+    let views: Vec<_> = padded.iter().map(|t| t.view()).collect();
+    match concatenate(axis, &views) {
+        Ok(arr2) => Value::from(arr2),
+        Err(_) => Value::Error(ExcelError::Value)
+    }
+}
+
+#[function]
+fn vstack(args: Vec<Value>) -> Value {
+    stack(args, Axis(0))
+}
+
+#[function]
+fn hstack(args: Vec<Value>) -> Value {
+    stack(args, Axis(1))
+}
+
+/*
+ * Indices kept by TAKE (take = true) or DROP along an axis of length len:
+ * n from the start, or from the end when n is negative.
+ */
+fn keep_range(len: usize, n: Option<i64>, take: bool) -> Vec<usize> {
+    let n = match n {
+        Some(n) => n,
+        None => return (0..len).collect()
+    };
+    let count = (n.unsigned_abs() as usize).min(len);
+    match (take, n >= 0) {
+        (true, true) => (0..count).collect(),
+        (true, false) => (len - count..len).collect(),
+        (false, true) => (count..len).collect(),
+        (false, false) => (0..len - count).collect(),
+    }
+}
+
+fn take_or_drop(array: Value, rows: Value, columns: Option<Value>, take: bool) -> Value {
+    let table = as_table(&array);
+    let rows = tryv!(int_arg(&rows));
+    let columns = match columns {
+        Some(c) => Some(tryv!(int_arg(&c))),
+        None => None
+    };
+    if take && (rows == 0 || columns == Some(0)) {
+        return Value::Error(ExcelError::Calc);
+    }
+    let keep_rows = keep_range(table.nrows(), Some(rows), take);
+    let keep_cols = keep_range(table.ncols(), columns, take);
+    if keep_rows.is_empty() || keep_cols.is_empty() {
+        return Value::Error(ExcelError::Calc);
+    }
+    Value::from(table.select(Axis(0), &keep_rows).select(Axis(1), &keep_cols))
+}
+
+// TAKE(array, rows, [columns])
+#[function]
+fn take(array: Value, rows: Value, columns: Option<Value>) -> Value {
+    take_or_drop(array, rows, columns, true)
+}
+
+// DROP(array, rows, [columns])
+#[function("DROP")]
+fn dropfn(array: Value, rows: Value, columns: Option<Value>) -> Value {
+    take_or_drop(array, rows, columns, false)
+}
+
+// 1-based positions, negative ones counted from the end.
+fn choose(array: Value, args: Vec<Value>, axis: Axis) -> Value {
+    let table = as_table(&array);
+    let len = table.len_of(axis) as i64;
+    let mut indices = vec![];
+    for n in args.iter().flat_map(|a| a.as_array()) {
+        let n = tryv!(int_arg(&n));
+        if n == 0 || n.abs() > len {
+            return Value::Error(ExcelError::Value);
+        }
+        indices.push(if n > 0 { n - 1 } else { len + n } as usize);
+    }
+    if indices.is_empty() {
+        return Value::Error(ExcelError::Value);
+    }
+    Value::from(table.select(axis, &indices))
+}
+
+#[function]
+fn choosecols(array: Value, args: Vec<Value>) -> Value {
+    choose(array, args, Axis(1))
+}
+
+#[function]
+fn chooserows(array: Value, args: Vec<Value>) -> Value {
+    choose(array, args, Axis(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        parser::ast::Error as ExcelError,
+        workbook::Book,
+        errors::Error,
+    };
+
+    fn grid(rows: &[&[f64]]) -> Value {
+        let cols = rows[0].len();
+        Value::from(ndarray::Array2::from_shape_fn((rows.len(), cols), |(i, j)| Value::from(rows[i][j])))
+    }
+
+    #[test]
+    fn test_sequence() -> Result<(), Error> {
+        assert_eq!(evaluate_str("SEQUENCE(2, 3)")?, grid(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]));
+        assert_eq!(evaluate_str("SEQUENCE(3, 1, 10, -5)")?, grid(&[&[10.0], &[5.0], &[0.0]]));
+        assert_eq!(evaluate_str("SEQUENCE(0)")?, Value::Error(ExcelError::Calc));
+        assert_eq!(evaluate_str("TRANSPOSE(SEQUENCE(2, 3))")?, grid(&[&[1.0, 4.0], &[2.0, 5.0], &[3.0, 6.0]]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort() -> Result<(), Error> {
+        assert_eq!(evaluate_str("SORT({3, 1, 2}, 1, 1, TRUE)")?, grid(&[&[1.0, 2.0, 3.0]]));
+        assert_eq!(evaluate_str("SORT(TRANSPOSE({3, 1, 2}), 1, -1)")?, grid(&[&[3.0], &[2.0], &[1.0]]));
+        assert_eq!(evaluate_str("SORT({1, 2}, 3)")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("SORT({1, 2}, 1, 0)")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("SORTBY(TRANSPOSE({10, 20, 30}), TRANSPOSE({2, 3, 1}))")?, grid(&[&[30.0], &[10.0], &[20.0]]));
+        assert_eq!(evaluate_str("SORTBY({10, 20, 30}, {1, 1, 2}, -1, {3, 2, 1}, 1)")?, grid(&[&[30.0, 20.0, 10.0]]));
+        assert_eq!(evaluate_str("SORTBY({10, 20}, {1, 2, 3})")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_and_filter() -> Result<(), Error> {
+        assert_eq!(evaluate_str("UNIQUE(TRANSPOSE({\"a\", \"B\", \"A\", \"b\", \"c\"}))")?, Value::from(arr2(&[[Value::from("a")], [Value::from("B")], [Value::from("c")]])));
+        assert_eq!(evaluate_str("UNIQUE(TRANSPOSE({1, 2, 1, 3}), FALSE, TRUE)")?, grid(&[&[2.0], &[3.0]]));
+        assert_eq!(evaluate_str("UNIQUE(TRANSPOSE({1, 2, 1, 3}),, TRUE)")?, grid(&[&[2.0], &[3.0]]));
+        assert_eq!(evaluate_str("UNIQUE({1, 2, 1}, TRUE)")?, grid(&[&[1.0, 2.0]]));
+        assert_eq!(evaluate_str("FILTER({1, 2, 3, 4}, {TRUE, FALSE, 1, 0})")?, grid(&[&[1.0, 3.0]]));
+        assert_eq!(evaluate_str("FILTER({1, 2}, {FALSE, FALSE})")?, Value::Error(ExcelError::Calc));
+        assert_eq!(evaluate_str("FILTER({1, 2}, {FALSE, FALSE}, \"none\")")?, Value::from("none"));
+        assert_eq!(evaluate_str("FILTER({1, 2}, {TRUE, TRUE, FALSE})")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reshape() -> Result<(), Error> {
+        let seq = "SEQUENCE(3, 3)";
+        assert_eq!(evaluate_str(&format!("TAKE({}, 2)", seq))?, grid(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]));
+        assert_eq!(evaluate_str(&format!("TAKE({}, -1, -2)", seq))?, grid(&[&[8.0, 9.0]]));
+        assert_eq!(evaluate_str(&format!("TAKE({}, 0)", seq))?, Value::Error(ExcelError::Calc));
+        assert_eq!(evaluate_str(&format!("DROP({}, 1, -1)", seq))?, grid(&[&[4.0, 5.0], &[7.0, 8.0]]));
+        assert_eq!(evaluate_str(&format!("DROP({}, 3)", seq))?, Value::Error(ExcelError::Calc));
+        assert_eq!(evaluate_str(&format!("CHOOSECOLS({}, 3, -3)", seq))?, grid(&[&[3.0, 1.0], &[6.0, 4.0], &[9.0, 7.0]]));
+        assert_eq!(evaluate_str(&format!("CHOOSEROWS({}, {{2, 2}})", seq))?, grid(&[&[4.0, 5.0, 6.0], &[4.0, 5.0, 6.0]]));
+        assert_eq!(evaluate_str(&format!("CHOOSEROWS({}, 4)", seq))?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("VSTACK({1, 2}, 3)")?, Value::from(arr2(&[[Value::from(1.0), Value::from(2.0)], [Value::from(3.0), Value::Error(ExcelError::NA)]])));
+        assert_eq!(evaluate_str("HSTACK({1, 2}, SEQUENCE(2))")?, Value::from(arr2(&[
+            [Value::from(1.0), Value::from(2.0), Value::from(1.0)],
+            [Value::Error(ExcelError::NA), Value::Error(ExcelError::NA), Value::from(2.0)]
+        ])));
+        Ok(())
+    }
+
+    fn cell(book: &Book, r: &str) -> Value {
+        book.resolve_str_ref(r).unwrap()[[0, 0]].clone()
+    }
+
+    #[test]
+    fn test_spill() -> Result<(), Error> {
+        let mut book = Book::from("assets/dynamic.xlsx");
+        book.load(false).unwrap();
+        book.calculate(false, false)?;
+        // A1 =SEQUENCE(4) spills down to A4, read by C1 =SUM(A1#) and C2 =A3*10
+        assert_eq!(book.resolve_str_ref("Sheet1!A1:A4")?, arr2(&[[Value::from(1.0)], [Value::from(2.0)], [Value::from(3.0)], [Value::from(4.0)]]));
+        assert_eq!(cell(&book, "Sheet1!C1"), Value::from(10.0));
+        assert_eq!(cell(&book, "Sheet1!C2"), Value::from(30.0));
+        assert_eq!(cell(&book, "Sheet1!C3"), Value::from(4.0)); // ROWS(A1#)
+        // E1 =SORT(A1#, 1, -1) spills into E1:E4
+        assert_eq!(book.resolve_str_ref("Sheet1!E1:E4")?, arr2(&[[Value::from(4.0)], [Value::from(3.0)], [Value::from(2.0)], [Value::from(1.0)]]));
+        // G1 =SEQUENCE(3) runs into the value in G3
+        assert_eq!(cell(&book, "Sheet1!G1"), Value::Error(ExcelError::Spill));
+        assert_eq!(cell(&book, "Sheet1!G2"), Value::Empty);
+        // Removing the obstacle lets it spill
+        book.set_value("Sheet1!G3", Value::Empty);
+        book.calculate(false, false)?;
+        assert_eq!(book.resolve_str_ref("Sheet1!G1:G3")?, arr2(&[[Value::from(1.0)], [Value::from(2.0)], [Value::from(3.0)]]));
+        // I1 =ANCHORARRAY(E1) as Excel saves E1#
+        assert_eq!(cell(&book, "Sheet1!I1"), Value::from(4.0));
+        assert_eq!(cell(&book, "Sheet1!I4"), Value::from(1.0));
+        // A spill range loaded from the file is replaced, not in the way
+        assert_eq!(book.resolve_str_ref("Sheet1!K1:K2")?, arr2(&[[Value::from(1.0)], [Value::from(2.0)]]));
+        // M1 =B10# refers to a cell that does not spill
+        assert_eq!(cell(&book, "Sheet1!M1"), Value::Error(ExcelError::Ref));
+        Ok(())
+    }
+
+    #[test]
+    fn test_implicit_intersection() -> Result<(), Error> {
+        let mut book = Book::from("assets/dynamic.xlsx");
+        book.load(false).unwrap();
+        book.calculate(false, false)?;
+        // Formulas without dynamic array metadata take the row they are in from O1:O3, and do not spill
+        assert_eq!(cell(&book, "Sheet1!P2"), Value::from(4.0));
+        assert_eq!(cell(&book, "Sheet1!P3"), Value::from(7.0));
+        assert_eq!(cell(&book, "Sheet1!Q2"), Value::from(2.0));
+        assert_eq!(cell(&book, "Sheet1!Q3"), Value::Empty);
+        // R5 =O1:O3*10 shares no row with O1:O3
+        assert_eq!(cell(&book, "Sheet1!R5"), Value::Error(ExcelError::Value));
+        book.set_dynamic("Sheet1!R5");
+        book.calculate(false, false)?;
+        assert_eq!(book.resolve_str_ref("Sheet1!R5:R7")?, arr2(&[[Value::from(10.0)], [Value::from(20.0)], [Value::from(30.0)]]));
+        Ok(())
+    }
+}
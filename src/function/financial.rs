@@ -0,0 +1,468 @@
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    function::{Function, broadcast, number_arg, collect_numbers, xirr},
+};
+use excel_emulator_macro::function;
+
+// Applies f to the numbers in each element of the arguments.
+fn numeric(args: Vec<Value>, f: impl Fn(&[f64]) -> Value) -> Value {
+    broadcast(args, |a| {
+        let xs: Result<Vec<f64>, ExcelError> = a.iter().map(number_arg).collect();
+        f(&tryv!(xs))
+    })
+}
+
+fn or_zero(v: Option<Value>) -> Value {
+    v.unwrap_or(Value::from(0.0))
+}
+
+// Excel has no infinities or NaN, they are #NUM!
+fn checked(x: f64) -> Value {
+    if x.is_finite() {
+        Value::from(x)
+    } else {
+        Value::Error(ExcelError::Num)
+    }
+}
+
+// Payments are due at the end of each period unless type is 1.
+pub fn fv_value(rate: f64, nper: f64, pmt: f64, pv: f64, f_type: f64) -> f64 {
+    if rate == 0.0 {
+        -(pv + pmt * nper)
+    } else {
+        let growth = (1.0 + rate).powf(nper);
+        -(pv * growth + pmt * (1.0 + rate * f_type) * (growth - 1.0) / rate)
+    }
+}
+
+pub fn pv_value(rate: f64, nper: f64, pmt: f64, fv: f64, f_type: f64) -> f64 {
+    if rate == 0.0 {
+        -(fv + pmt * nper)
+    } else {
+        let growth = (1.0 + rate).powf(nper);
+        -(fv + pmt * (1.0 + rate * f_type) * (growth - 1.0) / rate) / growth
+    }
+}
+
+pub fn pmt_value(rate: f64, nper: f64, pv: f64, fv: f64, f_type: f64) -> f64 {
+    if rate == 0.0 {
+        -(pv + fv) / nper
+    } else {
+        let growth = (1.0 + rate).powf(nper);
+        -rate * (fv + pv * growth) / ((1.0 + rate * f_type) * (growth - 1.0))
+    }
+}
+
+// Interest part of the payment in period per (counted from 1).
+fn ipmt_value(rate: f64, per: f64, nper: f64, pv: f64, fv: f64, f_type: f64) -> f64 {
+    let pmt = pmt_value(rate, nper, pv, fv, f_type);
+    let interest = if per == 1.0 {
+        if f_type == 1.0 { 0.0 } else { -pv }
+    } else if f_type == 1.0 {
+        fv_value(rate, per - 2.0, pmt, pv, 1.0) - pmt
+    } else {
+        fv_value(rate, per - 1.0, pmt, pv, 0.0)
+    };
+    interest * rate
+}
+
+fn payment_type(f_type: f64) -> f64 {
+    if f_type != 0.0 { 1.0 } else { 0.0 }
+}
+
+#[function]
+fn npv(args: Vec<Value>) -> Value {
+    if args.len() < 2 {
+        return Value::Error(ExcelError::Value);
+    }
+    let rate = tryv!(number_arg(&args[0]));
+    let values = tryv!(collect_numbers(&args[1..]));
+    checked(values.iter().enumerate().map(|(i, v)| v / (1.0 + rate).powi(i as i32 + 1)).sum())
+}
+
+#[function]
+fn irr(values: Value, guess: Option<Value>) -> Value {
+    let values = tryv!(collect_numbers(&[Value::from(values.as_array())]));
+    let guess = tryv!(number_arg(&guess.unwrap_or(Value::from(0.1))));
+    if !values.iter().any(|v| *v > 0.0) || !values.iter().any(|v| *v < 0.0) {
+        return Value::Error(ExcelError::Num);
+    }
+    let f = |r: f64| values.iter().enumerate().map(|(i, v)| v / (1.0 + r).powi(i as i32)).sum::<f64>();
+    let df = |r: f64| values.iter().enumerate().map(|(i, v)| -(i as f64) * v / (1.0 + r).powi(i as i32 + 1)).sum::<f64>();
+    checked(xirr::solve(f, df, guess))
+}
+
+#[function]
+fn mirr(values: Value, finance_rate: Value, reinvest_rate: Value) -> Value {
+    let values = tryv!(collect_numbers(&[Value::from(values.as_array())]));
+    let finance_rate = tryv!(number_arg(&finance_rate));
+    let reinvest_rate = tryv!(number_arg(&reinvest_rate));
+    let n = values.len() as f64;
+    let npv = |rate: f64, keep: fn(&f64) -> bool| -> f64 {
+        values.iter().enumerate().filter(|(_, v)| keep(v)).map(|(i, v)| v / (1.0 + rate).powi(i as i32 + 1)).sum()
+    };
+    let positive = npv(reinvest_rate, |v| *v > 0.0);
+    let negative = npv(finance_rate, |v| *v < 0.0);
+    if positive == 0.0 || negative == 0.0 {
+        return Value::Error(ExcelError::Div);
+    }
+    checked((-positive * (1.0 + reinvest_rate).powf(n) / (negative * (1.0 + finance_rate))).powf(1.0 / (n - 1.0)) - 1.0)
+}
+
+#[function]
+fn fv(rate: Value, nper: Value, pmt: Value, pv: Option<Value>, f_type: Option<Value>) -> Value {
+    numeric(vec![rate, nper, pmt, or_zero(pv), or_zero(f_type)], |a| {
+        checked(fv_value(a[0], a[1], a[2], a[3], payment_type(a[4])))
+    })
+}
+
+#[function]
+fn pv(rate: Value, nper: Value, pmt: Value, fv: Option<Value>, f_type: Option<Value>) -> Value {
+    numeric(vec![rate, nper, pmt, or_zero(fv), or_zero(f_type)], |a| {
+        checked(pv_value(a[0], a[1], a[2], a[3], payment_type(a[4])))
+    })
+}
+
+#[function]
+fn nper(rate: Value, pmt: Value, pv: Value, fv: Option<Value>, f_type: Option<Value>) -> Value {
+    numeric(vec![rate, pmt, pv, or_zero(fv), or_zero(f_type)], |a| {
+        let (rate, pmt, pv, fv, f_type) = (a[0], a[1], a[2], a[3], payment_type(a[4]));
+        if rate == 0.0 {
+            if pmt == 0.0 {
+                return Value::Error(ExcelError::Num);
+            }
+            return checked(-(pv + fv) / pmt);
+        }
+        let z = pmt * (1.0 + rate * f_type) / rate;
+        checked(((z - fv) / (pv + z)).ln() / (1.0 + rate).ln())
+    })
+}
+
+#[function]
+fn rate(nper: Value, pmt: Value, pv: Value, fv: Option<Value>, f_type: Option<Value>, guess: Option<Value>) -> Value {
+    numeric(vec![nper, pmt, pv, or_zero(fv), or_zero(f_type), guess.unwrap_or(Value::from(0.1))], |a| {
+        let (nper, pmt, pv, fv, f_type) = (a[0], a[1], a[2], a[3], payment_type(a[4]));
+        if nper <= 0.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        // The future value of all cash flows at rate r, which is zero at the rate sought.
+        let f = |r: f64| if r == 0.0 {
+            pv + pmt * nper + fv
+        } else {
+            let growth = (1.0 + r).powf(nper);
+            pv * growth + pmt * (1.0 + r * f_type) * (growth - 1.0) / r + fv
+        };
+        let h = 1e-7;
+        let df = |r: f64| (f(r + h) - f(r - h)) / (2.0 * h);
+        checked(xirr::solve(f, df, a[5]))
+    })
+}
+
+#[function]
+fn ipmt(rate: Value, per: Value, nper: Value, pv: Value, fv: Option<Value>, f_type: Option<Value>) -> Value {
+    numeric(vec![rate, per, nper, pv, or_zero(fv), or_zero(f_type)], |a| {
+        if a[1] < 1.0 || a[1] > a[2] {
+            return Value::Error(ExcelError::Num);
+        }
+        checked(ipmt_value(a[0], a[1], a[2], a[3], a[4], payment_type(a[5])))
+    })
+}
+
+#[function]
+fn ppmt(rate: Value, per: Value, nper: Value, pv: Value, fv: Option<Value>, f_type: Option<Value>) -> Value {
+    numeric(vec![rate, per, nper, pv, or_zero(fv), or_zero(f_type)], |a| {
+        if a[1] < 1.0 || a[1] > a[2] {
+            return Value::Error(ExcelError::Num);
+        }
+        let f_type = payment_type(a[5]);
+        checked(pmt_value(a[0], a[2], a[3], a[4], f_type) - ipmt_value(a[0], a[1], a[2], a[3], a[4], f_type))
+    })
+}
+
+// Sums f over the periods start..=end of a loan, which CUMIPMT and CUMPRINC validate alike.
+fn cumulative(args: Vec<Value>, f: impl Fn(f64, f64, f64, f64, f64) -> f64) -> Value {
+    numeric(args, |a| {
+        let (rate, nper, pv, f_type) = (a[0], a[1].trunc(), a[2], a[5]);
+        let (start, end) = (a[3].trunc(), a[4].trunc());
+        if rate <= 0.0 || nper <= 0.0 || pv <= 0.0 || start < 1.0 || end < start || end > nper || (f_type != 0.0 && f_type != 1.0) {
+            return Value::Error(ExcelError::Num);
+        }
+        let mut total = 0.0;
+        let mut per = start;
+        while per <= end {
+            total += f(rate, per, nper, pv, f_type);
+            per += 1.0;
+        }
+        checked(total)
+    })
+}
+
+#[function]
+fn cumipmt(rate: Value, nper: Value, pv: Value, start_period: Value, end_period: Value, f_type: Value) -> Value {
+    cumulative(vec![rate, nper, pv, start_period, end_period, f_type], |rate, per, nper, pv, f_type| {
+        ipmt_value(rate, per, nper, pv, 0.0, f_type)
+    })
+}
+
+#[function]
+fn cumprinc(rate: Value, nper: Value, pv: Value, start_period: Value, end_period: Value, f_type: Value) -> Value {
+    cumulative(vec![rate, nper, pv, start_period, end_period, f_type], |rate, per, nper, pv, f_type| {
+        pmt_value(rate, nper, pv, 0.0, f_type) - ipmt_value(rate, per, nper, pv, 0.0, f_type)
+    })
+}
+
+#[function]
+fn sln(cost: Value, salvage: Value, life: Value) -> Value {
+    numeric(vec![cost, salvage, life], |a| {
+        if a[2] == 0.0 {
+            return Value::Error(ExcelError::Div);
+        }
+        Value::from((a[0] - a[1]) / a[2])
+    })
+}
+
+#[function]
+fn syd(cost: Value, salvage: Value, life: Value, per: Value) -> Value {
+    numeric(vec![cost, salvage, life, per], |a| {
+        let (cost, salvage, life, per) = (a[0], a[1], a[2], a[3]);
+        if life <= 0.0 || per <= 0.0 || per > life || salvage < 0.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        checked((cost - salvage) * (life - per + 1.0) * 2.0 / (life * (life + 1.0)))
+    })
+}
+
+// Fixed-declining balance, with the rate rounded to three decimals and a partial first year of month months.
+#[function]
+fn db(cost: Value, salvage: Value, life: Value, period: Value, month: Option<Value>) -> Value {
+    numeric(vec![cost, salvage, life, period, month.unwrap_or(Value::from(12.0))], |a| {
+        let (cost, salvage, life, period, month) = (a[0], a[1], a[2].trunc(), a[3].trunc(), a[4].trunc());
+        if cost < 0.0 || salvage < 0.0 || life <= 0.0 || period <= 0.0 || !(1.0..=12.0).contains(&month)
+            || period > life + 1.0 || (period == life + 1.0 && month == 12.0) {
+            return Value::Error(ExcelError::Num);
+        }
+        if cost == 0.0 {
+            return Value::from(0.0);
+        }
+        let rate = ((1.0 - (salvage / cost).powf(1.0 / life)) * 1000.0).round() / 1000.0;
+        let mut total = cost * rate * month / 12.0;
+        let mut depreciation = total;
+        let mut p = 2.0;
+        while p <= period {
+            depreciation = if p == life + 1.0 {
+                (cost - total) * rate * (12.0 - month) / 12.0
+            } else {
+                (cost - total) * rate
+            };
+            total += depreciation;
+            p += 1.0;
+        }
+        checked(depreciation)
+    })
+}
+
+// Declining balance depreciation in period, never taking the book value below salvage.
+fn ddb_value(cost: f64, salvage: f64, life: f64, period: f64, factor: f64) -> f64 {
+    let rate = factor / life;
+    let (rate, old) = if rate >= 1.0 {
+        (1.0, if period == 1.0 { cost } else { 0.0 })
+    } else {
+        (rate, cost * (1.0 - rate).powf(period - 1.0))
+    };
+    let new = cost * (1.0 - rate).powf(period);
+    let depreciation = if new < salvage { old - salvage } else { old - new };
+    depreciation.max(0.0)
+}
+
+#[function]
+fn ddb(cost: Value, salvage: Value, life: Value, period: Value, factor: Option<Value>) -> Value {
+    numeric(vec![cost, salvage, life, period, factor.unwrap_or(Value::from(2.0))], |a| {
+        let (cost, salvage, life, period, factor) = (a[0], a[1], a[2], a[3], a[4]);
+        if cost < 0.0 || salvage < 0.0 || life <= 0.0 || period <= 0.0 || period > life || factor <= 0.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        checked(ddb_value(cost, salvage, life, period, factor))
+    })
+}
+
+/*
+ * Depreciation over the first period periods, switching to straight line
+ * on the remaining value once that is larger than the declining balance.
+ * The last period counts in part when period is fractional.
+ */
+fn switching_depreciation(cost: f64, salvage: f64, life: f64, remaining_life: f64, period: f64, factor: f64) -> f64 {
+    let end = period.ceil();
+    let mut remaining = cost - salvage;
+    let mut straight = None;
+    let mut total = 0.0;
+    let mut i = 1.0;
+    while i <= end {
+        let mut term = match straight {
+            Some(sln) => sln,
+            None => {
+                let ddb = ddb_value(cost, salvage, life, i, factor);
+                let sln = remaining / (remaining_life - (i - 1.0));
+                if sln > ddb {
+                    straight = Some(sln);
+                    sln
+                } else {
+                    remaining -= ddb;
+                    ddb
+                }
+            }
+        };
+        if i == end {
+            term *= period + 1.0 - end;
+        }
+        total += term;
+        i += 1.0;
+    }
+    total
+}
+
+#[function]
+fn vdb(cost: Value, salvage: Value, life: Value, start_period: Value, end_period: Value, factor: Option<Value>, no_switch: Option<Value>) -> Value {
+    let args = vec![cost, salvage, life, start_period, end_period, factor.unwrap_or(Value::from(2.0)), no_switch.unwrap_or(Value::from(false))];
+    numeric(args, |a| {
+        let (cost, salvage, life, start, end, factor) = (a[0], a[1], a[2], a[3], a[4], a[5]);
+        if start < 0.0 || end < start || end > life || cost < 0.0 || salvage > cost || factor <= 0.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        let (int_start, int_end) = (start.floor(), end.ceil());
+        if a[6] != 0.0 {
+            let mut total = 0.0;
+            let mut i = int_start + 1.0;
+            while i <= int_end {
+                let mut term = ddb_value(cost, salvage, life, i, factor);
+                if i == int_start + 1.0 {
+                    term *= end.min(int_start + 1.0) - start;
+                } else if i == int_end {
+                    term *= end + 1.0 - int_end;
+                }
+                total += term;
+                i += 1.0;
+            }
+            return checked(total);
+        }
+        // Whole periods, less the parts of the first and last periods outside start..end.
+        let mut part = 0.0;
+        if start != int_start {
+            let value = cost - switching_depreciation(cost, salvage, life, life, int_start, factor);
+            part += (start - int_start) * switching_depreciation(value, salvage, life, life - int_start, 1.0, factor);
+        }
+        if end != int_end {
+            let value = cost - switching_depreciation(cost, salvage, life, life, int_end - 1.0, factor);
+            part += (int_end - end) * switching_depreciation(value, salvage, life, life - int_end + 1.0, 1.0, factor);
+        }
+        let value = cost - switching_depreciation(cost, salvage, life, life, int_start, factor);
+        checked(switching_depreciation(value, salvage, life, life - int_start, int_end - int_start, factor) - part)
+    })
+}
+
+#[function]
+fn effect(nominal_rate: Value, npery: Value) -> Value {
+    numeric(vec![nominal_rate, npery], |a| {
+        let (rate, npery) = (a[0], a[1].trunc());
+        if rate <= 0.0 || npery < 1.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        checked((1.0 + rate / npery).powf(npery) - 1.0)
+    })
+}
+
+#[function]
+fn nominal(effect_rate: Value, npery: Value) -> Value {
+    numeric(vec![effect_rate, npery], |a| {
+        let (rate, npery) = (a[0], a[1].trunc());
+        if rate <= 0.0 || npery < 1.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        checked(npery * ((1.0 + rate).powf(1.0 / npery) - 1.0))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        parser::ast::Error as ExcelError,
+        errors::Error,
+    };
+
+    fn approx(formula: &str, expected: f64) -> Result<(), Error> {
+        let value = evaluate_str(formula)?.as_num();
+        assert!((value - expected).abs() < 1e-6 * expected.abs().max(1.0), "{} = {}, expected {}", formula, value, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cash_flows() -> Result<(), Error> {
+        approx("NPV(0.1, -10000, 3000, 4200, 6800)", 1188.443412)?;
+        approx("NPV(0.08, {8000, 9200, 10000, 12000, 14500}) - 40000", 1922.061555)?;
+        approx("IRR({-70000, 12000, 15000, 18000, 21000, 26000})", 0.086630948)?;
+        approx("IRR({-70000, 12000, 15000, 18000, 21000})", -0.021244848)?;
+        approx("IRR({-70000, 12000, 15000}, -0.1)", -0.443506941)?;
+        assert_eq!(evaluate_str("IRR({1000, 2000})")?, Value::Error(ExcelError::Num));
+        approx("MIRR({-120000, 39000, 30000, 21000, 37000, 46000}, 0.1, 0.12)", 0.126094493)?;
+        assert_eq!(evaluate_str("MIRR({1000, 2000}, 0.1, 0.12)")?, Value::Error(ExcelError::Div));
+        Ok(())
+    }
+
+    #[test]
+    fn test_annuities() -> Result<(), Error> {
+        approx("FV(0.06/12, 10, -200, -500, 1)", 2581.403374)?;
+        approx("FV(0, 12, -100)", 1200.0)?;
+        approx("PV(0.08/12, 12*20, 500, 0, 0)", -59777.14585)?;
+        approx("NPER(0.12/12, -100, -1000, 10000, 1)", 59.67386567)?;
+        approx("NPER(0.01, -100, 1000)", 10.58864446)?;
+        approx("RATE(4*12, -200, 8000)", 0.007701472)?;
+        approx("RATE(4*12, -200, 8000, 0, 0, 0.5)", 0.007701472)?;
+        assert_eq!(evaluate_str("RATE(10, 100, 1000)")?, Value::Error(ExcelError::Num));
+        approx("IPMT(0.1/12, 1, 3*12, 8000)", -66.66666667)?;
+        approx("IPMT(0.1, 3, 3, 8000)", -292.4471299)?;
+        approx("IPMT(0.1, 2, 3, 8000, 0, 1)", -507.5528701)?;
+        approx("PPMT(0.1/12, 1, 2*12, 2000)", -75.62318601)?;
+        approx("PPMT(0.08, 10, 10, 200000)", -27598.05346)?;
+        assert_eq!(evaluate_str("PPMT(0.1, 4, 3, 8000)")?, Value::Error(ExcelError::Num));
+        approx("CUMIPMT(0.09/12, 30*12, 125000, 13, 24, 0)", -11135.23213)?;
+        approx("CUMIPMT(0.09/12, 30*12, 125000, 1, 1, 0)", -937.5)?;
+        approx("CUMPRINC(0.09/12, 30*12, 125000, 13, 24, 0)", -934.1071234)?;
+        approx("CUMPRINC(0.09/12, 30*12, 125000, 1, 1, 0)", -68.27827118)?;
+        assert_eq!(evaluate_str("CUMIPMT(0.09/12, 360, 125000, 0, 1, 0)")?, Value::Error(ExcelError::Num));
+        Ok(())
+    }
+
+    #[test]
+    fn test_depreciation() -> Result<(), Error> {
+        approx("SLN(30000, 7500, 10)", 2250.0)?;
+        approx("SYD(30000, 7500, 10, 1)", 4090.909091)?;
+        approx("SYD(30000, 7500, 10, 10)", 409.0909091)?;
+        approx("DB(1000000, 100000, 6, 1, 7)", 186083.3333)?;
+        approx("DB(1000000, 100000, 6, 2, 7)", 259639.4167)?;
+        approx("DB(1000000, 100000, 6, 6, 7)", 55841.75667)?;
+        approx("DB(1000000, 100000, 6, 7, 7)", 15845.09847)?;
+        assert_eq!(evaluate_str("DB(1000000, 100000, 6, 7)")?, Value::Error(ExcelError::Num));
+        approx("DDB(2400, 300, 10*365, 1)", 1.315068493)?;
+        approx("DDB(2400, 300, 10, 1, 2)", 480.0)?;
+        approx("DDB(2400, 300, 10, 10)", 22.1225472)?;
+        approx("VDB(2400, 300, 10*365, 0, 1)", 1.315068493)?;
+        approx("VDB(2400, 300, 10*12, 0, 1)", 40.0)?;
+        approx("VDB(2400, 300, 10, 0, 1)", 480.0)?;
+        approx("VDB(2400, 300, 10*12, 6, 18)", 396.3060533)?;
+        approx("VDB(2400, 300, 10*12, 6, 18, 1.5)", 311.8089366)?;
+        approx("VDB(2400, 300, 10, 0, 0.875, 1.5)", 315.0)?;
+        approx("VDB(2400, 300, 10, 8, 10)", 102.653184)?;
+        approx("VDB(1000, 0, 5, 0, 5, 1)", 1000.0)?;
+        approx("VDB(1000, 0, 5, 0, 5, 1, TRUE)", 672.32)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rates() -> Result<(), Error> {
+        approx("EFFECT(0.0525, 4)", 0.053542667)?;
+        approx("NOMINAL(0.053543, 4)", 0.052500319)?;
+        assert_eq!(evaluate_str("EFFECT(0, 4)")?, Value::Error(ExcelError::Num));
+        Ok(())
+    }
+}
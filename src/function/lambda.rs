@@ -0,0 +1,256 @@
+use ndarray::{Array2, Axis};
+use crate::{
+    evaluate::{evaluate_expr_with_context, ensure_non_range, value::Value},
+    parser::{parse_str, ast::{Expr, Error as ExcelError}},
+    function::lookup::as_table,
+    workbook::Book,
+    errors::Error,
+};
+
+/*
+ * LET and LAMBDA bind names in the book's scope while their body is evaluated, the
+ * helpers below call a LAMBDA for each element, row or column of an array.
+ */
+pub const LAMBDA_FUNCTIONS: [&str; 8] = ["LET", "LAMBDA", "MAP", "REDUCE", "SCAN", "BYROW", "BYCOL", "MAKEARRAY"];
+
+pub fn evaluate(name: &str, args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
+    match name {
+        "LET" => let_in(args, book, debug),
+        "LAMBDA" => Ok(lambda(args, book)),
+        _ => {
+            let values: Vec<Value> = args.into_iter()
+                .map(|a| evaluate_expr_with_context(a, book, debug).map(ensure_non_range))
+                .collect::<Result<Vec<Value>, Error>>()?;
+            if let Some(err) = values.iter().find(|v| v.is_err()) {
+                return Ok(err.clone());
+            }
+            match (name, values.as_slice()) {
+                ("MAP", [arrays @ .., f]) if !arrays.is_empty() => map(arrays, f, book, debug),
+                ("REDUCE", [initial, array, f]) => reduce(initial, array, f, book, debug).map(|(total, _)| total),
+                ("SCAN", [initial, array, f]) => reduce(initial, array, f, book, debug).map(|(_, steps)| steps),
+                ("BYROW", [array, f]) => by_row(as_table(array), f, book, debug),
+                ("BYCOL", [array, f]) => by_row(as_table(array).reversed_axes(), f, book, debug).map(|v| Value::from(as_table(&v).reversed_axes())),
+                ("MAKEARRAY", [rows, columns, f]) => make_array(rows, columns, f, book, debug),
+                _ => Ok(Value::Error(ExcelError::Value))
+            }
+        }
+    }
+}
+
+// The value of a name: a LET variable or LAMBDA parameter in scope, then a defined name.
+pub fn name(name: &str, book: &Book, debug: bool) -> Result<Value, Error> {
+    if let Some(value) = book.scope.borrow().get(&name.to_uppercase()) {
+        return Ok(value.clone());
+    }
+    match book.defined_name(name) {
+        // Defined names do not see the LET names around the formula using them
+        Some(formula) => {
+            let expr = parse_str(formula)?;
+            let saved = book.scope.take();
+            let value = evaluate_expr_with_context(expr, book, debug);
+            book.scope.replace(saved);
+            value
+        },
+        None => Ok(Value::Error(ExcelError::Name))
+    }
+}
+
+pub fn is_name(name: &str, book: &Book) -> bool {
+    book.scope.borrow().contains_key(&name.to_uppercase()) || book.defined_name(name).is_some()
+}
+
+// LET(name1, value1, [name2, value2, ...], calculation)
+fn let_in(mut args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
+    if args.len() < 3 || args.len() % 2 == 0 {
+        return Ok(Value::Error(ExcelError::Value));
+    }
+    let calculation = args.pop().unwrap();
+    let saved = book.scope.borrow().clone();
+    let value = bind(&args, calculation, book, debug);
+    book.scope.replace(saved);
+    value
+}
+
+fn bind(pairs: &[Expr], calculation: Expr, book: &Book, debug: bool) -> Result<Value, Error> {
+    for pair in pairs.chunks(2) {
+        let Expr::Name(name) = &pair[0] else {
+            return Ok(Value::Error(ExcelError::Value));
+        };
+        let value = evaluate_expr_with_context(pair[1].clone(), book, debug)?;
+        book.scope.borrow_mut().insert(name.to_uppercase(), value);
+    }
+    evaluate_expr_with_context(calculation, book, debug)
+}
+
+// LAMBDA([parameter1, ...], calculation), keeping the names in scope where it is made.
+fn lambda(mut args: Vec<Expr>, book: &Book) -> Value {
+    let body = match args.pop() {
+        Some(body) => body,
+        None => return Value::Error(ExcelError::Value)
+    };
+    let params: Option<Vec<String>> = args.into_iter().map(|a| match a {
+        Expr::Name(n) => Some(n.to_uppercase()),
+        _ => None
+    }).collect();
+    match params {
+        Some(params) => Value::Lambda { params, body: Box::new(body), scope: book.scope.borrow().clone() },
+        None => Value::Error(ExcelError::Value)
+    }
+}
+
+// Calls a LAMBDA, #VALUE! when it is not one or gets the wrong number of arguments.
+pub fn call(function: &Value, args: Vec<Value>, book: &Book, debug: bool) -> Result<Value, Error> {
+    match function {
+        Value::Lambda { params, body, scope } if params.len() == args.len() => {
+            let mut inner = scope.clone();
+            inner.extend(params.iter().cloned().zip(args));
+            let saved = book.scope.replace(inner);
+            let value = evaluate_expr_with_context(*body.clone(), book, debug);
+            book.scope.replace(saved);
+            value
+        },
+        Value::Error(_) => Ok(function.clone()),
+        _ => Ok(Value::Error(ExcelError::Value))
+    }
+}
+
+// What a LAMBDA returns for one element of an array, which cannot be another array.
+fn element(value: Value) -> Value {
+    match ensure_non_range(value) {
+        Value::Array2(arr2) if arr2.len() == 1 => arr2[[0, 0]].clone(),
+        Value::Array(arr) if arr.len() == 1 => arr[0].clone(),
+        Value::Array2(_) | Value::Array(_) | Value::Lambda { .. } => Value::Error(ExcelError::Calc),
+        v => v
+    }
+}
+
+fn map(arrays: &[Value], f: &Value, book: &Book, debug: bool) -> Result<Value, Error> {
+    let grids: Vec<Array2<Value>> = arrays.iter().map(as_table).collect();
+    let shape = grids[0].dim();
+    if grids.iter().any(|g| g.dim() != shape) {
+        return Ok(Value::Error(ExcelError::Value));
+    }
+    let mut values = Vec::with_capacity(grids[0].len());
+    for (i, j) in (0..shape.0).flat_map(|i| (0..shape.1).map(move |j| (i, j))) {
+        let args: Vec<Value> = grids.iter().map(|g| g[[i, j]].clone()).collect();
+        values.push(element(call(f, args, book, debug)?));
+    }
+    Ok(Value::from(Array2::from_shape_vec(shape, values).unwrap()))
+}
+
+// The final accumulator for REDUCE and every step of it, shaped like the array, for SCAN.
+fn reduce(initial: &Value, array: &Value, f: &Value, book: &Book, debug: bool) -> Result<(Value, Value), Error> {
+    let grid = as_table(array);
+    let mut total = initial.clone();
+    let mut steps = Vec::with_capacity(grid.len());
+    for v in grid.iter() {
+        total = ensure_non_range(call(f, vec![total, v.clone()], book, debug)?);
+        steps.push(element(total.clone()));
+    }
+    Ok((total, Value::from(Array2::from_shape_vec(grid.dim(), steps).unwrap())))
+}
+
+// One result per row, each row passed as a 1 x n array.
+fn by_row(grid: Array2<Value>, f: &Value, book: &Book, debug: bool) -> Result<Value, Error> {
+    let mut values = Vec::with_capacity(grid.nrows());
+    for row in grid.rows() {
+        let row = Value::from(row.to_owned().insert_axis(Axis(0)));
+        values.push(element(call(f, vec![row], book, debug)?));
+    }
+    Ok(Value::from(Array2::from_shape_vec((values.len(), 1), values).unwrap()))
+}
+
+fn make_array(rows: &Value, columns: &Value, f: &Value, book: &Book, debug: bool) -> Result<Value, Error> {
+    let (rows, columns) = (rows.ensure_single().as_num().trunc(), columns.ensure_single().as_num().trunc());
+    if rows < 1.0 || columns < 1.0 || rows > 1048576.0 || columns > 16384.0 {
+        return Ok(Value::Error(ExcelError::Value));
+    }
+    let (rows, columns) = (rows as usize, columns as usize);
+    let mut values = Vec::with_capacity(rows * columns);
+    for (i, j) in (1..=rows).flat_map(|i| (1..=columns).map(move |j| (i, j))) {
+        values.push(element(call(f, vec![Value::from(i), Value::from(j)], book, debug)?));
+    }
+    Ok(Value::from(Array2::from_shape_vec((rows, columns), values).unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use crate::{
+        evaluate::{value::Value, evaluate_expr_with_context},
+        parser::{parse_str, ast::Error as ExcelError},
+        workbook::Book,
+        errors::Error,
+    };
+
+    fn eval(book: &Book, formula: &str) -> Result<Value, Error> {
+        evaluate_expr_with_context(parse_str(formula)?, book, false)
+    }
+
+    fn column(values: &[f64]) -> Value {
+        Value::from(arr2(&[[Value::from(values[0])], [Value::from(values[1])]]))
+    }
+
+    #[test]
+    fn test_let_and_lambda() -> Result<(), Error> {
+        let book = Book::new();
+        assert_eq!(eval(&book, "LET(x, 2, y, x * 3, x + y)")?, Value::from(8.0));
+        assert_eq!(eval(&book, "LET(_xlpm.x, 2, _xlpm.x ^ 2)")?, Value::from(4.0));
+        assert_eq!(eval(&book, "LAMBDA(x, y, x - y)(10, 4)")?, Value::from(6.0));
+        assert_eq!(eval(&book, "LET(add, LAMBDA(x, y, x + y), add(1, add(2, 3)))")?, Value::from(6.0));
+        // A LAMBDA keeps the names around where it was made, its parameters shadow them
+        assert_eq!(eval(&book, "LET(k, 10, f, LAMBDA(x, x * k), k, 1, f(2))")?, Value::from(20.0));
+        assert_eq!(eval(&book, "LET(x, 1, LAMBDA(x, x)(5) + x)")?, Value::from(6.0));
+        // IF on an array picks element by element
+        assert_eq!(eval(&book, "LET(x, {1,2,3}, SUM(IF(x > 1, x, 0)))")?, Value::from(5.0));
+        assert_eq!(eval(&book, "LAMBDA(v, IF(v > 1, v * 10))({1,2})")?, Value::from(vec![Value::from(false), Value::from(20.0)]));
+        let picked = Value::from(vec![Value::from(1.0), Value::from(4.0)]);
+        assert_eq!(eval(&book, "LET(c, {TRUE,FALSE}, IF(c, {1,2}, {3,4}))")?, picked);
+        assert_eq!(eval(&book, "LAMBDA(c, IF(c, {1,2}, {3,4}))({TRUE,FALSE})")?, picked);
+        assert_eq!(eval(&book, "LAMBDA(x, x)(1, 2)")?, Value::Error(ExcelError::Value));
+        assert_eq!(eval(&book, "LET(1, 2, 3)")?, Value::Error(ExcelError::Value));
+        assert_eq!(eval(&book, "missing + 1")?, Value::Error(ExcelError::Name));
+        assert!(book.scope.borrow().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lambda_helpers() -> Result<(), Error> {
+        let book = Book::new();
+        let grid = "VSTACK({1,2},{3,4})";
+        assert_eq!(eval(&book, &format!("MAP({}, LAMBDA(v, v * 10))", grid))?, Value::from(arr2(&[[Value::from(10.0), Value::from(20.0)], [Value::from(30.0), Value::from(40.0)]])));
+        assert_eq!(eval(&book, "MAP({1,2}, {3,4}, LAMBDA(a, b, a * b))")?, Value::from(arr2(&[[Value::from(3.0), Value::from(8.0)]])));
+        assert_eq!(eval(&book, "MAP({1,2}, {3}, LAMBDA(a, b, a * b))")?, Value::Error(ExcelError::Value));
+        assert_eq!(eval(&book, &format!("REDUCE(0, {}, LAMBDA(acc, v, acc + v))", grid))?, Value::from(10.0));
+        assert_eq!(eval(&book, "SCAN(1, {1,2,3}, LAMBDA(acc, v, acc * v + 1))")?, Value::from(arr2(&[[Value::from(2.0), Value::from(5.0), Value::from(16.0)]])));
+        assert_eq!(eval(&book, &format!("BYROW({}, LAMBDA(row, SUM(row)))", grid))?, column(&[3.0, 7.0]));
+        assert_eq!(eval(&book, &format!("BYCOL({}, LAMBDA(col, MAX(col)))", grid))?, Value::from(arr2(&[[Value::from(3.0), Value::from(4.0)]])));
+        assert_eq!(eval(&book, "MAKEARRAY(2, 1, LAMBDA(i, j, i * 10 + j))")?, column(&[11.0, 21.0]));
+        assert_eq!(eval(&book, "MAKEARRAY(1E+9, 1E+9, LAMBDA(r, c, 1))")?, Value::Error(ExcelError::Value));
+        assert_eq!(eval(&book, "MAKEARRAY(0, 1, LAMBDA(r, c, 1))")?, Value::Error(ExcelError::Value));
+        // Each call gives one value
+        assert_eq!(eval(&book, "BYROW({1,2}, LAMBDA(row, row))")?, Value::from(arr2(&[[Value::Error(ExcelError::Calc)]])));
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_lambdas() -> Result<(), Error> {
+        let mut book = Book::from("assets/lambda.xlsx");
+        book.load(false).unwrap();
+        book.calculate(false, false)?;
+        let cell = |book: &Book, r: &str| book.resolve_str_ref(r).unwrap()[[0, 0]].clone();
+        assert_eq!(cell(&book, "Sheet1!B1"), Value::from(90.0));
+        assert_eq!(cell(&book, "Sheet1!B2"), Value::from(400.0));
+        assert_eq!(cell(&book, "Sheet1!B3"), Value::from(120.0));
+        assert_eq!(cell(&book, "Sheet1!B4"), Value::from(300.0));
+        assert_eq!(cell(&book, "Sheet1!B5"), Value::Error(ExcelError::Calc));
+        assert_eq!(cell(&book, "Sheet1!C2"), Value::from(400.0));
+        // Rate is Sheet1!$A$2, so the cells using it depend on A2
+        book.set_value("Sheet1!A2", Value::from(50.0));
+        book.calculate(false, false)?;
+        assert_eq!(cell(&book, "Sheet1!B2"), Value::from(100.0));
+        assert_eq!(cell(&book, "Sheet1!B4"), Value::from(150.0));
+        assert_eq!(cell(&book, "Sheet1!C2"), Value::from(100.0));
+        Ok(())
+    }
+}
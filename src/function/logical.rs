@@ -0,0 +1,405 @@
+use std::cmp::Ordering;
+use crate::{
+    evaluate::{value::Value, evaluate_expr_with_context},
+    parser::ast::{Expr, Error as ExcelError},
+    reference::Reference,
+    errors::Error,
+    workbook::Book,
+    utils::{date_time_to_excel, split_external_sheet},
+    function::{Function, broadcast, number_arg, lookup::compare},
+};
+use excel_emulator_macro::function;
+
+/*
+ * A logical the way AND, OR and NOT read it: numbers are TRUE unless 0 and
+ * text has to spell TRUE or FALSE. Anything else is #VALUE!.
+ */
+fn logical_arg(v: &Value) -> Result<bool, ExcelError> {
+    match v {
+        Value::Bool(b) => Ok(*b),
+        Value::Num(n) => Ok(*n != 0.0),
+        Value::Date(d) => Ok(date_time_to_excel(*d) != 0.0),
+        Value::Empty => Ok(false),
+        Value::Text(t) if t.eq_ignore_ascii_case("TRUE") => Ok(true),
+        Value::Text(t) if t.eq_ignore_ascii_case("FALSE") => Ok(false),
+        Value::Error(e) => Err(e.clone()),
+        Value::Array(_) | Value::Array2(_) => logical_arg(&v.ensure_single()),
+        _ => Err(ExcelError::Value)
+    }
+}
+
+// Logicals of the arguments. Inside ranges and arrays only logicals and numbers count.
+fn collect_logicals(args: &[Value]) -> Result<Vec<bool>, ExcelError> {
+    let mut output = vec![];
+    for arg in args.iter() {
+        match arg {
+            Value::Array(_) | Value::Array2(_) => {
+                for v in arg.as_array() {
+                    match v {
+                        Value::Bool(b) => output.push(b),
+                        Value::Num(n) => output.push(n != 0.0),
+                        Value::Date(d) => output.push(date_time_to_excel(d) != 0.0),
+                        Value::Error(e) => return Err(e),
+                        _ => {}
+                    }
+                }
+            },
+            v => output.push(logical_arg(v)?)
+        }
+    }
+    // AND(A1:A3) over blanks and text has nothing to decide on
+    if output.is_empty() {
+        return Err(ExcelError::Value);
+    }
+    Ok(output)
+}
+
+fn fold_logicals(args: Vec<Value>, f: impl Fn(Vec<bool>) -> bool) -> Value {
+    match collect_logicals(&args) {
+        Ok(logicals) => Value::from(f(logicals)),
+        Err(e) => Value::Error(e)
+    }
+}
+
+#[function("AND")]
+fn andfunc(args: Vec<Value>) -> Value {
+    fold_logicals(args, |l| l.into_iter().all(|b| b))
+}
+
+#[function("OR")]
+fn orfunc(args: Vec<Value>) -> Value {
+    fold_logicals(args, |l| l.into_iter().any(|b| b))
+}
+
+// TRUE when an odd number of the arguments are.
+#[function]
+fn xor(args: Vec<Value>) -> Value {
+    fold_logicals(args, |l| l.into_iter().filter(|b| *b).count() % 2 == 1)
+}
+
+#[function("NOT")]
+fn notfunc(logical: Value) -> Value {
+    broadcast(vec![logical], |v| match logical_arg(&v[0]) {
+        Ok(b) => Value::from(!b),
+        Err(e) => Value::Error(e)
+    })
+}
+
+// Logicals are not numbers here, ISEVEN(TRUE) is #VALUE!
+fn parity(number: Value, even: bool) -> Value {
+    broadcast(vec![number], |v| match &v[0] {
+        Value::Bool(_) => Value::Error(ExcelError::Value),
+        v => match number_arg(v) {
+            Ok(n) => Value::from((n.trunc() % 2.0 == 0.0) == even),
+            Err(e) => Value::Error(e)
+        }
+    })
+}
+
+#[function]
+fn iseven(number: Value) -> Value {
+    parity(number, true)
+}
+
+#[function]
+fn isodd(number: Value) -> Value {
+    parity(number, false)
+}
+
+// First cell of a range, like N(A1:A3)
+fn first(v: Value) -> Value {
+    match v {
+        Value::Array(_) | Value::Array2(_) => v.ensure_single(),
+        v => v
+    }
+}
+
+#[function]
+fn n(value: Value) -> Value {
+    match first(value) {
+        Value::Num(n) => Value::from(n),
+        Value::Date(d) => Value::from(date_time_to_excel(d)),
+        Value::Bool(b) => Value::from(if b { 1.0 } else { 0.0 }),
+        Value::Error(e) => Value::Error(e),
+        _ => Value::from(0.0)
+    }
+}
+
+#[function]
+fn t(value: Value) -> Value {
+    match first(value) {
+        Value::Text(t) => Value::Text(t),
+        Value::Error(e) => Value::Error(e),
+        _ => Value::from("")
+    }
+}
+
+pub const ERROR_AWARE_FUNCTIONS: [&str; 13] = ["IFS", "SWITCH", "IFNA", "ISBLANK", "ISNUMBER", "ISTEXT", "ISNONTEXT", "ISLOGICAL", "ISERROR", "ISERR", "ISNA", "TYPE", "ERROR.TYPE"];
+
+/*
+ * Functions that look at errors instead of passing them on: the IS* family,
+ * TYPE, ERROR.TYPE, IFNA and the IF chains. Arrays are checked element by
+ * element, a single cell is a scalar.
+ */
+pub fn evaluate(name: &str, args: Vec<Value>) -> Value {
+    match name {
+        "IFS" => ifs(args),
+        "SWITCH" => switch(args),
+        "IFNA" => match <[Value; 2]>::try_from(args) {
+            Ok([value, value_if_na]) => map_values(value, |v| match v {
+                Value::Error(ExcelError::NA) => value_if_na.ensure_single(),
+                v => v.clone()
+            }),
+            Err(_) => Value::Error(ExcelError::Value)
+        },
+        _ => match <[Value; 1]>::try_from(args) {
+            Ok([value]) => match name {
+                // Array arguments are a single value to TYPE
+                "TYPE" => Value::from(type_of(&value)),
+                _ => map_values(value, |v| info(name, v))
+            },
+            Err(_) => Value::Error(ExcelError::Value)
+        }
+    }
+}
+
+fn map_values(value: Value, f: impl Fn(&Value) -> Value) -> Value {
+    match value {
+        Value::Array2(arr2) if arr2.len() == 1 => f(&arr2[[0, 0]]),
+        Value::Array2(arr2) => Value::from(arr2.map(f)),
+        Value::Array(arr) => Value::from(arr.iter().map(f).collect::<Vec<Value>>()),
+        v => f(&v)
+    }
+}
+
+fn info(name: &str, v: &Value) -> Value {
+    match name {
+        "ISBLANK" => Value::from(v.is_empty()),
+        "ISNUMBER" => Value::from(v.is_num() || v.is_date()),
+        "ISTEXT" => Value::from(v.is_text()),
+        "ISNONTEXT" => Value::from(!v.is_text()),
+        "ISLOGICAL" => Value::from(v.is_bool()),
+        "ISERROR" => Value::from(v.is_err()),
+        "ISERR" => Value::from(v.is_err() && *v != Value::Error(ExcelError::NA)),
+        "ISNA" => Value::from(*v == Value::Error(ExcelError::NA)),
+        "ERROR.TYPE" => match v {
+            Value::Error(e) => Value::from(error_number(e)),
+            _ => Value::Error(ExcelError::NA)
+        },
+        _ => unreachable!("{} is not an information function", name)
+    }
+}
+
+fn type_of(v: &Value) -> f64 {
+    match v {
+        Value::Array2(arr2) if arr2.len() == 1 => type_of(&arr2[[0, 0]]),
+        Value::Array(_) | Value::Array2(_) => 64.0,
+        Value::Text(_) => 2.0,
+        Value::Bool(_) => 4.0,
+        Value::Error(_) => 16.0,
+        _ => 1.0
+    }
+}
+
+fn error_number(e: &ExcelError) -> f64 {
+    match e {
+        ExcelError::Null => 1.0,
+        ExcelError::Div => 2.0,
+        ExcelError::Value => 3.0,
+        ExcelError::Ref => 4.0,
+        ExcelError::Name => 5.0,
+        ExcelError::Num => 6.0,
+        ExcelError::NA => 7.0,
+        ExcelError::GettingData => 8.0,
+        ExcelError::Spill => 9.0,
+        ExcelError::Calc => 14.0,
+    }
+}
+
+// IFS(condition1, value1, ...), #N/A when no condition holds.
+fn ifs(args: Vec<Value>) -> Value {
+    if args.is_empty() || args.len() % 2 != 0 {
+        return Value::Error(ExcelError::Value);
+    }
+    for pair in args.chunks(2) {
+        match logical_arg(&pair[0]) {
+            Ok(true) => return pair[1].clone(),
+            Ok(false) => {},
+            Err(e) => return Value::Error(e)
+        }
+    }
+    Value::Error(ExcelError::NA)
+}
+
+// SWITCH(expression, value1, result1, ..., [default])
+fn switch(args: Vec<Value>) -> Value {
+    if args.len() < 3 {
+        return Value::Error(ExcelError::Value);
+    }
+    let expression = args[0].ensure_single();
+    if expression.is_err() {
+        return expression;
+    }
+    let cases = &args[1..];
+    for pair in cases.chunks_exact(2) {
+        let value = pair[0].ensure_single();
+        if value.is_err() {
+            return value;
+        }
+        if compare(&expression, &value) == Some(Ordering::Equal) {
+            return pair[1].clone();
+        }
+    }
+    match cases.len() % 2 {
+        1 => cases[cases.len() - 1].clone(),
+        _ => Value::Error(ExcelError::NA)
+    }
+}
+
+// The cell a reference argument points at, with its formula if it has one.
+fn referenced_value(expr: Expr, book: &Book) -> Result<Value, ExcelError> {
+    match expr {
+        Expr::Reference { sheet, reference } => {
+            let sheet_idx = match sheet {
+                Some(s) if split_external_sheet(&s).is_some() => return Err(ExcelError::NA),
+                Some(s) => book.sheets.iter().position(|x| x.name.eq_ignore_ascii_case(&s)).ok_or(ExcelError::Ref)?,
+                None => book.current_sheet
+            };
+            let (row, column, _, _) = Reference::from(reference).get_dimensions();
+            Ok(match book.get_sheet_by_idx(sheet_idx).values.get((row.max(1) - 1, column.max(1) - 1)) {
+                Some(v) => v.value.clone(),
+                None => Value::Empty
+            })
+        },
+        Expr::Error(e) => Err(e),
+        _ => Err(ExcelError::Value)
+    }
+}
+
+/*
+ * ISREF, ISFORMULA and FORMULATEXT look at the reference itself rather
+ * than the value in it, so they get the unevaluated arguments.
+ */
+pub fn formula_info(name: &str, args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
+    let arg = match <[Expr; 1]>::try_from(args) {
+        Ok([arg]) => arg,
+        Err(_) => return Ok(Value::Error(ExcelError::Value))
+    };
+    let value = match (name, arg) {
+        ("ISREF", Expr::Reference { .. }) => Value::from(true),
+        ("ISREF", Expr::Func { name, args }) if matches!(name.as_str(), "OFFSET" | "INDIRECT") => {
+            let target = evaluate_expr_with_context(Expr::Func { name, args }, book, debug)?;
+            Value::from(!target.is_err())
+        },
+        ("ISREF", _) => Value::from(false),
+        ("ISFORMULA", arg) => match referenced_value(arg, book) {
+            Ok(v) => Value::from(v.is_formula()),
+            Err(e) => Value::Error(e)
+        },
+        ("FORMULATEXT", arg) => match referenced_value(arg, book) {
+            Ok(Value::Formula(f)) => Value::Text(f),
+            Ok(_) => Value::Error(ExcelError::NA),
+            Err(e) => Value::Error(e)
+        },
+        _ => unreachable!("{} does not take references", name)
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        parser::ast::Error as ExcelError,
+        workbook::Book,
+        errors::Error,
+    };
+
+    #[test]
+    fn test_logical() -> Result<(), Error> {
+        assert_eq!(evaluate_str("AND(TRUE, 1, 2 > 1)")?, Value::from(true));
+        assert_eq!(evaluate_str("AND(TRUE, 0)")?, Value::from(false));
+        assert_eq!(evaluate_str("AND({TRUE, \"x\", 1})")?, Value::from(true));
+        assert_eq!(evaluate_str("AND(\"true\", TRUE)")?, Value::from(true));
+        assert_eq!(evaluate_str("AND(\"x\")")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("AND({\"x\"})")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("OR(FALSE, 0, 3)")?, Value::from(true));
+        assert_eq!(evaluate_str("OR(FALSE, #DIV/0!)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("XOR(TRUE, TRUE, TRUE)")?, Value::from(true));
+        assert_eq!(evaluate_str("XOR(TRUE, 1)")?, Value::from(false));
+        assert_eq!(evaluate_str("NOT(0)")?, Value::from(true));
+        assert_eq!(evaluate_str("NOT({TRUE, FALSE})")?, Value::from(vec![Value::from(false), Value::from(true)]));
+        assert_eq!(evaluate_str("NOT(\"abc\")")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_conditionals() -> Result<(), Error> {
+        assert_eq!(evaluate_str("IFS(1 > 2, \"a\", 2 > 1, \"b\")")?, Value::from("b"));
+        assert_eq!(evaluate_str("IFS(FALSE, 1)")?, Value::Error(ExcelError::NA));
+        assert_eq!(evaluate_str("IFS(TRUE, 1, #DIV/0!, 2)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("IFS(#DIV/0!, 1, TRUE, 2)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("SWITCH(2, 1, \"one\", 2, \"two\")")?, Value::from("two"));
+        assert_eq!(evaluate_str("SWITCH(\"B\", \"a\", 1, \"b\", 2)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("SWITCH(3, 1, \"one\", \"other\")")?, Value::from("other"));
+        assert_eq!(evaluate_str("SWITCH(3, 1, \"one\")")?, Value::Error(ExcelError::NA));
+        assert_eq!(evaluate_str("IFNA(NA(), 0)")?, Value::from(0.0));
+        assert_eq!(evaluate_str("IFNA(#DIV/0!, 0)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("IFNA(5, 0)")?, Value::from(5.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_information() -> Result<(), Error> {
+        assert_eq!(evaluate_str("ISNUMBER(1)")?, Value::from(true));
+        assert_eq!(evaluate_str("ISNUMBER(\"1\")")?, Value::from(false));
+        assert_eq!(evaluate_str("ISNUMBER(DATE(2024, 1, 1))")?, Value::from(true));
+        assert_eq!(evaluate_str("ISTEXT(\"a\")")?, Value::from(true));
+        assert_eq!(evaluate_str("ISNONTEXT(1)")?, Value::from(true));
+        assert_eq!(evaluate_str("ISLOGICAL(FALSE)")?, Value::from(true));
+        assert_eq!(evaluate_str("ISERROR(#DIV/0!)")?, Value::from(true));
+        assert_eq!(evaluate_str("ISERR(NA())")?, Value::from(false));
+        assert_eq!(evaluate_str("ISERR(#REF!)")?, Value::from(true));
+        assert_eq!(evaluate_str("ISNA(NA())")?, Value::from(true));
+        assert_eq!(evaluate_str("ISERROR({1, #N/A})")?, Value::from(vec![Value::from(false), Value::from(true)]));
+        assert_eq!(evaluate_str("ISEVEN(-2.5)")?, Value::from(true));
+        assert_eq!(evaluate_str("ISODD(3)")?, Value::from(true));
+        assert_eq!(evaluate_str("ISEVEN(TRUE)")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("ISODD(\"x\")")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("N(TRUE)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("N(\"7\")")?, Value::from(0.0));
+        assert_eq!(evaluate_str("N(DATE(1900, 1, 2))")?, Value::from(2.0));
+        assert_eq!(evaluate_str("T(\"a\")")?, Value::from("a"));
+        assert_eq!(evaluate_str("T(1)")?, Value::from(""));
+        assert_eq!(evaluate_str("TYPE(1)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("TYPE(\"a\")")?, Value::from(2.0));
+        assert_eq!(evaluate_str("TYPE(TRUE)")?, Value::from(4.0));
+        assert_eq!(evaluate_str("TYPE(#DIV/0!)")?, Value::from(16.0));
+        assert_eq!(evaluate_str("TYPE({1, 2})")?, Value::from(64.0));
+        assert_eq!(evaluate_str("ERROR.TYPE(#DIV/0!)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("ERROR.TYPE(NA())")?, Value::from(7.0));
+        assert_eq!(evaluate_str("ERROR.TYPE(1)")?, Value::Error(ExcelError::NA));
+        Ok(())
+    }
+
+    #[test]
+    fn test_references() -> Result<(), Error> {
+        let mut book = Book::from("assets/logical.xlsx");
+        book.load(false).unwrap();
+        book.calculate(false, false)?;
+        let cell = |r: &str| book.resolve_str_ref(r).unwrap()[[0, 0]].clone();
+        assert_eq!(cell("Sheet1!B1"), Value::from(true)); // ISBLANK(A1)
+        assert_eq!(cell("Sheet1!B2"), Value::from(false)); // ISBLANK(A2), A2 is ""
+        assert_eq!(cell("Sheet1!B3"), Value::from(false)); // ISBLANK(A3), A3 is =A1
+        assert_eq!(cell("Sheet1!B4"), Value::from(true)); // ISREF(A1)
+        assert_eq!(cell("Sheet1!B5"), Value::from(false)); // ISREF(1)
+        assert_eq!(cell("Sheet1!B6"), Value::from(true)); // ISFORMULA(A3)
+        assert_eq!(cell("Sheet1!B7"), Value::from(false)); // ISFORMULA(A4)
+        assert_eq!(cell("Sheet1!B8"), Value::from("=A1")); // FORMULATEXT(A3)
+        assert_eq!(cell("Sheet1!B9"), Value::Error(ExcelError::NA)); // FORMULATEXT(A4)
+        assert_eq!(cell("Sheet1!B10"), Value::from(false)); // AND(A4:A5)
+        assert_eq!(cell("Sheet1!B11"), Value::from(true)); // OR(A1:A5)
+        assert_eq!(cell("Sheet1!B12"), Value::from(1.0)); // TYPE(A1)
+        Ok(())
+    }
+}
@@ -0,0 +1,400 @@
+use std::cmp::Ordering;
+use ndarray::{Array2, Axis};
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    utils::date_time_to_excel,
+    function::Function,
+};
+use excel_emulator_macro::function;
+
+/*
+ * Lookups compare values the way Excel sorts them: numbers and dates before text
+ * before logicals, with text compared case-insensitively. Values of different
+ * kinds (and blanks) are never equal, smaller or larger than each other.
+ */
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Text(x), Value::Text(y)) => Some(x.to_lowercase().cmp(&y.to_lowercase())),
+        (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+        (Value::Num(_) | Value::Date(_), Value::Num(_) | Value::Date(_)) => as_number(a).partial_cmp(&as_number(b)),
+        _ => None
+    }
+}
+
+fn as_number(v: &Value) -> f64 {
+    match v {
+        Value::Date(d) => date_time_to_excel(*d),
+        v => v.as_num()
+    }
+}
+
+// * matches any run of characters, ? any single character and ~ escapes the next one.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None; // Last * and where its run ends
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            star = Some((p, t));
+            p += 1;
+            continue;
+        }
+        let (c, width) = match pattern.get(p) {
+            Some('~') if p + 1 < pattern.len() => (Some(pattern[p + 1]), 2),
+            c => (c.copied(), 1)
+        };
+        if c.is_some_and(|c| (c == '?' && width == 1) || c == text[t]) {
+            p += width;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn is_match(lookup: &Value, v: &Value, wildcards: bool) -> bool {
+    match (lookup, v) {
+        (Value::Text(pattern), Value::Text(text)) if wildcards => wildcard_match(pattern, text),
+        _ => compare(lookup, v) == Some(Ordering::Equal)
+    }
+}
+
+// Largest value not above lookup in a list sorted ascending, like VLOOKUP(.., TRUE).
+fn approximate(lookup: &Value, values: &[Value]) -> Option<usize> {
+    let mut found = None;
+    for (i, v) in values.iter().enumerate() {
+        match compare(v, lookup) {
+            Some(Ordering::Greater) => break,
+            Some(_) => found = Some(i),
+            None => {}
+        }
+    }
+    found
+}
+
+fn find(lookup: &Value, values: &[Value], approximate_match: bool) -> Option<usize> {
+    if approximate_match {
+        approximate(lookup, values)
+    } else {
+        values.iter().position(|v| is_match(lookup, v, true))
+    }
+}
+
+/*
+ * Position used by XMATCH and XLOOKUP.
+ * match_mode: 0 exact, -1 exact or next smaller, 1 exact or next larger, 2 wildcard.
+ * search_mode: 1 first-to-last, -1 last-to-first, 2 binary ascending, -2 binary descending.
+ */
+pub fn xmatch_position(lookup: &Value, values: &[Value], match_mode: i32, search_mode: i32) -> Option<usize> {
+    if search_mode.abs() == 2 {
+        return binary_search(lookup, values, match_mode, search_mode > 0);
+    }
+    let order: Vec<usize> = if search_mode < 0 {
+        (0..values.len()).rev().collect()
+    } else {
+        (0..values.len()).collect()
+    };
+    let wanted = match match_mode {
+        -1 => Some(Ordering::Less),
+        1 => Some(Ordering::Greater),
+        _ => None
+    };
+    let mut best: Option<usize> = None;
+    for i in order {
+        let v = &values[i];
+        if is_match(lookup, v, match_mode == 2) {
+            return Some(i);
+        }
+        // Closest value on the wanted side, the first one found wins ties
+        if let Some(wanted) = wanted {
+            if compare(v, lookup) == Some(wanted) && best.map_or(true, |b| compare(&values[b], v) == Some(wanted)) {
+                best = Some(i);
+            }
+        }
+    }
+    best
+}
+
+fn binary_search(lookup: &Value, values: &[Value], match_mode: i32, ascending: bool) -> Option<usize> {
+    // First position that sorts after lookup
+    let (mut lo, mut hi) = (0, values.len());
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let ord = compare(&values[mid], lookup).map(|o| if ascending { o } else { o.reverse() });
+        match ord {
+            Some(Ordering::Greater) => hi = mid,
+            _ => lo = mid + 1
+        }
+    }
+    if lo > 0 && compare(&values[lo - 1], lookup) == Some(Ordering::Equal) {
+        return Some(lo - 1);
+    }
+    let (smaller, larger) = if ascending {
+        (lo.checked_sub(1), Some(lo))
+    } else {
+        (Some(lo), lo.checked_sub(1))
+    };
+    let candidate = match match_mode {
+        -1 => smaller,
+        1 => larger,
+        _ => None
+    };
+    candidate.filter(|&i| i < values.len() && compare(&values[i], lookup).is_some())
+}
+
+// Ranges arrive as Array2, array constants as a single row.
+pub fn as_table(v: &Value) -> Array2<Value> {
+    match v {
+        Value::Array2(arr2) => arr2.clone(),
+        Value::Array(arr) => Array2::from_shape_vec((1, arr.len()), arr.clone()).unwrap(),
+        v => Array2::from_elem((1, 1), v.clone())
+    }
+}
+
+// A blank cell looks up as 0.
+fn found(v: &Value) -> Value {
+    match v {
+        Value::Empty => Value::from(0.0),
+        v => v.clone()
+    }
+}
+
+fn is_true(v: Option<Value>, default: bool) -> bool {
+    match v.map(|v| v.ensure_single()) {
+        Some(Value::Bool(b)) => b,
+        Some(Value::Num(n)) => n != 0.0,
+        Some(_) => false,
+        None => default
+    }
+}
+
+fn as_mode(v: Option<Value>, default: i32) -> i32 {
+    v.map_or(default, |v| v.ensure_single().as_num() as i32)
+}
+
+#[function]
+fn vlookup(lookup_value: Value, table_array: Value, col_index_num: Value, range_lookup: Option<Value>) -> Value {
+    let table = as_table(&table_array);
+    let column = col_index_num.ensure_single().as_num();
+    if column < 1.0 {
+        return Value::Error(ExcelError::Value);
+    } else if column as usize > table.ncols() {
+        return Value::Error(ExcelError::Ref);
+    }
+    let keys: Vec<Value> = table.column(0).to_vec();
+    match find(&lookup_value.ensure_single(), &keys, is_true(range_lookup, true)) {
+        Some(row) => found(&table[[row, column as usize - 1]]),
+        None => Value::Error(ExcelError::NA)
+    }
+}
+
+#[function]
+fn hlookup(lookup_value: Value, table_array: Value, row_index_num: Value, range_lookup: Option<Value>) -> Value {
+    let table = as_table(&table_array);
+    let row = row_index_num.ensure_single().as_num();
+    if row < 1.0 {
+        return Value::Error(ExcelError::Value);
+    } else if row as usize > table.nrows() {
+        return Value::Error(ExcelError::Ref);
+    }
+    let keys: Vec<Value> = table.row(0).to_vec();
+    match find(&lookup_value.ensure_single(), &keys, is_true(range_lookup, true)) {
+        Some(column) => found(&table[[row as usize - 1, column]]),
+        None => Value::Error(ExcelError::NA)
+    }
+}
+
+/*
+ * Vector form LOOKUP(value, lookup_vector, result_vector), or array form
+ * LOOKUP(value, array) which searches the first row or column, whichever is longer,
+ * and returns from the last one.
+ */
+#[function]
+fn lookup(lookup_value: Value, lookup_vector: Value, result_vector: Option<Value>) -> Value {
+    let table = as_table(&lookup_vector);
+    let (keys, results): (Vec<Value>, Vec<Value>) = match result_vector {
+        Some(r) => (table.iter().cloned().collect(), as_table(&r).iter().cloned().collect()),
+        None if table.ncols() > table.nrows() => (table.row(0).to_vec(), table.row(table.nrows() - 1).to_vec()),
+        None => (table.column(0).to_vec(), table.column(table.ncols() - 1).to_vec())
+    };
+    match approximate(&lookup_value.ensure_single(), &keys).and_then(|i| results.get(i)) {
+        Some(v) => found(v),
+        None => Value::Error(ExcelError::NA)
+    }
+}
+
+#[function]
+fn xlookup(lookup_value: Value, lookup_array: Value, return_array: Value, if_not_found: Option<Value>, match_mode: Option<Value>, search_mode: Option<Value>) -> Value {
+    let keys = as_table(&lookup_array);
+    let returns = as_table(&return_array);
+    let (match_mode, search_mode) = (as_mode(match_mode, 0), as_mode(search_mode, 1));
+    let vertical = keys.ncols() == 1;
+    if !(-1..=2).contains(&match_mode) || ![-2, -1, 1, 2].contains(&search_mode)
+        || (!vertical && keys.nrows() != 1)
+        || (vertical && returns.nrows() != keys.nrows())
+        || (!vertical && returns.ncols() != keys.ncols()) {
+        return Value::Error(ExcelError::Value);
+    }
+    let values: Vec<Value> = keys.iter().cloned().collect();
+    match xmatch_position(&lookup_value.ensure_single(), &values, match_mode, search_mode) {
+        Some(i) => {
+            // A whole row (or column) of return_array comes back when it is wider than one cell
+            let result = if vertical { returns.row(i) } else { returns.column(i) };
+            if result.len() == 1 {
+                found(&result[0])
+            } else if vertical {
+                Value::from(result.to_owned().insert_axis(Axis(0)).mapv(|v| found(&v)))
+            } else {
+                Value::from(result.to_owned().insert_axis(Axis(1)).mapv(|v| found(&v)))
+            }
+        },
+        None => if_not_found.unwrap_or(Value::Error(ExcelError::NA))
+    }
+}
+
+#[function]
+fn xmatch(lookup_value: Value, lookup_array: Value, match_mode: Option<Value>, search_mode: Option<Value>) -> Value {
+    let keys = as_table(&lookup_array);
+    let (match_mode, search_mode) = (as_mode(match_mode, 0), as_mode(search_mode, 1));
+    if !(-1..=2).contains(&match_mode) || ![-2, -1, 1, 2].contains(&search_mode) || (keys.nrows() != 1 && keys.ncols() != 1) {
+        return Value::Error(ExcelError::Value);
+    }
+    let values: Vec<Value> = keys.iter().cloned().collect();
+    match xmatch_position(&lookup_value.ensure_single(), &values, match_mode, search_mode) {
+        Some(i) => Value::from(i + 1),
+        None => Value::Error(ExcelError::NA)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        function::{Function, lookup::*},
+        parser::ast::Error as ExcelError,
+        workbook::Book,
+        errors::Error,
+    };
+    use ndarray::arr2;
+
+    fn prices() -> Value {
+        Value::from(arr2(&[
+            [Value::from(1.0), Value::from("Apple"), Value::from(0.5)],
+            [Value::from(5.0), Value::from("Banana"), Value::from(0.25)],
+            [Value::from(10.0), Value::from("Cherry"), Value::Empty],
+            [Value::from(20.0), Value::from("Date"), Value::from(3.0)],
+        ]))
+    }
+
+    fn vlookup(value: Value, column: f64, approximate: bool) -> Value {
+        Vlookup::from(vec![value, prices(), Value::from(column), Value::from(approximate)]).evaluate()
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("a*", "Apple"));
+        assert!(wildcard_match("?pple", "apple"));
+        assert!(wildcard_match("*an*", "Banana"));
+        assert!(wildcard_match("a~*b", "A*B"));
+        assert!(!wildcard_match("a~*b", "AxB"));
+        assert!(!wildcard_match("a?", "Apple"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn test_vlookup() {
+        assert_eq!(vlookup(Value::from(5.0), 2.0, false), Value::from("Banana"));
+        assert_eq!(vlookup(Value::from(7.0), 2.0, false), Value::Error(ExcelError::NA));
+        assert_eq!(vlookup(Value::from(7.0), 2.0, true), Value::from("Banana"));
+        assert_eq!(vlookup(Value::from(100.0), 3.0, true), Value::from(3.0));
+        assert_eq!(vlookup(Value::from(0.0), 2.0, true), Value::Error(ExcelError::NA));
+        assert_eq!(vlookup(Value::from(10.0), 3.0, false), Value::from(0.0));
+        assert_eq!(vlookup(Value::from(5.0), 4.0, false), Value::Error(ExcelError::Ref));
+        assert_eq!(vlookup(Value::from(5.0), 0.0, false), Value::Error(ExcelError::Value));
+        let by_name = Vlookup::from(vec![Value::from("b*"), Value::from(prices().as_array2().slice(ndarray::s![.., 1..]).to_owned()), Value::from(2.0), Value::from(false)]).evaluate();
+        assert_eq!(by_name, Value::from(0.25));
+    }
+
+    #[test]
+    fn test_hlookup() {
+        let table = Value::from(arr2(&[
+            [Value::from("Axles"), Value::from("Bearings"), Value::from("Bolts")],
+            [Value::from(4.0), Value::from(4.0), Value::from(9.0)],
+            [Value::from(5.0), Value::from(7.0), Value::from(10.0)],
+        ]));
+        assert_eq!(Hlookup::from(vec![Value::from("bearings"), table.clone(), Value::from(3.0), Value::from(false)]).evaluate(), Value::from(7.0));
+        assert_eq!(Hlookup::from(vec![Value::from("B"), table.clone(), Value::from(2.0)]).evaluate(), Value::from(4.0));
+        assert_eq!(Hlookup::from(vec![Value::from("Nuts"), table, Value::from(2.0), Value::from(false)]).evaluate(), Value::Error(ExcelError::NA));
+    }
+
+    #[test]
+    fn test_lookup() -> Result<(), Error> {
+        assert_eq!(evaluate_str("LOOKUP(4.19, {4.14, 4.19, 5.17}, {\"red\", \"orange\", \"yellow\"})")?, Value::from("orange"));
+        assert_eq!(evaluate_str("LOOKUP(5, {4.14, 4.19, 5.17}, {\"red\", \"orange\", \"yellow\"})")?, Value::from("orange"));
+        assert_eq!(evaluate_str("LOOKUP(0, {4.14, 4.19, 5.17}, {\"red\", \"orange\", \"yellow\"})")?, Value::Error(ExcelError::NA));
+        // Array form searches the first column of a tall array and returns from the last
+        assert_eq!(Lookup::from(vec![Value::from(12.0), prices()]).evaluate(), Value::from(0.0));
+        assert_eq!(Lookup::from(vec![Value::from(25.0), prices()]).evaluate(), Value::from(3.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_xlookup() -> Result<(), Error> {
+        let keys = Value::from(prices().as_array2().column(0).to_owned().insert_axis(ndarray::Axis(1)));
+        let xlookup = |value: f64, modes: Vec<f64>| {
+            let mut args = vec![Value::from(value), keys.clone(), prices(), Value::from("none")];
+            args.extend(modes.into_iter().map(Value::from));
+            Xlookup::from(args).evaluate()
+        };
+        assert_eq!(xlookup(5.0, vec![]), Value::from(arr2(&[[Value::from(5.0), Value::from("Banana"), Value::from(0.25)]])));
+        assert_eq!(xlookup(7.0, vec![]), Value::from("none"));
+        assert_eq!(xlookup(7.0, vec![-1.0]).as_array2()[[0, 1]], Value::from("Banana"));
+        assert_eq!(xlookup(7.0, vec![1.0]).as_array2()[[0, 1]], Value::from("Cherry"));
+        assert_eq!(xlookup(7.0, vec![1.0, 2.0]).as_array2()[[0, 1]], Value::from("Cherry"));
+        assert_eq!(xlookup(25.0, vec![1.0, 2.0]), Value::from("none"));
+        assert_eq!(xlookup(7.0, vec![3.0]), Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("XLOOKUP(\"b*\", {\"apple\", \"banana\", \"blueberry\"}, {1, 2, 3}, 0, 2, -1)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("XLOOKUP(\"kiwi\", {\"apple\", \"banana\"}, {1, 2})")?, Value::Error(ExcelError::NA));
+        // Left out arguments take their defaults
+        assert_eq!(evaluate_str("XLOOKUP(3, {1, 2, 3}, {10, 20, 30},, 0, 2)")?, Value::from(30.0));
+        assert_eq!(evaluate_str("XLOOKUP(4, {1, 2, 3}, {10, 20, 30},, 0, 2)")?, Value::Error(ExcelError::NA));
+        assert_eq!(evaluate_str("XLOOKUP(4, {1, 2, 3}, {10, 20, 30},, -1)")?, Value::from(30.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_xmatch() -> Result<(), Error> {
+        assert_eq!(evaluate_str("XMATCH(\"b\", {\"a\", \"b\", \"c\"})")?, Value::from(2.0));
+        assert_eq!(evaluate_str("XMATCH(3, {1, 3, 3, 5}, 0, -1)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("XMATCH(4, {1, 3, 5, 7}, -1)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("XMATCH(4, {1, 3, 5, 7}, 1)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("XMATCH(4, {1, 3, 5, 7}, -1, 2)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("XMATCH(4, {7, 5, 3, 1}, 1, -2)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("XMATCH(4, {7, 5, 3, 1}, -1, -2)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("XMATCH(5, {1, 3, 5, 7}, 0, 2)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("XMATCH(\"?an*\", {\"apple\", \"banana\"}, 2)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("XMATCH(9, {1, 3, 5, 7})")?, Value::Error(ExcelError::NA));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_workbook() -> Result<(), Error> {
+        let mut book = Book::from("assets/lookups.xlsx");
+        book.load(false).unwrap();
+        book.calculate(false, false)?;
+        assert_eq!(book.resolve_str_ref("Sheet1!B1")?[[0, 0]], Value::from("Banana"));
+        assert_eq!(book.resolve_str_ref("Sheet1!B2")?[[0, 0]], Value::from(0.25));
+        assert_eq!(book.resolve_str_ref("Sheet1!B3")?[[0, 0]], Value::from(3.0));
+        assert_eq!(book.resolve_str_ref("Sheet1!B4")?[[0, 0]], Value::from("Cherry"));
+        assert_eq!(book.resolve_str_ref("Sheet1!B5")?[[0, 0]], Value::from(4.0));
+        assert_eq!(book.resolve_str_ref("Sheet1!B6")?[[0, 0]], Value::Error(ExcelError::NA));
+        Ok(())
+    }
+}
@@ -0,0 +1,759 @@
+use ndarray::Array2;
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    function::{Function, broadcast, number_arg, collect_numbers},
+};
+use excel_emulator_macro::function;
+
+// SIN, TAN and friends give #NUM! from 2^27 on.
+const TRIG_LIMIT: f64 = 134217728.0;
+
+// x cut to the 15 significant digits Excel works with, so 2.675 * 100 is 267.5 and not 267.49999999999997.
+pub fn significant(x: f64) -> f64 {
+    if x == 0.0 || !x.is_finite() {
+        x
+    } else {
+        format!("{:.14e}", x).parse::<f64>().unwrap_or(x)
+    }
+}
+
+/*
+ * Rounds x to num_digits decimals (tens, hundreds... when negative) with
+ * f applied to the shifted value: f64::round for half away from zero,
+ * f64::trunc toward zero, or away_from_zero.
+ */
+pub fn round_with(x: f64, num_digits: f64, f: fn(f64) -> f64) -> f64 {
+    let digits = num_digits.trunc().clamp(-308.0, 308.0) as i32;
+    let scale = 10f64.powi(digits.abs());
+    let shifted = significant(if digits >= 0 { x * scale } else { x / scale });
+    if !shifted.is_finite() {
+        return x;
+    }
+    let rounded = f(shifted);
+    significant(if digits >= 0 { rounded / scale } else { rounded * scale })
+}
+
+pub fn away_from_zero(x: f64) -> f64 {
+    if x < 0.0 { x.floor() } else { x.ceil() }
+}
+
+// Excel has no infinities or NaN, they are #NUM!
+fn checked(x: f64) -> Value {
+    if x.is_finite() {
+        Value::from(x)
+    } else {
+        Value::Error(ExcelError::Num)
+    }
+}
+
+// Applies f to the number in each element.
+fn unary(x: Value, f: impl Fn(f64) -> Value) -> Value {
+    broadcast(vec![x], |a| f(tryv!(number_arg(&a[0]))))
+}
+
+fn binary(x: Value, y: Value, f: impl Fn(f64, f64) -> Value) -> Value {
+    broadcast(vec![x, y], |a| f(tryv!(number_arg(&a[0])), tryv!(number_arg(&a[1]))))
+}
+
+#[function]
+fn round(number: Value, num_digits: Value) -> Value {
+    binary(number, num_digits, |x, d| Value::from(round_with(x, d, f64::round)))
+}
+
+#[function]
+fn roundup(number: Value, num_digits: Value) -> Value {
+    binary(number, num_digits, |x, d| Value::from(round_with(x, d, away_from_zero)))
+}
+
+#[function]
+fn rounddown(number: Value, num_digits: Value) -> Value {
+    binary(number, num_digits, |x, d| Value::from(round_with(x, d, f64::trunc)))
+}
+
+#[function]
+fn trunc(number: Value, num_digits: Option<Value>) -> Value {
+    binary(number, num_digits.unwrap_or(Value::from(0.0)), |x, d| Value::from(round_with(x, d, f64::trunc)))
+}
+
+#[function]
+fn int(number: Value) -> Value {
+    unary(number, |x| Value::from(significant(x).floor()))
+}
+
+#[function]
+fn mround(number: Value, multiple: Value) -> Value {
+    binary(number, multiple, |x, m| {
+        if m == 0.0 {
+            Value::from(0.0)
+        } else if x * m < 0.0 {
+            Value::Error(ExcelError::Num)
+        } else {
+            Value::from(significant(significant(x / m).round() * m))
+        }
+    })
+}
+
+// Rounds up to a multiple of significance; negative numbers round toward zero unless significance is negative too.
+#[function]
+fn ceiling(number: Value, significance: Option<Value>) -> Value {
+    binary(number, significance.unwrap_or(Value::from(1.0)), |x, s| {
+        if s == 0.0 {
+            Value::from(0.0)
+        } else if x > 0.0 && s < 0.0 {
+            Value::Error(ExcelError::Num)
+        } else {
+            Value::from(significant(significant(x / s).ceil() * s))
+        }
+    })
+}
+
+#[function]
+fn floor(number: Value, significance: Option<Value>) -> Value {
+    binary(number, significance.unwrap_or(Value::from(1.0)), |x, s| {
+        if s == 0.0 {
+            if x == 0.0 { Value::from(0.0) } else { Value::Error(ExcelError::Div) }
+        } else if x > 0.0 && s < 0.0 {
+            Value::Error(ExcelError::Num)
+        } else {
+            Value::from(significant(significant(x / s).floor() * s))
+        }
+    })
+}
+
+// A non-zero mode rounds negative numbers away from zero.
+#[function("CEILING.MATH")]
+fn ceilingmath(number: Value, significance: Option<Value>, mode: Option<Value>) -> Value {
+    let args = vec![number, significance.unwrap_or(Value::from(1.0)), mode.unwrap_or(Value::from(0.0))];
+    broadcast(args, |a| {
+        let (x, s, mode) = (tryv!(number_arg(&a[0])), tryv!(number_arg(&a[1])).abs(), tryv!(number_arg(&a[2])));
+        if s == 0.0 {
+            return Value::from(0.0);
+        }
+        let q = significant(x / s);
+        let rounded = if x < 0.0 && mode != 0.0 { q.floor() } else { q.ceil() };
+        Value::from(significant(rounded * s))
+    })
+}
+
+// A non-zero mode rounds negative numbers toward zero.
+#[function("FLOOR.MATH")]
+fn floormath(number: Value, significance: Option<Value>, mode: Option<Value>) -> Value {
+    let args = vec![number, significance.unwrap_or(Value::from(1.0)), mode.unwrap_or(Value::from(0.0))];
+    broadcast(args, |a| {
+        let (x, s, mode) = (tryv!(number_arg(&a[0])), tryv!(number_arg(&a[1])).abs(), tryv!(number_arg(&a[2])));
+        if s == 0.0 {
+            return Value::from(0.0);
+        }
+        let q = significant(x / s);
+        let rounded = if x < 0.0 && mode != 0.0 { q.ceil() } else { q.floor() };
+        Value::from(significant(rounded * s))
+    })
+}
+
+#[function]
+fn even(number: Value) -> Value {
+    unary(number, |x| {
+        let n = away_from_zero(significant(x) / 2.0) * 2.0;
+        Value::from(if n == 0.0 { 0.0 } else { n })
+    })
+}
+
+#[function]
+fn odd(number: Value) -> Value {
+    unary(number, |x| {
+        let n = away_from_zero(significant(x));
+        let odd = if n % 2.0 == 0.0 { n + if x < 0.0 { -1.0 } else { 1.0 } } else { n };
+        Value::from(odd)
+    })
+}
+
+// The result takes the sign of the divisor, MOD(-3, 2) is 1.
+#[function("MOD")]
+fn modfn(number: Value, divisor: Value) -> Value {
+    binary(number, divisor, |n, d| {
+        if d == 0.0 {
+            Value::Error(ExcelError::Div)
+        } else {
+            Value::from(significant(n - d * significant(n / d).floor()))
+        }
+    })
+}
+
+#[function]
+fn quotient(numerator: Value, denominator: Value) -> Value {
+    binary(numerator, denominator, |n, d| {
+        if d == 0.0 {
+            Value::Error(ExcelError::Div)
+        } else {
+            Value::from(significant(n / d).trunc())
+        }
+    })
+}
+
+#[function]
+fn abs(number: Value) -> Value {
+    unary(number, |x| Value::from(x.abs()))
+}
+
+#[function]
+fn sign(number: Value) -> Value {
+    unary(number, |x| Value::from(if x > 0.0 { 1.0 } else if x < 0.0 { -1.0 } else { 0.0 }))
+}
+
+#[function]
+fn sqrt(number: Value) -> Value {
+    unary(number, |x| if x < 0.0 { Value::Error(ExcelError::Num) } else { Value::from(x.sqrt()) })
+}
+
+#[function]
+fn power(number: Value, power: Value) -> Value {
+    binary(number, power, |x, y| {
+        if x == 0.0 && y == 0.0 {
+            Value::Error(ExcelError::Num)
+        } else if x == 0.0 && y < 0.0 {
+            Value::Error(ExcelError::Div)
+        } else {
+            checked(x.powf(y))
+        }
+    })
+}
+
+#[function]
+fn exp(number: Value) -> Value {
+    unary(number, |x| checked(x.exp()))
+}
+
+#[function]
+fn ln(number: Value) -> Value {
+    unary(number, |x| if x <= 0.0 { Value::Error(ExcelError::Num) } else { Value::from(x.ln()) })
+}
+
+#[function]
+fn log(number: Value, base: Option<Value>) -> Value {
+    binary(number, base.unwrap_or(Value::from(10.0)), |x, b| {
+        if x <= 0.0 || b <= 0.0 {
+            Value::Error(ExcelError::Num)
+        } else if b == 1.0 {
+            Value::Error(ExcelError::Div)
+        } else if b == 10.0 {
+            Value::from(x.log10())
+        } else {
+            Value::from(x.ln() / b.ln())
+        }
+    })
+}
+
+#[function]
+fn log10(number: Value) -> Value {
+    unary(number, |x| if x <= 0.0 { Value::Error(ExcelError::Num) } else { Value::from(x.log10()) })
+}
+
+#[function]
+fn product(args: Vec<Value>) -> Value {
+    let numbers = tryv!(collect_numbers(&args));
+    if numbers.is_empty() {
+        Value::from(0.0)
+    } else {
+        checked(numbers.iter().product())
+    }
+}
+
+fn gcd_pair(mut a: f64, mut b: f64) -> f64 {
+    while b != 0.0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn whole_numbers(args: &[Value]) -> Result<Vec<f64>, ExcelError> {
+    let numbers: Vec<f64> = collect_numbers(args)?.into_iter().map(f64::trunc).collect();
+    if numbers.iter().any(|n| *n < 0.0 || *n >= 2f64.powi(53)) {
+        Err(ExcelError::Num)
+    } else {
+        Ok(numbers)
+    }
+}
+
+#[function]
+fn gcd(args: Vec<Value>) -> Value {
+    let numbers = tryv!(whole_numbers(&args));
+    Value::from(numbers.into_iter().fold(0.0, gcd_pair))
+}
+
+#[function]
+fn lcm(args: Vec<Value>) -> Value {
+    let numbers = tryv!(whole_numbers(&args));
+    if numbers.contains(&0.0) {
+        return Value::from(0.0);
+    }
+    checked(numbers.into_iter().fold(1.0, |a, b| a / gcd_pair(a, b) * b))
+}
+
+#[function]
+fn fact(number: Value) -> Value {
+    unary(number, |x| {
+        if x < 0.0 {
+            Value::Error(ExcelError::Num)
+        } else {
+            checked((1..=x.trunc() as u64).map(|k| k as f64).product())
+        }
+    })
+}
+
+// n! / (n - k)!, the number of ordered selections.
+fn permutations(n: f64, k: f64) -> f64 {
+    (0..k as u64).map(|i| n - i as f64).product()
+}
+
+#[function]
+fn combin(number: Value, number_chosen: Value) -> Value {
+    binary(number, number_chosen, |n, k| {
+        let (n, k) = (n.trunc(), k.trunc());
+        if n < 0.0 || k < 0.0 || n < k {
+            return Value::Error(ExcelError::Num);
+        }
+        let k = k.min(n - k);
+        checked((permutations(n, k) / permutations(k, k)).round())
+    })
+}
+
+#[function]
+fn permut(number: Value, number_chosen: Value) -> Value {
+    binary(number, number_chosen, |n, k| {
+        let (n, k) = (n.trunc(), k.trunc());
+        if n < 0.0 || k < 0.0 || n < k {
+            return Value::Error(ExcelError::Num);
+        }
+        checked(permutations(n, k))
+    })
+}
+
+fn trig(x: Value, f: fn(f64) -> f64) -> Value {
+    unary(x, |x| if x.abs() >= TRIG_LIMIT { Value::Error(ExcelError::Num) } else { checked(f(x)) })
+}
+
+// 1 / f(x), #DIV/0! where f(x) is zero.
+fn reciprocal(x: Value, f: fn(f64) -> f64) -> Value {
+    unary(x, |x| {
+        let y = f(x);
+        if x.abs() >= TRIG_LIMIT {
+            Value::Error(ExcelError::Num)
+        } else if y == 0.0 {
+            Value::Error(ExcelError::Div)
+        } else {
+            Value::from(1.0 / y)
+        }
+    })
+}
+
+#[function]
+fn sin(number: Value) -> Value {
+    trig(number, f64::sin)
+}
+
+#[function]
+fn cos(number: Value) -> Value {
+    trig(number, f64::cos)
+}
+
+#[function]
+fn tan(number: Value) -> Value {
+    trig(number, f64::tan)
+}
+
+#[function]
+fn cot(number: Value) -> Value {
+    reciprocal(number, f64::tan)
+}
+
+#[function]
+fn sec(number: Value) -> Value {
+    reciprocal(number, f64::cos)
+}
+
+#[function]
+fn csc(number: Value) -> Value {
+    reciprocal(number, f64::sin)
+}
+
+#[function]
+fn asin(number: Value) -> Value {
+    unary(number, |x| if x.abs() > 1.0 { Value::Error(ExcelError::Num) } else { Value::from(x.asin()) })
+}
+
+#[function]
+fn acos(number: Value) -> Value {
+    unary(number, |x| if x.abs() > 1.0 { Value::Error(ExcelError::Num) } else { Value::from(x.acos()) })
+}
+
+#[function]
+fn atan(number: Value) -> Value {
+    unary(number, |x| Value::from(x.atan()))
+}
+
+// Note the argument order, ATAN2(x, y) is the angle of the point (x, y).
+#[function]
+fn atan2(x_num: Value, y_num: Value) -> Value {
+    binary(x_num, y_num, |x, y| {
+        if x == 0.0 && y == 0.0 {
+            Value::Error(ExcelError::Div)
+        } else {
+            Value::from(y.atan2(x))
+        }
+    })
+}
+
+#[function]
+fn sinh(number: Value) -> Value {
+    unary(number, |x| checked(x.sinh()))
+}
+
+#[function]
+fn cosh(number: Value) -> Value {
+    unary(number, |x| checked(x.cosh()))
+}
+
+#[function]
+fn tanh(number: Value) -> Value {
+    unary(number, |x| Value::from(x.tanh()))
+}
+
+#[function]
+fn asinh(number: Value) -> Value {
+    unary(number, |x| Value::from(x.asinh()))
+}
+
+#[function]
+fn acosh(number: Value) -> Value {
+    unary(number, |x| if x < 1.0 { Value::Error(ExcelError::Num) } else { Value::from(x.acosh()) })
+}
+
+#[function]
+fn atanh(number: Value) -> Value {
+    unary(number, |x| if x.abs() >= 1.0 { Value::Error(ExcelError::Num) } else { Value::from(x.atanh()) })
+}
+
+#[function]
+fn degrees(angle: Value) -> Value {
+    unary(angle, |x| Value::from(x.to_degrees()))
+}
+
+#[function]
+fn radians(angle: Value) -> Value {
+    unary(angle, |x| Value::from(x.to_radians()))
+}
+
+// A range or array of numbers as a matrix. Blank, text and logical cells are #VALUE!.
+fn matrix(v: &Value) -> Result<Array2<f64>, ExcelError> {
+    let table = match v {
+        Value::Array2(arr2) => arr2.clone(),
+        Value::Array(arr) => Array2::from_shape_vec((1, arr.len()), arr.clone()).unwrap(),
+        v => Array2::from_elem((1, 1), v.clone())
+    };
+    if table.is_empty() {
+        return Err(ExcelError::Value);
+    }
+    let mut output = Array2::zeros(table.dim());
+    for (idx, v) in table.indexed_iter() {
+        output[idx] = match v {
+            Value::Num(n) => *n,
+            Value::Error(e) => return Err(e.clone()),
+            _ => return Err(ExcelError::Value)
+        };
+    }
+    Ok(output)
+}
+
+fn square(v: &Value) -> Result<Array2<f64>, ExcelError> {
+    let m = matrix(v)?;
+    if m.nrows() != m.ncols() {
+        return Err(ExcelError::Value);
+    }
+    Ok(m)
+}
+
+fn from_matrix(m: Array2<f64>) -> Value {
+    Value::from(m.mapv(|x| Value::from(significant(x))))
+}
+
+// Row of the largest entry in col at or below row col, None when they are all (close to) 0.
+fn pivot(m: &Array2<f64>, col: usize, scale: f64) -> Option<usize> {
+    let n = m.nrows();
+    let pivot = (col..n).max_by(|a, b| m[[*a, col]].abs().partial_cmp(&m[[*b, col]].abs()).unwrap()).unwrap();
+    (m[[pivot, col]].abs() > scale * f64::EPSILON * n as f64).then_some(pivot)
+}
+
+fn swap_rows(m: &mut Array2<f64>, a: usize, b: usize) {
+    for j in 0..m.ncols() {
+        m.swap([a, j], [b, j]);
+    }
+}
+
+// Product of the pivots of an LU decomposition with partial pivoting.
+fn determinant(mut m: Array2<f64>) -> f64 {
+    let n = m.nrows();
+    let scale = m.iter().fold(0.0_f64, |s, x| s.max(x.abs()));
+    let mut determinant = 1.0;
+    for col in 0..n {
+        let p = match pivot(&m, col, scale) {
+            Some(p) => p,
+            None => return 0.0
+        };
+        if p != col {
+            swap_rows(&mut m, p, col);
+            determinant = -determinant;
+        }
+        determinant *= m[[col, col]];
+        for i in col + 1..n {
+            let factor = m[[i, col]] / m[[col, col]];
+            for j in col..n {
+                m[[i, j]] -= factor * m[[col, j]];
+            }
+        }
+    }
+    determinant
+}
+
+// Gauss-Jordan elimination with partial pivoting, None when m is singular.
+fn inverse(mut m: Array2<f64>) -> Option<Array2<f64>> {
+    let n = m.nrows();
+    let scale = m.iter().fold(0.0_f64, |s, x| s.max(x.abs()));
+    let mut inverse = Array2::eye(n);
+    for col in 0..n {
+        let p = pivot(&m, col, scale)?;
+        swap_rows(&mut m, p, col);
+        swap_rows(&mut inverse, p, col);
+        let d = m[[col, col]];
+        for j in 0..n {
+            m[[col, j]] /= d;
+            inverse[[col, j]] /= d;
+        }
+        for i in (0..n).filter(|i| *i != col) {
+            let factor = m[[i, col]];
+            for j in 0..n {
+                m[[i, j]] -= factor * m[[col, j]];
+                inverse[[i, j]] -= factor * inverse[[col, j]];
+            }
+        }
+    }
+    Some(inverse)
+}
+
+#[function]
+fn mmult(array1: Value, array2: Value) -> Value {
+    let (a, b) = (tryv!(matrix(&array1)), tryv!(matrix(&array2)));
+    if a.ncols() != b.nrows() {
+        return Value::Error(ExcelError::Value);
+    }
+    from_matrix(a.dot(&b))
+}
+
+// 0 for singular matrices.
+#[function]
+fn mdeterm(array: Value) -> Value {
+    Value::from(significant(determinant(tryv!(square(&array)))))
+}
+
+#[function]
+fn minverse(array: Value) -> Value {
+    match inverse(tryv!(square(&array))) {
+        Some(inverse) => from_matrix(inverse),
+        None => Value::Error(ExcelError::Num)
+    }
+}
+
+#[function]
+fn munit(dimension: Value) -> Value {
+    broadcast(vec![dimension], |a| {
+        let n = tryv!(number_arg(&a[0])).trunc();
+        // An n by n matrix has to fit on a sheet
+        if !(1.0..=16384.0).contains(&n) {
+            return Value::Error(ExcelError::Value);
+        }
+        from_matrix(Array2::eye(n as usize))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        parser::ast::Error as ExcelError,
+        errors::Error,
+    };
+
+    fn approx(formula: &str, expected: f64) -> Result<(), Error> {
+        let value = evaluate_str(formula)?.as_num();
+        assert!((value - expected).abs() < 1e-9 * expected.abs().max(1.0), "{} = {}, expected {}", formula, value, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round() -> Result<(), Error> {
+        assert_eq!(evaluate_str("ROUND(2.675, 2)")?, Value::from(2.68));
+        assert_eq!(evaluate_str("ROUND(2.15, 1)")?, Value::from(2.2));
+        assert_eq!(evaluate_str("ROUND(-1.475, 2)")?, Value::from(-1.48));
+        assert_eq!(evaluate_str("ROUND(21.5, -1)")?, Value::from(20.0));
+        assert_eq!(evaluate_str("ROUND(626.3, -3)")?, Value::from(1000.0));
+        assert_eq!(evaluate_str("ROUND(-50.55, -2)")?, Value::from(-100.0));
+        assert_eq!(evaluate_str("ROUND(1.005, 2)")?, Value::from(1.01));
+        assert_eq!(evaluate_str("ROUNDUP(0.1 + 0.2, 1)")?, Value::from(0.3));
+        assert_eq!(evaluate_str("ROUNDDOWN(-3.14159, 1)")?, Value::from(-3.1));
+        assert_eq!(evaluate_str("TRUNC(8.9)")?, Value::from(8.0));
+        assert_eq!(evaluate_str("TRUNC(-8.9)")?, Value::from(-8.0));
+        assert_eq!(evaluate_str("TRUNC(0.45, 1)")?, Value::from(0.4));
+        assert_eq!(evaluate_str("INT(8.9)")?, Value::from(8.0));
+        assert_eq!(evaluate_str("INT(-8.9)")?, Value::from(-9.0));
+        assert_eq!(evaluate_str("ROUND({1.25, 2.5}, 0)")?, Value::from(vec![Value::from(1.0), Value::from(3.0)]));
+        assert_eq!(evaluate_str("ROUND(\"abc\", 0)")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiples() -> Result<(), Error> {
+        assert_eq!(evaluate_str("MROUND(10, 3)")?, Value::from(9.0));
+        assert_eq!(evaluate_str("MROUND(-10, -3)")?, Value::from(-9.0));
+        assert_eq!(evaluate_str("MROUND(1.3, 0.2)")?, Value::from(1.4));
+        assert_eq!(evaluate_str("MROUND(5, -2)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("CEILING(2.5, 1)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("CEILING(-2.5, -2)")?, Value::from(-4.0));
+        assert_eq!(evaluate_str("CEILING(-2.5, 2)")?, Value::from(-2.0));
+        assert_eq!(evaluate_str("CEILING(1.5, 0.1)")?, Value::from(1.5));
+        assert_eq!(evaluate_str("CEILING(0.234, 0.01)")?, Value::from(0.24));
+        assert_eq!(evaluate_str("CEILING(1.5, -1)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("FLOOR(-2.5, -2)")?, Value::from(-2.0));
+        assert_eq!(evaluate_str("FLOOR(1.58, 0.1)")?, Value::from(1.5));
+        assert_eq!(evaluate_str("FLOOR(0.234, 0.01)")?, Value::from(0.23));
+        assert_eq!(evaluate_str("FLOOR(3, 0)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("CEILING.MATH(24.3, 5)")?, Value::from(25.0));
+        assert_eq!(evaluate_str("CEILING.MATH(6.7)")?, Value::from(7.0));
+        assert_eq!(evaluate_str("CEILING.MATH(-8.1, 2)")?, Value::from(-8.0));
+        assert_eq!(evaluate_str("CEILING.MATH(-5.5, 2, -1)")?, Value::from(-6.0));
+        assert_eq!(evaluate_str("FLOOR.MATH(24.3, 5)")?, Value::from(20.0));
+        assert_eq!(evaluate_str("FLOOR.MATH(-8.1, 2)")?, Value::from(-10.0));
+        assert_eq!(evaluate_str("FLOOR.MATH(-5.5, 2, -1)")?, Value::from(-4.0));
+        assert_eq!(evaluate_str("EVEN(1.5)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("EVEN(3)")?, Value::from(4.0));
+        assert_eq!(evaluate_str("EVEN(-1)")?, Value::from(-2.0));
+        assert_eq!(evaluate_str("ODD(1.5)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("ODD(2)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("ODD(-1)")?, Value::from(-1.0));
+        assert_eq!(evaluate_str("ODD(0)")?, Value::from(1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_division() -> Result<(), Error> {
+        assert_eq!(evaluate_str("MOD(3, 2)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("MOD(-3, 2)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("MOD(3, -2)")?, Value::from(-1.0));
+        assert_eq!(evaluate_str("MOD(-3, -2)")?, Value::from(-1.0));
+        assert_eq!(evaluate_str("MOD(5.5, 1)")?, Value::from(0.5));
+        assert_eq!(evaluate_str("MOD(3, 0)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("QUOTIENT(5, 2)")?, Value::from(2.0));
+        assert_eq!(evaluate_str("QUOTIENT(-10, 3)")?, Value::from(-3.0));
+        assert_eq!(evaluate_str("QUOTIENT(1, 0)")?, Value::Error(ExcelError::Div));
+        Ok(())
+    }
+
+    #[test]
+    fn test_powers() -> Result<(), Error> {
+        assert_eq!(evaluate_str("ABS(-4)")?, Value::from(4.0));
+        assert_eq!(evaluate_str("SIGN(-0.00001)")?, Value::from(-1.0));
+        assert_eq!(evaluate_str("SQRT(16)")?, Value::from(4.0));
+        assert_eq!(evaluate_str("SQRT(-16)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("POWER(5, 2)")?, Value::from(25.0));
+        assert_eq!(evaluate_str("POWER(4, 5/4)")?.as_num(), 4f64.powf(1.25));
+        assert_eq!(evaluate_str("POWER(0, 0)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("POWER(0, -1)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("POWER(-8, 1/3)")?, Value::Error(ExcelError::Num));
+        approx("EXP(1)", std::f64::consts::E)?;
+        assert_eq!(evaluate_str("EXP(710)")?, Value::Error(ExcelError::Num));
+        approx("LN(86)", 4.454347296253507)?;
+        assert_eq!(evaluate_str("LN(0)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("LOG(8, 2)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("LOG(10)")?, Value::from(1.0));
+        approx("LOG(86, 2.7182818)", 4.454347343)?;
+        assert_eq!(evaluate_str("LOG(10, 1)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("LOG10(100000)")?, Value::from(5.0));
+        assert_eq!(evaluate_str("LOG10(-1)")?, Value::Error(ExcelError::Num));
+        approx("PI()", std::f64::consts::PI)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregates() -> Result<(), Error> {
+        assert_eq!(evaluate_str("PRODUCT(5, 15, 30)")?, Value::from(2250.0));
+        assert_eq!(evaluate_str("PRODUCT({5, 15, \"a\"}, 2)")?, Value::from(150.0));
+        assert_eq!(evaluate_str("PRODUCT({\"a\"})")?, Value::from(0.0));
+        assert_eq!(evaluate_str("GCD(5, 2)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("GCD(24, 36)")?, Value::from(12.0));
+        assert_eq!(evaluate_str("GCD(5, 0)")?, Value::from(5.0));
+        assert_eq!(evaluate_str("GCD(-1, 2)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("LCM(24, 36)")?, Value::from(72.0));
+        assert_eq!(evaluate_str("LCM(5, 2)")?, Value::from(10.0));
+        assert_eq!(evaluate_str("LCM(5, 0)")?, Value::from(0.0));
+        assert_eq!(evaluate_str("FACT(5)")?, Value::from(120.0));
+        assert_eq!(evaluate_str("FACT(1.9)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("FACT(0)")?, Value::from(1.0));
+        assert_eq!(evaluate_str("FACT(-1)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("FACT(171)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("COMBIN(8, 2)")?, Value::from(28.0));
+        assert_eq!(evaluate_str("COMBIN(60, 30)")?, Value::from(118264581564861424.0));
+        assert_eq!(evaluate_str("COMBIN(2, 3)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("PERMUT(100, 3)")?, Value::from(970200.0));
+        assert_eq!(evaluate_str("PERMUT(3, 2)")?, Value::from(6.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trig() -> Result<(), Error> {
+        approx("SIN(PI()/2)", 1.0)?;
+        approx("COS(1.047)", 0.500171075)?;
+        approx("TAN(0.785)", 0.99920399)?;
+        approx("COT(30)", -0.156119952)?;
+        approx("SEC(45)", 1.903594407)?;
+        approx("CSC(15)", 1.537780562)?;
+        assert_eq!(evaluate_str("COT(0)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("SIN(2^27)")?, Value::Error(ExcelError::Num));
+        approx("ASIN(-0.5)", -std::f64::consts::FRAC_PI_6)?;
+        approx("ACOS(-0.5)", 2.094395102)?;
+        assert_eq!(evaluate_str("ACOS(2)")?, Value::Error(ExcelError::Num));
+        approx("ATAN(1)", std::f64::consts::FRAC_PI_4)?;
+        approx("ATAN2(1, 1)", std::f64::consts::FRAC_PI_4)?;
+        approx("ATAN2(-1, -1)", -2.35619449)?;
+        assert_eq!(evaluate_str("ATAN2(0, 0)")?, Value::Error(ExcelError::Div));
+        approx("SINH(1)", 1.175201194)?;
+        approx("COSH(4)", 27.30823284)?;
+        approx("TANH(0.5)", 0.462117157)?;
+        approx("ASINH(10)", 2.99822295)?;
+        approx("ACOSH(10)", 2.993222846)?;
+        assert_eq!(evaluate_str("ACOSH(0.5)")?, Value::Error(ExcelError::Num));
+        approx("ATANH(-0.1)", -0.100335348)?;
+        assert_eq!(evaluate_str("ATANH(1)")?, Value::Error(ExcelError::Num));
+        approx("DEGREES(PI())", 180.0)?;
+        approx("RADIANS(270)", 4.71238898)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix() -> Result<(), Error> {
+        let grid = |rows: &[&[f64]]| Value::from(ndarray::Array2::from_shape_fn((rows.len(), rows[0].len()), |(i, j)| Value::from(rows[i][j])));
+        assert_eq!(evaluate_str("MMULT({1, 2, 3}, TRANSPOSE({4, 5, 6}))")?, grid(&[&[32.0]]));
+        assert_eq!(evaluate_str("MMULT(VSTACK({1, 2}, {3, 4}), VSTACK({5, 6}, {7, 8}))")?, grid(&[&[19.0, 22.0], &[43.0, 50.0]]));
+        assert_eq!(evaluate_str("MMULT({1, 2}, {1, 2})")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("MMULT({1, \"a\"}, TRANSPOSE({1, 2}))")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("MDETERM(VSTACK({1, 2}, {3, 4}))")?, Value::from(-2.0));
+        assert_eq!(evaluate_str("MDETERM(VSTACK({3, 6, 1}, {1, 1, 0}, {3, 10, 2}))")?, Value::from(1.0));
+        assert_eq!(evaluate_str("MDETERM(VSTACK({1, 2}, {2, 4}))")?, Value::from(0.0));
+        assert_eq!(evaluate_str("MDETERM({1, 2})")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("MINVERSE(VSTACK({4, -1}, {2, 1}))")?, grid(&[&[0.166666666666667, 0.166666666666667], &[-0.333333333333333, 0.666666666666667]]));
+        assert_eq!(evaluate_str("MINVERSE(VSTACK({1, 2}, {2, 4}))")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("MINVERSE(VSTACK({0, 1}, {1, 0}))")?, grid(&[&[0.0, 1.0], &[1.0, 0.0]]));
+        assert_eq!(evaluate_str("MUNIT(2)")?, grid(&[&[1.0, 0.0], &[0.0, 1.0]]));
+        assert_eq!(evaluate_str("MUNIT(0)")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("MUNIT(1E+9)")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+}
@@ -1,61 +1,83 @@
+// Unwraps a Result<_, ExcelError>, returning the error from the enclosing function as a Value. 
+macro_rules! tryv {
+    ($e:expr) => {
+        match $e {
+            Ok(x) => x, 
+            Err(e) => return Value::Error(e)
+        }
+    };
+}
+
 pub mod xirr; 
+pub mod lookup; 
+pub mod text; 
+pub mod math; 
+pub mod stats; 
+pub mod financial; 
+pub mod date; 
+pub mod volatile; 
+pub mod logical; 
+pub mod dynamic; 
+pub mod criteria; 
+pub mod database; 
+pub mod lambda; 
+pub mod registry; 
 
 use crate::{
     evaluate::{
         evaluate_expr_with_context, 
-        evaluate_str, 
         ensure_non_range,
         value::Value, 
     }, 
     reference::Reference, 
-    cell::Cell, 
+    utils::date_time_to_excel, 
+    cell::{Cell, CellIndex}, 
     errors::Error, 
-    parser::ast::{Expr, Error as ExcelError},  
+    parser::{
+        parse_str, 
+        parse_r1c1_str, 
+        printer::quote_sheet, 
+        ast::{Expr, Error as ExcelError}, 
+    }, 
     workbook::Book,
 }; 
 use excel_emulator_macro::function; 
-use chrono::{Months, naive::NaiveDate, Datelike}; 
+use chrono::naive::NaiveDate; 
+use ndarray::Array2; 
+
+// Functions that are not #[function] structs, calculated below or by evaluate_expr_with_context. 
+pub const OTHER_FUNCTIONS: [&str; 17] = [
+    "IFERROR", "NA", "PI", "OFFSET", "INDEX", "INDIRECT", "ROW", "COLUMN", "ROWS", "COLUMNS", 
+    "SHEET", "SHEETS", "SUBTOTAL", "AGGREGATE", "ISREF", "ISFORMULA", "FORMULATEXT"
+]; 
+
+// Whether a workbook formula can call the function, leaving out ones registered at runtime. 
+pub fn is_supported(name: &str) -> bool {
+    let name = name.to_uppercase(); 
+    let name = name.as_str(); 
+    registry::builtin(name).is_some()
+        || OTHER_FUNCTIONS.contains(&name)
+        || volatile::VOLATILE_FUNCTIONS.contains(&name)
+        || logical::ERROR_AWARE_FUNCTIONS.contains(&name)
+        || lambda::LAMBDA_FUNCTIONS.contains(&name)
+}
 
+// Functions made with #[function] register themselves, see registry::Builtin. 
 pub fn get_function_value(name: &str, args: Vec<Value>) -> Result<Value, Error> {
     match name {
-		"SUM" => Ok(Box::new(Sum::from(args)).evaluate()), 
-		"SUMIF" => Ok(Box::new(Sumifs::from(args)).evaluate()), 
-		"AVERAGE" => Ok(Box::new(Average::from(args)).evaluate()), 
-		"AVERAGEIF" => Ok(Box::new(Averageif::from(args)).evaluate()), 
-		"COUNT" => Ok(Box::new(Count::from(args)).evaluate()),	
-		"EXPONENT" => Ok(Box::new(Exponent::from(args)).evaluate()),	
-		"CONCAT" => Ok(Box::new(Concat::from(args)).evaluate()),	
-		"AND" => Ok(Box::new(Andfunc::from(args)).evaluate()),	
-		"OR" => Ok(Box::new(Orfunc::from(args)).evaluate()),	
-		"MAX" => Ok(Box::new(Max::from(args)).evaluate()),	
-		"MIN" => Ok(Box::new(Min::from(args)).evaluate()),	
-		"MATCH" => Ok(Box::new(Matchfn::from(args)).evaluate()),	
-		"DATE" => Ok(Box::new(Date::from(args)).evaluate()),	
-		"FLOOR" => Ok(Box::new(Floor::from(args)).evaluate()),	
 		"IFERROR" => {
-            let a = args.get(0).unwrap().clone(); 
-            let b = args.get(1).unwrap().clone(); 
+            registry::check_arity(name, registry::Arity::exactly(2), args.len())?; 
+            let [a, b]: [Value; 2] = args.try_into().unwrap(); 
             Ok(Box::new(Iferror { a, b }).evaluate())
         },	
-		"EOMONTH" => Ok(Box::new(Eomonth::from(args)).evaluate()),	
-		"SUMIFS" => Ok(Box::new(Sumifs::from(args)).evaluate()),	
-		"COUNTIFS" => Ok(Box::new(Countifs::from(args)).evaluate()),	
-		"AVERAGEIFS" => Ok(Box::new(Averageifs::from(args)).evaluate()),	
-		"XIRR" => Ok(Box::new(Xirrfunc::from(args)).evaluate()),	
-		"IF" => Ok(Box::new(Iffunc::from(args)).evaluate()),	
-		"XNPV" => Ok(Box::new(Xnpv::from(args)).evaluate()),	
-		"YEARFRAC" => Ok(Box::new(Yearfrac::from(args)).evaluate()),	
-		"DATEDIF" => Ok(Box::new(Datedif::from(args)).evaluate()),	
-		"PMT" => Ok(Box::new(Pmt::from(args)).evaluate()),	
-		"COUNTA" => Ok(Box::new(Counta::from(args)).evaluate()),	
-		"ROUNDDOWN" => Ok(Box::new(Rounddown::from(args)).evaluate()),	
-		"ROUNDUP" => Ok(Box::new(Roundup::from(args)).evaluate()),	
-		"SEARCH" => Ok(Box::new(Search::from(args)).evaluate()),	
-		"COUNTIF" => Ok(Box::new(Countif::from(args)).evaluate()),	
-		"MONTH" => Ok(Box::new(Month::from(args)).evaluate()),	
-		"YEAR" => Ok(Box::new(Year::from(args)).evaluate()),	
-		"SUMPRODUCT" => Ok(Box::new(Sumproduct::from(args)).evaluate()),	
-        _ => Err(Error::FunctionNotSupport(name.to_string()))
+		c if volatile::VOLATILE_FUNCTIONS.contains(&c) => Ok(volatile::evaluate(name, args, &volatile::CalculationContext::default())),	
+		c if logical::ERROR_AWARE_FUNCTIONS.contains(&c) => Ok(logical::evaluate(name, args)),	
+		"NA" => Ok(Value::Error(ExcelError::NA)),	
+		"PI" => Ok(Value::from(std::f64::consts::PI)),	
+        _ => match registry::builtin(name) {
+            Some(builtin) => builtin.call(name, args), 
+            None => Err(Error::FunctionNotSupport(name.to_string()))
+        }
     }
 }
 
@@ -63,6 +85,92 @@ pub trait Function {
    fn evaluate(self) -> Value; 
 }
 
+/*
+ * Excel lifts scalar functions over arrays: when any argument is an array, f is applied 
+ * element by element. Single rows or columns stretch to fit the others, cells outside a 
+ * smaller array are #N/A. An error in any argument is the result for that element. 
+ */
+pub fn broadcast(args: Vec<Value>, f: impl Fn(&[Value]) -> Value) -> Value {
+    elementwise(args, |values| {
+        match values.iter().find(|v| v.is_err()) {
+            Some(err) => err.clone(), 
+            None => f(values)
+        }
+    })
+}
+
+// As broadcast, leaving errors to f, for IF where an error only matters in the branch taken. 
+pub fn elementwise(args: Vec<Value>, f: impl Fn(&[Value]) -> Value) -> Value {
+    // A single cell is a scalar 
+    let args: Vec<Value> = args.into_iter().map(|a| match a {
+        Value::Array2(ref arr2) if arr2.len() == 1 => arr2[[0, 0]].clone(), 
+        Value::Array(ref arr) if arr.len() == 1 => arr[0].clone(), 
+        a => a
+    }).collect(); 
+    if !args.iter().any(|a| a.is_array() || a.is_array2()) {
+        return f(&args); 
+    }
+    let grids: Vec<Array2<Value>> = args.iter().map(|a| match a {
+        Value::Array2(arr2) => arr2.clone(), 
+        Value::Array(arr) => Array2::from_shape_vec((1, arr.len()), arr.clone()).unwrap(), 
+        a => Array2::from_elem((1, 1), a.clone())
+    }).collect(); 
+    let rows = grids.iter().map(|g| g.nrows()).max().unwrap_or(1); 
+    let cols = grids.iter().map(|g| g.ncols()).max().unwrap_or(1); 
+    let output = Array2::from_shape_fn((rows, cols), |(i, j)| {
+        let values: Option<Vec<Value>> = grids.iter().map(|g| {
+            let row = if g.nrows() == 1 { 0 } else { i }; 
+            let col = if g.ncols() == 1 { 0 } else { j }; 
+            g.get((row, col)).cloned()
+        }).collect(); 
+        match values {
+            Some(values) => f(&values), 
+            None => Value::Error(ExcelError::NA)
+        }
+    }); 
+    if args.iter().any(|a| a.is_array2()) {
+        Value::from(output)
+    } else {
+        Value::from(output.into_raw_vec())
+    }
+}
+
+// Numeric argument the way Excel coerces it: text must parse, blanks are 0. 
+pub fn number_arg(v: &Value) -> Result<f64, ExcelError> {
+    match v {
+        Value::Num(n) => Ok(*n), 
+        Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }), 
+        Value::Empty => Ok(0.0), 
+        Value::Date(d) => Ok(date_time_to_excel(*d)), 
+        Value::Text(t) => text::parse_value(t).ok_or(ExcelError::Value), 
+        Value::Error(e) => Err(e.clone()), 
+        Value::Array(_) | Value::Array2(_) => number_arg(&v.ensure_single()), 
+        _ => Err(ExcelError::Value)
+    }
+}
+
+// Numbers to aggregate: only numbers and dates count inside ranges and arrays, 
+// while values passed directly are coerced like number_arg. 
+pub fn collect_numbers(args: &[Value]) -> Result<Vec<f64>, ExcelError> {
+    let mut output = vec![]; 
+    for arg in args.iter() {
+        match arg {
+            Value::Array(_) | Value::Array2(_) => {
+                for v in arg.as_array() {
+                    match v {
+                        Value::Num(n) => output.push(n), 
+                        Value::Date(d) => output.push(date_time_to_excel(d)), 
+                        Value::Error(e) => return Err(e), 
+                        _ => {}
+                    }
+                }
+            }, 
+            v => output.push(number_arg(v)?)
+        }
+    }
+    Ok(output)
+}
+
 pub fn offset_reference(r: &mut Reference, rows: i32, cols: i32, height: Option<i32>, width: Option<i32>) -> Reference {
     if r.row() as i32 + rows < 0 || r.column() as i32 + cols < 0 {
         panic!("Invalid offset");
@@ -131,8 +239,8 @@ fn average(args: Vec<Value>) -> Value {
     let mut count = 0.0;
     let mut sum_values: Vec<Value> = vec![]; 
     for arg in args.into_iter() {
-        if let Value::Array(arr) = arg {
-            for x in arr {
+        if arg.is_array() || arg.is_array2() {
+            for x in arg.as_array() {
                 if x.is_num() {
                     sum_values.push(x); 
                     count += 1.0; 
@@ -167,76 +275,27 @@ fn count(args: Vec<Value>) -> Value {
     Value::from(count)
 }
 
-#[function]
-fn concat(a: Value, b: Value) -> Value {
-    Value::from(format!("{}{}", a.as_text(), b.as_text()))
-}
-
-#[function]
-fn andfunc(a: Value, b: Value) -> Value {
-    Value::from(a.as_bool() && b.as_bool())
-}
-
-#[function]
-fn orfunc(a: Value, b: Value) -> Value {
-    Value::from(a.as_bool() || b.as_bool())
-}
-
+// 0 when there are no numbers 
 #[function]
 fn max(args: Vec<Value>) -> Value {
-    let mut output = args[0].clone(); 
-    for v in args.into_iter() {
-        if let Value::Array(arr) = v {
-            for x in arr {
-                if x.is_num() {
-                    output = output.max(x); 
-                }
-            }
-        } else if let Value::Array2(arr2) = v {
-            for x in arr2 {
-                if x.is_num() {
-                    output = output.max(x); 
-                }
-            }
-        } else {
-            output = output.max(v); 
-        }
-    }
-    output
+    Value::from(tryv!(collect_numbers(&args)).into_iter().reduce(f64::max).unwrap_or(0.0))
 }
 
 #[function]
 fn min(args: Vec<Value>) -> Value {
-    let mut output = args[0].clone(); 
-    for v in args.into_iter() {
-        if let Value::Array(arr) = v {
-            for x in arr {
-                if x.is_num() {
-                    output = output.min(x); 
-                }
-            }
-        } else if let Value::Array2(arr2) = v {
-            for x in arr2 {
-                if x.is_num() {
-                    output = output.min(x); 
-                }
-            }
-        } else {
-            output = output.min(v); 
-        }
-    }
-    output
+    Value::from(tryv!(collect_numbers(&args)).into_iter().reduce(f64::min).unwrap_or(0.0))
 }
 
-#[function]
-fn matchfn(lookup_value: Value, lookup_array: Value, match_type: Value) -> Value {
+#[function("MATCH")]
+fn matchfn(lookup_value: Value, lookup_array: Value, match_type: Option<Value>) -> Value {
     let lookup_value = lookup_value.ensure_single(); 
+    let match_type = match_type.unwrap_or(Value::from(1.0)); 
     let mut lookup_array_mut = lookup_array.as_array();
     if match_type.as_num() == -1.0 {
         // Smallest value that is greater than or equal to the lookup-value.
         // Lookup array placed in descending order.
         lookup_array_mut.sort_by(|a, b| b.cmp(a)); // Descending Order
-        match lookup_array.as_array().into_iter().enumerate().filter(|(_,v)| v >= &lookup_value).last() {
+        match lookup_array.as_array().into_iter().enumerate().rfind(|(_,v)| v >= &lookup_value) {
             Some(v) => { Value::from(v.0 + 1) },
             _ => Value::Error(ExcelError::NA)
         }
@@ -249,25 +308,13 @@ fn matchfn(lookup_value: Value, lookup_array: Value, match_type: Value) -> Value
         // Largest value that is less than or equal to the lookup-value
         // Lookup array placed in ascending order.
         lookup_array_mut.sort(); // Ascending Order
-        match lookup_array_mut.into_iter().enumerate().filter(|(_, v)| v <= &lookup_value).last() {
+        match lookup_array_mut.into_iter().enumerate().rfind(|(_, v)| v <= &lookup_value) {
             Some(v) => { Value::from(v.0 + 1) }, 
             _ => Value::Error(ExcelError::NA)
         }
     }
 }
 
-#[function]
-fn date(year: Value, month: Value, day: Value) -> Value {
-   Value::from(NaiveDate::from_ymd(year.as_num() as i32, month.as_num() as u32, day.as_num() as u32))
-}
-
-
-#[function]
-// FIXME: significance
-fn floor(x: Value, _significance: Value) -> Value {
-    Value::from(math::round::floor(x.as_num(), 0))
-}
-
 /*
  * Index function can return either a value or a reference. 
  * Excel treats them different depending on what the parent function needs.
@@ -276,8 +323,11 @@ fn floor(x: Value, _significance: Value) -> Value {
 */
 pub fn index(args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
 	let mut arg_values = args.into_iter(); 
-	let array: Value = evaluate_expr_with_context(arg_values.next().unwrap(), book, debug)?; // This can be a range or an array
-	let row_num: Value = evaluate_expr_with_context(arg_values.next().unwrap(), book, debug)?; 
+	let (Some(array), Some(row_num)) = (arg_values.next(), arg_values.next()) else {
+		return Ok(Value::Error(ExcelError::Value)); 
+	}; 
+	let array: Value = evaluate_expr_with_context(array, book, debug)?; // This can be a range or an array
+	let row_num: Value = evaluate_expr_with_context(row_num, book, debug)?; 
 	let col_num_option = arg_values.next(); 
 	let col_num = match col_num_option {
 		Some(expr) => evaluate_expr_with_context(expr, book, debug)?,
@@ -294,7 +344,6 @@ pub fn index(args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error>
     let row_idx = row_num.as_num() as usize - 1;
     let col_idx = col_num.as_num() as usize - 1; 
     if let Value::Range { sheet, reference, value } = array {
-		let reference = Reference::from(reference); 
 		let (start_row, start_col, _, _) = reference.get_dimensions(); 
 
         // If row value is zero, reference entire column.
@@ -314,25 +363,28 @@ pub fn index(args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error>
         let new_row = start_row + row_idx;  
         let new_col = start_col + col_idx; 
         let new_value: Value = value.unwrap().as_array2()[[row_idx, col_idx]].clone(); 
-        return Ok(Value::Range { sheet: sheet.clone(), reference: Reference::from((new_row, new_col)), value: Some(Box::new(new_value)) }); 
+        Ok(Value::Range { sheet: sheet.clone(), reference: Reference::from((new_row, new_col)), value: Some(Box::new(new_value)) })
 	} else {
 		panic!("First argument must be a range."); 
 	}
 } 
 
 pub fn offset(args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
-    let array = evaluate_expr_with_context(args.get(0).unwrap().clone(), book, debug)?; 
+    let (Some(array), Some(rows), Some(cols)) = (args.first(), args.get(1), args.get(2)) else {
+        return Ok(Value::Error(ExcelError::Value)); 
+    }; 
+    let array = evaluate_expr_with_context(array.clone(), book, debug)?; 
 	if let Value::Range { sheet, reference, value: _ } = array { 
-		let rows = ensure_non_range(evaluate_expr_with_context(args.get(1).unwrap().clone(), book, debug)?);
-		let cols = ensure_non_range(evaluate_expr_with_context(args.get(2).unwrap().clone(), book, debug)?); 
+		let rows = ensure_non_range(evaluate_expr_with_context(rows.clone(), book, debug)?);
+		let cols = ensure_non_range(evaluate_expr_with_context(cols.clone(), book, debug)?); 
 		let height = args.get(3); 
 		let height_opt: Option<i32> = height.map(|h| {
-			ensure_non_range(evaluate_expr_with_context(h.clone(), book, debug).unwrap()).as_num() as i32
-		}); 
+			evaluate_expr_with_context(h.clone(), book, debug).map(|v| ensure_non_range(v).as_num() as i32)
+		}).transpose()?; 
 		let width = args.get(4); 
 		let width_opt: Option<i32> = width.map(|w| {
-			ensure_non_range(evaluate_expr_with_context(w.clone(), book, debug).unwrap()).as_num() as i32
-		}); 
+			evaluate_expr_with_context(w.clone(), book, debug).map(|v| ensure_non_range(v).as_num() as i32)
+		}).transpose()?; 
 		let new_reference = offset_reference(&mut reference.clone(), rows.as_num() as i32, cols.as_num() as i32, height_opt, width_opt); 
         let new_expr = Expr::Reference { sheet: sheet.clone(), reference: new_reference.to_string() }; 
         if book.is_calculated(new_expr.clone()) {
@@ -346,13 +398,261 @@ pub fn offset(args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error>
         }
     } else {
         if array.is_err() {
-            return Ok(array); 
+            Ok(array)
         } else {
             panic!("First expression must be a Reference.")
         }
     }
 }
 
+/*
+ * INDIRECT turns text into a reference. Like OFFSET the target is only known while 
+ * calculating, so an uncalculated target raises Error::Volatile to add the dependency. 
+ */
+pub fn indirect(args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
+    let mut arg_values = args.into_iter(); 
+    let Some(ref_text) = arg_values.next() else {
+        return Ok(Value::Error(ExcelError::Value)); 
+    }; 
+    let ref_text = ensure_non_range(evaluate_expr_with_context(ref_text, book, debug)?).ensure_single(); 
+    let a1 = match arg_values.next() {
+        Some(expr) => ensure_non_range(evaluate_expr_with_context(expr, book, debug)?).ensure_single(), 
+        None => Value::from(true)
+    }; 
+    if ref_text.is_err() {
+        return Ok(ref_text); 
+    } else if a1.is_err() {
+        return Ok(a1); 
+    }
+    let a1 = match a1 {
+        Value::Bool(b) => b, 
+        Value::Num(n) => n != 0.0, 
+        _ => true
+    }; 
+    let parsed = if a1 {
+        parse_str(&ref_text.as_text())
+    } else {
+        parse_r1c1_str(&ref_text.as_text(), book.current_cell)
+    }; 
+    let (sheet, reference) = match parsed {
+        Ok(Expr::Reference { sheet, reference }) => (sheet, reference), 
+        _ => return Ok(Value::Error(ExcelError::Ref))
+    }; 
+    // Sheet names are case insensitive, the dependency tree needs the exact one
+    let sheet = match sheet {
+        Some(s) => match book.sheets.iter().find(|x| x.name.eq_ignore_ascii_case(&s)) {
+            Some(x) => Some(x.name.clone()), 
+            None => return Ok(Value::Error(ExcelError::Ref))
+        }, 
+        None => None
+    }; 
+    let new_expr = Expr::Reference { sheet: sheet.clone(), reference: reference.clone() }; 
+    if book.is_calculated(new_expr.clone()) {
+        let value = ensure_non_range(evaluate_expr_with_context(new_expr, book, debug)?); 
+        Ok(Value::Range { sheet, reference: Reference::from(reference), value: Some(Box::new(value)) })
+    } else {
+        Err(Error::Volatile(Box::new(new_expr)))
+    }
+}
+
+/*
+ * ROW, COLUMN, ROWS, COLUMNS, SHEET and SHEETS need where a reference points rather than 
+ * its values. Without an argument they describe the cell being calculated. 
+ */
+pub fn reference_info(name: &str, args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
+    let target: Option<Value> = match args.into_iter().next() {
+        Some(Expr::Reference { sheet, reference }) => Some(Value::Range { sheet, reference: Reference::from(reference), value: None }), 
+        Some(Expr::Func { name, args }) if name.as_str() == "OFFSET" => Some(offset(args, book, debug)?), 
+        Some(expr) => Some(evaluate_expr_with_context(expr, book, debug)?), 
+        None => None
+    }; 
+    let sheet_position = |s: &str| book.sheets.iter().position(|x| x.name.eq_ignore_ascii_case(s)); 
+    let value = match (name, target) {
+        (_, Some(Value::Error(e))) => Value::Error(e), 
+        ("ROW", None) => Value::from(book.current_cell.row.index), 
+        ("COLUMN", None) => Value::from(book.current_cell.column.index), 
+        ("ROWS" | "COLUMNS", None) => return Err(Error::FunctionNotSupport(format!("{} without arguments", name))), 
+        ("ROW", Some(Value::Range { reference, .. })) => {
+            let (row, _, num_rows, _) = reference.get_dimensions(); 
+            if reference.is_vrange() || num_rows == 1 {
+                Value::from(row.max(1))
+            } else {
+                Value::from(Array2::from_shape_fn((num_rows, 1), |(i, _)| Value::from(row + i)))
+            }
+        }, 
+        ("COLUMN", Some(Value::Range { reference, .. })) => {
+            let (_, column, _, num_cols) = reference.get_dimensions(); 
+            if reference.is_hrange() || num_cols == 1 {
+                Value::from(column.max(1))
+            } else {
+                Value::from(Array2::from_shape_fn((1, num_cols), |(_, j)| Value::from(column + j)))
+            }
+        }, 
+        ("ROWS", Some(Value::Range { reference, .. })) => Value::from(reference.num_rows().min(1048576)), 
+        ("COLUMNS", Some(Value::Range { reference, .. })) => Value::from(reference.num_cols().min(16384)), 
+        ("ROWS", Some(Value::Array2(arr2))) => Value::from(arr2.nrows()), 
+        ("COLUMNS", Some(Value::Array2(arr2))) => Value::from(arr2.ncols()), 
+        ("ROWS", Some(_)) => Value::from(1.0), 
+        ("COLUMNS", Some(Value::Array(arr))) => Value::from(arr.len()), 
+        ("COLUMNS", Some(_)) => Value::from(1.0), 
+        ("SHEET", None) => Value::from(book.current_sheet + 1), 
+        ("SHEET", Some(Value::Range { sheet: None, .. })) => Value::from(book.current_sheet + 1), 
+        ("SHEET", Some(Value::Range { sheet: Some(s), .. })) => {
+            // First sheet of a 3D reference like Jan:Mar!A1 
+            match sheet_position(s.split(':').next().unwrap()) {
+                Some(i) => Value::from(i + 1), 
+                None => Value::Error(ExcelError::Ref)
+            }
+        }, 
+        ("SHEET", Some(Value::Text(s))) => {
+            match sheet_position(&s) {
+                Some(i) => Value::from(i + 1), 
+                None => Value::Error(ExcelError::NA)
+            }
+        }, 
+        ("SHEETS", None) => Value::from(book.sheets.len()), 
+        ("SHEETS", Some(Value::Range { sheet: Some(s), .. })) => {
+            match s.split_once(':') {
+                Some((first, last)) => match (sheet_position(first), sheet_position(last)) {
+                    (Some(a), Some(b)) => Value::from(a.abs_diff(b) + 1), 
+                    _ => Value::Error(ExcelError::Ref)
+                }, 
+                None => Value::from(1.0)
+            }
+        }, 
+        ("SHEETS", Some(Value::Range { .. })) => Value::from(1.0), 
+        _ => Value::Error(ExcelError::Value)
+    }; 
+    Ok(value)
+}
+
+// Functions SUBTOTAL and AGGREGATE apply, by function_num. From 14 on they take an array and k. 
+const AGGREGATES: [&str; 19] = [
+    "AVERAGE", "COUNT", "COUNTA", "MAX", "MIN", "PRODUCT", "STDEV.S", "STDEV.P", "SUM", "VAR.S", "VAR.P", 
+    "MEDIAN", "MODE.SNGL", "LARGE", "SMALL", "PERCENTILE.INC", "QUARTILE.INC", "PERCENTILE.EXC", "QUARTILE.EXC", 
+]; 
+
+fn is_subtotal_formula(value: &Value) -> bool {
+    match value {
+        Value::Formula(f) => {
+            let f = f.to_uppercase(); 
+            f.contains("SUBTOTAL(") || f.contains("AGGREGATE(")
+        }, 
+        _ => false
+    }
+}
+
+// Values of a SUBTOTAL or AGGREGATE argument, leaving out the cells of a reference the options skip. 
+fn subtotal_values(expr: Expr, book: &Book, debug: bool, skip_nested: bool, skip_hidden: bool) -> Result<Vec<Value>, Error> {
+    let sheet_idx = match &expr {
+        Expr::Reference { sheet: Some(s), .. } => book.sheets.iter().position(|x| x.name == *s), 
+        Expr::Reference { sheet: None, .. } => Some(book.current_sheet), 
+        _ => None
+    }; 
+    match (sheet_idx, &expr) {
+        (Some(idx), Expr::Reference { reference, .. }) => {
+            let values = book.resolve_ref(expr.clone())?; 
+            let sheet = book.get_sheet_by_idx(idx); 
+            let (mut row, mut column, num_rows, num_cols) = Reference::from(reference.as_str()).get_dimensions(); 
+            if num_rows == usize::MAX { row = 1; }
+            if num_cols == usize::MAX { column = 1; }
+            Ok(values.indexed_iter()
+                .filter(|((i, _), _)| !(skip_hidden && sheet.hidden_rows.contains(&(row + i))))
+                .filter(|((i, j), _)| !(skip_nested && sheet.values.get((row + i - 1, column + j - 1)).is_some_and(|v| is_subtotal_formula(&v.value))))
+                .map(|(_, v)| v.clone())
+                .collect())
+        }, 
+        _ => Ok(ensure_non_range(evaluate_expr_with_context(expr, book, debug)?).as_array())
+    }
+}
+
+/*
+ * SUBTOTAL(function_num, ref1, ...) and AGGREGATE(function_num, options, ref1, ...) leave out 
+ * cells with their own SUBTOTAL or AGGREGATE. SUBTOTAL codes from 101 also skip hidden rows, 
+ * AGGREGATE options choose between those, hidden rows and error values. 
+ */
+pub fn subtotal(name: &str, args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
+    let mut args = args.into_iter(); 
+    let number = |args: &mut dyn Iterator<Item = Expr>| -> Result<Result<f64, ExcelError>, Error> {
+        match args.next() {
+            Some(expr) => Ok(number_arg(&ensure_non_range(evaluate_expr_with_context(expr, book, debug)?))), 
+            None => Ok(Err(ExcelError::Value))
+        }
+    }; 
+    let code = match number(&mut args)? {
+        Ok(code) => code.trunc() as usize, 
+        Err(e) => return Ok(Value::Error(e))
+    }; 
+    let (function, skip_nested, skip_hidden, skip_errors) = match name {
+        "SUBTOTAL" => match code {
+            1..=11 => (code, true, false, false), 
+            101..=111 => (code - 100, true, true, false), 
+            _ => return Ok(Value::Error(ExcelError::Value))
+        }, 
+        _ => {
+            let options = match number(&mut args)? {
+                Ok(options) => options.trunc() as usize, 
+                Err(e) => return Ok(Value::Error(e))
+            }; 
+            if !(1..=19).contains(&code) || options > 7 {
+                return Ok(Value::Error(ExcelError::Value)); 
+            }
+            (code, options < 4, options % 2 == 1, matches!(options, 2 | 3 | 6 | 7))
+        }
+    }; 
+    let mut args: Vec<Expr> = args.collect(); 
+    let k: Option<Expr> = if function >= 14 && args.len() > 1 { args.pop() } else { None }; 
+    let mut values: Vec<Value> = vec![]; 
+    for arg in args.into_iter() {
+        values.extend(subtotal_values(arg, book, debug, skip_nested, skip_hidden)?); 
+    }
+    if skip_errors {
+        values.retain(|v| !v.is_err()); 
+    } else if !matches!(function, 2 | 3) {
+        if let Some(err) = values.iter().find(|v| v.is_err()) {
+            return Ok(err.clone()); 
+        }
+    }
+    let mut function_args = vec![Value::Array(values)]; 
+    if let Some(k) = k {
+        function_args.push(ensure_non_range(evaluate_expr_with_context(k, book, debug)?)); 
+    } else if function >= 14 {
+        return Ok(Value::Error(ExcelError::Value)); 
+    }
+    get_function_value(AGGREGATES[function - 1], function_args)
+}
+
+#[function]
+fn address(row_num: Value, column_num: Value, abs_num: Option<Value>, a1: Option<Value>, sheet_text: Option<Value>) -> Value {
+    let row = row_num.ensure_single().as_num(); 
+    let column = column_num.ensure_single().as_num(); 
+    let abs_num = abs_num.map_or(1.0, |v| v.ensure_single().as_num()); 
+    if row < 1.0 || column < 1.0 || !(1.0..=4.0).contains(&abs_num) {
+        return Value::Error(ExcelError::Value); 
+    }
+    let (row, column) = (row as usize, column as usize); 
+    // 1: $A$1, 2: A$1, 3: $A1, 4: A1 
+    let (absolute_row, absolute_column) = match abs_num as usize {
+        1 => (true, true), 
+        2 => (true, false), 
+        3 => (false, true), 
+        _ => (false, false)
+    }; 
+    let a1 = !matches!(a1.map(|v| v.ensure_single()), Some(Value::Bool(false)) | Some(Value::Num(0.0))); 
+    let address = if a1 {
+        Cell { row: CellIndex::from((row, absolute_row)), column: CellIndex::from((column, absolute_column)) }.to_string()
+    } else {
+        let part = |letter: char, index: usize, absolute: bool| {
+            if absolute { format!("{}{}", letter, index) } else { format!("{}[{}]", letter, index) }
+        }; 
+        format!("{}{}", part('R', row, absolute_row), part('C', column, absolute_column))
+    }; 
+    match sheet_text {
+        Some(sheet) => Value::from(format!("{}!{}", quote_sheet(&sheet.as_text()), address)), 
+        None => Value::from(address)
+    }
+}
+
 struct Iferror {
     a: Value, 
     b: Value, 
@@ -368,190 +668,76 @@ impl Function for Iferror {
     }
 }
 
-#[function]
-fn eomonth(start_date: Value, months: Value) -> Value {
-    let start_date: NaiveDate = start_date.as_date(); 
-    let bom = NaiveDate::from_ymd(start_date.year(), start_date.month(), 1);
-    let eom: NaiveDate; 
-    if months.as_num() > 0.0 {
-        eom = bom.checked_add_months(Months::new((months.as_num()+1.0) as u32)).unwrap(); 
-    } else if months.as_num() < 0.0 {
-        eom = bom.checked_sub_months(Months::new((months.as_num()*-1.0-1.0) as u32)).unwrap(); 
-    } else {
-        eom = bom.checked_add_months(Months::new(1)).unwrap(); 
+// Values of range where every criteria_range matches its criteria. 
+fn criteria_filter(range: &Value, args: &[Value]) -> Result<Vec<Value>, ExcelError> {
+    let mask = criteria::criteria_mask(args)?; 
+    let range = range.as_array(); 
+    if range.len() != mask.len() {
+        return Err(ExcelError::Value); 
     }
-    Value::from(eom.pred())
+    Ok(range.into_iter().zip(mask).filter_map(|(v, keep)| keep.then_some(v)).collect())
+}
+
+fn matching_numbers(range: &Value, args: &[Value]) -> Result<Vec<f64>, ExcelError> {
+    collect_numbers(&[Value::Array(criteria_filter(range, args)?)])
 }
 
 #[function]
-fn sumifs(sum_range: Value, args: Vec<Value>) -> Value {
-    let mut keep_index: Vec<usize> = vec![]; 
-    for (idx, i) in (0..args.len()).step_by(2).enumerate() {
-        let cell_range: Vec<Value> = args.get(i).unwrap().as_array();
-        let criteria: Value = args.get(i+1).unwrap().ensure_single(); 
-        let criteria_text = criteria.as_text(); 
-        for (y, cell) in cell_range.iter().enumerate() {
-            let eval: bool = parse_criteria(criteria_text.as_str(), cell); 
-            if idx == 0 {
-                if eval {
-                    keep_index.push(y); 
-                }
-            } else {
-                if ! eval && keep_index.contains(&y) {
-                    keep_index.retain(|x| x != &y); 
-                }
-           }
-       } 
-    }
-    Value::from(sum_range.as_array()
-        .into_iter()
-        .enumerate()
-        .filter_map(|(i, v)| match keep_index.contains(&i) {
-            true => Some(v.as_num()), 
-            false => None
-        })
-        .collect::<Vec<f64>>()
-        .iter()
-        .sum::<f64>()) 
-} 
+fn sumif(range: Value, criteria: Value, sum_range: Option<Value>) -> Value {
+    let xs = tryv!(matching_numbers(sum_range.as_ref().unwrap_or(&range), &[range.clone(), criteria])); 
+    Value::from(xs.iter().sum::<f64>())
+}
 
 #[function]
-fn countifs(args: Vec<Value>) -> Value {
-    let mut keep_index: Vec<usize> = vec![]; 
-    for (idx, i) in (0..args.len()).step_by(2).enumerate() {
-        let cell_range: Vec<Value> = args.get(i).unwrap().as_array();
-        let criteria: Value = args.get(i+1).unwrap().ensure_single(); 
-        let criteria_text = criteria.as_text(); 
-        for (y, cell) in cell_range.iter().enumerate() {
-            let eval: bool = parse_criteria(criteria_text.as_str(), cell); 
-            if idx == 0 {
-                if eval {
-                    keep_index.push(y); 
-                }
-            } else {
-                if ! eval && keep_index.contains(&y) {
-                    keep_index.retain(|x| x != &y); 
-                }
-           }
-       } 
-    }
-    Value::from(keep_index.len())
-} 
+fn sumifs(sum_range: Value, args: Vec<Value>) -> Value {
+    Value::from(tryv!(matching_numbers(&sum_range, &args)).iter().sum::<f64>())
+}
 
+#[function]
+fn countif(range: Value, criteria: Value) -> Value {
+    Value::from(tryv!(criteria::criteria_mask(&[range, criteria])).into_iter().filter(|m| *m).count())
+}
 
 #[function]
-fn sumif(range: Value, criteria: Value, sum_range: Option<Value>) -> Value {
-    let mut keep_index: Vec<usize> = vec![]; 
-    let range: Vec<Value> = range.as_array(); 
-    let criteria = criteria.ensure_single(); 
-    let criteria_text = format!("{}", criteria); 
-    for (i, cell) in range.iter().enumerate() {
-        let eval = parse_criteria(criteria_text.as_str(), cell); 
-        if eval && !keep_index.contains(&i) {
-            keep_index.push(i); 
-        }
-    } 
-    let sum_range = match sum_range {
-        Some(val) => val.as_array(), 
-        None => range
-    }; 
-    Value::from(sum_range
-        .into_iter()
-        .enumerate()
-        .filter_map(|(i, v)| match keep_index.contains(&i) {
-            true => Some(v.as_num()), 
-            false => None
-        }) 
-        .collect::<Vec<f64>>()
-        .iter()
-        .sum::<f64>()) 
-} 
+fn countifs(args: Vec<Value>) -> Value {
+    Value::from(tryv!(criteria::criteria_mask(&args)).into_iter().filter(|m| *m).count())
+}
 
-fn parse_criteria(c: &str, cell: &Value) -> bool {
-    let cell = cell.ensure_single().as_text(); 
-    let mut op: &str = if c.split("<>").count() > 1 {
-        "<>"
-    } else if c.split("<=").count() > 1 {
-        "<="
-    } else if c.split("<").count() > 1 {
-        "<"
-    } else if c.split(">=").count() > 1 {
-        ">="
-    } else if c.split(">").count() > 1 {
-        ">"
-    } else if c.split("=").count() > 1 {
-        "="
-    } else {
-        "" 
-    }; 
-    let lh: String; 
-    let rh: String; 
-    if ! op.is_empty() {
-        lh = c.split(op).collect::<Vec<&str>>()[1].replace("\"", "").to_string(); 
-        rh = cell.replace("\"", ""); 
-    } else {
-        lh = c.replace("\"", "").to_string(); 
-        rh = cell.replace("\"", ""); 
-        op = "="; 
-    } 
-    evaluate_str(format!("\"{}\"{}\"{}\"", lh, op, rh).as_str()).unwrap().as_bool()
+fn average_of(xs: Vec<f64>) -> Value {
+    if xs.is_empty() {
+        return Value::Error(ExcelError::Div); 
+    }
+    Value::from(xs.iter().sum::<f64>() / xs.len() as f64)
 }
 
 #[function]
 fn averageif(range: Value, criteria: Value, average_range: Option<Value>) -> Value {
-    let mut keep_index: Vec<usize> = vec![]; 
-    let range: Vec<Value> = range.as_array(); 
-    let criteria = criteria.ensure_single(); 
-    let criteria_text = criteria.as_text(); 
-    for (i, cell) in range.iter().enumerate() {
-        let eval = parse_criteria(criteria_text.as_str(), cell); 
-        if eval && !keep_index.contains(&i) {
-            keep_index.push(i); 
-        }
-    } 
-    let average_range = match average_range {
-        Some(val) => val.as_array(), 
-        None => range
-    }; 
-    let average_range_filter = average_range
-        .into_iter()
-        .enumerate()
-        .filter_map(|(i, v)| match keep_index.contains(&i) {
-            true => Some(v.as_num()), 
-            false => None
-        }).collect::<Vec<f64>>(); 
-    Value::from(average_range_filter
-        .iter()
-        .sum::<f64>()/average_range_filter.len() as f64)
-} 
+    average_of(tryv!(matching_numbers(average_range.as_ref().unwrap_or(&range), &[range.clone(), criteria])))
+}
 
+#[function]
+fn averageifs(average_range: Value, args: Vec<Value>) -> Value {
+    average_of(tryv!(matching_numbers(&average_range, &args)))
+}
 
+// MINIFS and MAXIFS are 0 when nothing matches. 
+#[function]
+fn minifs(min_range: Value, args: Vec<Value>) -> Value {
+    let xs = tryv!(matching_numbers(&min_range, &args)); 
+    Value::from(xs.into_iter().reduce(f64::min).unwrap_or(0.0))
+}
 
 #[function]
-fn averageifs(average_range: Value, args: Vec<Value>) -> Value {
-    let mut keep_index: Vec<usize> = vec![]; 
-    for i in (0..args.len()).step_by(2) {
-        let cell_range: Vec<Value> = args.get(i).unwrap().as_array(); 
-        let criteria: Value = args.get(i+1).unwrap().ensure_single(); 
-        let criteria_text = criteria.as_text(); 
-        for (i, cell) in cell_range.iter().enumerate() {
-            let eval = parse_criteria(criteria_text.as_str(), cell); 
-            if eval && !keep_index.contains(&i) {
-                keep_index.push(i); 
-            }
-        } 
-    } 
-    let average_range_filter = average_range.as_array()
-        .into_iter()
-        .enumerate()
-        .filter_map(|(i, v)| match keep_index.contains(&i) {
-            true => Some(v.as_num()), 
-            false => None
-        }).collect::<Vec<f64>>(); 
-    Value::from(average_range_filter
-        .iter()
-        .sum::<f64>()/average_range_filter.len() as f64) 
-} 
+fn maxifs(max_range: Value, args: Vec<Value>) -> Value {
+    let xs = tryv!(matching_numbers(&max_range, &args)); 
+    Value::from(xs.into_iter().reduce(f64::max).unwrap_or(0.0))
+}
+
+// Empty cells and formulas returning "" 
+#[function]
+fn countblank(range: Value) -> Value {
+    Value::from(range.as_array().into_iter().filter(|v| matches!(v, Value::Empty) || matches!(v, Value::Text(t) if t.is_empty())).count())
+}
 
 #[function]
 fn sumproduct(args: Vec<Value>) -> Value {
@@ -559,16 +745,17 @@ fn sumproduct(args: Vec<Value>) -> Value {
     let mut output = Value::from(0.0); 
     for i in 0..args[0].len() {
         let mut a = Value::from(1.0); 
-        for j in 0..args.len() {
-            a = a * args[j][i].clone(); 
+        for arg in args.iter() {
+            a = a * arg[i].clone(); 
         }
         output += a; 
     }
     output
 }
 
-#[function]
-fn xirrfunc(values: Value, dates: Value) -> Value {
+#[function("XIRR")]
+fn xirrfunc(values: Value, dates: Value, guess: Option<Value>) -> Value {
+    let guess = tryv!(number_arg(&guess.unwrap_or(Value::from(0.1)))); 
     let payments: Vec<xirr::Payment> = values
         .as_array()
         .iter()
@@ -578,67 +765,42 @@ fn xirrfunc(values: Value, dates: Value) -> Value {
             .iter()
         ).map(|(v, d)| xirr::Payment { amount: v.as_num(), date: d.as_date() })
         .collect(); 
-    match xirr::compute(&payments) {
-        Ok(v) => Value::from(v), 
+    match xirr::compute(&payments, guess) {
+        Ok(v) if v.is_finite() => Value::from(v), 
         _ => Value::Error(ExcelError::Num), 
     }
 }
 
-#[function]
-fn iffunc(condition: Value, a: Value, b: Value) -> Value {
-    if condition.as_bool() {
-        a
-    } else {
-        b
-    }
+// An array condition picks from the branches element by element, as in {=SUM(IF(A1:A3>1,B1:B3,0))}. 
+pub fn if_values(condition: Value, a: Value, b: Value) -> Value {
+    elementwise(vec![condition, a, b], |v| {
+        match &v[0] {
+            Value::Error(_) => v[0].clone(), 
+            c if c.as_bool() => v[1].clone(), 
+            _ => v[2].clone()
+        }
+    })
+}
+
+#[function("IF")]
+fn iffunc(condition: Value, a: Value, b: Option<Value>) -> Value {
+    if_values(condition, a, b.unwrap_or(Value::Bool(false)))
 }
 
 #[function]
 fn xnpv(rate: Value, values: Value, dates: Value) -> Value {
     let rate: f64 = rate.as_num(); 
     let dates: Vec<NaiveDate> = dates.as_array().iter().map(|x| x.as_date()).collect(); 
-    let start_date = *dates.get(0).unwrap(); 
+    let start_date = *dates.first().unwrap(); 
     Value::from(
         values.as_array().iter().map(|x| x.as_num())
-        .into_iter()
-        .zip(
-            dates
-            .into_iter()
-        ).fold(0.0, |s, (value, date)| {
+        .zip(dates).fold(0.0, |s, (value, date)| {
             let days = NaiveDate::signed_duration_since(date, start_date).num_days() as f64; 
             s + (value / ((1.0+rate).powf(days / 365.0)))
         })
     ) 
 }
 
-#[function]
-//TODO: Implement basis
-fn yearfrac(start_date: Value, end_date: Value) -> Value {
-    let start_date: NaiveDate = start_date.as_date(); 
-    let end_date: NaiveDate = end_date.as_date(); 
-    Value::from(
-        (
-            ((end_date.year() as i32 - start_date.year() as i32) * 360) + 
-            (end_date.ordinal() as i32 - start_date.ordinal() as i32)
-        ) as f64 / 360.0
-    )    
-}
-
-#[function]
-fn datedif(start_date: Value, end_date: Value, unit: Value) -> Value {
-    let start_date: NaiveDate = start_date.as_date(); 
-    let end_date: NaiveDate = end_date.as_date(); 
-    match unit.as_text().as_str() {
-        "Y" | "y" => Value::from(end_date.year() - start_date.year()),
-        "M" | "m" => Value::from((end_date.year() as i32 - start_date.year() as i32)*12 + (end_date.month() as i32 - start_date.month() as i32)),
-        "D" | "d" => Value::from(NaiveDate::signed_duration_since(end_date, start_date).num_days() as f64),
-        "MD" | "md" => Value::from(end_date.day() as i32 - start_date.day() as i32), 
-        "YM" | "ym" => Value::from(end_date.month() as i32 - start_date.month() as i32), 
-        "YD" | "yd" => Value::from(end_date.ordinal() as i32 - start_date.ordinal() as i32),
-        _ => panic!("Not a valid unit.")
-    }
-}
-
 #[function]
 fn pmt(rate: Value, nper: Value, pv: Value, fv: Option<Value>, f_type: Option<Value>) -> Value {
     let rate = rate.as_num();
@@ -646,8 +808,8 @@ fn pmt(rate: Value, nper: Value, pv: Value, fv: Option<Value>, f_type: Option<Va
     let pv = pv.as_num();
     let fv = fv.unwrap_or_else(|| Value::from(0.0)).as_num(); 
     let f_type = f_type.unwrap_or_else(|| Value::from(0.0)).as_num();
-    let value = rate*(fv*-1.0+pv*(1.0+rate).powf(nper))/((1.0+rate*f_type)*(1.0-(1.0+rate).powf(nper)));
-    if value == f64::INFINITY || value == f64::NEG_INFINITY {
+    let value = rate*(-fv+pv*(1.0+rate).powf(nper))/((1.0+rate*f_type)*(1.0-(1.0+rate).powf(nper)));
+    if value.is_infinite() {
         Value::Error(ExcelError::Num)
     } else {
         Value::from(value)
@@ -677,88 +839,23 @@ fn counta(args: Vec<Value>) -> Value {
     )
 }
 
-//FIXME
-#[function]
-fn rounddown(x: Value, num_digits: Value) -> Value {
-    let x: f64 = x.as_num(); 
-    let num_digits: f64 = num_digits.as_num(); 
-    if num_digits > 0.0 {
-        Value::from(((x * 10.0_f64.powf(num_digits)) as i64) as f64 / 10.0_f64.powf(num_digits))
-    } else if num_digits < 0.0 {
-        Value::from(((x / 10.0_f64.powf(-num_digits)) as i64) as f64 * 10.0_f64.powf(-num_digits))
-    } else {
-        Value::from((x as i64) as f64)
-    }
-}
-
-//FIXME
-#[function]
-fn roundup(x: Value, num_digits: Value) -> Value {
-    let x: f64 = x.as_num(); 
-    let num_digits: f64 = num_digits.as_num(); 
-    if num_digits > 0.0 {
-        Value::from((((x * 10.0_f64.powf(num_digits)) as i64 + x.signum() as i64) as f64) / 10.0_f64.powf(num_digits))
-    } else if num_digits < 0.0 {
-        Value::from((((x / 10.0_f64.powf(-num_digits)) as i64 + x.signum() as i64) as f64) * 10.0_f64.powf(-num_digits))
-    } else {
-        Value::from((x as i64 + x.signum() as i64) as f64)
-    }
-}
-
-
-// TODO: Wildcard usage
+// Wildcards in find_text match like in the *IF criteria. 
 #[function]
 fn search(find_text: Value, within_text: Value, start_num: Option<Value>) -> Value {
-    let find_text = find_text.as_text().to_lowercase(); 
-    let within_text = within_text.as_text().to_lowercase(); 
-    let start_num = start_num.unwrap_or(Value::from(1.0)).as_num() as usize - 1; 
-    let mut within_text_chars = within_text.chars(); 
-    for _ in 0..start_num {
-        within_text_chars.next(); 
-    }
-    if let Some(idx) =  (&within_text_chars.collect::<String>()).find(&find_text) {
-        Value::from(idx + start_num + 1)
-    } else {
-        Value::Error(ExcelError::Value)
+    let pattern = format!("{}*", find_text.as_text()); 
+    let within_text: Vec<char> = within_text.as_text().chars().collect(); 
+    let start_num = start_num.unwrap_or(Value::from(1.0)).as_num() as usize; 
+    if start_num < 1 || start_num > within_text.len() + 1 {
+        return Value::Error(ExcelError::Value); 
+    }
+    match (start_num - 1..=within_text.len()).find(|i| lookup::wildcard_match(&pattern, &within_text[*i..].iter().collect::<String>())) {
+        Some(idx) => Value::from(idx + 1), 
+        None => Value::Error(ExcelError::Value)
     }
 }
  
-#[function]
-fn countif(range: Value, criteria: Value) -> Value {
-    let mut keep_index: Vec<usize> = vec![]; 
-    let range: Vec<Value> = range.as_array(); 
-    let criteria = criteria.ensure_single(); 
-    let criteria_text = format!("{}", criteria); 
-    for (i, cell) in range.iter().enumerate() {
-        let eval = parse_criteria(criteria_text.as_str(), cell); 
-        if eval && !keep_index.contains(&i) {
-            keep_index.push(i); 
-        }
-    } 
-    Value::from(range
-        .into_iter()
-        .enumerate()
-        .filter_map(|(i, v)| match keep_index.contains(&i) {
-            true => Some(v.as_num()), 
-            false => None
-        }) 
-        .collect::<Vec<f64>>()
-        .iter()
-        .count())
-} 
-
-#[function]
-fn month(date: Value) -> Value {
-    Value::from(date.as_date().month() as f64)
-}
-
-#[function]
-fn year(date: Value) -> Value {
-    Value::from(date.as_date().year() as f64)
-}
-
-
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests {
     use crate::{
         evaluate::{
@@ -767,6 +864,7 @@ mod tests {
         },
         workbook::Book,
         errors::Error, 
+        parser::ast::Error as ExcelError, 
     };
     use chrono::naive::NaiveDate; 
 
@@ -783,7 +881,10 @@ mod tests {
     fn test_search() -> Result<(), Error> {
         assert_eq!(evaluate_str("SEARCH(\"a\",\"Apple\") ")?, Value::from(1.0)); 
         assert_eq!(evaluate_str("SEARCH(\"the\",\"The cat in the hat\")")?, Value::from(1.0)); 
-        assert_eq!(evaluate_str("SEARCH(\"the\",\"The cat in the hat\",4)")?, Value::from(12.0)); 
+        assert_eq!(evaluate_str("SEARCH(\"the\",\"The cat in the hat\",4)")?, Value::from(12.0));
+        assert_eq!(evaluate_str("SEARCH(\"c?t\",\"The cat in the hat\")")?, Value::from(5.0)); 
+        assert_eq!(evaluate_str("SEARCH(\"h*t\",\"The cat in the hat\",3)")?, Value::from(13.0)); 
+        assert_eq!(evaluate_str("SEARCH(\"~?\",\"What?\")")?, Value::from(5.0));  
         Ok(())
     }
 
@@ -903,16 +1004,16 @@ mod tests {
 
     #[test]
     fn test_date() -> Result<(), Error> {
-		assert_eq!(evaluate_str("DATE(2022, 1, 1)")?, Value::from(NaiveDate::from_ymd(2022, 1, 1)));
+		assert_eq!(evaluate_str("DATE(2022, 1, 1)")?, Value::from(NaiveDate::from_ymd_opt(2022, 1, 1).unwrap()));
         Ok(())
     }
 
     #[test]
     fn test_floor() -> Result<(), Error> {
         assert_eq!(evaluate_str("FLOOR(3.7, 1)")?, Value::from(3.0)); 
-        // assert_eq!(evaluate_str("FLOOR(-2.5, -2)"), Value::from(-2.0)); 
-        // assert_eq!(evaluate_str("FLOOR(1.58, 0.01)"), Value::from(1.5)); 
-        // assert_eq!(evaluate_str("FLOOR(0.234, 0.01)"), Value::from(0.23)); 
+        assert_eq!(evaluate_str("FLOOR(-2.5, -2)")?, Value::from(-2.0)); 
+        assert_eq!(evaluate_str("FLOOR(1.58, 0.1)")?, Value::from(1.5)); 
+        assert_eq!(evaluate_str("FLOOR(0.234, 0.01)")?, Value::from(0.23)); 
         Ok(())
     }
 
@@ -924,10 +1025,10 @@ mod tests {
 
     #[test]
     fn test_eomonth() -> Result<(), Error> {
-        assert_eq!(evaluate_str("EOMONTH(DATE(2004, 2, 29), 12)")?, Value::from(NaiveDate::from_ymd(2005, 2, 28))); 
-        assert_eq!(evaluate_str("EOMONTH(DATE(2004, 2, 28), 12)")?, Value::from(NaiveDate::from_ymd(2005, 2, 28))); 
-        assert_eq!(evaluate_str("EOMONTH(DATE(2004, 1, 15), -23)")?, Value::from(NaiveDate::from_ymd(2002, 2, 28))); 
-        assert_eq!(evaluate_str("EOMONTH(DATE(2004, 1, 15), 0)")?, Value::from(NaiveDate::from_ymd(2004, 1, 31))); 
+        assert_eq!(evaluate_str("EOMONTH(DATE(2004, 2, 29), 12)")?, Value::from(NaiveDate::from_ymd_opt(2005, 2, 28).unwrap())); 
+        assert_eq!(evaluate_str("EOMONTH(DATE(2004, 2, 28), 12)")?, Value::from(NaiveDate::from_ymd_opt(2005, 2, 28).unwrap())); 
+        assert_eq!(evaluate_str("EOMONTH(DATE(2004, 1, 15), -23)")?, Value::from(NaiveDate::from_ymd_opt(2002, 2, 28).unwrap())); 
+        assert_eq!(evaluate_str("EOMONTH(DATE(2004, 1, 15), 0)")?, Value::from(NaiveDate::from_ymd_opt(2004, 1, 31).unwrap())); 
         Ok(())
     }
 
@@ -971,6 +1072,9 @@ mod tests {
     fn test_if() -> Result<(), Error> {
         assert_eq!(evaluate_str("IF(TRUE, 1, 2)")?, Value::from(1.0)); 
         assert_eq!(evaluate_str("IF(FALSE, 1, 2)")?, Value::from(2.0)); 
+        // An array condition picks from the branches element by element 
+        assert_eq!(evaluate_str("IF({TRUE, FALSE}, {1, 2}, {3, 4})")?, Value::from(vec![Value::from(1.0), Value::from(4.0)])); 
+        assert_eq!(evaluate_str("IF({TRUE, FALSE}, 1, {3, 4})")?, Value::from(vec![Value::from(1.0), Value::from(4.0)])); 
         Ok(())
     }
 
@@ -988,4 +1092,102 @@ mod tests {
         assert!((0.58055 - evaluate_str("YEARFRAC(DATE(2012, 1, 1), DATE(2012, 7, 30))")?.as_num() < 0.01)); 
         Ok(())
     }
+
+    #[test]
+    fn test_address() -> Result<(), Error> {
+        assert_eq!(evaluate_str("ADDRESS(2, 3)")?, Value::from("$C$2")); 
+        assert_eq!(evaluate_str("ADDRESS(2, 3, 2)")?, Value::from("C$2")); 
+        assert_eq!(evaluate_str("ADDRESS(2, 3, 3)")?, Value::from("$C2")); 
+        assert_eq!(evaluate_str("ADDRESS(2, 28, 4)")?, Value::from("AB2")); 
+        assert_eq!(evaluate_str("ADDRESS(2, 3, 1, FALSE)")?, Value::from("R2C3")); 
+        assert_eq!(evaluate_str("ADDRESS(2, 3, 4, FALSE)")?, Value::from("R[2]C[3]")); 
+        assert_eq!(evaluate_str("ADDRESS(1, 1, 1, TRUE, \"My Sheet\")")?, Value::from("'My Sheet'!$A$1")); 
+        assert_eq!(evaluate_str("ADDRESS(1, 1, 4, TRUE, \"Sheet1\")")?, Value::from("Sheet1!A1")); 
+        assert_eq!(evaluate_str("ADDRESS(0, 1)")?, Value::Error(ExcelError::Value)); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_indirect() -> Result<(), Error> {
+        let mut book = Book::from("assets/indirect.xlsx"); 
+        book.load(false).unwrap(); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Control!B1")?[[0,0]], Value::from(20.0)); 
+        assert_eq!(book.resolve_str_ref("Control!B2")?[[0,0]], Value::from(30.0)); 
+        assert_eq!(book.resolve_str_ref("Control!B3")?[[0,0]], Value::from(2.0)); 
+        assert_eq!(book.resolve_str_ref("Control!B9")?[[0,0]], Value::Error(ExcelError::Ref)); 
+        // F1:F3 are formulas calculated after E3 takes them through INDIRECT 
+        assert_eq!(book.resolve_str_ref("Control!E3")?[[0,0]], Value::from(18.0)); 
+        book.set_value("Control!A1", Value::from("South")); 
+        book.set_value("Control!D1", Value::from(1.0)); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Control!B1")?[[0,0]], Value::from(40.0)); 
+        assert_eq!(book.resolve_str_ref("Control!E3")?[[0,0]], Value::from(6.0)); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_info() -> Result<(), Error> {
+        let mut book = Book::from("assets/indirect.xlsx"); 
+        book.load(false).unwrap(); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Control!B4")?[[0,0]], Value::from(7.0)); 
+        assert_eq!(book.resolve_str_ref("Control!B5")?[[0,0]], Value::from(5.0)); 
+        assert_eq!(book.resolve_str_ref("Control!B6")?[[0,0]], Value::from(4.0)); 
+        assert_eq!(book.resolve_str_ref("Control!B7")?[[0,0]], Value::from(3.0)); 
+        assert_eq!(book.resolve_str_ref("Control!B8")?[[0,0]], Value::from(3.0)); 
+        assert_eq!(book.resolve_str_ref("Control!B10")?[[0,0]], Value::from(2.0)); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_minifs_maxifs() -> Result<(), Error> {
+        assert_eq!(evaluate_str("MINIFS({5, 3, 8}, {\"a\", \"b\", \"a\"}, \"a\")")?, Value::from(5.0)); 
+        assert_eq!(evaluate_str("MAXIFS({5, 3, 8}, {\"a\", \"b\", \"a\"}, \"a\", {1, 2, 3}, \"<>3\")")?, Value::from(5.0)); 
+        assert_eq!(evaluate_str("MAXIFS({5, 3, 8}, {\"a\", \"b\", \"a\"}, \"a\", {1, 2, 3}, \"<3\")")?, Value::from(5.0)); 
+        assert_eq!(evaluate_str("MAXIFS({5, 3, 8}, {\"a\", \"b\", \"a\"}, \"c\")")?, Value::from(0.0)); 
+        assert_eq!(evaluate_str("COUNTBLANK({1, \"\", 2})")?, Value::from(1.0)); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_criteria_functions() -> Result<(), Error> {
+        assert_eq!(evaluate_str("COUNTIF({9, 10, 100, \"100\"}, \">=10\")")?, Value::from(2.0)); 
+        assert_eq!(evaluate_str("COUNTIF({\"apple\", \"Avocado\", \"pear\", \"\"}, \"a*\")")?, Value::from(2.0)); 
+        assert_eq!(evaluate_str("COUNTIF({\"apple\", \"pear\", \"\"}, \"<>\")")?, Value::from(2.0)); 
+        assert_eq!(evaluate_str("SUMIF({1, 5, 10}, \">2\")")?, Value::from(15.0)); 
+        assert_eq!(evaluate_str("SUMIF({\"a\", \"b\", \"a\"}, \"A\", {1, 5, 10})")?, Value::from(11.0)); 
+        assert_eq!(evaluate_str("SUMIFS({1, 5, 10}, {\"a\", \"b\", \"a\"}, \"a\", {3, 2, 1}, \">1\")")?, Value::from(1.0)); 
+        assert_eq!(evaluate_str("AVERAGEIFS({1, 5, 10}, {\"a\", \"b\", \"a\"}, \"a\")")?, Value::from(5.5)); 
+        assert_eq!(evaluate_str("AVERAGEIF({1, 5, 10}, \">20\")")?, Value::Error(ExcelError::Div)); 
+        assert_eq!(evaluate_str("COUNTIFS({1, 2}, 1, {1}, 1)")?, Value::Error(ExcelError::Value)); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_subtotal() -> Result<(), Error> {
+        let mut book = Book::from("assets/subtotal.xlsx"); 
+        book.load(false).unwrap(); 
+        book.calculate(false, false)?; 
+        let cell = |r: &str| book.resolve_str_ref(r).unwrap()[[0,0]].clone(); 
+        // A4 is a SUBTOTAL itself and row 3 is hidden 
+        assert_eq!(cell("Sheet1!B1"), Value::from(65.0)); 
+        assert_eq!(cell("Sheet1!B2"), Value::from(35.0)); 
+        assert_eq!(cell("Sheet1!B3"), Value::from(16.25)); 
+        assert_eq!(cell("Sheet1!B4"), Value::from(3.0)); 
+        assert_eq!(cell("Sheet1!B5"), Value::from(30.0)); 
+        assert_eq!(cell("Sheet1!B6"), Value::from(5.0)); 
+        assert_eq!(cell("Sheet1!B7"), Value::from(50.0)); 
+        assert_eq!(cell("Sheet1!B8"), Value::Error(ExcelError::Value)); 
+        // C2 is #DIV/0! 
+        assert_eq!(cell("Sheet1!D1"), Value::from(8.0)); 
+        assert_eq!(cell("Sheet1!D2"), Value::Error(ExcelError::Div)); 
+        assert_eq!(cell("Sheet1!D3"), Value::from(3.0)); 
+        assert_eq!(cell("Sheet1!D4"), Value::from(60.0)); 
+        assert_eq!(cell("Sheet1!D5"), Value::from(20.0)); 
+        assert_eq!(cell("Sheet1!D6"), Value::from(10.0)); 
+        assert_eq!(cell("Sheet1!D7"), Value::from(3.0)); 
+        assert_eq!(cell("Sheet1!D8"), Value::from(4.0)); 
+        Ok(())
+    }
 }
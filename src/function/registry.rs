@@ -0,0 +1,436 @@
+use std::{collections::HashMap, fmt, sync::{Arc, OnceLock}};
+use crate::{
+    evaluate::{evaluate_expr_with_context, ensure_non_range, value::Value},
+    parser::ast::{Expr, Error as ExcelError},
+    function::Function,
+    workbook::Book,
+    errors::Error,
+};
+
+// How many arguments a function takes, no maximum for ones like SUM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exactly(n: usize) -> Arity {
+        Arity { min: n, max: Some(n) }
+    }
+
+    pub fn between(min: usize, max: usize) -> Arity {
+        Arity { min, max: Some(max) }
+    }
+
+    pub fn at_least(min: usize) -> Arity {
+        Arity { min, max: None }
+    }
+
+    pub fn accepts(&self, n: usize) -> bool {
+        n >= self.min && self.max.map_or(true, |max| n <= max)
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    Required,
+    Optional,
+    Variadic, // Takes any number of arguments, like SUM's
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ParamKind,
+}
+
+// What #[function] records about a built-in function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionInfo {
+    pub names: &'static [&'static str], // Excel names, f.ex. STDEV.S and STDEV
+    pub params: &'static [Param],
+}
+
+impl FunctionInfo {
+    pub fn arity(&self) -> Arity {
+        let min = self.params.iter().filter(|p| p.kind == ParamKind::Required).count();
+        if self.params.iter().any(|p| p.kind == ParamKind::Variadic) {
+            Arity::at_least(min)
+        } else {
+            Arity::between(min, self.params.len())
+        }
+    }
+}
+
+// A built-in function, collected from every #[function] in the crate.
+#[derive(Debug)]
+pub struct Builtin {
+    pub info: FunctionInfo,
+    pub evaluate: fn(Vec<Value>) -> Value,
+}
+
+inventory::collect!(Builtin);
+
+static BUILTINS: OnceLock<HashMap<&'static str, &'static Builtin>> = OnceLock::new();
+
+fn builtins() -> &'static HashMap<&'static str, &'static Builtin> {
+    BUILTINS.get_or_init(|| {
+        inventory::iter::<Builtin>.into_iter()
+            .flat_map(|b| b.info.names.iter().map(move |name| (*name, b)))
+            .collect()
+    })
+}
+
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    builtins().get(name.to_uppercase().as_str()).copied()
+}
+
+/*
+ * The arity of a function the evaluator calculates by hand rather than through a
+ * #[function] struct, so check_arities can check them too.
+ */
+fn evaluator_arity(name: &str) -> Option<Arity> {
+    let arity = match name {
+        "NA" | "PI" | "TODAY" | "NOW" | "RAND" => Arity::exactly(0),
+        "ROWS" | "COLUMNS" | "ISREF" | "ISFORMULA" | "FORMULATEXT" | "ISBLANK" | "ISNUMBER"
+            | "ISTEXT" | "ISNONTEXT" | "ISLOGICAL" | "ISERROR" | "ISERR" | "ISNA" | "TYPE"
+            | "ERROR.TYPE" => Arity::exactly(1),
+        "IFERROR" | "IFNA" | "RANDBETWEEN" | "BYROW" | "BYCOL" => Arity::exactly(2),
+        "REDUCE" | "SCAN" | "MAKEARRAY" => Arity::exactly(3),
+        "ROW" | "COLUMN" | "SHEET" | "SHEETS" => Arity::between(0, 1),
+        "INDIRECT" => Arity::between(1, 2),
+        "INDEX" => Arity::between(2, 4),
+        "OFFSET" => Arity::between(3, 5),
+        "RANDARRAY" => Arity::between(0, 5),
+        "LAMBDA" => Arity::at_least(1),
+        "SUBTOTAL" | "MAP" | "IFS" => Arity::at_least(2),
+        "AGGREGATE" | "LET" | "SWITCH" => Arity::at_least(3),
+        _ => return None
+    };
+    Some(arity)
+}
+
+// The number of arguments a built-in or evaluator function takes.
+pub fn arity(name: &str) -> Option<Arity> {
+    match builtin(name) {
+        Some(b) => Some(b.info.arity()),
+        None => evaluator_arity(name.to_uppercase().as_str())
+    }
+}
+
+// The Excel names of every built-in function, sorted.
+pub fn builtin_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = builtins().keys().copied().collect();
+    names.sort();
+    names
+}
+
+impl Builtin {
+    pub fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        check_arity(name, self.info.arity(), args.len())?;
+        Ok((self.evaluate)(args))
+    }
+}
+
+pub fn check_arity(name: &str, arity: Arity, found: usize) -> Result<(), Error> {
+    if arity.accepts(found) {
+        Ok(())
+    } else {
+        Err(Error::ArgumentCount { name: name.to_string(), arity, found })
+    }
+}
+
+/*
+ * Every call to a built-in function in expr with the right number of arguments. Functions
+ * in `registry` replace the built-in ones and are checked when they are called.
+ */
+pub fn check_arities(expr: &Expr, registry: &FunctionRegistry) -> Result<(), Error> {
+    match expr {
+        Expr::Func { name, args } => {
+            if let Some(arity) = arity(name).filter(|_| !registry.contains(name)) {
+                check_arity(name, arity, args.len())?;
+            }
+            args.iter().try_for_each(|a| check_arities(a, registry))
+        },
+        Expr::Call(f, args) => {
+            check_arities(f, registry)?;
+            args.iter().try_for_each(|a| check_arities(a, registry))
+        },
+        Expr::Prefix(_, a) => check_arities(a, registry),
+        Expr::Infix(_, a, b) => {
+            check_arities(a, registry)?;
+            check_arities(b, registry)
+        },
+        Expr::Array(rows) => rows.iter().flatten().try_for_each(|a| check_arities(a, registry)),
+        _ => Ok(())
+    }
+}
+
+/*
+ * The arguments of a lazy function, each evaluated only when it is asked for,
+ * so a function like IF can leave the branch it does not take alone.
+ */
+pub struct LazyArgs<'a> {
+    args: Vec<Expr>,
+    book: &'a Book,
+    debug: bool,
+}
+
+impl LazyArgs<'_> {
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    pub fn expr(&self, idx: usize) -> Option<&Expr> {
+        self.args.get(idx)
+    }
+
+    pub fn value(&self, idx: usize) -> Result<Value, Error> {
+        match self.args.get(idx) {
+            Some(arg) => Ok(ensure_non_range(evaluate_expr_with_context(arg.clone(), self.book, self.debug)?)),
+            None => Ok(Value::Empty)
+        }
+    }
+}
+
+// Send and Sync so a Book holding them can move to another thread.
+type EagerFn = Arc<dyn Fn(Vec<Value>) -> Value + Send + Sync>;
+type LazyFn = Arc<dyn Fn(&LazyArgs) -> Result<Value, Error> + Send + Sync>;
+
+#[derive(Clone)]
+enum Implementation {
+    Eager(EagerFn),
+    Lazy(LazyFn),
+}
+
+#[derive(Clone)]
+pub struct UserFunction {
+    pub arity: Arity,
+    implementation: Implementation,
+}
+
+impl UserFunction {
+    pub fn is_lazy(&self) -> bool {
+        matches!(self.implementation, Implementation::Lazy(_))
+    }
+}
+
+/*
+ * Functions registered at runtime, f.ex. Rust versions of a model's VBA functions.
+ * Names are case-insensitive and a registered function takes the place of a built-in
+ * one with the same name.
+ */
+#[derive(Clone, Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, UserFunction>,
+}
+
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.functions.keys()).finish()
+    }
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    // A function of the argument values, errors included.
+    pub fn register(&mut self, name: &str, arity: Arity, f: impl Fn(Vec<Value>) -> Value + Send + Sync + 'static) {
+        self.insert(name, UserFunction { arity, implementation: Implementation::Eager(Arc::new(f)) });
+    }
+
+    // A function that evaluates its arguments itself.
+    pub fn register_lazy(&mut self, name: &str, arity: Arity, f: impl Fn(&LazyArgs) -> Result<Value, Error> + Send + Sync + 'static) {
+        self.insert(name, UserFunction { arity, implementation: Implementation::Lazy(Arc::new(f)) });
+    }
+
+    // A struct made by the #[function] attribute.
+    pub fn register_function<T: Function + From<Vec<Value>>>(&mut self, name: &str, arity: Arity) {
+        self.register(name, arity, |args| T::from(args).evaluate());
+    }
+
+    fn insert(&mut self, name: &str, function: UserFunction) {
+        self.functions.insert(name.to_uppercase(), function);
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<UserFunction> {
+        self.functions.remove(&name.to_uppercase())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(&name.to_uppercase())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(&name.to_uppercase())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.functions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+// Calls a registered function, #VALUE! for the wrong number of arguments as Excel does for VBA ones.
+pub fn call(function: &UserFunction, args: Vec<Expr>, book: &Book, debug: bool) -> Result<Value, Error> {
+    if !function.arity.accepts(args.len()) {
+        return Ok(Value::Error(ExcelError::Value));
+    }
+    match &function.implementation {
+        Implementation::Eager(f) => {
+            let arg_values = args.into_iter()
+                .map(|a| evaluate_expr_with_context(a, book, debug).map(ensure_non_range))
+                .collect::<Result<Vec<Value>, Error>>()?;
+            Ok(f(arg_values))
+        },
+        Implementation::Lazy(f) => f(&LazyArgs { args, book, debug })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::{value::Value, evaluate_expr_with_context},
+        parser::{parse_str, ast::Error as ExcelError},
+        function::{Function, get_function_value, registry::{Arity, FunctionRegistry, ParamKind, builtin, builtin_names, check_arities}},
+        workbook::Book,
+        errors::Error,
+    };
+    use excel_emulator_macro::function;
+
+    #[function]
+    fn markup(cost: Value, rate: Option<Value>) -> Value {
+        Value::from(cost.as_num() * (1.0 + rate.map(|r| r.as_num()).unwrap_or(0.25)))
+    }
+
+    fn eval(book: &Book, formula: &str) -> Result<Value, Error> {
+        evaluate_expr_with_context(parse_str(formula)?, book, false)
+    }
+
+    #[test]
+    fn test_registry() -> Result<(), Error> {
+        let mut book = Book::new();
+        assert!(matches!(eval(&book, "TWICE(2)"), Err(Error::FunctionNotSupport(_))));
+        book.functions.register("Twice", Arity::exactly(1), |args| args[0].clone() * Value::from(2.0));
+        book.functions.register_function::<Markup>("MARKUP", Arity::between(1, 2));
+        assert_eq!(eval(&book, "TWICE(SUM(1, 2))")?, Value::from(6.0));
+        assert_eq!(eval(&book, "twice(2) + MARKUP(8) + MARKUP(10, 0.5)")?, Value::from(29.0));
+        assert_eq!(eval(&book, "TWICE(1, 2)")?, Value::Error(ExcelError::Value));
+        assert_eq!(eval(&book, "MARKUP()")?, Value::Error(ExcelError::Value));
+        // Registered functions replace built-in ones
+        book.functions.register("PI", Arity::exactly(0), |_| Value::from(22.0 / 7.0));
+        assert_eq!(eval(&book, "PI()")?, Value::from(22.0 / 7.0));
+        book.functions.unregister("PI");
+        assert_eq!(eval(&book, "PI() < 3.1416")?, Value::from(true));
+        assert_eq!(book.functions.names(), vec!["MARKUP".to_string(), "TWICE".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_functions() -> Result<(), Error> {
+        let mut book = Book::new();
+        // The first argument that is not an error, later ones are never evaluated
+        book.functions.register_lazy("FIRSTOK", Arity::at_least(1), |args| {
+            for i in 0..args.len() {
+                let value = args.value(i)?;
+                if !value.is_err() {
+                    return Ok(value);
+                }
+            }
+            Ok(Value::Error(ExcelError::NA))
+        });
+        assert!(book.functions.get("firstok").unwrap().is_lazy());
+        assert_eq!(eval(&book, "FIRSTOK(#DIV/0!, 2, UNKNOWN())")?, Value::from(2.0));
+        assert!(eval(&book, "FIRSTOK(#DIV/0!, UNKNOWN())").is_err());
+        assert_eq!(eval(&book, "FIRSTOK(#N/A)")?, Value::Error(ExcelError::NA));
+        Ok(())
+    }
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_book_is_send() {
+        // Registered functions must not keep a Book on one thread
+        assert_send::<Book>();
+    }
+
+    #[test]
+    fn test_builtin_metadata() -> Result<(), Error> {
+        let vlookup = builtin("vlookup").unwrap();
+        assert_eq!(vlookup.info.arity(), Arity::between(3, 4));
+        assert_eq!(vlookup.info.params.iter().map(|p| p.kind).collect::<Vec<ParamKind>>(), vec![ParamKind::Required, ParamKind::Required, ParamKind::Required, ParamKind::Optional]);
+        assert_eq!(builtin("SUM").unwrap().info.arity(), Arity::at_least(0));
+        assert_eq!(builtin("STDEV.S").unwrap().info.names, &["STDEV", "STDEV.S"]);
+        assert!(builtin("STDEV").is_some());
+        assert!(builtin("LAMBDA").is_none());
+        // Every Excel name belongs to one function
+        let names: usize = inventory::iter::<super::Builtin>.into_iter().map(|b| b.info.names.len()).sum();
+        assert_eq!(builtin_names().len(), names);
+        match get_function_value("ROUND", vec![Value::from(1.5)]) {
+            Err(e) => assert_eq!(e.to_string(), "ROUND takes 2 arguments, found 1"),
+            other => panic!("Expected an argument count error, got {:?}", other)
+        }
+        assert_eq!(get_function_value("IF", vec![Value::from(false), Value::from(1.0)])?, Value::from(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_arity_on_load() {
+        let mut book = Book::from("assets/arity.xlsx");
+        match book.load(false) {
+            Err(e) => assert_eq!(e.to_string(), "Unable to load formula in 'Sheet1'!B1: ROUND takes 2 arguments, found 1"),
+            Ok(()) => panic!("ROUND(A1) should not load")
+        }
+    }
+
+    #[test]
+    fn test_evaluator_function_arity_on_load() {
+        let mut book = Book::from("assets/arity_evaluator.xlsx");
+        match book.load(false) {
+            Err(e) => assert_eq!(e.to_string(), "Unable to load formula in 'Sheet1'!B1: INDIRECT takes 1 to 2 arguments, found 0"),
+            Ok(()) => panic!("INDIRECT() should not load")
+        }
+        // Load runs the same check on every formula
+        let registry = FunctionRegistry::new();
+        for (formula, message) in [
+            ("INDEX({1,2})", "INDEX takes 2 to 4 arguments, found 1"),
+            ("OFFSET(A1)", "OFFSET takes 3 to 5 arguments, found 1"),
+            ("IFERROR(1)", "IFERROR takes 2 arguments, found 1"),
+            ("LET(x, 1)", "LET takes at least 3 arguments, found 2"),
+            ("RAND(1)", "RAND takes 0 arguments, found 1"),
+        ] {
+            match check_arities(&parse_str(formula).unwrap(), &registry) {
+                Err(e) => assert_eq!(e.to_string(), message),
+                Ok(()) => panic!("{} should not load", formula)
+            }
+        }
+        assert!(check_arities(&parse_str("OFFSET(A1, 1, 1) + INDEX({1,2}, 1)").unwrap(), &registry).is_ok());
+    }
+
+    #[test]
+    fn test_evaluator_function_missing_args() -> Result<(), Error> {
+        let book = Book::new();
+        assert_eq!(eval(&book, "INDIRECT()")?, Value::Error(ExcelError::Value));
+        assert_eq!(eval(&book, "INDEX({1,2})")?, Value::Error(ExcelError::Value));
+        assert_eq!(eval(&book, "OFFSET(A1)")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+}
@@ -0,0 +1,693 @@
+use std::f64::consts::PI;
+use ndarray::Array2;
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    utils::date_time_to_excel,
+    function::{Function, broadcast, number_arg, collect_numbers},
+};
+use excel_emulator_macro::function;
+
+fn mean(xs: &[f64]) -> f64 {
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+// Sum of squared deviations, divided by n - ddof.
+fn variance(xs: &[f64], ddof: usize) -> Result<f64, ExcelError> {
+    if xs.len() <= ddof || xs.is_empty() {
+        return Err(ExcelError::Div);
+    }
+    let m = mean(xs);
+    Ok(xs.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (xs.len() - ddof) as f64)
+}
+
+fn sorted(mut xs: Vec<f64>) -> Vec<f64> {
+    xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    xs
+}
+
+// Numbers of a range argument, like collect_numbers for a single argument.
+fn range_numbers(v: &Value) -> Result<Vec<f64>, ExcelError> {
+    collect_numbers(&[Value::from(v.as_array())])
+}
+
+/*
+ * (x, y) pairs of two equally sized ranges, skipping positions where either
+ * is not a number. Ranges of different sizes are #N/A.
+ */
+fn pairs(ys: &Value, xs: &Value) -> Result<Vec<(f64, f64)>, ExcelError> {
+    let (ys, xs) = (ys.as_array(), xs.as_array());
+    if ys.len() != xs.len() {
+        return Err(ExcelError::NA);
+    }
+    let as_number = |v: &Value| match v {
+        Value::Num(n) => Ok(Some(*n)),
+        Value::Date(d) => Ok(Some(date_time_to_excel(*d))),
+        Value::Error(e) => Err(e.clone()),
+        _ => Ok(None)
+    };
+    let mut output = vec![];
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        if let (Some(x), Some(y)) = (as_number(x)?, as_number(y)?) {
+            output.push((x, y));
+        }
+    }
+    Ok(output)
+}
+
+// Means and sums of squares and cross products about them: (mx, my, sxx, syy, sxy).
+fn moments(pairs: &[(f64, f64)]) -> (f64, f64, f64, f64, f64) {
+    let xs: Vec<f64> = pairs.iter().map(|p| p.0).collect();
+    let ys: Vec<f64> = pairs.iter().map(|p| p.1).collect();
+    let (mx, my) = (mean(&xs), mean(&ys));
+    let sxx = xs.iter().map(|x| (x - mx).powi(2)).sum();
+    let syy = ys.iter().map(|y| (y - my).powi(2)).sum();
+    let sxy = pairs.iter().map(|(x, y)| (x - mx) * (y - my)).sum();
+    (mx, my, sxx, syy, sxy)
+}
+
+// Least squares line through the pairs, (slope, intercept).
+fn linear_fit(pairs: &[(f64, f64)], constant: bool) -> Result<(f64, f64), ExcelError> {
+    if pairs.is_empty() {
+        return Err(ExcelError::Div);
+    }
+    if !constant {
+        let sxx: f64 = pairs.iter().map(|(x, _)| x * x).sum();
+        let sxy: f64 = pairs.iter().map(|(x, y)| x * y).sum();
+        return if sxx == 0.0 { Err(ExcelError::Div) } else { Ok((sxy / sxx, 0.0)) };
+    }
+    let (mx, my, sxx, _, sxy) = moments(pairs);
+    if sxx == 0.0 {
+        return Err(ExcelError::Div);
+    }
+    let slope = sxy / sxx;
+    Ok((slope, my - slope * mx))
+}
+
+// Interpolated k-th quantile of sorted values at zero-based position `rank`.
+fn interpolate(xs: &[f64], rank: f64) -> f64 {
+    let lower = rank.floor() as usize;
+    let fraction = rank - rank.floor();
+    match xs.get(lower + 1) {
+        Some(upper) => xs[lower] + fraction * (upper - xs[lower]),
+        None => xs[lower]
+    }
+}
+
+fn percentile_inc(xs: Vec<f64>, k: f64) -> Result<f64, ExcelError> {
+    if xs.is_empty() || !(0.0..=1.0).contains(&k) {
+        return Err(ExcelError::Num);
+    }
+    let xs = sorted(xs);
+    Ok(interpolate(&xs, k * (xs.len() - 1) as f64))
+}
+
+fn percentile_exc(xs: Vec<f64>, k: f64) -> Result<f64, ExcelError> {
+    let rank = k * (xs.len() + 1) as f64 - 1.0;
+    if xs.is_empty() || rank < 0.0 || rank > (xs.len() - 1) as f64 {
+        return Err(ExcelError::Num);
+    }
+    Ok(interpolate(&sorted(xs), rank))
+}
+
+// Error function: a series without cancellation near zero, a continued fraction for the tails.
+fn erf(x: f64) -> f64 {
+    if x < 0.0 {
+        return -erf(-x);
+    }
+    if x < 3.0 {
+        let mut term = x;
+        let mut sum = x;
+        let mut n = 0.0;
+        while term.abs() > 1e-17 * sum.abs() {
+            n += 1.0;
+            term *= 2.0 * x * x / (2.0 * n + 1.0);
+            sum += term;
+        }
+        2.0 / PI.sqrt() * (-x * x).exp() * sum
+    } else {
+        1.0 - erfc_tail(x)
+    }
+}
+
+// erfc for x >= 3 by Lentz's continued fraction.
+fn erfc_tail(x: f64) -> f64 {
+    let tiny = 1e-300;
+    let mut f = x;
+    let mut c = x;
+    let mut d = 0.0;
+    for n in 1..200 {
+        let a = n as f64 / 2.0;
+        d = x + a * d;
+        d = if d.abs() < tiny { tiny } else { d };
+        c = x + a / c;
+        c = if c.abs() < tiny { tiny } else { c };
+        d = 1.0 / d;
+        let delta = c * d;
+        f *= delta;
+        if (delta - 1.0).abs() < 1e-16 {
+            break;
+        }
+    }
+    (-x * x).exp() / (f * PI.sqrt())
+}
+
+pub fn norm_s_cdf(z: f64) -> f64 {
+    if z < -3.0 * std::f64::consts::SQRT_2 {
+        0.5 * erfc_tail(-z / std::f64::consts::SQRT_2)
+    } else {
+        0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+    }
+}
+
+pub fn norm_s_pdf(z: f64) -> f64 {
+    (-z * z / 2.0).exp() / (2.0 * PI).sqrt()
+}
+
+// Acklam's rational approximation, polished with Halley steps against norm_s_cdf.
+pub fn norm_s_inv(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02, 1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00];
+    const B: [f64; 5] = [-5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02, 6.680131188771972e+01, -1.328068155288572e+01];
+    const C: [f64; 6] = [-7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00, -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00];
+    const D: [f64; 4] = [7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00, 3.754408661907416e+00];
+    let tail = |q: f64| (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0);
+    let mut x = if p < 0.02425 {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - 0.02425 {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    };
+    for _ in 0..2 {
+        let e = norm_s_cdf(x) - p;
+        let u = e / norm_s_pdf(x);
+        x -= u / (1.0 + x * u / 2.0);
+    }
+    x
+}
+
+// Lanczos approximation of ln Γ(x) for x > 0.
+pub fn ln_gamma(x: f64) -> f64 {
+    const G: [f64; 9] = [
+        0.999_999_999_999_809_9, 676.5203681218851, -1259.1392167224028, 771.323_428_777_653_1,
+        -176.615_029_162_140_6, 12.507343278686905, -0.13857109526572012, 9.984_369_578_019_572e-6, 1.5056327351493116e-7,
+    ];
+    if x < 0.5 {
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = G.iter().enumerate().skip(1).fold(G[0], |s, (i, g)| s + g / (x + i as f64));
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// Regularized incomplete beta function I_x(a, b).
+pub fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    let tiny = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    d = if d.abs() < tiny { tiny } else { d };
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..300 {
+        let m = m as f64;
+        let numerators = [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ];
+        for (k, n) in numerators.iter().enumerate() {
+            d = 1.0 + n * d;
+            d = if d.abs() < tiny { tiny } else { d };
+            c = 1.0 + n / c;
+            c = if c.abs() < tiny { tiny } else { c };
+            d = 1.0 / d;
+            h *= d * c;
+            if k == 1 && (d * c - 1.0).abs() < 1e-16 {
+                return h;
+            }
+        }
+    }
+    h
+}
+
+// Student's t distribution with df degrees of freedom.
+pub fn t_cdf(t: f64, df: f64) -> f64 {
+    let tail = 0.5 * incomplete_beta(df / (df + t * t), df / 2.0, 0.5);
+    if t > 0.0 { 1.0 - tail } else { tail }
+}
+
+pub fn t_pdf(t: f64, df: f64) -> f64 {
+    (ln_gamma((df + 1.0) / 2.0) - ln_gamma(df / 2.0)).exp() / (df * PI).sqrt() * (1.0 + t * t / df).powf(-(df + 1.0) / 2.0)
+}
+
+#[function]
+fn median(args: Vec<Value>) -> Value {
+    let xs = sorted(tryv!(collect_numbers(&args)));
+    if xs.is_empty() {
+        return Value::Error(ExcelError::Num);
+    }
+    let n = xs.len();
+    Value::from(if n % 2 == 1 { xs[n / 2] } else { (xs[n / 2 - 1] + xs[n / 2]) / 2.0 })
+}
+
+// The most frequent number, the first one seen on ties; #N/A when nothing repeats.
+#[function("MODE", "MODE.SNGL")]
+fn mode(args: Vec<Value>) -> Value {
+    let xs = tryv!(collect_numbers(&args));
+    let mut best: Option<(f64, usize)> = None;
+    for x in xs.iter() {
+        let count = xs.iter().filter(|y| *y == x).count();
+        if count > 1 && best.map_or(true, |(_, c)| count > c) {
+            best = Some((*x, count));
+        }
+    }
+    match best {
+        Some((x, _)) => Value::from(x),
+        None => Value::Error(ExcelError::NA)
+    }
+}
+
+#[function("STDEV", "STDEV.S")]
+fn stdevs(args: Vec<Value>) -> Value {
+    Value::from(tryv!(variance(&tryv!(collect_numbers(&args)), 1)).sqrt())
+}
+
+#[function("STDEVP", "STDEV.P")]
+fn stdevp(args: Vec<Value>) -> Value {
+    Value::from(tryv!(variance(&tryv!(collect_numbers(&args)), 0)).sqrt())
+}
+
+#[function("VAR", "VAR.S")]
+fn vars(args: Vec<Value>) -> Value {
+    Value::from(tryv!(variance(&tryv!(collect_numbers(&args)), 1)))
+}
+
+#[function("VARP", "VAR.P")]
+fn varp(args: Vec<Value>) -> Value {
+    Value::from(tryv!(variance(&tryv!(collect_numbers(&args)), 0)))
+}
+
+#[function]
+fn geomean(args: Vec<Value>) -> Value {
+    let xs = tryv!(collect_numbers(&args));
+    if xs.is_empty() || xs.iter().any(|x| *x <= 0.0) {
+        return Value::Error(ExcelError::Num);
+    }
+    Value::from((xs.iter().map(|x| x.ln()).sum::<f64>() / xs.len() as f64).exp())
+}
+
+#[function("PERCENTILE", "PERCENTILE.INC")]
+fn percentileinc(array: Value, k: Value) -> Value {
+    let xs = tryv!(range_numbers(&array));
+    broadcast(vec![k], |a| Value::from(tryv!(percentile_inc(xs.clone(), tryv!(number_arg(&a[0]))))))
+}
+
+#[function("PERCENTILE.EXC")]
+fn percentileexc(array: Value, k: Value) -> Value {
+    let xs = tryv!(range_numbers(&array));
+    broadcast(vec![k], |a| Value::from(tryv!(percentile_exc(xs.clone(), tryv!(number_arg(&a[0]))))))
+}
+
+#[function("QUARTILE", "QUARTILE.INC")]
+fn quartileinc(array: Value, quart: Value) -> Value {
+    let xs = tryv!(range_numbers(&array));
+    broadcast(vec![quart], |a| {
+        let q = tryv!(number_arg(&a[0])).trunc();
+        if !(0.0..=4.0).contains(&q) {
+            return Value::Error(ExcelError::Num);
+        }
+        Value::from(tryv!(percentile_inc(xs.clone(), q / 4.0)))
+    })
+}
+
+#[function("QUARTILE.EXC")]
+fn quartileexc(array: Value, quart: Value) -> Value {
+    let xs = tryv!(range_numbers(&array));
+    broadcast(vec![quart], |a| {
+        let q = tryv!(number_arg(&a[0])).trunc();
+        if !(1.0..=3.0).contains(&q) {
+            return Value::Error(ExcelError::Num);
+        }
+        Value::from(tryv!(percentile_exc(xs.clone(), q / 4.0)))
+    })
+}
+
+// Position of number in ref, largest first unless order is non-zero; ties share a rank or average it.
+fn rank(number: Value, reference: Value, order: Option<Value>, average: bool) -> Value {
+    let xs = tryv!(range_numbers(&reference));
+    let ascending = tryv!(number_arg(&order.unwrap_or(Value::from(0.0)))) != 0.0;
+    broadcast(vec![number], |a| {
+        let x = tryv!(number_arg(&a[0]));
+        let ties = xs.iter().filter(|y| **y == x).count();
+        if ties == 0 {
+            return Value::Error(ExcelError::NA);
+        }
+        let before = xs.iter().filter(|y| if ascending { **y < x } else { **y > x }).count();
+        if average {
+            Value::from(before as f64 + (ties as f64 + 1.0) / 2.0)
+        } else {
+            Value::from(before + 1)
+        }
+    })
+}
+
+#[function("RANK", "RANK.EQ")]
+fn rankeq(number: Value, reference: Value, order: Option<Value>) -> Value {
+    rank(number, reference, order, false)
+}
+
+#[function("RANK.AVG")]
+fn rankavg(number: Value, reference: Value, order: Option<Value>) -> Value {
+    rank(number, reference, order, true)
+}
+
+fn kth(array: Value, k: Value, largest: bool) -> Value {
+    let mut xs = sorted(tryv!(range_numbers(&array)));
+    if largest {
+        xs.reverse();
+    }
+    broadcast(vec![k], |a| {
+        let k = tryv!(number_arg(&a[0])).ceil();
+        if k < 1.0 || k as usize > xs.len() {
+            return Value::Error(ExcelError::Num);
+        }
+        Value::from(xs[k as usize - 1])
+    })
+}
+
+#[function]
+fn large(array: Value, k: Value) -> Value {
+    kth(array, k, true)
+}
+
+#[function]
+fn small(array: Value, k: Value) -> Value {
+    kth(array, k, false)
+}
+
+#[function]
+fn correl(array1: Value, array2: Value) -> Value {
+    let pairs = tryv!(pairs(&array2, &array1));
+    if pairs.len() < 2 {
+        return Value::Error(ExcelError::Div);
+    }
+    let (_, _, sxx, syy, sxy) = moments(&pairs);
+    if sxx == 0.0 || syy == 0.0 {
+        return Value::Error(ExcelError::Div);
+    }
+    Value::from(sxy / (sxx * syy).sqrt())
+}
+
+#[function]
+fn rsq(known_ys: Value, known_xs: Value) -> Value {
+    match Correl::correl(known_ys, known_xs) {
+        Value::Num(r) => Value::from(r * r),
+        e => e
+    }
+}
+
+#[function("COVAR", "COVARIANCE.P")]
+fn covariancep(array1: Value, array2: Value) -> Value {
+    let pairs = tryv!(pairs(&array2, &array1));
+    if pairs.is_empty() {
+        return Value::Error(ExcelError::Div);
+    }
+    let (_, _, _, _, sxy) = moments(&pairs);
+    Value::from(sxy / pairs.len() as f64)
+}
+
+#[function("COVARIANCE.S")]
+fn covariances(array1: Value, array2: Value) -> Value {
+    let pairs = tryv!(pairs(&array2, &array1));
+    if pairs.len() < 2 {
+        return Value::Error(ExcelError::Div);
+    }
+    let (_, _, _, _, sxy) = moments(&pairs);
+    Value::from(sxy / (pairs.len() - 1) as f64)
+}
+
+#[function]
+fn slope(known_ys: Value, known_xs: Value) -> Value {
+    let pairs = tryv!(pairs(&known_ys, &known_xs));
+    Value::from(tryv!(linear_fit(&pairs, true)).0)
+}
+
+#[function]
+fn intercept(known_ys: Value, known_xs: Value) -> Value {
+    let pairs = tryv!(pairs(&known_ys, &known_xs));
+    Value::from(tryv!(linear_fit(&pairs, true)).1)
+}
+
+#[function("FORECAST", "FORECAST.LINEAR")]
+fn forecast(x: Value, known_ys: Value, known_xs: Value) -> Value {
+    let pairs = tryv!(pairs(&known_ys, &known_xs));
+    let (slope, intercept) = tryv!(linear_fit(&pairs, true));
+    broadcast(vec![x], |a| Value::from(intercept + slope * tryv!(number_arg(&a[0]))))
+}
+
+// known_xs defaults to 1, 2, 3..., and new_xs to known_xs.
+fn fitted(known_ys: &Value, known_xs: Option<Value>, new_xs: Option<Value>) -> (Value, Value) {
+    let known_xs = known_xs.unwrap_or_else(|| {
+        let n = known_ys.as_array().len();
+        match known_ys {
+            Value::Array2(arr2) => Value::from(Array2::from_shape_fn(arr2.dim(), |(i, j)| Value::from((i * arr2.ncols() + j + 1) as f64))),
+            _ => Value::from((1..=n).map(|i| Value::from(i as f64)).collect::<Vec<Value>>())
+        }
+    });
+    let new_xs = new_xs.unwrap_or(known_xs.clone());
+    (known_xs, new_xs)
+}
+
+fn map_numbers(xs: &Value, f: impl Fn(f64) -> Value) -> Value {
+    match xs {
+        Value::Array2(arr2) => Value::from(arr2.map(|x| match number_arg(x) {
+            Ok(x) => f(x),
+            Err(e) => Value::Error(e)
+        })),
+        Value::Array(arr) => Value::from(arr.iter().map(|x| match number_arg(x) {
+            Ok(x) => f(x),
+            Err(e) => Value::Error(e)
+        }).collect::<Vec<Value>>()),
+        x => match number_arg(x) {
+            Ok(x) => f(x),
+            Err(e) => Value::Error(e)
+        }
+    }
+}
+
+// Values on the least squares line, for each of new_xs.
+#[function]
+fn trend(known_ys: Value, known_xs: Option<Value>, new_xs: Option<Value>, constant: Option<Value>) -> Value {
+    let (known_xs, new_xs) = fitted(&known_ys, known_xs, new_xs);
+    let constant = tryv!(number_arg(&constant.unwrap_or(Value::from(true)))) != 0.0;
+    let pairs = tryv!(pairs(&known_ys, &known_xs));
+    let (slope, intercept) = tryv!(linear_fit(&pairs, constant));
+    map_numbers(&new_xs, |x| Value::from(intercept + slope * x))
+}
+
+// Like TREND, on the exponential curve y = b * m^x.
+#[function]
+fn growth(known_ys: Value, known_xs: Option<Value>, new_xs: Option<Value>, constant: Option<Value>) -> Value {
+    let (known_xs, new_xs) = fitted(&known_ys, known_xs, new_xs);
+    let constant = tryv!(number_arg(&constant.unwrap_or(Value::from(true)))) != 0.0;
+    let pairs = tryv!(pairs(&known_ys, &known_xs));
+    if pairs.iter().any(|(_, y)| *y <= 0.0) {
+        return Value::Error(ExcelError::Num);
+    }
+    let logs: Vec<(f64, f64)> = pairs.iter().map(|(x, y)| (*x, y.ln())).collect();
+    let (slope, intercept) = tryv!(linear_fit(&logs, constant));
+    map_numbers(&new_xs, |x| Value::from((intercept + slope * x).exp()))
+}
+
+#[function("NORMDIST", "NORM.DIST")]
+fn normdist(x: Value, mean: Value, standard_dev: Value, cumulative: Value) -> Value {
+    broadcast(vec![x, mean, standard_dev, cumulative], |a| {
+        let (x, m, sd) = (tryv!(number_arg(&a[0])), tryv!(number_arg(&a[1])), tryv!(number_arg(&a[2])));
+        if sd <= 0.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        let z = (x - m) / sd;
+        if tryv!(number_arg(&a[3])) != 0.0 {
+            Value::from(norm_s_cdf(z))
+        } else {
+            Value::from(norm_s_pdf(z) / sd)
+        }
+    })
+}
+
+#[function("NORMSDIST", "NORM.S.DIST")]
+fn normsdist(z: Value, cumulative: Option<Value>) -> Value {
+    broadcast(vec![z, cumulative.unwrap_or(Value::from(true))], |a| {
+        let z = tryv!(number_arg(&a[0]));
+        Value::from(if tryv!(number_arg(&a[1])) != 0.0 { norm_s_cdf(z) } else { norm_s_pdf(z) })
+    })
+}
+
+#[function("NORMINV", "NORM.INV")]
+fn norminv(probability: Value, mean: Value, standard_dev: Value) -> Value {
+    broadcast(vec![probability, mean, standard_dev], |a| {
+        let (p, m, sd) = (tryv!(number_arg(&a[0])), tryv!(number_arg(&a[1])), tryv!(number_arg(&a[2])));
+        if p <= 0.0 || p >= 1.0 || sd <= 0.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        Value::from(m + sd * norm_s_inv(p))
+    })
+}
+
+#[function("NORMSINV", "NORM.S.INV")]
+fn normsinv(probability: Value) -> Value {
+    broadcast(vec![probability], |a| {
+        let p = tryv!(number_arg(&a[0]));
+        if p <= 0.0 || p >= 1.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        Value::from(norm_s_inv(p))
+    })
+}
+
+#[function("T.DIST")]
+fn tdist(x: Value, deg_freedom: Value, cumulative: Value) -> Value {
+    broadcast(vec![x, deg_freedom, cumulative], |a| {
+        let (x, df) = (tryv!(number_arg(&a[0])), tryv!(number_arg(&a[1])).trunc());
+        if df < 1.0 {
+            return Value::Error(ExcelError::Num);
+        }
+        Value::from(if tryv!(number_arg(&a[2])) != 0.0 { t_cdf(x, df) } else { t_pdf(x, df) })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        parser::ast::Error as ExcelError,
+        errors::Error,
+    };
+
+    fn approx(formula: &str, expected: f64) -> Result<(), Error> {
+        let value = evaluate_str(formula)?.as_num();
+        assert!((value - expected).abs() < 1e-8 * expected.abs().max(1.0), "{} = {}, expected {}", formula, value, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_center() -> Result<(), Error> {
+        assert_eq!(evaluate_str("MEDIAN(1, 2, 3, 4, 5)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("MEDIAN(1, 2, 3, 4, 5, 6)")?, Value::from(3.5));
+        assert_eq!(evaluate_str("MEDIAN({1, \"a\", TRUE, 9}, 4)")?, Value::from(4.0));
+        assert_eq!(evaluate_str("MEDIAN(\"3\", TRUE, 10)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("MEDIAN(\"a\", 1)")?, Value::Error(ExcelError::Value));
+        assert_eq!(evaluate_str("MEDIAN({\"a\"})")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("MODE({5.6, 4, 4, 3, 2, 4})")?, Value::from(4.0));
+        assert_eq!(evaluate_str("MODE({1, 2, 2, 1})")?, Value::from(1.0));
+        assert_eq!(evaluate_str("MODE({1, 2, 3})")?, Value::Error(ExcelError::NA));
+        approx("GEOMEAN(4, 5, 8, 7, 11, 4, 3)", 5.476986969)?;
+        assert_eq!(evaluate_str("GEOMEAN(4, 0)")?, Value::Error(ExcelError::Num));
+        Ok(())
+    }
+
+    #[test]
+    fn test_spread() -> Result<(), Error> {
+        let data = "{1345, 1301, 1368, 1322, 1310, 1370, 1318, 1350, 1303, 1299}";
+        approx(&format!("STDEV({})", data), 27.46391572)?;
+        approx(&format!("STDEV.S({})", data), 27.46391572)?;
+        approx(&format!("STDEV.P({})", data), 26.05455814)?;
+        approx(&format!("VAR({})", data), 754.2666667)?;
+        approx(&format!("VAR.P({})", data), 678.84)?;
+        assert_eq!(evaluate_str("STDEV(1)")?, Value::Error(ExcelError::Div));
+        assert_eq!(evaluate_str("VAR.S({1, TRUE}, TRUE, 4)")?, Value::from(3.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_percentiles() -> Result<(), Error> {
+        assert_eq!(evaluate_str("PERCENTILE({1, 3, 2, 4}, 0.3)")?, Value::from(1.9));
+        assert_eq!(evaluate_str("PERCENTILE.INC({1, 2, 3, 4}, 1)")?, Value::from(4.0));
+        assert_eq!(evaluate_str("PERCENTILE.INC({1, 2, 3, 4}, 1.1)")?, Value::Error(ExcelError::Num));
+        approx("PERCENTILE.EXC({1, 2, 3, 6, 6, 6, 7, 8, 9}, 0.25)", 2.5)?;
+        assert_eq!(evaluate_str("PERCENTILE.EXC({1, 2, 3}, 0.1)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("QUARTILE({1, 2, 4, 7, 8, 9, 10, 12}, 1)")?, Value::from(3.5));
+        assert_eq!(evaluate_str("QUARTILE.INC({1, 2, 4, 7, 8, 9, 10, 12}, 3)")?, Value::from(9.25));
+        assert_eq!(evaluate_str("QUARTILE.EXC({6, 7, 15, 36, 39, 40, 41, 42, 43, 47, 49}, 1)")?, Value::from(15.0));
+        assert_eq!(evaluate_str("QUARTILE.EXC({6, 7, 15, 36, 39, 40, 41, 42, 43, 47, 49}, 3)")?, Value::from(43.0));
+        assert_eq!(evaluate_str("QUARTILE.EXC({1, 2}, 0)")?, Value::Error(ExcelError::Num));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank() -> Result<(), Error> {
+        let data = "{7, 3.5, 3.5, 1, 2}";
+        assert_eq!(evaluate_str(&format!("RANK(7, {})", data))?, Value::from(1.0));
+        assert_eq!(evaluate_str(&format!("RANK.EQ(3.5, {})", data))?, Value::from(2.0));
+        assert_eq!(evaluate_str(&format!("RANK.EQ(2, {}, 1)", data))?, Value::from(2.0));
+        assert_eq!(evaluate_str(&format!("RANK.AVG(3.5, {})", data))?, Value::from(2.5));
+        assert_eq!(evaluate_str(&format!("RANK(5, {})", data))?, Value::Error(ExcelError::NA));
+        assert_eq!(evaluate_str("LARGE({3, 5, 3, 5, 4}, 3)")?, Value::from(4.0));
+        assert_eq!(evaluate_str("SMALL({3, 5, 3, 5, 4}, 2)")?, Value::from(3.0));
+        assert_eq!(evaluate_str("SMALL({3, 5}, 3)")?, Value::Error(ExcelError::Num));
+        assert_eq!(evaluate_str("LARGE({3, 5, 4}, {1, 2})")?, Value::from(vec![Value::from(5.0), Value::from(4.0)]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_regression() -> Result<(), Error> {
+        let (ys, xs) = ("{2, 3, 9, 1, 8, 7, 5}", "{6, 5, 11, 7, 5, 4, 4}");
+        approx(&format!("SLOPE({}, {})", ys, xs), 0.305555556)?;
+        approx(&format!("INTERCEPT({}, {})", ys, xs), 3.166666667)?;
+        approx(&format!("RSQ({}, {})", ys, xs), 0.057950192)?;
+        approx("CORREL({3, 2, 4, 5, 6}, {9, 7, 12, 15, 17})", 0.997054486)?;
+        approx("COVARIANCE.P({3, 2, 4, 5, 6}, {9, 7, 12, 15, 17})", 5.2)?;
+        approx("COVAR({3, 2, 4, 5, 6}, {9, 7, 12, 15, 17})", 5.2)?;
+        approx("COVARIANCE.S({2, 4, 8}, {5, 11, 12})", 9.666666667)?;
+        approx("FORECAST(30, {6, 7, 9, 15, 21}, {20, 28, 31, 38, 40})", 10.60725308)?;
+        approx("FORECAST.LINEAR(30, {6, 7, 9, 15, 21}, {20, 28, 31, 38, 40})", 10.60725308)?;
+        assert_eq!(evaluate_str("SLOPE({1, 2}, {1, 2, 3})")?, Value::Error(ExcelError::NA));
+        assert_eq!(evaluate_str("SLOPE({1, 2}, {3, 3})")?, Value::Error(ExcelError::Div));
+        let trend = evaluate_str("TREND({1, 3, 5}, {1, 2, 3}, {4, 5})")?.as_array();
+        assert!((trend[0].as_num() - 7.0).abs() < 1e-9 && (trend[1].as_num() - 9.0).abs() < 1e-9);
+        let trend = evaluate_str("TREND({2, 4, 6})")?.as_array();
+        assert!((trend[2].as_num() - 6.0).abs() < 1e-9);
+        let growth = evaluate_str("GROWTH({33100, 47300, 69000, 102000, 150000, 220000}, {11, 12, 13, 14, 15, 16}, {17, 18})")?.as_array();
+        assert!((growth[0].as_num() - 320196.7184).abs() < 1e-3);
+        assert!((growth[1].as_num() - 468536.054).abs() < 1e-3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_distributions() -> Result<(), Error> {
+        approx("NORM.DIST(42, 40, 1.5, TRUE)", 0.908788780)?;
+        approx("NORM.DIST(42, 40, 1.5, FALSE)", 0.10934005)?;
+        approx("NORMDIST(42, 40, 1.5, TRUE)", 0.908788780)?;
+        assert_eq!(evaluate_str("NORM.DIST(42, 40, 0, TRUE)")?, Value::Error(ExcelError::Num));
+        approx("NORM.S.DIST(1.333333, TRUE)", 0.908788726)?;
+        approx("NORM.S.DIST(1.333333, FALSE)", 0.164010148)?;
+        approx("NORMSDIST(-6)", 9.86587645e-10)?;
+        approx("NORM.S.DIST(-10, TRUE)", 7.61985302e-24)?;
+        approx("NORM.INV(0.908789, 40, 1.5)", 42.00000201)?;
+        approx("NORM.S.INV(0.908789)", 1.333334673)?;
+        approx("NORMSINV(0.001)", -3.090232306)?;
+        assert_eq!(evaluate_str("NORM.INV(0, 40, 1.5)")?, Value::Error(ExcelError::Num));
+        approx("T.DIST(60, 1, TRUE)", 0.99469533)?;
+        approx("T.DIST(8, 3, FALSE)", 0.00073691)?;
+        approx("T.DIST(-1.5, 10, TRUE)", 0.0822536632)?;
+        assert_eq!(evaluate_str("T.DIST(1, 0, TRUE)")?, Value::Error(ExcelError::Num));
+        Ok(())
+    }
+}
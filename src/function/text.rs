@@ -0,0 +1,735 @@
+use ndarray::Array2;
+use chrono::NaiveDate;
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    utils::{date_to_excel, date_time_to_excel},
+    format::NumberFormat,
+    function::{Function, broadcast, number_arg},
+};
+use excel_emulator_macro::function;
+
+// Longest text a cell can hold.
+const MAX_TEXT: usize = 32767;
+
+/*
+ * Text of a value as Excel converts it: numbers in General format with up to
+ * 15 significant digits, logicals as TRUE/FALSE and blanks as "".
+ */
+pub fn text_arg(v: &Value) -> String {
+    match v {
+        Value::Text(t) => t.clone(),
+        Value::Num(n) => general_number(*n),
+        Value::Bool(b) => String::from(if *b { "TRUE" } else { "FALSE" }),
+        Value::Date(d) => general_number(date_time_to_excel(*d)),
+        Value::Empty => String::new(),
+        Value::Array(_) | Value::Array2(_) => text_arg(&v.ensure_single()),
+        v => v.as_text()
+    }
+}
+
+pub fn general_number(x: f64) -> String {
+    if x == 0.0 || !x.is_finite() {
+        return String::from("0");
+    }
+    let a = x.abs();
+    if !(1e-9..1e15).contains(&a) {
+        // 1.5E+20 or 1E-10
+        let s = format!("{:.14e}", x);
+        let (mantissa, exponent) = s.split_once('e').unwrap();
+        let mantissa = trim_zeros(mantissa);
+        let exponent: i32 = exponent.parse().unwrap();
+        format!("{}E{}{:02}", mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+    } else {
+        let decimals = (14 - a.log10().floor() as i32).max(0) as usize;
+        trim_zeros(&format!("{:.*}", decimals, x))
+    }
+}
+
+fn trim_zeros(s: &str) -> String {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+// Positions and lengths count UTF-16 code units, like Excel.
+fn units(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+fn from_units(u: &[u16]) -> String {
+    String::from_utf16_lossy(u)
+}
+
+// Whole number argument, truncated toward zero.
+fn int_arg(v: &Value) -> Result<i64, ExcelError> {
+    number_arg(v).map(|n| n.trunc() as i64)
+}
+
+fn limit(s: String) -> Value {
+    if s.encode_utf16().count() > MAX_TEXT {
+        Value::Error(ExcelError::Value)
+    } else {
+        Value::from(s)
+    }
+}
+
+/*
+ * VALUE and numeric coercion of text: plain and scientific numbers, thousands
+ * separators, a leading $, a trailing %, (negative) amounts, times and ISO or
+ * US dates.
+ */
+pub fn parse_value(s: &str) -> Option<f64> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    if let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        return parse_value(inner).map(|n| -n);
+    }
+    if let Some(number) = s.strip_suffix('%') {
+        return parse_value(number).map(|n| n / 100.0);
+    }
+    if let Some(rest) = s.strip_prefix('-') {
+        return parse_value(rest).map(|n| -n);
+    }
+    if let Some(rest) = s.strip_prefix('+').or_else(|| s.strip_prefix('$')) {
+        return parse_value(rest);
+    }
+    let plain = s.replace(',', "");
+    let is_number = plain.chars().all(|c| c.is_ascii_digit() || ".eE+-".contains(c))
+        && plain.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        && (!s.contains(',') || s.split('.').next().unwrap().split(',').skip(1).all(|g| g.len() == 3));
+    match plain.parse::<f64>() {
+        Ok(n) if is_number => Some(n),
+        _ => parse_date_time(s)
+    }
+}
+
+// Date formats DATEVALUE and text coercion accept.
+const DATE_FORMATS: [&str; 7] = ["%Y-%m-%d", "%m/%d/%Y", "%Y/%m/%d", "%d-%b-%Y", "%d %B %Y", "%B %d, %Y", "%B %d %Y"];
+
+// A date, a time or a date followed by a time, as a serial.
+pub fn parse_date_time(s: &str) -> Option<f64> {
+    let (date, time) = match s.find(':') {
+        Some(colon) => {
+            let start = s[..colon].rfind(' ').map(|i| i + 1).unwrap_or(0);
+            (s[..start].trim(), Some(s[start..].trim()))
+        },
+        None => (s, None)
+    };
+    let days = if date.is_empty() {
+        0.0
+    } else {
+        let parsed = DATE_FORMATS.iter().find_map(|f| NaiveDate::parse_from_str(date, f).ok())?;
+        date_to_excel(parsed)
+    };
+    let fraction = match time {
+        Some(time) => parse_time(time)?,
+        None => 0.0
+    };
+    Some(days + fraction)
+}
+
+// 13:30, 1:30:15 PM
+fn parse_time(s: &str) -> Option<f64> {
+    let upper = s.to_uppercase();
+    let (clock, offset) = match upper.strip_suffix("PM").or_else(|| upper.strip_suffix("AM")) {
+        Some(clock) => (clock.trim().to_string(), if upper.ends_with("PM") { Some(12.0) } else { Some(0.0) }),
+        None => (upper.clone(), None)
+    };
+    let parts: Vec<f64> = clock.split(':').map(|p| p.trim().parse::<f64>().ok()).collect::<Option<Vec<f64>>>()?;
+    if parts.len() < 2 || parts.len() > 3 {
+        return None;
+    }
+    let mut hours = parts[0];
+    if let Some(offset) = offset {
+        if !(1.0..=12.0).contains(&hours) {
+            return None;
+        }
+        hours = hours % 12.0 + offset;
+    }
+    let seconds = hours * 3600.0 + parts[1] * 60.0 + parts.get(2).copied().unwrap_or(0.0);
+    Some(seconds / 86400.0)
+}
+
+// Windows-1252 characters 128 to 159, which CHAR and CODE use.
+const CP1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+#[function]
+fn left(text: Value, num_chars: Option<Value>) -> Value {
+    broadcast(vec![text, num_chars.unwrap_or(Value::from(1.0))], |a| {
+        let n = tryv!(int_arg(&a[1]));
+        if n < 0 {
+            return Value::Error(ExcelError::Value);
+        }
+        let u = units(&text_arg(&a[0]));
+        Value::from(from_units(&u[..u.len().min(n as usize)]))
+    })
+}
+
+#[function]
+fn right(text: Value, num_chars: Option<Value>) -> Value {
+    broadcast(vec![text, num_chars.unwrap_or(Value::from(1.0))], |a| {
+        let n = tryv!(int_arg(&a[1]));
+        if n < 0 {
+            return Value::Error(ExcelError::Value);
+        }
+        let u = units(&text_arg(&a[0]));
+        Value::from(from_units(&u[u.len() - u.len().min(n as usize)..]))
+    })
+}
+
+#[function]
+fn mid(text: Value, start_num: Value, num_chars: Value) -> Value {
+    broadcast(vec![text, start_num, num_chars], |a| {
+        let (start, n) = (tryv!(int_arg(&a[1])), tryv!(int_arg(&a[2])));
+        if start < 1 || n < 0 {
+            return Value::Error(ExcelError::Value);
+        }
+        let u = units(&text_arg(&a[0]));
+        let start = (start as usize - 1).min(u.len());
+        let end = start.saturating_add(n as usize).min(u.len());
+        Value::from(from_units(&u[start..end]))
+    })
+}
+
+#[function]
+fn len(text: Value) -> Value {
+    broadcast(vec![text], |a| Value::from(units(&text_arg(&a[0])).len()))
+}
+
+// Drops leading and trailing spaces and collapses runs of spaces inside to one.
+#[function]
+fn trim(text: Value) -> Value {
+    broadcast(vec![text], |a| {
+        Value::from(text_arg(&a[0]).split(' ').filter(|w| !w.is_empty()).collect::<Vec<&str>>().join(" "))
+    })
+}
+
+#[function]
+fn upper(text: Value) -> Value {
+    broadcast(vec![text], |a| Value::from(text_arg(&a[0]).to_uppercase()))
+}
+
+#[function]
+fn lower(text: Value) -> Value {
+    broadcast(vec![text], |a| Value::from(text_arg(&a[0]).to_lowercase()))
+}
+
+// Capitalizes every letter that follows a non-letter, so "2-way it's" becomes "2-Way It'S".
+#[function]
+fn proper(text: Value) -> Value {
+    broadcast(vec![text], |a| {
+        let mut previous_is_letter = false;
+        let mut output = String::new();
+        for c in text_arg(&a[0]).chars() {
+            if previous_is_letter {
+                output.extend(c.to_lowercase());
+            } else {
+                output.extend(c.to_uppercase());
+            }
+            previous_is_letter = c.is_alphabetic();
+        }
+        Value::from(output)
+    })
+}
+
+#[function]
+fn substitute(text: Value, old_text: Value, new_text: Value, instance_num: Option<Value>) -> Value {
+    broadcast(vec![text, old_text, new_text, instance_num.unwrap_or(Value::Empty)], |a| {
+        let (text, old, new) = (text_arg(&a[0]), text_arg(&a[1]), text_arg(&a[2]));
+        if old.is_empty() {
+            return Value::from(text);
+        }
+        if a[3].is_empty() {
+            return limit(text.replace(&old, &new));
+        }
+        let instance = tryv!(int_arg(&a[3]));
+        if instance < 1 {
+            return Value::Error(ExcelError::Value);
+        }
+        match text.match_indices(&old).nth(instance as usize - 1) {
+            Some((i, _)) => limit(format!("{}{}{}", &text[..i], new, &text[i + old.len()..])),
+            None => Value::from(text)
+        }
+    })
+}
+
+#[function]
+fn replace(old_text: Value, start_num: Value, num_chars: Value, new_text: Value) -> Value {
+    broadcast(vec![old_text, start_num, num_chars, new_text], |a| {
+        let (start, n) = (tryv!(int_arg(&a[1])), tryv!(int_arg(&a[2])));
+        if start < 1 || n < 0 {
+            return Value::Error(ExcelError::Value);
+        }
+        let u = units(&text_arg(&a[0]));
+        let start = (start as usize - 1).min(u.len());
+        let end = start.saturating_add(n as usize).min(u.len());
+        limit(format!("{}{}{}", from_units(&u[..start]), text_arg(&a[3]), from_units(&u[end..])))
+    })
+}
+
+// Case-sensitive and without wildcards, unlike SEARCH.
+#[function]
+fn find(find_text: Value, within_text: Value, start_num: Option<Value>) -> Value {
+    broadcast(vec![find_text, within_text, start_num.unwrap_or(Value::from(1.0))], |a| {
+        let (needle, haystack) = (units(&text_arg(&a[0])), units(&text_arg(&a[1])));
+        let start = tryv!(int_arg(&a[2]));
+        if start < 1 || start as usize > haystack.len() + 1 {
+            return Value::Error(ExcelError::Value);
+        }
+        let start = start as usize - 1;
+        if needle.is_empty() {
+            return Value::from(start + 1);
+        }
+        match haystack[start..].windows(needle.len()).position(|w| w == needle.as_slice()) {
+            Some(i) => Value::from(start + i + 1),
+            None => Value::Error(ExcelError::Value)
+        }
+    })
+}
+
+#[function]
+fn rept(text: Value, number_times: Value) -> Value {
+    broadcast(vec![text, number_times], |a| {
+        let n = tryv!(int_arg(&a[1]));
+        let text = text_arg(&a[0]);
+        if n < 0 || text.encode_utf16().count().saturating_mul(n as usize) > MAX_TEXT {
+            return Value::Error(ExcelError::Value);
+        }
+        Value::from(text.repeat(n as usize))
+    })
+}
+
+#[function]
+fn exact(text1: Value, text2: Value) -> Value {
+    broadcast(vec![text1, text2], |a| Value::from(text_arg(&a[0]) == text_arg(&a[1])))
+}
+
+// Joins every cell of every argument, ranges included.
+#[function]
+fn concat(args: Vec<Value>) -> Value {
+    let mut output = String::new();
+    for v in args.iter().flat_map(|a| a.as_array()) {
+        if v.is_err() {
+            return v;
+        }
+        output.push_str(&text_arg(&v));
+    }
+    limit(output)
+}
+
+// Unlike CONCAT, arrays are joined element by element.
+#[function]
+fn concatenate(args: Vec<Value>) -> Value {
+    broadcast(args, |a| limit(a.iter().map(text_arg).collect::<String>()))
+}
+
+#[function]
+fn textjoin(delimiter: Value, ignore_empty: Value, args: Vec<Value>) -> Value {
+    // Several delimiters are used in turn
+    let delimiters: Vec<String> = delimiter.as_array().iter().map(text_arg).collect();
+    let ignore_empty = tryv!(number_arg(&ignore_empty)) != 0.0;
+    let mut pieces: Vec<String> = vec![];
+    for v in args.iter().flat_map(|a| a.as_array()) {
+        if v.is_err() {
+            return v;
+        }
+        let s = text_arg(&v);
+        if !(ignore_empty && s.is_empty()) {
+            pieces.push(s);
+        }
+    }
+    let mut output = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        if i > 0 && !delimiters.is_empty() {
+            output.push_str(&delimiters[(i - 1) % delimiters.len()]);
+        }
+        output.push_str(piece);
+    }
+    limit(output)
+}
+
+fn fold_case(c: char, ignore_case: bool) -> char {
+    if ignore_case {
+        c.to_lowercase().next().unwrap_or(c)
+    } else {
+        c
+    }
+}
+
+// Non-overlapping (start, end) char positions of any of the delimiters.
+fn delimiter_matches(text: &[char], delimiters: &[Vec<char>], ignore_case: bool) -> Vec<(usize, usize)> {
+    let mut matches = vec![];
+    let mut i = 0;
+    while i <= text.len() {
+        let found = delimiters.iter().find(|d| {
+            i + d.len() <= text.len() && d.iter().zip(&text[i..]).all(|(a, b)| fold_case(*a, ignore_case) == fold_case(*b, ignore_case))
+        });
+        match found {
+            Some(d) => {
+                matches.push((i, i + d.len()));
+                i += d.len().max(1);
+            },
+            None => i += 1
+        }
+        if i == text.len() && delimiters.iter().all(|d| !d.is_empty()) {
+            break;
+        }
+    }
+    matches
+}
+
+fn text_around(text: Value, delimiter: Value, instance_num: Option<Value>, match_mode: Option<Value>, match_end: Option<Value>, if_not_found: Option<Value>, after: bool) -> Value {
+    let delimiters: Vec<Vec<char>> = delimiter.as_array().iter().map(|d| text_arg(d).chars().collect()).collect();
+    let instance = tryv!(int_arg(&instance_num.unwrap_or(Value::from(1.0))));
+    let ignore_case = tryv!(number_arg(&match_mode.unwrap_or(Value::from(0.0)))) != 0.0;
+    let match_end = tryv!(number_arg(&match_end.unwrap_or(Value::from(0.0)))) != 0.0;
+    broadcast(vec![text], |a| {
+        let text: Vec<char> = text_arg(&a[0]).chars().collect();
+        if instance == 0 || instance.unsigned_abs() as usize > text.len().max(1) {
+            return Value::Error(ExcelError::Value);
+        }
+        let mut matches = delimiter_matches(&text, &delimiters, ignore_case);
+        // The end of the text (or its start, searching backwards) counts as a delimiter
+        if match_end {
+            if instance > 0 {
+                matches.push((text.len(), text.len()));
+            } else {
+                matches.insert(0, (0, 0));
+            }
+        }
+        let found = if instance > 0 {
+            matches.get(instance as usize - 1)
+        } else {
+            matches.len().checked_sub(instance.unsigned_abs() as usize).and_then(|i| matches.get(i))
+        };
+        match found {
+            Some((_, end)) if after => Value::from(text[*end..].iter().collect::<String>()),
+            Some((start, _)) => Value::from(text[..*start].iter().collect::<String>()),
+            None => if_not_found.clone().unwrap_or(Value::Error(ExcelError::NA))
+        }
+    })
+}
+
+#[function]
+fn textbefore(text: Value, delimiter: Value, instance_num: Option<Value>, match_mode: Option<Value>, match_end: Option<Value>, if_not_found: Option<Value>) -> Value {
+    text_around(text, delimiter, instance_num, match_mode, match_end, if_not_found, false)
+}
+
+#[function]
+fn textafter(text: Value, delimiter: Value, instance_num: Option<Value>, match_mode: Option<Value>, match_end: Option<Value>, if_not_found: Option<Value>) -> Value {
+    text_around(text, delimiter, instance_num, match_mode, match_end, if_not_found, true)
+}
+
+fn split(text: &[char], delimiters: &[Vec<char>], ignore_case: bool, ignore_empty: bool) -> Vec<Vec<char>> {
+    let delimiters: Vec<Vec<char>> = delimiters.iter().filter(|d| !d.is_empty()).cloned().collect();
+    let mut pieces = vec![];
+    let mut start = 0;
+    if !delimiters.is_empty() {
+        for (a, b) in delimiter_matches(text, &delimiters, ignore_case) {
+            pieces.push(text[start..a].to_vec());
+            start = b;
+        }
+    }
+    pieces.push(text[start..].to_vec());
+    if ignore_empty {
+        pieces.retain(|p| !p.is_empty());
+    }
+    pieces
+}
+
+// Splits into rows by row_delimiter, then into columns, padding short rows with pad_with.
+#[function]
+fn textsplit(text: Value, col_delimiter: Value, row_delimiter: Option<Value>, ignore_empty: Option<Value>, match_mode: Option<Value>, pad_with: Option<Value>) -> Value {
+    let text: Vec<char> = text_arg(&text).chars().collect();
+    let delimiters = |v: &Value| -> Vec<Vec<char>> { v.as_array().iter().map(|d| text_arg(d).chars().collect()).collect() };
+    let col_delimiters = delimiters(&col_delimiter);
+    let row_delimiters = row_delimiter.map(|r| delimiters(&r)).unwrap_or_default();
+    if col_delimiters.iter().chain(row_delimiters.iter()).all(|d| d.is_empty()) {
+        return Value::Error(ExcelError::Value);
+    }
+    let ignore_empty = tryv!(number_arg(&ignore_empty.unwrap_or(Value::from(0.0)))) != 0.0;
+    let ignore_case = tryv!(number_arg(&match_mode.unwrap_or(Value::from(0.0)))) != 0.0;
+    let rows: Vec<Vec<Vec<char>>> = split(&text, &row_delimiters, ignore_case, ignore_empty).iter()
+        .map(|row| split(row, &col_delimiters, ignore_case, ignore_empty))
+        .collect();
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(1).max(1);
+    let pad = pad_with.unwrap_or(Value::Error(ExcelError::NA));
+    Value::from(Array2::from_shape_fn((rows.len().max(1), width), |(i, j)| {
+        match rows.get(i).and_then(|r| r.get(j)) {
+            Some(piece) => Value::from(piece.iter().collect::<String>()),
+            None => pad.clone()
+        }
+    }))
+}
+
+#[function]
+fn char(number: Value) -> Value {
+    broadcast(vec![number], |a| {
+        match tryv!(int_arg(&a[0])) {
+            n @ 128..=159 => Value::from(CP1252[n as usize - 128].to_string()),
+            n @ 1..=255 => Value::from(char::from(n as u8).to_string()),
+            _ => Value::Error(ExcelError::Value)
+        }
+    })
+}
+
+#[function]
+fn code(text: Value) -> Value {
+    broadcast(vec![text], |a| {
+        match text_arg(&a[0]).chars().next() {
+            Some(c) => match CP1252.iter().position(|x| *x == c) {
+                Some(i) => Value::from(i + 128),
+                None if (c as u32) < 256 => Value::from(c as u32 as usize),
+                None => Value::from(63.0) // Shown as ? in the ANSI code page
+            },
+            None => Value::Error(ExcelError::Value)
+        }
+    })
+}
+
+#[function]
+fn unichar(number: Value) -> Value {
+    broadcast(vec![number], |a| {
+        let n = tryv!(int_arg(&a[0]));
+        if !(1..=0x10FFFF).contains(&n) {
+            return Value::Error(ExcelError::Value);
+        }
+        match char::from_u32(n as u32) {
+            Some(c) => Value::from(c.to_string()),
+            None => Value::Error(ExcelError::NA)
+        }
+    })
+}
+
+#[function]
+fn unicode(text: Value) -> Value {
+    broadcast(vec![text], |a| {
+        match text_arg(&a[0]).chars().next() {
+            Some(c) => Value::from(c as u32 as usize),
+            None => Value::Error(ExcelError::Value)
+        }
+    })
+}
+
+// Removes the non-printable ASCII characters 0 to 31.
+#[function]
+fn clean(text: Value) -> Value {
+    broadcast(vec![text], |a| Value::from(text_arg(&a[0]).chars().filter(|c| *c as u32 > 31).collect::<String>()))
+}
+
+#[function("VALUE")]
+fn valuefn(text: Value) -> Value {
+    broadcast(vec![text], |a| {
+        match &a[0] {
+            Value::Num(n) => Value::from(*n),
+            Value::Empty => Value::from(0.0),
+            Value::Date(d) => Value::from(date_time_to_excel(*d)),
+            Value::Text(t) => match parse_value(t) {
+                Some(n) => Value::from(n),
+                None => Value::Error(ExcelError::Value)
+            },
+            _ => Value::Error(ExcelError::Value)
+        }
+    })
+}
+
+// Numbers, dates and numeric text rendered with a number format code.
+#[function]
+fn text(value: Value, format_text: Value) -> Value {
+    broadcast(vec![value, format_text], |a| {
+        let value = match &a[0] {
+            Value::Empty => Value::from(0.0),
+            Value::Text(t) => parse_value(t).map(Value::from).unwrap_or(a[0].clone()),
+            v => v.clone()
+        };
+        match NumberFormat::from(text_arg(&a[1]).as_str()).format(&value) {
+            Ok(s) => Value::from(s),
+            Err(e) => Value::Error(e)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        function::text::general_number,
+        parser::ast::Error as ExcelError,
+        errors::Error,
+    };
+
+    fn text(s: &str) -> Result<Value, Error> {
+        evaluate_str(s)
+    }
+
+    #[test]
+    fn test_general_number() {
+        assert_eq!(general_number(1.0), "1");
+        assert_eq!(general_number(0.1 + 0.2), "0.3");
+        assert_eq!(general_number(1.0 / 3.0), "0.333333333333333");
+        assert_eq!(general_number(-1234.5), "-1234.5");
+        assert_eq!(general_number(1e20), "1E+20");
+        assert_eq!(general_number(1.5e-10), "1.5E-10");
+        assert_eq!(general_number(123456789012.0), "123456789012");
+    }
+
+    #[test]
+    fn test_left_right_mid() -> Result<(), Error> {
+        assert_eq!(text("LEFT(\"Sale Price\", 4)")?, Value::from("Sale"));
+        assert_eq!(text("LEFT(\"Sweden\")")?, Value::from("S"));
+        assert_eq!(text("LEFT(\"abc\", 10)")?, Value::from("abc"));
+        assert_eq!(text("LEFT(\"abc\", -1)")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("RIGHT(\"Stock Number\", 6)")?, Value::from("Number"));
+        assert_eq!(text("RIGHT(1234, 2)")?, Value::from("34"));
+        assert_eq!(text("MID(\"Fluid Flow\", 7, 20)")?, Value::from("Flow"));
+        assert_eq!(text("MID(\"Fluid Flow\", 20, 5)")?, Value::from(""));
+        assert_eq!(text("MID(\"Fluid Flow\", 0, 5)")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("LEFT({\"abc\", \"de\"}, 1)")?, Value::from(vec![Value::from("a"), Value::from("d")]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_len() -> Result<(), Error> {
+        assert_eq!(text("LEN(\"Phoenix, AZ\")")?, Value::from(11.0));
+        assert_eq!(text("LEN(\"\")")?, Value::from(0.0));
+        assert_eq!(text("LEN(\"😀a\")")?, Value::from(3.0));
+        assert_eq!(text("LEFT(\"😀a\", 2)")?, Value::from("😀"));
+        assert_eq!(text("LEN(TRUE)")?, Value::from(4.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_case_and_trim() -> Result<(), Error> {
+        assert_eq!(text("TRIM(\"  First   Quarter Earnings \")")?, Value::from("First Quarter Earnings"));
+        assert_eq!(text("UPPER(\"total\")")?, Value::from("TOTAL"));
+        assert_eq!(text("LOWER(\"E. E. Cummings\")")?, Value::from("e. e. cummings"));
+        assert_eq!(text("PROPER(\"this is a TITLE\")")?, Value::from("This Is A Title"));
+        assert_eq!(text("PROPER(\"2-way street\")")?, Value::from("2-Way Street"));
+        assert_eq!(text("PROPER(\"76BudGet\")")?, Value::from("76Budget"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_substitute_replace() -> Result<(), Error> {
+        assert_eq!(text("SUBSTITUTE(\"Sales Data\", \"Sales\", \"Cost\")")?, Value::from("Cost Data"));
+        assert_eq!(text("SUBSTITUTE(\"Quarter 1, 2008\", \"1\", \"2\", 1)")?, Value::from("Quarter 2, 2008"));
+        assert_eq!(text("SUBSTITUTE(\"Quarter 1, 2011\", \"1\", \"2\", 3)")?, Value::from("Quarter 1, 2012"));
+        assert_eq!(text("SUBSTITUTE(\"abc\", \"\", \"x\")")?, Value::from("abc"));
+        assert_eq!(text("SUBSTITUTE(\"abc\", \"b\", \"x\", 0)")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("REPLACE(\"abcdefghijk\", 6, 5, \"*\")")?, Value::from("abcde*k"));
+        assert_eq!(text("REPLACE(\"2009\", 3, 2, \"10\")")?, Value::from("2010"));
+        assert_eq!(text("REPLACE(\"abc\", 0, 1, \"x\")")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_find() -> Result<(), Error> {
+        assert_eq!(text("FIND(\"M\", \"Miriam McGovern\")")?, Value::from(1.0));
+        assert_eq!(text("FIND(\"m\", \"Miriam McGovern\")")?, Value::from(6.0));
+        assert_eq!(text("FIND(\"M\", \"Miriam McGovern\", 3)")?, Value::from(8.0));
+        assert_eq!(text("FIND(\"\", \"abc\", 2)")?, Value::from(2.0));
+        assert_eq!(text("FIND(\"z\", \"abc\")")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("FIND(\"a\", \"abc\", 5)")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rept_exact() -> Result<(), Error> {
+        assert_eq!(text("REPT(\"*-\", 3)")?, Value::from("*-*-*-"));
+        assert_eq!(text("REPT(\"ab\", 20000)")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("EXACT(\"word\", \"word\")")?, Value::from(true));
+        assert_eq!(text("EXACT(\"Word\", \"word\")")?, Value::from(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_concat() -> Result<(), Error> {
+        assert_eq!(text("CONCAT(\"a\", 1, TRUE, {\"b\", \"c\"})")?, Value::from("a1TRUEbc"));
+        assert_eq!(text("CONCATENATE(\"Stream \", \"population\")")?, Value::from("Stream population"));
+        assert_eq!(text("CONCATENATE({\"a\", \"b\"}, \"-\", 1)")?, Value::from(vec![Value::from("a-1"), Value::from("b-1")]));
+        assert_eq!(text("TEXTJOIN(\", \", TRUE, \"a\", \"\", \"b\")")?, Value::from("a, b"));
+        assert_eq!(text("TEXTJOIN(\", \", FALSE, \"a\", \"\", \"b\")")?, Value::from("a, , b"));
+        assert_eq!(text("TEXTJOIN({\"-\", \"+\"}, TRUE, 1, 2, 3)")?, Value::from("1-2+3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_textbefore_after() -> Result<(), Error> {
+        assert_eq!(text("TEXTBEFORE(\"Red riding hood's, red hood\", \"hood\")")?, Value::from("Red riding "));
+        assert_eq!(text("TEXTBEFORE(\"Red riding hood's, red hood\", \"hood\", -1)")?, Value::from("Red riding hood's, red "));
+        assert_eq!(text("TEXTBEFORE(\"Red riding hood's, red hood\", \"HOOD\", 1, 1)")?, Value::from("Red riding "));
+        assert_eq!(text("TEXTBEFORE(\"abc\", \"x\")")?, Value::Error(ExcelError::NA));
+        assert_eq!(text("TEXTBEFORE(\"abc\", \"x\", 1, 0, 1)")?, Value::from("abc"));
+        assert_eq!(text("TEXTBEFORE(\"abc\", \"x\", 1, 0, 0, \"none\")")?, Value::from("none"));
+        assert_eq!(text("TEXTAFTER(\"Red riding hood's, red hood\", \"hood\")")?, Value::from("'s, red hood"));
+        assert_eq!(text("TEXTAFTER(\"Red riding hood's, red hood\", \"red\", 2)")?, Value::Error(ExcelError::NA));
+        assert_eq!(text("TEXTAFTER(\"Red riding hood's, red hood\", \"red\", 2, 1)")?, Value::from(" hood"));
+        assert_eq!(text("TEXTAFTER(\"a-b_c\", {\"-\", \"_\"}, -1)")?, Value::from("c"));
+        assert_eq!(text("TEXTAFTER(\"abc\", \"b\", 0)")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_textsplit() -> Result<(), Error> {
+        let split = text("TEXTSPLIT(\"Dakota Lennon Sanchez\", \" \")")?.as_array2();
+        assert_eq!(split.dim(), (1, 3));
+        assert_eq!(split[[0, 2]], Value::from("Sanchez"));
+        let split = text("TEXTSPLIT(\"1,2,3;4,5\", \",\", \";\")")?.as_array2();
+        assert_eq!(split.dim(), (2, 3));
+        assert_eq!(split[[1, 1]], Value::from("5"));
+        assert_eq!(split[[1, 2]], Value::Error(ExcelError::NA));
+        let split = text("TEXTSPLIT(\"a,,b\", \",\", \";\", TRUE)")?.as_array2();
+        assert_eq!(split.dim(), (1, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_codes() -> Result<(), Error> {
+        assert_eq!(text("CHAR(65)")?, Value::from("A"));
+        assert_eq!(text("CHAR(128)")?, Value::from("€"));
+        assert_eq!(text("CHAR(0)")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("CODE(\"A\")")?, Value::from(65.0));
+        assert_eq!(text("CODE(\"€\")")?, Value::from(128.0));
+        assert_eq!(text("CODE(\"\")")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("UNICHAR(66)")?, Value::from("B"));
+        assert_eq!(text("UNICHAR(128512)")?, Value::from("😀"));
+        assert_eq!(text("UNICODE(\"😀\")")?, Value::from(128512.0));
+        assert_eq!(text("CLEAN(CHAR(9)&\"Monthly report\"&CHAR(10))")?, Value::from("Monthly report"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_value() -> Result<(), Error> {
+        assert_eq!(text("VALUE(\"$1,000\")")?, Value::from(1000.0));
+        assert_eq!(text("VALUE(\" 12.5 \")")?, Value::from(12.5));
+        assert_eq!(text("VALUE(\"50%\")")?, Value::from(0.5));
+        assert_eq!(text("VALUE(\"(100)\")")?, Value::from(-100.0));
+        assert_eq!(text("VALUE(\"1.5E3\")")?, Value::from(1500.0));
+        assert_eq!(text("VALUE(\"16:48:00\")")?.as_num(), 0.7);
+        assert_eq!(text("VALUE(\"2024-01-15\")")?, Value::from(45306.0));
+        assert_eq!(text("VALUE(\"abc\")")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("VALUE(\"1,23\")")?, Value::Error(ExcelError::Value));
+        Ok(())
+    }
+
+    #[test]
+    fn test_text() -> Result<(), Error> {
+        assert_eq!(text("TEXT(1234.567, \"#,##0.00\")")?, Value::from("1,234.57"));
+        assert_eq!(text("TEXT(0.285, \"0.0%\")")?, Value::from("28.5%"));
+        assert_eq!(text("TEXT(45356, \"mmm yyyy\")")?, Value::from("Mar 2024"));
+        assert_eq!(text("TEXT(\"2024-03-05\", \"dd/mm/yyyy\")")?, Value::from("05/03/2024"));
+        assert_eq!(text("TEXT(\"12\", \"000\")")?, Value::from("012"));
+        assert_eq!(text("TEXT(\"abc\", \"0.00\")")?, Value::from("abc"));
+        assert_eq!(text("TEXT(-1, \"yyyy\")")?, Value::Error(ExcelError::Value));
+        assert_eq!(text("TEXT({1, 2}, \"0.0\")")?, Value::from(vec![Value::from("1.0"), Value::from("2.0")]));
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{Local, NaiveDateTime};
+use ndarray::Array2;
+use crate::{
+    evaluate::value::Value,
+    parser::ast::Error as ExcelError,
+    function::number_arg,
+};
+
+// Functions whose value changes on every calculation.
+pub const VOLATILE_FUNCTIONS: [&str; 5] = ["TODAY", "NOW", "RAND", "RANDBETWEEN", "RANDARRAY"];
+
+/*
+ * The clock and random numbers volatile functions use. Without a fixed time
+ * the system clock is read, and without a seed one is taken from it, so set
+ * both for calculations that have to be reproducible.
+ */
+#[derive(Debug, Clone)]
+pub struct CalculationContext {
+    pub now: Option<NaiveDateTime>,
+    state: Cell<u64>,
+}
+
+impl Default for CalculationContext {
+    fn default() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        CalculationContext { now: None, state: Cell::new(nanos) }
+    }
+}
+
+impl CalculationContext {
+    pub fn with_now(mut self, now: NaiveDateTime) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.state.set(seed);
+        self
+    }
+
+    pub fn now(&self) -> NaiveDateTime {
+        self.now.unwrap_or_else(|| Local::now().naive_local())
+    }
+
+    // SplitMix64, small and good enough for spreadsheet sampling.
+    fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9E3779B97F4A7C15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1).
+    pub fn random(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+pub fn evaluate(name: &str, args: Vec<Value>, context: &CalculationContext) -> Value {
+    if let Some(e) = args.iter().find(|a| a.is_err()) {
+        return e.clone();
+    }
+    let result = match name {
+        "TODAY" => Ok(Value::from(context.now().date())),
+        "NOW" => Ok(Value::from(context.now())),
+        "RAND" => Ok(Value::from(context.random())),
+        "RANDBETWEEN" => randbetween(&args, context),
+        "RANDARRAY" => randarray(&args, context),
+        _ => unreachable!("{} is not volatile", name)
+    };
+    result.unwrap_or_else(Value::Error)
+}
+
+fn arg_or(args: &[Value], idx: usize, default: f64) -> Result<f64, ExcelError> {
+    match args.get(idx) {
+        Some(v) => number_arg(v),
+        None => Ok(default)
+    }
+}
+
+fn randbetween(args: &[Value], context: &CalculationContext) -> Result<Value, ExcelError> {
+    if args.len() != 2 {
+        return Err(ExcelError::Value);
+    }
+    let (bottom, top) = (number_arg(&args[0])?.ceil(), number_arg(&args[1])?.floor());
+    if bottom > top {
+        return Err(ExcelError::Num);
+    }
+    Ok(Value::from(bottom + (context.random() * (top - bottom + 1.0)).floor()))
+}
+
+// RANDARRAY([rows], [columns], [min], [max], [whole_number])
+fn randarray(args: &[Value], context: &CalculationContext) -> Result<Value, ExcelError> {
+    let (rows, columns) = (arg_or(args, 0, 1.0)?.trunc(), arg_or(args, 1, 1.0)?.trunc());
+    let (min, max) = (arg_or(args, 2, 0.0)?, arg_or(args, 3, 1.0)?);
+    let whole = arg_or(args, 4, 0.0)? != 0.0;
+    if rows < 1.0 || columns < 1.0 || rows > 1048576.0 || columns > 16384.0 || min > max || (whole && (min.fract() != 0.0 || max.fract() != 0.0)) {
+        return Err(ExcelError::Value);
+    }
+    Ok(Value::from(Array2::from_shape_fn((rows as usize, columns as usize), |_| {
+        let r = context.random();
+        Value::from(if whole { min + (r * (max - min + 1.0)).floor() } else { min + r * (max - min) })
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use crate::{
+        evaluate::{value::Value, evaluate_str},
+        parser::ast::Error as ExcelError,
+        errors::Error,
+    };
+    use super::{CalculationContext, evaluate};
+
+    #[test]
+    fn test_clock() {
+        let now = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap().and_hms_opt(10, 30, 0).unwrap();
+        let context = CalculationContext::default().with_now(now);
+        assert_eq!(evaluate("TODAY", vec![], &context), Value::from(NaiveDate::from_ymd_opt(2024, 3, 15).unwrap()));
+        assert_eq!(evaluate("NOW", vec![], &context), Value::from(now));
+    }
+
+    #[test]
+    fn test_seeded() {
+        let draws = |seed: u64| {
+            let context = CalculationContext::default().with_seed(seed);
+            (0..5).map(|_| evaluate("RAND", vec![], &context).as_num()).collect::<Vec<f64>>()
+        };
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+        assert!(draws(7).iter().all(|r| (0.0..1.0).contains(r)));
+        let context = CalculationContext::default().with_seed(1);
+        for _ in 0..100 {
+            let n = evaluate("RANDBETWEEN", vec![Value::from(-2.5), Value::from(3.0)], &context).as_num();
+            assert!((-2.0..=3.0).contains(&n) && n.fract() == 0.0);
+        }
+        assert_eq!(evaluate("RANDBETWEEN", vec![Value::from(5.0), Value::from(1.0)], &context), Value::Error(ExcelError::Num));
+        let array = evaluate("RANDARRAY", vec![Value::from(2.0), Value::from(3.0), Value::from(10.0), Value::from(20.0), Value::from(true)], &context).as_array2();
+        assert_eq!(array.dim(), (2, 3));
+        assert!(array.iter().all(|v| (10.0..=20.0).contains(&v.as_num()) && v.as_num().fract() == 0.0));
+        assert_eq!(evaluate("RANDARRAY", vec![Value::from(0.0)], &context), Value::Error(ExcelError::Value));
+        assert_eq!(evaluate("RANDARRAY", vec![Value::from(1048577.0)], &context), Value::Error(ExcelError::Value));
+        assert_eq!(evaluate("RANDARRAY", vec![Value::from(1.0), Value::from(16385.0)], &context), Value::Error(ExcelError::Value));
+        assert_eq!(evaluate("RANDARRAY", vec![Value::from(1E+9), Value::from(1E+9)], &context), Value::Error(ExcelError::Value));
+    }
+
+    #[test]
+    fn test_without_book() -> Result<(), Error> {
+        let r = evaluate_str("RAND()")?.as_num();
+        assert!((0.0..1.0).contains(&r));
+        assert!(evaluate_str("TODAY()")?.is_date());
+        Ok(())
+    }
+}
@@ -14,29 +14,37 @@ pub struct Payment {
 
 /// Calculates the internal rate of return of a series of irregular payments.
 ///
-/// It tries to identify the rate of return using Newton's method with an initial guess of 0.1.
-/// If that does not provide a solution, it attempts with guesses from -0.99 to 0.99
-/// in increments of 0.01 and returns NaN if that fails too.
+/// It tries to identify the rate of return using [`solve`](fn.solve.html) starting from `guess`,
+/// and returns NaN if that fails.
 ///
 /// # Errors
 ///
 /// This function will return [`InvalidPaymentsError`](struct.InvalidPaymentsError.html)
 /// if both positive and negative payments are not provided.
-pub fn compute(payments: &[Payment]) -> Result<f64, InvalidPaymentsError> {
+pub fn compute(payments: &[Payment], guess: f64) -> Result<f64, InvalidPaymentsError> {
     validate(payments)?;
 
     let mut sorted: Vec<_> = payments.iter().collect();
     sorted.sort_by_key(|p| p.date);
 
-    let mut rate = compute_with_guess(&sorted, 0.1);
+    Ok(solve(|r| xirr(&sorted, r), |r| dxirr(&sorted, r), guess))
+}
+
+/// Finds a root of `f`, whose derivative is `df`.
+///
+/// It uses Newton's method with the initial `guess`. If that does not provide a solution,
+/// it attempts with guesses from -0.99 to 0.99 in increments of 0.01 and returns NaN if
+/// that fails too.
+pub fn solve(f: impl Fn(f64) -> f64, df: impl Fn(f64) -> f64, guess: f64) -> f64 {
+    let mut rate = compute_with_guess(&f, &df, guess);
     let mut guess = -0.99;
 
     while guess < 1.0 && (rate.is_nan() || rate.is_infinite()) {
-        rate = compute_with_guess(&sorted, guess);
+        rate = compute_with_guess(&f, &df, guess);
         guess += 0.01;
     }
 
-    Ok(rate)
+    rate
 }
 
 /// An error returned when the payments provided to [`compute`](fn.compute.html) do not contain
@@ -52,7 +60,7 @@ impl Display for InvalidPaymentsError {
 
 impl Error for InvalidPaymentsError {}
 
-fn compute_with_guess(payments: &[&Payment], guess: f64) -> f64 {
+fn compute_with_guess(f: &impl Fn(f64) -> f64, df: &impl Fn(f64) -> f64, guess: f64) -> f64 {
     let mut r = guess;
     let mut e = 1.0;
 
@@ -61,7 +69,7 @@ fn compute_with_guess(payments: &[&Payment], guess: f64) -> f64 {
             return r;
         }
 
-        let r1 = r - xirr(payments, r) / dxirr(payments, r);
+        let r1 = r - f(r) / df(r);
         e = (r1 - r).abs();
         r = r1;
     }
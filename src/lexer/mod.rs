@@ -1,9 +1,8 @@
 use nom::branch::*;
-use nom::bytes::complete::{tag, take, take_while, take_while1};
-use nom::character::complete::{alpha1, alphanumeric1, digit1, multispace0};
-use nom::combinator::{map, map_res, recognize, opt};
-use nom::multi::many0;
-use nom::sequence::{terminated, delimited, separated_pair, pair};
+use nom::bytes::complete::{tag, take_while, take_while1};
+use nom::character::complete::{alpha1, digit1, multispace0, one_of, satisfy};
+use nom::combinator::{map, map_res, recognize, opt, not, peek};
+use nom::sequence::{terminated, delimited, preceded, separated_pair, pair, tuple};
 use nom::*;
 
 use std::str;
@@ -12,11 +11,12 @@ use std::str::Utf8Error;
 
 pub mod token; 
 use crate::lexer::token::*; 
-use crate::errors::Error; 
+use crate::errors::{Error, SyntaxError}; 
+use crate::parser::ast::{TableRef, TableItem}; 
 
 macro_rules! syntax {
     ($func_name: ident, $tag_string: literal, $output_token: expr) => {
-        fn $func_name<'a>(s: &'a [u8]) -> IResult<&[u8], Token> {
+        fn $func_name(s: &[u8]) -> IResult<&[u8], Token> {
             map(tag($tag_string), |_| $output_token)(s)
         }
     };
@@ -27,10 +27,13 @@ syntax! {null_err, "#NULL!", Token::Null}
 syntax! {div_err, "#DIV/0!", Token::Div}
 syntax! {value_err, "#VALUE!", Token::Value}
 syntax! {ref_err, "#REF!", Token::Ref}
-syntax! {name_err, "#NAME!", Token::Name}
+syntax! {name_err, "#NAME?", Token::Name}
+syntax! {name_err_legacy, "#NAME!", Token::Name}
 syntax! {num_err, "#NUM!", Token::Num}
 syntax! {na_err, "#N/A", Token::NA}
 syntax! {getting_data_err, "#GETTING_DATA", Token::GettingData}
+syntax! {spill_err, "#SPILL!", Token::Spill}
+syntax! {calc_err, "#CALC!", Token::Calc}
 syntax! {plus, "+", Token::Plus}
 syntax! {minus, "-", Token::Minus}
 syntax! {divide, "/", Token::Divide}
@@ -61,9 +64,12 @@ pub fn lex_syntax(input: &[u8]) -> IResult<&[u8], Token> {
             value_err, 
             ref_err, 
             name_err, 
+            name_err_legacy, 
             num_err, 
             na_err, 
-            getting_data_err
+            getting_data_err, 
+            spill_err, 
+            calc_err
         )), 
         alt((
             plus,
@@ -96,26 +102,27 @@ pub fn lex_syntax(input: &[u8]) -> IResult<&[u8], Token> {
 }
 
 // String
-fn pis(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
-    use std::result::Result::*;
-
-    let (i1, c1) = take(1usize)(input)?;
-    match c1.as_bytes() {
-        b"\"" => Ok((input, vec![])),
-        b"\\" => {
-            let (i2, c2) = take(1usize)(i1)?;
-            pis(i2).map(|(slice, done)| (slice, concat_slice_vec(c2, done)))
+// Body of a quoted run up to the closing `quote`, a doubled quote stands for itself. 
+fn quoted_body(quote: u8) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<u8>> {
+    move |input: &[u8]| {
+        let mut output: Vec<u8> = vec![]; 
+        let mut i = 0; 
+        while i < input.len() {
+            if input[i] == quote {
+                if input.get(i + 1) == Some(&quote) {
+                    output.push(quote); 
+                    i += 2; 
+                    continue; 
+                }
+                return Ok((&input[i..], output)); 
+            }
+            output.push(input[i]); 
+            i += 1; 
         }
-        c => pis(i1).map(|(slice, done)| (slice, concat_slice_vec(c, done))),
+        Err(Err::Error(error::Error::new(input, error::ErrorKind::Char)))
     }
 }
 
-fn concat_slice_vec(c: &[u8], done: Vec<u8>) -> Vec<u8> {
-	let mut new_vec = c.to_vec();
-    new_vec.extend(&done);
-    new_vec
-}
-
 fn convert_vec_utf8(v: Vec<u8>) -> Result<String, Utf8Error> {
 	let slice = v.as_slice();
 	str::from_utf8(slice).map(|s| s.to_owned())
@@ -126,7 +133,7 @@ fn complete_byte_slice_str_from_utf8(c: &[u8]) -> Result<&str, Utf8Error> {
 }
 
 fn string(input: &[u8]) -> IResult<&[u8], String> {
-	delimited(tag("\""), map_res(pis, convert_vec_utf8), tag("\""))(input)
+	delimited(tag("\""), map_res(quoted_body(b'"'), convert_vec_utf8), tag("\""))(input)
 }
 
 fn lex_string(input: &[u8]) -> IResult<&[u8], Token> {
@@ -164,11 +171,6 @@ fn lex_hrange(input: &[u8]) -> IResult<&[u8], Token> {
     )(input)
 }
 
-fn in_quote_sheet_name(chr: u8) -> bool {
-    let is_special = b"`~@#$%^&-_=+{}|;,<.>() ".contains(&chr); 
-    is_digit_or_alpha(chr) || is_special
-}
-
 fn in_sheet_name(chr: u8) -> bool {
     let is_special = b"`~@#$%^_{}|;.".contains(&chr); 
     is_digit_or_alpha(chr) || is_special
@@ -186,13 +188,31 @@ fn is_digit_or_alpha(chr: u8) -> bool {
     is_digit(chr) || is_alpha(chr)
 }
 
+fn lex_quoted_sheet_name(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(delimited(tag("'"), quoted_body(b'\''), tag("'")))(input)
+}
+
+// External workbooks prefix the sheet, by link index ([1]Inputs) or by file name ([Rates.xlsx]Curve). 
+fn lex_external_book(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(delimited(tag("["), take_while1(|c| c != b']' && c != b'[' && c != b'\''), tag("]")))(input)
+}
+
 fn lex_sheet_name(input: &[u8]) -> IResult<&[u8], &[u8]> {
     alt((
+        recognize(pair(lex_external_book, take_while1(in_sheet_name))), 
         take_while1(in_sheet_name),
-        recognize(delimited(tag("'"), take_while(in_quote_sheet_name), tag("'")))
+        lex_quoted_sheet_name
     ))(input)
 }
 
+// Quoted names lose their quotes, and a doubled quote inside them becomes one. 
+fn unquote_sheet_name(s: &str) -> String {
+    match s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        Some(inner) => inner.replace("''", "'"), 
+        None => s.to_string()
+    }
+}
+
 fn lex_sheet(input: &[u8]) -> IResult<&[u8], Token> {
     map_res(
         alt((
@@ -201,11 +221,16 @@ fn lex_sheet(input: &[u8]) -> IResult<&[u8], Token> {
         )), 
         |s| {
             let c = complete_byte_slice_str_from_utf8(s);
-            c.map(|syntax| Token::Sheet(syntax.replace('\'', "")))
+            c.map(|syntax| Token::Sheet(unquote_sheet_name(syntax)))
         }
     )(input)
 }
 
+// Must run before lex_table, which would otherwise take [1] as a column. 
+fn lex_external_sheet(input: &[u8]) -> IResult<&[u8], Token> {
+    preceded(peek(tag("[")), lex_sheet)(input)
+}
+
 fn lex_multisheet(input: &[u8]) -> IResult<&[u8], Token> {
     map(
         terminated(recognize(separated_pair(lex_sheet_name, tag(":"), lex_sheet_name)), tag("!")), 
@@ -216,9 +241,13 @@ fn lex_multisheet(input: &[u8]) -> IResult<&[u8], Token> {
     )(input)
 }
 
+// LOG10( and ATAN2( are functions, not cells. 
 fn lex_cell(input: &[u8]) -> IResult<&[u8], Token> {
     map(
-        recognize(pair(pair(opt(tag("$")), alpha1), pair(opt(tag("$")), digit1))), 
+        terminated(
+            recognize(pair(pair(opt(tag("$")), alpha1), pair(opt(tag("$")), digit1))), 
+            not(peek(satisfy(|c| c.is_ascii_alphanumeric() || "_.(".contains(c))))
+        ), 
         |c| {
             let s = complete_byte_slice_str_from_utf8(c).unwrap(); 
             Token::Cell(s.to_string())
@@ -235,6 +264,14 @@ fn lex_range(input: &[u8]) -> IResult<&[u8], Token> {
     )(input)
 }
 
+// A1# is the whole spill range of the dynamic array anchored in A1. 
+fn lex_spill_ref(input: &[u8]) -> IResult<&[u8], Token> {
+    map(
+        terminated(lex_cell, tag("#")), 
+        |c| Token::SpillRef(c.to_string())
+    )(input)
+}
+
 fn lex_references(input: &[u8]) -> IResult<&[u8], Token> {
     alt((
         lex_multisheet,
@@ -242,10 +279,199 @@ fn lex_references(input: &[u8]) -> IResult<&[u8], Token> {
         lex_hrange, 
         lex_vrange, 
         lex_range, 
+        lex_spill_ref, 
         lex_cell
     ))(input)
 }
 
+// R1C1 references, only lexed in R1C1 mode where they replace A1 references. 
+// R2 and C3 are absolute, R[-1] and C[2] relative and a bare R or C is the anchor's own row or column. 
+fn r1c1_part<'a>(letter: &'static str) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
+    recognize(pair(
+        tag(letter), 
+        opt(alt((
+            digit1, 
+            recognize(delimited(tag("["), pair(opt(tag("-")), digit1), tag("]")))
+        )))
+    ))
+}
+
+fn r1c1_ref(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    terminated(
+        alt((
+            recognize(pair(r1c1_part("R"), r1c1_part("C"))), 
+            r1c1_part("R"), 
+            r1c1_part("C"), 
+        )), 
+        // ROUND( or RATE are names, not a row followed by garbage. 
+        not(peek(satisfy(|c| c.is_ascii_alphanumeric() || "_.(".contains(c))))
+    )(input)
+}
+
+fn lex_r1c1(input: &[u8]) -> IResult<&[u8], Token> {
+    map_res(
+        recognize(pair(r1c1_ref, opt(pair(tag(":"), r1c1_ref)))), 
+        |s| {
+            let c = complete_byte_slice_str_from_utf8(s); 
+            c.map(|syntax| Token::R1C1(syntax.to_string()))
+        }
+    )(input)
+}
+
+// RC# like A1#, the anchor of a spill range is always a single cell. 
+fn lex_r1c1_spill(input: &[u8]) -> IResult<&[u8], Token> {
+    map_res(
+        terminated(recognize(pair(r1c1_part("R"), r1c1_part("C"))), tag("#")), 
+        |s| {
+            let c = complete_byte_slice_str_from_utf8(s); 
+            c.map(|syntax| Token::SpillRef(syntax.to_string()))
+        }
+    )(input)
+}
+
+fn lex_r1c1_references(input: &[u8]) -> IResult<&[u8], Token> {
+    alt((
+        lex_multisheet,
+        lex_sheet, 
+        lex_r1c1_spill, 
+        lex_r1c1
+    ))(input)
+}
+
+/*
+ * Structured references: an optional table name directly followed by a bracketed specifier. 
+ *   Sales[Amount], Sales[#Totals], [@Qty], [@[Unit Price]], 
+ *   Sales[[#Headers],[#Data],[Region]:[Amount]]
+ * A quote escapes the next character inside a column name. 
+ */
+fn lex_table(input: &[u8]) -> IResult<&[u8], Token> {
+    let fail = || Err(Err::Error(error::Error::new(input, error::ErrorKind::Tag))); 
+    let name_len = input.iter().take_while(|c| c.is_ascii_alphanumeric() || b"_.\\".contains(c)).count(); 
+    if name_len > 0 && input[0].is_ascii_digit() {
+        return fail(); 
+    }
+    let mut scanner = TableScanner { input, pos: name_len }; 
+    let table = match name_len {
+        0 => None, 
+        _ => Some(String::from_utf8_lossy(&input[..name_len]).to_string())
+    }; 
+    match scanner.body() {
+        Some(t) => Ok((&input[scanner.pos..], Token::Table(TableRef { table, ..t }))), 
+        None => fail()
+    }
+}
+
+struct TableScanner<'a> {
+    input: &'a [u8], 
+    pos: usize, 
+}
+
+impl TableScanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1; 
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(b' ') {
+            self.pos += 1; 
+        }
+    }
+
+    // Text up to (not including) the closing bracket. 
+    fn name(&mut self) -> Option<String> {
+        let mut output: Vec<u8> = vec![]; 
+        loop {
+            match self.peek()? {
+                b']' => break, 
+                b'[' => return None, 
+                b'\'' => {
+                    self.pos += 1; 
+                    output.push(self.peek()?); 
+                }, 
+                c => output.push(c)
+            }
+            self.pos += 1; 
+        }
+        String::from_utf8(output).ok()
+    }
+
+    // [name] 
+    fn bracketed(&mut self) -> Option<String> {
+        if !self.eat(b'[') {
+            return None; 
+        }
+        let name = self.name()?; 
+        self.eat(b']').then_some(name)
+    }
+
+    // [First] or [First]:[Last] 
+    fn column_range(&mut self, first: String) -> Option<(String, String)> {
+        if self.eat(b':') {
+            let last = self.bracketed()?; 
+            Some((first, last))
+        } else {
+            Some((first.clone(), first))
+        }
+    }
+
+    // Everything after the table name, which is filled in by the caller 
+    fn body(&mut self) -> Option<TableRef> {
+        if !self.eat(b'[') {
+            return None; 
+        }
+        let mut items: Vec<TableItem> = vec![]; 
+        let mut columns: Option<(String, String)> = None; 
+        match self.peek()? {
+            b']' => {}, 
+            b'[' => {
+                loop {
+                    self.skip_spaces(); 
+                    let spec = self.bracketed()?; 
+                    if spec.starts_with('#') && columns.is_none() {
+                        items.push(TableItem::from_name(&spec)?); 
+                    } else if columns.is_none() {
+                        columns = Some(self.column_range(spec)?); 
+                    } else {
+                        return None; 
+                    }
+                    self.skip_spaces(); 
+                    if !self.eat(b',') {
+                        break; 
+                    }
+                }
+            }, 
+            b'@' => {
+                self.pos += 1; 
+                items.push(TableItem::ThisRow); 
+                if self.peek()? == b'[' {
+                    let first = self.bracketed()?; 
+                    columns = Some(self.column_range(first)?); 
+                } else if self.peek()? != b']' {
+                    let name = self.name()?; 
+                    columns = Some((name.clone(), name)); 
+                }
+            }, 
+            b'#' => {
+                items.push(TableItem::from_name(&self.name()?)?); 
+            }, 
+            _ => {
+                let name = self.name()?; 
+                columns = Some((name.clone(), name)); 
+            }
+        }
+        self.eat(b']').then_some(TableRef { table: None, items, columns })
+    }
+}
+
 // Integer
 fn complete_str_from_str<F: FromStr>(c: &str) -> Result<F, F::Err> {
     FromStr::from_str(c)
@@ -261,10 +487,18 @@ fn lex_int(input: &[u8]) -> IResult<&[u8], Token> {
     )(input)
 }
 
+fn exponent_part(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(tuple((one_of("eE"), opt(one_of("+-")), digit1)))(input)
+}
+
+// 1.5, 1.5E-3 and 1E3 are all floats, a bare run of digits is an integer. 
 fn lex_float(input: &[u8]) -> IResult<&[u8], Token> {
     map(
         map_res(
-            recognize(separated_pair(digit1, period, digit1)),
+            alt((
+                recognize(tuple((digit1, period, digit1, opt(exponent_part)))), 
+                recognize(pair(digit1, exponent_part)), 
+            )),
             complete_byte_slice_str_from_utf8
         ), 
         |c: &str| {
@@ -273,11 +507,17 @@ fn lex_float(input: &[u8]) -> IResult<&[u8], Token> {
     )(input)
 }
 
-// Ident
+// Ident, with dotted names like CEILING.MATH 
 fn lex_ident(input: &[u8]) -> IResult<&[u8], Token> {
     map(
         map_res(
-            map_res(alphanumeric1, complete_byte_slice_str_from_utf8),
+            map_res(
+                recognize(pair(
+                    satisfy(|c| c.is_ascii_alphabetic() || c == '_'), 
+                    take_while(|c: u8| c.is_ascii_alphanumeric() || c == b'.' || c == b'_')
+                )), 
+                complete_byte_slice_str_from_utf8
+            ),
             complete_str_from_str,
         ),
         Token::Ident,
@@ -287,6 +527,8 @@ fn lex_ident(input: &[u8]) -> IResult<&[u8], Token> {
 // Tokens
 fn lex_token(input: &[u8]) -> IResult<&[u8], Token> {
     alt((
+        lex_external_sheet, 
+        lex_table, 
         lex_syntax,
         lex_string,
         lex_references,
@@ -296,22 +538,66 @@ fn lex_token(input: &[u8]) -> IResult<&[u8], Token> {
     ))(input)
 }
 
-fn lex_tokens(input: &[u8]) -> IResult<&[u8], Vec<Token>> {
-    many0(delimited(multispace0, lex_token, multispace0))(input)
+// R[-1]C would otherwise read as a column of table R. 
+fn lex_token_r1c1(input: &[u8]) -> IResult<&[u8], Token> {
+    alt((
+        lex_r1c1_spill, 
+        lex_r1c1, 
+        lex_external_sheet, 
+        lex_table, 
+        lex_syntax,
+        lex_string,
+        lex_r1c1_references,
+        lex_float, 
+        lex_int, 
+        lex_ident, 
+    ))(input)
 }
 
 pub struct Lexer; 
 impl Lexer {
     pub fn lex_tokens(bytes: &[u8]) -> Result<Vec<Token>, Error> {
-		match lex_tokens(bytes)
-			.map(|(slice, result)| (slice, [&result[..]].concat())) {
-                Ok((_, mut tokens)) => {
-                    tokens.push(Token::EOF); 
-                    Ok(tokens)
+        Lexer::lex_spanned(bytes).map(|(tokens, _)| tokens)
+	}
+
+    // Tokens with the byte span each was lexed from. 
+    // The trailing Token::EOF gets an empty span at the end of the input. 
+    pub fn lex_spanned(bytes: &[u8]) -> Result<(Vec<Token>, Vec<Span>), Error> {
+        Lexer::lex_with(bytes, lex_token)
+    }
+
+    // Same as lex_spanned, but references are read as R1C1 (Token::R1C1) instead of A1. 
+    pub fn lex_spanned_r1c1(bytes: &[u8]) -> Result<(Vec<Token>, Vec<Span>), Error> {
+        Lexer::lex_with(bytes, lex_token_r1c1)
+    }
+
+    fn lex_with(bytes: &[u8], lex_token: fn(&[u8]) -> IResult<&[u8], Token>) -> Result<(Vec<Token>, Vec<Span>), Error> {
+        let mut tokens: Vec<Token> = vec![]; 
+        let mut spans: Vec<Span> = vec![]; 
+        let mut input: &[u8] = bytes; 
+        loop {
+            let (rest, _) = multispace0::<&[u8], nom::error::Error<&[u8]>>(input).unwrap_or((input, input)); 
+            let start = bytes.len() - rest.len(); 
+            if rest.is_empty() {
+                break; 
+            }
+            match lex_token(rest) {
+                Ok((remaining, token)) => {
+                    tokens.push(token); 
+                    spans.push(Span::new(start, bytes.len() - remaining.len())); 
+                    input = remaining; 
                 }, 
-                _ => Err(Error::UnableToLex(String::from_utf8(bytes.to_vec()).unwrap()))
+                Err(_) => {
+                    let formula = String::from_utf8_lossy(bytes); 
+                    let width = formula.get(start..).and_then(|s| s.chars().next()).map(|c| c.len_utf8()).unwrap_or(1); 
+                    return Err(Error::UnableToLex(SyntaxError::new(&formula, Span::new(start, start + width), vec![]))); 
+                }
             }
-	}
+        }
+        tokens.push(Token::EOF); 
+        spans.push(Span::new(bytes.len(), bytes.len())); 
+        Ok((tokens, spans))
+    }
 }
 
 #[cfg(test)]
@@ -352,6 +638,12 @@ mod tests {
             Token::Text(String::from("test")), 
             Token::EOF, 
         ]);
+        assert_eq!(lex(b"\"say \"\"hi\"\"\" \"\" \"C:\\temp\"")?, vec![
+            Token::Text(String::from("say \"hi\"")), 
+            Token::Text(String::new()), 
+            Token::Text(String::from("C:\\temp")), 
+            Token::EOF, 
+        ]);
         Ok(())
     }
 
@@ -366,6 +658,9 @@ mod tests {
         assert_eq!(lex(b"12.30")?, vec![
             Token::Float(12.30), Token::EOF
         ]); 
+        assert_eq!(lex(b"1.5E-3 2e10 1E+2")?, vec![
+            Token::Float(1.5e-3), Token::Float(2e10), Token::Float(100.0), Token::EOF
+        ]); 
         Ok(())
     }
 
@@ -376,8 +671,11 @@ mod tests {
         assert_eq!(lex(b"#VALUE!")?, vec![Token::Value, Token::EOF]); 
         assert_eq!(lex(b"#REF!")?, vec![Token::Ref, Token::EOF]); 
         assert_eq!(lex(b"#NAME!")?, vec![Token::Name, Token::EOF]); 
+        assert_eq!(lex(b"#NAME?")?, vec![Token::Name, Token::EOF]); 
         assert_eq!(lex(b"#N/A")?, vec![Token::NA, Token::EOF]); 
         assert_eq!(lex(b"#GETTING_DATA")?, vec![Token::GettingData, Token::EOF]); 
+        assert_eq!(lex(b"#SPILL!")?, vec![Token::Spill, Token::EOF]); 
+        assert_eq!(lex(b"#CALC!")?, vec![Token::Calc, Token::EOF]); 
         Ok(())
     }
 
@@ -397,6 +695,9 @@ mod tests {
     #[test]
     fn test_sheet() -> Result<(), Error> {
         assert_eq!(lex(b"'Test'!")?, vec![Token::Sheet(String::from("Test")), Token::EOF]); 
+        assert_eq!(lex(b"'Bob''s (2)'!")?, vec![Token::Sheet(String::from("Bob's (2)")), Token::EOF]); 
+        assert_eq!(lex(b"[1]Inputs!B4")?, vec![Token::Sheet(String::from("[1]Inputs")), Token::Cell(String::from("B4")), Token::EOF]); 
+        assert_eq!(lex(b"'[Rates.xlsx]Curve'!A1:A30")?, vec![Token::Sheet(String::from("[Rates.xlsx]Curve")), Token::Range(String::from("A1:A30")), Token::EOF]); 
         Ok(())
     }
 
@@ -426,9 +727,129 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_spill_ref() -> Result<(), Error> {
+        assert_eq!(lex(b"$A$1#")?, vec![Token::SpillRef(String::from("$A$1")), Token::EOF]); 
+        assert_eq!(lex(b"Sheet2!B3#")?, vec![Token::Sheet(String::from("Sheet2")), Token::SpillRef(String::from("B3")), Token::EOF]); 
+        assert_eq!(lex(b"SUM(A1#)")?, vec![Token::Ident(String::from("SUM")), Token::LParen, Token::SpillRef(String::from("A1")), Token::RParen, Token::EOF]); 
+        Ok(())
+    }
+
     #[test]
     fn test_ident() -> Result<(), Error> {
         assert_eq!(lex(b"test")?, vec![Token::Ident("test".to_string()), Token::EOF]); 
+        assert_eq!(lex(b"CEILING.MATH(LOG10(A1))")?, vec![
+            Token::Ident("CEILING.MATH".to_string()), 
+            Token::LParen, 
+            Token::Ident("LOG10".to_string()), 
+            Token::LParen, 
+            Token::Cell("A1".to_string()), 
+            Token::RParen, 
+            Token::RParen, 
+            Token::EOF
+        ]); 
+        assert_eq!(lex(b"LOG10")?, vec![Token::Cell("LOG10".to_string()), Token::EOF]); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_r1c1() -> Result<(), Error> {
+        let (tokens, _) = Lexer::lex_spanned_r1c1(b"R[-1]C+RC[-2]*Sheet1!R2C3:R[1]C4+SUM(C2, R[3])+ROUND(R1C1, 0)")?; 
+        assert_eq!(tokens, vec![
+            Token::R1C1(String::from("R[-1]C")), 
+            Token::Plus, 
+            Token::R1C1(String::from("RC[-2]")), 
+            Token::Multiply, 
+            Token::Sheet(String::from("Sheet1")), 
+            Token::R1C1(String::from("R2C3:R[1]C4")), 
+            Token::Plus, 
+            Token::Ident(String::from("SUM")), 
+            Token::LParen, 
+            Token::R1C1(String::from("C2")), 
+            Token::Comma, 
+            Token::R1C1(String::from("R[3]")), 
+            Token::RParen, 
+            Token::Plus, 
+            Token::Ident(String::from("ROUND")), 
+            Token::LParen, 
+            Token::R1C1(String::from("R1C1")), 
+            Token::Comma, 
+            Token::Integer(0), 
+            Token::RParen, 
+            Token::EOF
+        ]); 
+        let (tokens, _) = Lexer::lex_spanned_r1c1(b"R[-4]C[-4]#+Sheet1!R1C1#")?; 
+        assert_eq!(tokens, vec![
+            Token::SpillRef(String::from("R[-4]C[-4]")), 
+            Token::Plus, 
+            Token::Sheet(String::from("Sheet1")), 
+            Token::SpillRef(String::from("R1C1")), 
+            Token::EOF
+        ]); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_table() -> Result<(), Error> {
+        let table = |table: Option<&str>, items: Vec<TableItem>, columns: Option<(&str, &str)>| {
+            Token::Table(TableRef { 
+                table: table.map(|t| t.to_string()), 
+                items, 
+                columns: columns.map(|(a, b)| (a.to_string(), b.to_string())) 
+            })
+        }; 
+        assert_eq!(lex(b"SUM(Sales[Amount])")?, vec![
+            Token::Ident(String::from("SUM")), 
+            Token::LParen, 
+            table(Some("Sales"), vec![], Some(("Amount", "Amount"))), 
+            Token::RParen, 
+            Token::EOF
+        ]); 
+        assert_eq!(lex(b"[@Qty]*[@[Unit Price]]")?, vec![
+            table(None, vec![TableItem::ThisRow], Some(("Qty", "Qty"))), 
+            Token::Multiply, 
+            table(None, vec![TableItem::ThisRow], Some(("Unit Price", "Unit Price"))), 
+            Token::EOF
+        ]); 
+        assert_eq!(lex(b"Sales[[#Headers], [#Data],[Region]:[Amount]]")?, vec![
+            table(Some("Sales"), vec![TableItem::Headers, TableItem::Data], Some(("Region", "Amount"))), 
+            Token::EOF
+        ]); 
+        assert_eq!(lex(b"Sales[#This Row] Sales[] Sales[[Price '[$']]]")?, vec![
+            table(Some("Sales"), vec![TableItem::ThisRow], None), 
+            table(Some("Sales"), vec![], None), 
+            table(Some("Sales"), vec![], Some(("Price [$]", "Price [$]"))), 
+            Token::EOF
+        ]); 
+        assert!(lex(b"Sales[#Bogus]").is_err()); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_spans() -> Result<(), Error> {
+        let (tokens, spans) = Lexer::lex_spanned(b"SUM( A1:B2 , 'My Sheet'!C3)")?; 
+        assert_eq!(tokens.len(), spans.len()); 
+        assert_eq!(spans, vec![
+            Span::new(0, 3), 
+            Span::new(3, 4), 
+            Span::new(5, 10), 
+            Span::new(11, 12), 
+            Span::new(13, 24), 
+            Span::new(24, 26), 
+            Span::new(26, 27), 
+            Span::new(27, 27), 
+        ]); 
         Ok(())
     }
+
+    #[test]
+    fn test_lex_error() {
+        match lex(b"A1 + ?") {
+            Err(Error::UnableToLex(e)) => {
+                assert_eq!(e.span, Span::new(5, 6)); 
+                assert_eq!(e.render(), "A1 + ?\n     ^"); 
+            }, 
+            other => panic!("Expected a lex error, got {:?}", other)
+        }
+    }
 }
@@ -2,6 +2,7 @@ use nom::*;
 use std::iter::Enumerate;
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
 use std::fmt; 
+use crate::parser::ast::TableRef; 
 
 #[derive(Debug, Clone)]
 pub enum Token {
@@ -19,6 +20,8 @@ pub enum Token {
     Num, 
     NA, 
     GettingData, 
+    Spill, 
+    Calc, 
     // References
     MultiSheet(String), 
     Sheet(String), 
@@ -26,6 +29,9 @@ pub enum Token {
     Cell(String), 
     VRange(String), 
     HRange(String), 
+    R1C1(String), 
+    SpillRef(String), // Anchor of A1#, without the # 
+    Table(TableRef), 
     // Symbols
     Plus,
     Minus,
@@ -78,15 +84,20 @@ impl fmt::Display for Token {
             Token::Cell(s) => write!(f, "{}", s), 
             Token::VRange(s) => write!(f, "{}", s), 
             Token::HRange(s) => write!(f, "{}", s), 
+            Token::R1C1(s) => write!(f, "{}", s), 
+            Token::SpillRef(s) => write!(f, "{}#", s), 
+            Token::Table(t) => write!(f, "{}", t), 
             Token::Ident(s) => write!(f, "{}", s), 
             Token::Null => write!(f, "#NULL!"), 
             Token::Div => write!(f, "#DIV/0!"), 
             Token::Value => write!(f, "#VALUE!"),
             Token::Ref => write!(f, "#REF!"), 
-            Token::Name => write!(f, "#NAME!"), 
+            Token::Name => write!(f, "#NAME?"), 
             Token::Num => write!(f, "#NUM!"), 
-            Token::NA => write!(f, "#N/A!"), 
+            Token::NA => write!(f, "#N/A"), 
             Token::GettingData => write!(f, "#GETTING_DATA"), 
+            Token::Spill => write!(f, "#SPILL!"), 
+            Token::Calc => write!(f, "#CALC!"), 
             Token::Plus => write!(f, "+"), 
             Token::Minus => write!(f, "-"), 
             Token::Divide => write!(f, "/"), 
@@ -112,33 +123,68 @@ impl fmt::Display for Token {
     }
 }
 
+// Byte offsets of a token in the formula it was lexed from (end exclusive). 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Span {
+    pub start: usize, 
+    pub end: usize, 
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Tokens<'a> {
     pub tok: &'a [Token], 
+    pub spans: &'a [Span], 
     pub start: usize, 
     pub end: usize, 
 }
 
 impl<'a> Tokens<'a> {
-    pub fn new(vec: &'a [Token]) -> Self {
+    pub fn new(vec: &'a [Token], spans: &'a [Span]) -> Self {
         Tokens {
             tok: vec,
+            spans, 
             start: 0,
             end: vec.len(),
         }
     }
+
+    // Span of the next token, or an empty span when no spans were supplied. 
+    pub fn span(&self) -> Span {
+        self.spans.first().copied().unwrap_or_default()
+    }
 }
 
-impl<'a> InputLength for Tokens<'a> {
+impl InputLength for Tokens<'_> {
     fn input_len(&self) -> usize {
         self.tok.len()
     }
 }
 
-impl<'a> InputTake for Tokens<'a> {
+impl InputTake for Tokens<'_> {
     fn take(&self, count: usize) -> Self {
         Tokens {
             tok: &self.tok[0..count],
+            spans: &self.spans[0..count.min(self.spans.len())],
             start: 0,
             end: count,
         }
@@ -146,13 +192,16 @@ impl<'a> InputTake for Tokens<'a> {
 
     fn take_split(&self, count: usize) -> (Self, Self) {
         let (prefix, suffix) = self.tok.split_at(count);
+        let (span_prefix, span_suffix) = self.spans.split_at(count.min(self.spans.len()));
         let first = Tokens {
             tok: prefix,
+            spans: span_prefix, 
             start: 0,
             end: prefix.len(),
         };
         let second = Tokens {
             tok: suffix,
+            spans: span_suffix, 
             start: 0,
             end: suffix.len(),
         };
@@ -166,34 +215,36 @@ impl InputLength for Token {
     }
 }
 
-impl<'a> Slice<Range<usize>> for Tokens<'a> {
+impl Slice<Range<usize>> for Tokens<'_> {
     fn slice(&self, range: Range<usize>) -> Self {
         Tokens {
             tok: self.tok.slice(range.clone()),
+            spans: self.spans.slice(range.start.min(self.spans.len())..range.end.min(self.spans.len())),
             start: self.start + range.start,
             end: self.start + range.end,
         }
     }
 }
 
-impl<'a> Slice<RangeTo<usize>> for Tokens<'a> {
+impl Slice<RangeTo<usize>> for Tokens<'_> {
     fn slice(&self, range: RangeTo<usize>) -> Self {
         self.slice(0..range.end)
     }
 }
 
-impl<'a> Slice<RangeFrom<usize>> for Tokens<'a> {
+impl Slice<RangeFrom<usize>> for Tokens<'_> {
     #[inline]
     fn slice(&self, range: RangeFrom<usize>) -> Self {
         self.slice(range.start..self.end - self.start)
     }
 }
 
-impl<'a> Slice<RangeFull> for Tokens<'a> {
+impl Slice<RangeFull> for Tokens<'_> {
     #[inline]
     fn slice(&self, _: RangeFull) -> Self {
         Tokens {
             tok: self.tok,
+            spans: self.spans,
             start: self.start,
             end: self.end,
         }
@@ -230,4 +281,4 @@ impl<'a> InputIter for Tokens<'a> {
     }
 }
 
-impl<'a> UnspecializedInput for Tokens<'a> { }
+impl UnspecializedInput for Tokens<'_> { }
@@ -4,8 +4,10 @@ pub mod evaluate;
 pub mod function; 
 pub mod tree; 
 pub mod utils;
+pub mod format; 
 pub mod workbook; 
 pub mod cell; 
 pub mod reference; 
 pub mod dependency; 
 pub mod errors; 
+pub mod coverage; 
@@ -1,4 +1,5 @@
 use std::fmt;  
+use crate::parser::printer; 
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Expr {
@@ -13,45 +14,26 @@ pub enum Expr {
         sheet: Option<String>, 
         reference: String 
     }, 
-	Array(Vec<Expr>),
-    Error(Error)
+    // A1#, the spill range of the dynamic array whose formula is in `anchor` 
+    Spill {
+        sheet: Option<String>, 
+        anchor: String 
+    }, 
+	// {1,2;3,4}, rows of equal length 
+	Array(Vec<Vec<Expr>>),
+    Error(Error), 
+    Table(TableRef), 
+    // A LET variable, LAMBDA parameter or defined name 
+    Name(String), 
+    // LAMBDA(x, x+1)(2), calls whatever function the first expression evaluates to 
+    Call(Box<Expr>, Vec<Expr>)
 }
 
+// Canonical Excel syntax, see parser::printer. 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Expr::Literal(l) => write!(f, "{}", l), 
-            Expr::Prefix(p, e) => write!(f, "{}{}", p, e), 
-            Expr::Infix(p, a, b) => write!(f, "({}{}{})", a, p, b), 
-            Expr::Func{name, args} => {
-                let output = format!("{}({})", name, exprs_string(args));
-                write!(f, "{}", output)
-            }, 
-            Expr::Reference{sheet, reference} => {
-                match sheet {
-                    Some(s) => {
-                        write!(f, "{}!{}", s, reference)
-                    }, 
-                    None => write!(f, "{}", reference)
-                }
-            }, 
-            Expr::Array(arr) => write!(f, "{{{}}}", exprs_string(arr)), 
-            Expr::Error(e) => write!(f, "{}", e)
-        }
-    }
-}
-
-fn exprs_string(v: &Vec<Expr>) -> String {
-    let mut output = String::new(); 
-    for (i, arg) in v.iter().enumerate() {
-        if i == v.len()-1 {
-            output.push_str(&arg.to_string());
-        } else {
-            output.push_str(&arg.to_string());
-            output.push_str(", ");
-        }
+        write!(f, "{}", printer::print(self))
     }
-    output
 }
 
 impl From<f64> for Expr {
@@ -77,6 +59,58 @@ impl From<bool> for Expr {
     }
 }
 
+/*
+ * Structured reference to an Excel Table, f.ex. Sales[[#Totals],[Amount]] or [@Qty]. 
+ * No table means the table the formula sits in, no items means #Data and 
+ * no columns means every column. 
+ */
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TableRef {
+    pub table: Option<String>, 
+    pub items: Vec<TableItem>, 
+    pub columns: Option<(String, String)>, // First and last column
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TableItem {
+    All, 
+    Data, 
+    Headers, 
+    Totals, 
+    ThisRow
+}
+
+impl TableItem {
+    pub fn from_name(s: &str) -> Option<TableItem> {
+        match s.to_uppercase().as_str() {
+            "#ALL" => Some(TableItem::All), 
+            "#DATA" => Some(TableItem::Data), 
+            "#HEADERS" => Some(TableItem::Headers), 
+            "#TOTALS" => Some(TableItem::Totals), 
+            "#THIS ROW" => Some(TableItem::ThisRow), 
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for TableItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableItem::All => write!(f, "#All"), 
+            TableItem::Data => write!(f, "#Data"), 
+            TableItem::Headers => write!(f, "#Headers"), 
+            TableItem::Totals => write!(f, "#Totals"), 
+            TableItem::ThisRow => write!(f, "#This Row"), 
+        }
+    }
+}
+
+impl fmt::Display for TableRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", printer::print_table_ref(self))
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Error {
     Null,
@@ -86,7 +120,9 @@ pub enum Error {
     Name, 
     Num, 
     NA, 
-    GettingData
+    GettingData, 
+    Spill, 
+    Calc
 }
 
 impl fmt::Display for Error {
@@ -96,10 +132,12 @@ impl fmt::Display for Error {
             Error::Div => write!(f, "#DIV/0!"), 
             Error::Value => write!(f, "#VALUE!"),
             Error::Ref => write!(f, "#REF!"),
-            Error::Name => write!(f, "#NAME!"), 
+            Error::Name => write!(f, "#NAME?"), 
             Error::Num => write!(f, "#NUM!"), 
-            Error::NA => write!(f, "#N/A!"), 
-            Error::GettingData => write!(f, "#GETTING_DATA")
+            Error::NA => write!(f, "#N/A"), 
+            Error::GettingData => write!(f, "#GETTING_DATA"), 
+            Error::Spill => write!(f, "#SPILL!"), 
+            Error::Calc => write!(f, "#CALC!")
         }
     }
 }
@@ -109,6 +147,8 @@ pub enum Literal {
     Number(f64),
     Boolean(bool),
     Text(String),
+    // A left out function argument 
+    Empty,
 }
 
 impl From<f64> for Literal {
@@ -137,17 +177,7 @@ impl From<bool> for Literal {
 
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Literal::Number(x) => write!(f, "{}", x), 
-            Literal::Boolean(b) => {
-                if *b {
-                    write!(f, "TRUE")
-                } else {
-                    write!(f, "FALSE")
-                }
-            },
-            Literal::Text(s) => write!(f, "{}", s)
-        }
+        write!(f, "{}", printer::print_literal(self))
     }
 }
 
@@ -1,27 +1,83 @@
 use nom::branch::*;
 use nom::bytes::complete::take;
-use nom::combinator::{map, verify, opt};
+use nom::combinator::{map, opt, peek};
 use nom::multi::many0;
 use nom::sequence::{preceded, delimited, pair, terminated};
 use nom::*;
 use nom::Err; 
-use nom::error::{Error as NomError, ErrorKind}; 
+use nom::error::{ParseError, ErrorKind}; 
 
 pub mod ast; 
+pub mod printer; 
 
 use crate::{
     lexer::{
         Lexer,
-        token::{Token, Tokens}, 
+        token::{Token, Tokens, Span}, 
     }, 
     parser::ast::{Expr, Error as ExcelError, Literal, Prefix, Infix, Precedence}, 
-    errors::Error
+    errors::{Error, SyntaxError}, 
+    cell::Cell, 
+    reference::Reference, 
 }; 
 
+/*
+ * nom error for the token parser. 
+ * Keeps the furthest position reached and what would have been accepted there, 
+ * so a failed parse can point at the offending token. 
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseFailure<'a> {
+    pub input: Tokens<'a>, 
+    pub expected: Vec<String>, 
+}
+
+impl<'a> ParseFailure<'a> {
+    fn expected(input: Tokens<'a>, kind: &str) -> ParseFailure<'a> {
+        ParseFailure { input, expected: vec![kind.to_string()] }
+    }
+}
+
+impl<'a> ParseError<Tokens<'a>> for ParseFailure<'a> {
+    fn from_error_kind(input: Tokens<'a>, _kind: ErrorKind) -> Self {
+        ParseFailure { input, expected: vec![] }
+    }
+
+    fn append(_input: Tokens<'a>, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn or(mut self, other: Self) -> Self {
+        match self.input.input_len().cmp(&other.input.input_len()) {
+            std::cmp::Ordering::Less => self, 
+            std::cmp::Ordering::Greater => other, 
+            std::cmp::Ordering::Equal => {
+                for e in other.expected {
+                    if !self.expected.contains(&e) {
+                        self.expected.push(e); 
+                    }
+                }
+                self
+            }
+        }
+    }
+}
+
+type ParseResult<'a, O> = IResult<Tokens<'a>, O, ParseFailure<'a>>; 
+
+fn fail<'a, O>(input: Tokens<'a>, kind: &str) -> ParseResult<'a, O> {
+    Err(Err::Error(ParseFailure::expected(input, kind)))
+}
+
 macro_rules! tag_token (
 	($func_name:ident, $tag: expr) => (
-		fn $func_name(tokens: Tokens) -> IResult<Tokens, Tokens> {
-			verify(take(1usize), |t: &Tokens| t.tok[0] == $tag)(tokens)
+		fn $func_name(tokens: Tokens) -> ParseResult<Tokens> {
+            let (i1, t1) = take(1usize)(tokens)?; 
+            if t1.tok[0] == $tag {
+                Ok((i1, t1))
+            } else {
+                fail(tokens, &format!("'{}'", $tag))
+            }
 		}
 	)
 );
@@ -41,31 +97,39 @@ tag_token!(lparen_tag, Token::LParen);
 tag_token!(rparen_tag, Token::RParen); 
 tag_token!(lbrace_tag, Token::LBrace); 
 tag_token!(rbrace_tag, Token::RBrace); 
-tag_token!(eof_tag, Token::EOF); 
 
-fn parse_literal(input: Tokens) -> IResult<Tokens, Literal> {
+fn eof_tag(tokens: Tokens) -> ParseResult<Tokens> {
+    let (i1, t1) = take(1usize)(tokens)?; 
+    if t1.tok[0] == Token::EOF {
+        Ok((i1, t1))
+    } else {
+        fail(tokens, "end of formula")
+    }
+}
+
+fn parse_literal(input: Tokens) -> ParseResult<Literal> {
     let (i1, t1) = take(1usize)(input)?;
 	if t1.tok.is_empty() {
-        Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+        fail(input, "literal")
     } else {
         match t1.tok[0].clone() {
             Token::Integer(x) => Ok((i1, Literal::Number(x as f64))), 
             Token::Float(x) => Ok((i1, Literal::Number(x))), 
             Token::Text(s) => Ok((i1, Literal::Text(s))),
             Token::Boolean(b) => Ok((i1, Literal::Boolean(b))),
-            _ => Err(Err::Error(NomError::new(input, ErrorKind::Tag))),
+            _ => fail(input, "literal"),
         }
     }
 }
 
-fn parse_literal_expr(input: Tokens) -> IResult<Tokens, Expr> {
+fn parse_literal_expr(input: Tokens) -> ParseResult<Expr> {
     map(parse_literal, Expr::Literal)(input)
 }
 
-fn parse_error(input: Tokens) -> IResult<Tokens, ExcelError> {
+fn parse_error(input: Tokens) -> ParseResult<ExcelError> {
     let (i1, t1) = take(1usize)(input)?;
 	if t1.tok.is_empty() {
-        Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+        fail(input, "error")
     } else {
         match t1.tok[0].clone() {
             Token::Null => Ok((i1, ExcelError::Null)), 
@@ -76,27 +140,47 @@ fn parse_error(input: Tokens) -> IResult<Tokens, ExcelError> {
             Token::Num => Ok((i1, ExcelError::Num)), 
             Token::NA => Ok((i1, ExcelError::NA)), 
             Token::GettingData => Ok((i1, ExcelError::GettingData)), 
-            _ => Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+            Token::Spill => Ok((i1, ExcelError::Spill)), 
+            Token::Calc => Ok((i1, ExcelError::Calc)), 
+            _ => fail(input, "error")
         }
     }
 }
 
-fn parse_error_expr(input: Tokens) -> IResult<Tokens, Expr> {
+fn parse_error_expr(input: Tokens) -> ParseResult<Expr> {
     map(parse_error, Expr::Error)(input)
 }
 
-fn parse_ident(input: Tokens) -> IResult<Tokens, Token> {
+fn parse_ident(input: Tokens) -> ParseResult<Token> {
     let (i1, t1) = take(1usize)(input)?;
     if t1.tok.is_empty() {
-        Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+        fail(input, "function name")
     } else if matches!(t1.tok[0], Token::Ident(_)) {
         Ok((i1, t1.tok[0].clone()))
     } else {
-        Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+        fail(input, "function name")
     }
 }
 
-fn parse_func_expr(input: Tokens) -> IResult<Tokens, Expr> {
+// Files write LAMBDA parameters and LET names with an _xlpm. prefix. 
+fn unprefixed(ident: Token) -> String {
+    let name = format!("{}", ident); 
+    match name.get(..6) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("_xlpm.") => name[6..].to_string(), 
+        _ => name
+    }
+}
+
+fn parse_func_expr(input: Tokens) -> ParseResult<Expr> {
+    let (i1, func) = parse_func_call(input)?; 
+    // LAMBDA(x, x+1)(2) calls the function it just made 
+    match delimited(lparen_tag, alt((parse_exprs, empty_boxed_vec)), rparen_tag)(i1) {
+        Ok((i2, args)) => Ok((i2, Expr::Call(Box::new(func), args))), 
+        Err(_) => Ok((i1, func))
+    }
+}
+
+fn parse_func_call(input: Tokens) -> ParseResult<Expr> {
    map(
        pair(
            parse_ident, 
@@ -107,12 +191,27 @@ fn parse_func_expr(input: Tokens) -> IResult<Tokens, Expr> {
            )
         ),
         |(ident, exprs)| {
-            Expr::Func { name: format!("{}", ident), args: exprs }
+            match (unprefixed(ident), exprs.as_slice()) {
+                // How files store A1#, with the _xlfn. prefix already dropped 
+                (name, [Expr::Reference { sheet, reference }]) if name.eq_ignore_ascii_case("ANCHORARRAY") => {
+                    Expr::Spill { sheet: sheet.clone(), anchor: reference.clone() }
+                }, 
+                (name, _) => Expr::Func { name, args: exprs }
+            }
         }
    )(input)
 }
 
-fn parse_prefix_expr(input: Tokens) -> IResult<Tokens, Expr> {
+// A bare identifier, one followed by ( is a function call instead. 
+fn parse_name_expr(input: Tokens) -> ParseResult<Expr> {
+    let (i1, ident) = parse_ident(input)?; 
+    match lparen_tag(i1) {
+        Ok(_) => fail(input, "name"), 
+        Err(_) => Ok((i1, Expr::Name(unprefixed(ident))))
+    }
+}
+
+fn parse_prefix_expr(input: Tokens) -> ParseResult<Expr> {
     map(
         pair(alt((plus_tag, minus_tag)), parse_atom_expr), 
         |(pre, expr)| {
@@ -128,70 +227,106 @@ fn parse_prefix_expr(input: Tokens) -> IResult<Tokens, Expr> {
 }
 
 
-fn parse_comma_exprs(input: Tokens) -> IResult<Tokens, Expr> {
+// A left out argument, like the fourth of XLOOKUP(3,A1:A3,B1:B3,,0,2). 
+fn parse_empty_arg(input: Tokens) -> ParseResult<Expr> {
     map(
-        preceded(alt((comma_tag, semicolon_tag)), parse_expr), 
+        peek(alt((comma_tag, semicolon_tag, rparen_tag))), 
+        |_| Expr::Literal(Literal::Empty)
+    )(input)
+}
+
+fn parse_arg(input: Tokens) -> ParseResult<Expr> {
+    alt((parse_expr, parse_empty_arg))(input)
+}
+
+fn parse_comma_exprs(input: Tokens) -> ParseResult<Expr> {
+    map(
+        preceded(alt((comma_tag, semicolon_tag)), parse_arg), 
         |expr| {
             expr
         }
     )(input)
 }
 
-fn parse_exprs(input: Tokens) -> IResult<Tokens, Vec<Expr>> {
-    map(
-        pair(parse_expr, many0(parse_comma_exprs)),
+fn parse_exprs(input: Tokens) -> ParseResult<Vec<Expr>> {
+    let (i1, exprs) = map(
+        pair(parse_arg, many0(parse_comma_exprs)),
         |(first, second)| {
             [&vec![first][..], &second[..]].concat()
         }
-    )(input)
+    )(input)?; 
+    // PI() has no arguments rather than one empty one 
+    match exprs.as_slice() {
+        [Expr::Literal(Literal::Empty)] => fail(input, "expression"), 
+        _ => Ok((i1, exprs))
+    }
 }
 
-fn empty_boxed_vec(input: Tokens) -> IResult<Tokens, Vec<Expr>> {
+fn empty_boxed_vec(input: Tokens) -> ParseResult<Vec<Expr>> {
     Ok((input, vec![]))
 }
 
-fn parse_array_expr(input: Tokens) -> IResult<Tokens, Expr> {
+// Commas separate the values of a row, semicolons the rows. 
+fn parse_array_row(input: Tokens) -> ParseResult<Vec<Expr>> {
     map(
-        delimited(
-            lbrace_tag, 
-            alt((parse_exprs, empty_boxed_vec)),
-            rbrace_tag,
-        ), 
-        |exprs| {
-            Expr::Array(exprs)
+        pair(parse_expr, many0(preceded(comma_tag, parse_expr))),
+        |(first, rest)| {
+            [&vec![first][..], &rest[..]].concat()
         }
     )(input)
 }
 
-fn parse_sheet_or_multisheet(input: Tokens) -> IResult<Tokens, Token> {
+fn parse_array_rows(input: Tokens) -> ParseResult<Vec<Vec<Expr>>> {
+    map(
+        pair(parse_array_row, many0(preceded(semicolon_tag, parse_array_row))),
+        |(first, rest)| {
+            [&vec![first][..], &rest[..]].concat()
+        }
+    )(input)
+}
+
+fn parse_array_expr(input: Tokens) -> ParseResult<Expr> {
+    let (i1, rows) = delimited(
+        lbrace_tag, 
+        alt((parse_array_rows, map(empty_boxed_vec, |_| vec![]))),
+        rbrace_tag,
+    )(input)?; 
+    if rows.iter().any(|row| row.len() != rows[0].len()) {
+        return fail(input, "rows of the same length"); 
+    }
+    Ok((i1, Expr::Array(rows)))
+}
+
+fn parse_sheet_or_multisheet(input: Tokens) -> ParseResult<Token> {
     let (i1, t1) = take(1usize)(input)?;
     if t1.tok.is_empty() {
-        Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+        fail(input, "sheet")
     } else {
         match &t1.tok[0] {
             Token::MultiSheet(s) => Ok((i1, Token::MultiSheet(s.to_string()))), 
             Token::Sheet(s) => Ok((i1, Token::Sheet(s.to_string()))), 
-            _ => Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+            _ => fail(input, "sheet")
         }
     }
 }
 
-fn parse_cell_or_range(input: Tokens) -> IResult<Tokens, Token> {
+fn parse_cell_or_range(input: Tokens) -> ParseResult<Token> {
     let (i1, t1) = take(1usize)(input)?;
     if t1.tok.is_empty() {
-        Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+        fail(input, "reference")
     } else {
         match &t1.tok[0] {
             Token::Range(s) => Ok((i1, Token::Range(s.to_string()))), 
             Token::Cell(s) => Ok((i1, Token::Cell(s.to_string()))), 
             Token::VRange(s) => Ok((i1, Token::VRange(s.to_string()))), 
             Token::HRange(s) => Ok((i1, Token::HRange(s.to_string()))), 
-            _ => Err(Err::Error(NomError::new(input, ErrorKind::Tag)))
+            Token::R1C1(s) => Ok((i1, Token::R1C1(s.to_string()))), 
+            _ => fail(input, "reference")
         }
     }
 }
 
-fn parse_reference_expr(input: Tokens) -> IResult<Tokens, Expr> {
+fn parse_reference_expr(input: Tokens) -> ParseResult<Expr> {
     map(
         pair(
             opt(parse_sheet_or_multisheet), parse_cell_or_range
@@ -205,13 +340,29 @@ fn parse_reference_expr(input: Tokens) -> IResult<Tokens, Expr> {
     )(input)
 }
 
+fn parse_spill_expr(input: Tokens) -> ParseResult<Expr> {
+    let (i1, sheet) = opt(parse_sheet_or_multisheet)(input)?; 
+    let (i2, t2) = take(1usize)(i1)?; 
+    match t2.tok.first() {
+        Some(Token::SpillRef(anchor)) => Ok((i2, Expr::Spill { sheet: sheet.map(|x| format!("{}", x)), anchor: anchor.clone() })), 
+        _ => fail(i1, "spill reference")
+    }
+}
+
+fn parse_table_expr(input: Tokens) -> ParseResult<Expr> {
+    let (i1, t1) = take(1usize)(input)?;
+    match t1.tok.first() {
+        Some(Token::Table(t)) => Ok((i1, Expr::Table(t.clone()))), 
+        _ => fail(input, "table reference")
+    }
+}
 
-fn parse_paren_expr(input: Tokens) -> IResult<Tokens, Expr> {
+fn parse_paren_expr(input: Tokens) -> ParseResult<Expr> {
     delimited(lparen_tag, parse_expr, rparen_tag)(input)
 }
 
 
-fn infix_precedence(infix: Infix) -> Precedence {
+pub(crate) fn infix_precedence(infix: Infix) -> Precedence {
     match infix {
         Infix::Equal
             | Infix::NotEqual 
@@ -226,7 +377,7 @@ fn infix_precedence(infix: Infix) -> Precedence {
     }
 }
 
-fn parse_infix_tags(input: Tokens) -> IResult<Tokens, Infix> {
+fn parse_infix_tags(input: Tokens) -> ParseResult<Infix> {
     alt((
         map(plus_tag, |_| Infix::Plus), 
         map(minus_tag, |_| Infix::Minus), 
@@ -243,12 +394,12 @@ fn parse_infix_tags(input: Tokens) -> IResult<Tokens, Infix> {
     ))(input)
 }
 
-fn parse_pratt(input: Tokens, precedence: Precedence) -> IResult<Tokens, Expr> {
+fn parse_pratt(input: Tokens, precedence: Precedence) -> ParseResult<Expr> {
     let (i1, left) = parse_atom_expr(input)?;
     go_parse_pratt(i1, left, precedence)
 }
 
-fn go_parse_pratt(input: Tokens, lhs: Expr, precedence: Precedence) -> IResult<Tokens, Expr> {
+fn go_parse_pratt(input: Tokens, lhs: Expr, precedence: Precedence) -> ParseResult<Expr> {
     let (i1, t1) = take(1usize)(input)?; 
     if t1.tok.is_empty() {
         Ok((i1, lhs))
@@ -273,10 +424,10 @@ fn go_parse_pratt(input: Tokens, lhs: Expr, precedence: Precedence) -> IResult<T
     }
 }
 
-fn parse_infix(input: Tokens, lhs: Expr) -> IResult<Tokens, Expr> {
+fn parse_infix(input: Tokens, lhs: Expr) -> ParseResult<Expr> {
     let (_i1, t1) = take(1usize)(input)?;
     if t1.tok.is_empty() {
-        Err(Err::Error(error_position!(input, ErrorKind::Tag)))
+        fail(input, "operator")
     } else {
         let (i2, infix) = parse_infix_tags(input)?;
         let p = infix_precedence(infix.clone()); 
@@ -285,46 +436,117 @@ fn parse_infix(input: Tokens, lhs: Expr) -> IResult<Tokens, Expr> {
     }
 }
 
-fn parse_infix_expr(input: Tokens) -> IResult<Tokens, Expr> {
+fn parse_infix_expr(input: Tokens) -> ParseResult<Expr> {
     parse_pratt(input, Precedence::Lowest)
 }
 
-fn parse_atom_expr(input: Tokens) -> IResult<Tokens, Expr> {
+fn parse_atom_expr(input: Tokens) -> ParseResult<Expr> {
     alt((
         parse_prefix_expr,
         parse_paren_expr, 
         parse_error_expr, 
         parse_func_expr, 
+        parse_name_expr, 
         parse_array_expr, 
         parse_reference_expr, 
+        parse_spill_expr, 
+        parse_table_expr, 
         parse_literal_expr, 
-    ))(input)
+    ))(input).map_err(|e| e.map(|failure| {
+        // Nothing matched at all, report a single expectation rather than every atom kind. 
+        if failure.input.input_len() == input.input_len() {
+            ParseFailure::expected(input, "expression")
+        } else {
+            failure
+        }
+    }))
 }
 
-fn parse_expr(input: Tokens) -> IResult<Tokens, Expr> {
-    alt((
-        parse_infix_expr, 
-        parse_atom_expr,
-    ))(input)
+fn parse_expr(input: Tokens) -> ParseResult<Expr> {
+    // A lone atom comes back from the pratt parser as is, so there is no need 
+    // to fall back to parse_atom_expr (which would also hide a dangling operator). 
+    parse_infix_expr(input)
 }
 
-pub fn parse(input: Tokens) -> IResult<Tokens, Expr> {
+pub fn parse(input: Tokens) -> ParseResult<Expr> {
     terminated(parse_expr, eof_tag)(input)
 }
 
 pub fn parse_str(s: &str) -> Result<Expr, Error> {
-    let t = Lexer::lex_tokens(s.as_bytes())?; 
-    let tokens = Tokens::new(&t); 
+    let (t, spans) = Lexer::lex_spanned(s.as_bytes())?; 
+    parse_tokens(s, &t, &spans)
+}
+
+/*
+ * Parse a formula written in R1C1 notation, as entered in `anchor`. 
+ * References come back in A1 form so the result evaluates like any other formula; 
+ * one that points off the sheet becomes #REF!. 
+ */
+pub fn parse_r1c1_str(s: &str, anchor: Cell) -> Result<Expr, Error> {
+    let (t, spans) = Lexer::lex_spanned_r1c1(s.as_bytes())?; 
+    let mut expr = parse_tokens(s, &t, &spans)?; 
+    resolve_r1c1(&mut expr, anchor); 
+    Ok(expr)
+}
+
+fn resolve_r1c1(expr: &mut Expr, anchor: Cell) {
+    match expr {
+        Expr::Reference { reference, .. } => {
+            match Reference::from_r1c1(reference, anchor) {
+                Some(r) => *reference = r.to_string(), 
+                None => *expr = Expr::Error(ExcelError::Ref)
+            }
+        }, 
+        Expr::Spill { anchor: spill_anchor, .. } => {
+            match Reference::from_r1c1(spill_anchor, anchor) {
+                Some(r) => *spill_anchor = r.to_string(), 
+                None => *expr = Expr::Error(ExcelError::Ref)
+            }
+        }, 
+        Expr::Prefix(_, a) => resolve_r1c1(a, anchor), 
+        Expr::Infix(_, a, b) => {
+            resolve_r1c1(a, anchor); 
+            resolve_r1c1(b, anchor); 
+        }, 
+        Expr::Func { args, .. } => {
+            for arg in args.iter_mut() {
+                resolve_r1c1(arg, anchor); 
+            }
+        }, 
+        Expr::Array(rows) => {
+            for arg in rows.iter_mut().flatten() {
+                resolve_r1c1(arg, anchor); 
+            }
+        }, 
+        Expr::Call(f, args) => {
+            resolve_r1c1(f, anchor); 
+            for arg in args.iter_mut() {
+                resolve_r1c1(arg, anchor); 
+            }
+        }, 
+        Expr::Literal(_) | Expr::Error(_) | Expr::Table(_) | Expr::Name(_) => {}
+    }
+}
+
+fn parse_tokens(s: &str, t: &[Token], spans: &[Span]) -> Result<Expr, Error> {
+    let tokens = Tokens::new(t, spans); 
     match parse(tokens) {
         Ok((_, expr)) => Ok(expr),
-        _ => Err(Error::UnableToParse(s.to_owned()))
+        Err(Err::Error(failure)) | Err(Err::Failure(failure)) => {
+            Err(Error::UnableToParse(SyntaxError::new(s, failure.input.span(), failure.expected)))
+        }, 
+        Err(Err::Incomplete(_)) => {
+            Err(Error::UnableToParse(SyntaxError::new(s, Span::new(s.len(), s.len()), vec![])))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::parse_str; 
-    use crate::parser::ast::{Expr, Error as ExcelError, Prefix, Infix}; 
+    use crate::parser::{parse_str, parse_r1c1_str}; 
+    use crate::cell::Cell; 
+    use crate::parser::ast::{Expr, Error as ExcelError, Literal, Prefix, Infix}; 
+    use crate::lexer::token::Span; 
     use crate::errors::Error; 
 
     #[test]
@@ -346,8 +568,9 @@ mod tests {
         assert_eq!(parse_str("#REF!")?, Expr::Error(ExcelError::Ref)); 
         assert_eq!(parse_str("#NAME!")?, Expr::Error(ExcelError::Name)); 
         assert_eq!(parse_str("#NUM!")?, Expr::Error(ExcelError::Num)); 
-        assert_eq!(parse_str("#N/A!")?, Expr::Error(ExcelError::NA)); 
+        assert_eq!(parse_str("#N/A")?, Expr::Error(ExcelError::NA)); 
         assert_eq!(parse_str("#GETTING_DATA")?, Expr::Error(ExcelError::GettingData)); 
+        assert_eq!(parse_str("#SPILL!")?, Expr::Error(ExcelError::Spill)); 
         Ok(())
     }
 
@@ -357,17 +580,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_names() -> Result<(), Error> {
+        assert_eq!(parse_str("_xlpm.x+1")?, Expr::Infix(Infix::Plus, Box::new(Expr::Name("x".to_string())), Box::new(Expr::from(1.0)))); 
+        assert_eq!(parse_str("LET(x, 1, x)")?, Expr::Func { name: "LET".to_string(), args: vec![Expr::Name("x".to_string()), Expr::from(1.0), Expr::Name("x".to_string())] }); 
+        assert_eq!(parse_str("LAMBDA(x, x)(2)")?, Expr::Call(
+            Box::new(Expr::Func { name: "LAMBDA".to_string(), args: vec![Expr::Name("x".to_string()), Expr::Name("x".to_string())] }), 
+            vec![Expr::from(2.0)]
+        )); 
+        Ok(())
+    }
+
     #[test]
     fn test_reference() -> Result<(), Error> {
         assert_eq!(parse_str("test!A1")?, Expr::Reference { sheet: Some("test".to_string()), reference: "A1".to_string()}); 
         assert_eq!(parse_str("test!A1:B2")?, Expr::Reference { sheet: Some("test".to_string()), reference: "A1:B2".to_string()}); 
+        assert_eq!(parse_str("test!A1#")?, Expr::Spill { sheet: Some("test".to_string()), anchor: "A1".to_string()}); 
+        assert_eq!(parse_str("ANCHORARRAY(test!A1)")?, Expr::Spill { sheet: Some("test".to_string()), anchor: "A1".to_string()}); 
+        assert_eq!(parse_str("SUM($B$2#)")?, Expr::Func { name: "SUM".to_string(), args: vec![Expr::Spill { sheet: None, anchor: "$B$2".to_string() }] }); 
         Ok(())
     }
 
     #[test]
     fn test_array() -> Result<(), Error> {
-        assert_eq!(parse_str("{1, 2, 3, 4}")?, Expr::Array(vec![Expr::from(1.0), Expr::from(2.0), Expr::from(3.0), Expr::from(4.0)])); 
-        assert_eq!(parse_str("{(1+2), 2, 3, 4}")?, Expr::Array(vec![
+        assert_eq!(parse_str("{1, 2, 3, 4}")?, Expr::Array(vec![vec![Expr::from(1.0), Expr::from(2.0), Expr::from(3.0), Expr::from(4.0)]])); 
+        assert_eq!(parse_str("{(1+2), 2, 3, 4}")?, Expr::Array(vec![vec![
                 Expr::Infix(
                     Infix::Plus, 
                     Box::new(Expr::from(1.0)), 
@@ -376,7 +613,9 @@ mod tests {
                 Expr::from(2.0), 
                 Expr::from(3.0), 
                 Expr::from(4.0)
-        ])); 
+        ]])); 
+        assert_eq!(parse_str("{1, 2; 3, 4}")?, Expr::Array(vec![vec![Expr::from(1.0), Expr::from(2.0)], vec![Expr::from(3.0), Expr::from(4.0)]])); 
+        assert!(parse_str("{1, 2; 3}").is_err()); 
         Ok(())
     }
 
@@ -433,6 +672,21 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_empty_args() -> Result<(), Error> {
+        let empty = Expr::Literal(Literal::Empty); 
+        let range = |r: &str| Expr::Reference { sheet: None, reference: r.to_string() }; 
+        assert_eq!(parse_str("XLOOKUP(3,A1:A3,B1:B3,,0,2)")?, Expr::Func {
+            name: "XLOOKUP".to_string(), 
+            args: vec![Expr::from(3.0), range("A1:A3"), range("B1:B3"), empty.clone(), Expr::from(0.0), Expr::from(2.0)]
+        }); 
+        assert_eq!(parse_str("UNIQUE(A1:A3,,TRUE)")?, Expr::Func { name: "UNIQUE".to_string(), args: vec![range("A1:A3"), empty.clone(), Expr::from(true)] }); 
+        assert_eq!(parse_str("IF(A1,)")?, Expr::Func { name: "IF".to_string(), args: vec![range("A1"), empty.clone()] }); 
+        assert_eq!(parse_str("PI()")?, Expr::Func { name: "PI".to_string(), args: vec![] }); 
+        assert!(parse_str("SUM(1,+)").is_err()); 
+        Ok(())
+    }
+
     #[test]
     fn test_floor() -> Result<(), Error> {
         assert_eq!(parse_str("FLOOR(3.7, 1)")?, Expr::Func {
@@ -485,4 +739,35 @@ mod tests {
         ); 
         Ok(())
     }
+
+    #[test]
+    fn test_r1c1() -> Result<(), Error> {
+        let anchor = Cell::from("C3"); 
+        assert_eq!(parse_r1c1_str("R[-1]C+RC[-2]", anchor)?, parse_str("C2+A3")?); 
+        assert_eq!(parse_r1c1_str("SUM(Sheet1!R1C1:R[1]C, C2, R[-2])", anchor)?, parse_str("SUM(Sheet1!$A$1:C4, $B:$B, 1:1)")?); 
+        assert_eq!(parse_r1c1_str("ROUND(R[-5]C, 0)", anchor)?, parse_str("ROUND(#REF!, 0)")?); 
+        assert_eq!(parse_r1c1_str("SUM(Sheet1!R1C1#, R[-1]C#)", anchor)?, parse_str("SUM(Sheet1!$A$1#, C2#)")?); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        match parse_str("SUM(A1 B2)") {
+            Err(Error::UnableToParse(e)) => {
+                assert_eq!(e.span, Span::new(7, 9)); 
+                assert_eq!(e.expected, vec!["')'".to_string()]); 
+                assert_eq!(e.found, "'B2'"); 
+                assert_eq!(e.render(), "SUM(A1 B2)\n       ^^"); 
+            }, 
+            other => panic!("Expected a parse error, got {:?}", other)
+        }
+        match parse_str("1+") {
+            Err(Error::UnableToParse(e)) => {
+                assert_eq!(e.span, Span::new(2, 2)); 
+                assert_eq!(e.expected, vec!["expression".to_string()]); 
+                assert_eq!(e.to_string(), "expected expression, found end of formula at 2..2\n1+\n  ^"); 
+            }, 
+            other => panic!("Expected a parse error, got {:?}", other)
+        }
+    }
 }
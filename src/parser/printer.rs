@@ -0,0 +1,314 @@
+use crate::parser::{
+    infix_precedence,
+    ast::{Expr, Literal, Prefix, Precedence, TableRef, TableItem},
+};
+use crate::cell::Cell;
+use crate::reference::Reference;
+use crate::utils::split_external_sheet;
+
+/*
+ * Canonical Excel syntax for an expression (without the leading '=').
+ * Parentheses are only emitted where precedence or left associativity need
+ * them, so that parse_str(&print(e)) gives back e.
+ */
+pub fn print(expr: &Expr) -> String {
+    print_with(expr, &|r| r.to_string())
+}
+
+// Same as print, but references are written in R1C1 relative to `anchor`,
+// so a formula filled down a column prints the same in every row.
+pub fn print_r1c1(expr: &Expr, anchor: Cell) -> String {
+    print_with(expr, &|r| Reference::from(r).to_r1c1(anchor))
+}
+
+fn print_with(expr: &Expr, reference: &dyn Fn(&str) -> String) -> String {
+    let print = |e: &Expr| print_with(e, reference);
+    match expr {
+        Expr::Literal(l) => print_literal(l),
+        Expr::Prefix(p, e) => {
+            let op = match p {
+                Prefix::Plus => "+",
+                Prefix::Minus => "-",
+            };
+            // Prefix operators bind to a single atom.
+            match **e {
+                Expr::Infix(_, _, _) => format!("{}({})", op, print(e)),
+                _ => format!("{}{}", op, print(e))
+            }
+        },
+        Expr::Infix(op, a, b) => {
+            let p = infix_precedence(op.clone());
+            let lhs = match precedence(a) {
+                Some(pa) if pa < p => format!("({})", print(a)),
+                _ => print(a)
+            };
+            let rhs = match precedence(b) {
+                Some(pb) if pb <= p => format!("({})", print(b)),
+                _ => print(b)
+            };
+            format!("{}{}{}", lhs, op, rhs)
+        },
+        Expr::Func { name, args } => format!("{}({})", name, print_list(args, reference)),
+        Expr::Reference { sheet, reference: r } => {
+            match sheet {
+                Some(s) => format!("{}!{}", quote_sheet(s), reference(r)),
+                None => reference(r)
+            }
+        },
+        Expr::Spill { sheet, anchor } => {
+            match sheet {
+                Some(s) => format!("{}!{}#", quote_sheet(s), reference(anchor)),
+                None => format!("{}#", reference(anchor))
+            }
+        },
+        Expr::Array(rows) => format!("{{{}}}", rows.iter().map(|row| print_list(row, reference)).collect::<Vec<String>>().join(";")),
+        Expr::Error(e) => e.to_string(),
+        Expr::Table(t) => print_table_ref(t), 
+        Expr::Name(n) => n.to_string(), 
+        Expr::Call(f, args) => format!("{}({})", print(f), print_list(args, reference))
+    }
+}
+
+// Short forms where Excel has them ([Amount], [@Qty]), the bracketed list otherwise.
+pub fn print_table_ref(t: &TableRef) -> String {
+    let column = |c: &str| format!("[{}]", escape_column(c));
+    let columns = t.columns.as_ref().map(|(first, last)| {
+        if first == last {
+            column(first)
+        } else {
+            format!("{}:{}", column(first), column(last))
+        }
+    });
+    let body = match (t.items.as_slice(), &columns) {
+        ([], None) => String::new(),
+        ([], Some(c)) => match &t.columns {
+            Some((first, last)) if first == last && is_plain_column(first) => first.to_string(),
+            _ => c.to_string()
+        },
+        ([TableItem::ThisRow], Some(c)) => match &t.columns {
+            Some((first, last)) if first == last && is_plain_column(first) => format!("@{}", first),
+            _ => format!("@{}", c)
+        },
+        ([item], None) => item.to_string(),
+        (items, c) => {
+            let mut parts: Vec<String> = items.iter().map(|i| format!("[{}]", i)).collect();
+            parts.extend(c.clone());
+            parts.join(",")
+        }
+    };
+    format!("{}[{}]", t.table.clone().unwrap_or_default(), body)
+}
+
+// Brackets, '#' and the escape character itself are escaped with a single quote.
+fn escape_column(c: &str) -> String {
+    let mut output = String::new();
+    for ch in c.chars() {
+        if "[]#'".contains(ch) {
+            output.push('\'');
+        }
+        output.push(ch);
+    }
+    output
+}
+
+fn is_plain_column(c: &str) -> bool {
+    !c.is_empty() && c.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+fn precedence(expr: &Expr) -> Option<Precedence> {
+    match expr {
+        Expr::Infix(op, _, _) => Some(infix_precedence(op.clone())),
+        _ => None
+    }
+}
+
+fn print_list(exprs: &[Expr], reference: &dyn Fn(&str) -> String) -> String {
+    exprs.iter().map(|e| print_with(e, reference)).collect::<Vec<String>>().join(",")
+}
+
+pub fn print_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::Number(x) => print_number(*x),
+        Literal::Boolean(true) => String::from("TRUE"),
+        Literal::Boolean(false) => String::from("FALSE"),
+        Literal::Text(s) => quote_text(s),
+        Literal::Empty => String::new()
+    }
+}
+
+// Shortest decimal that reads back as the same f64.
+// Very large or small magnitudes switch to scientific notation so the lexer
+// never has to deal with twenty digit integers.
+pub fn print_number(x: f64) -> String {
+    let a = x.abs();
+    if a != 0.0 && !(1e-5..1e15).contains(&a) {
+        format!("{:e}", x)
+    } else {
+        format!("{}", x)
+    }
+}
+
+// Double quotes inside a string are escaped by doubling them.
+pub fn quote_text(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+// Sheet names are quoted unless every part (a 3-D reference has two)
+// would lex back as a bare sheet name.
+pub fn quote_sheet(name: &str) -> String {
+    // [1]Inputs stays bare when both the workbook and the sheet would
+    let (book, sheet) = match split_external_sheet(name) {
+        Some((book, sheet)) if name.starts_with('[') => (Some(book), sheet),
+        _ => (None, name)
+    };
+    let bare_book = book.map_or(true, |b| b.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.'));
+    let parts: Vec<&str> = sheet.split(':').collect();
+    if bare_book && parts.len() <= 2 && parts.iter().all(|p| is_bare_sheet_name(p)) {
+        name.to_string()
+    } else {
+        format!("'{}'", name.replace('\'', "''"))
+    }
+}
+
+fn is_bare_sheet_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_ok = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_');
+    starts_ok
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && !looks_like_cell(name)
+        && !looks_like_r1c1(name)
+        && name != "TRUE" && name != "FALSE"
+}
+
+// A1 style: up to three column letters followed by a row number.
+fn looks_like_cell(name: &str) -> bool {
+    let digits = name.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    let letters = name.len() - digits.len();
+    (1..=3).contains(&letters) && (1..=7).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+// R1C1 style: R, C, R1, C1, R1C1, RC...
+fn looks_like_r1c1(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    let rest = upper.strip_prefix('R').map(|r| r.trim_start_matches(|c: char| c.is_ascii_digit())).unwrap_or(&upper);
+    let rest = rest.strip_prefix('C').map(|r| r.trim_start_matches(|c: char| c.is_ascii_digit())).unwrap_or(rest);
+    rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_str, parse_r1c1_str, printer::{print, print_r1c1}};
+    use crate::parser::ast::{Expr, Infix, Prefix};
+    use crate::utils::adjust_formula;
+    use crate::reference::Reference;
+    use crate::cell::Cell;
+    use crate::errors::Error;
+
+    fn round_trip(formula: &str) -> Result<String, Error> {
+        let expr = parse_str(formula)?;
+        let printed = print(&expr);
+        assert_eq!(parse_str(&printed)?, expr, "{} printed as {}", formula, printed);
+        Ok(printed)
+    }
+
+    #[test]
+    fn test_minimal_parens() -> Result<(), Error> {
+        assert_eq!(round_trip("(A1+A2)")?, "A1+A2");
+        assert_eq!(round_trip("1+2*3")?, "1+2*3");
+        assert_eq!(round_trip("(1+2)*3")?, "(1+2)*3");
+        assert_eq!(round_trip("1-(2-3)")?, "1-(2-3)");
+        assert_eq!(round_trip("(1-2)-3")?, "1-2-3");
+        assert_eq!(round_trip("2^(3^2)")?, "2^(3^2)");
+        assert_eq!(round_trip("-(1+1)-2")?, "-(1+1)-2");
+        assert_eq!(round_trip("-A1^2")?, "-A1^2");
+        assert_eq!(round_trip("A1&\"x\"=B1")?, "A1&\"x\"=B1");
+        assert_eq!(round_trip("1 <> (2 <= 3)")?, "1<>(2<=3)");
+        assert_eq!(round_trip("SUM( A1:B2 , (C3) )")?, "SUM(A1:B2,C3)");
+        assert_eq!(round_trip("{1, 2; 3, 4}")?, "{1,2;3,4}");
+        assert_eq!(round_trip("LET(_xlpm.x, 2, LAMBDA(y, x * y)(3))")?, "LET(x,2,LAMBDA(y,x*y)(3))");
+        Ok(())
+    }
+
+    #[test]
+    fn test_built_expressions() {
+        let one = || Box::new(Expr::from(1.0));
+        let sub = Expr::Infix(Infix::Minus, one(), Box::new(Expr::Infix(Infix::Minus, one(), one())));
+        assert_eq!(print(&sub), "1-(1-1)");
+        let neg = Expr::Prefix(Prefix::Minus, Box::new(Expr::Infix(Infix::Multiply, one(), one())));
+        assert_eq!(print(&neg), "-(1*1)");
+        let double = Expr::Prefix(Prefix::Minus, Box::new(Expr::Prefix(Prefix::Minus, one())));
+        assert_eq!(print(&double), "--1");
+    }
+
+    #[test]
+    fn test_literals() -> Result<(), Error> {
+        assert_eq!(round_trip("\"say \"\"hi\"\"\"")?, "\"say \"\"hi\"\"\"");
+        assert_eq!(round_trip("\"\"")?, "\"\"");
+        assert_eq!(round_trip("1.50")?, "1.5");
+        assert_eq!(round_trip("1E+21")?, "1e21");
+        assert_eq!(round_trip("0.0000001")?, "1e-7");
+        assert_eq!(round_trip("123456.789")?, "123456.789");
+        assert_eq!(round_trip("TRUE")?, "TRUE");
+        assert_eq!(round_trip("XLOOKUP(3,A1:A3,B1:B3,,0,2)")?, "XLOOKUP(3,A1:A3,B1:B3,,0,2)");
+        assert_eq!(print(&Expr::from(1e300)), "1e300");
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<(), Error> {
+        assert_eq!(round_trip("#N/A")?, "#N/A");
+        assert_eq!(round_trip("#NAME?")?, "#NAME?");
+        assert_eq!(round_trip("#NAME!")?, "#NAME?");
+        assert_eq!(round_trip("IFERROR(#DIV/0!,#NULL!)")?, "IFERROR(#DIV/0!,#NULL!)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sheets() -> Result<(), Error> {
+        assert_eq!(round_trip("Sheet1!A1")?, "Sheet1!A1");
+        assert_eq!(round_trip("'Sheet1'!A1")?, "Sheet1!A1");
+        assert_eq!(round_trip("'My Sheet'!A1:B2")?, "'My Sheet'!A1:B2");
+        assert_eq!(round_trip("'Bob''s'!A1")?, "'Bob''s'!A1");
+        assert_eq!(round_trip("'A1'!A1")?, "'A1'!A1");
+        assert_eq!(round_trip("'R1C1'!A1")?, "'R1C1'!A1");
+        assert_eq!(round_trip("'2020'!A1")?, "'2020'!A1");
+        assert_eq!(round_trip("Jan:Mar!B2")?, "Jan:Mar!B2");
+        assert_eq!(round_trip("[1]Inputs!B4")?, "[1]Inputs!B4");
+        assert_eq!(round_trip("'[Rates.xlsx]Curve'!A1:A30")?, "[Rates.xlsx]Curve!A1:A30");
+        assert_eq!(round_trip("'[My Rates.xlsx]Curve'!A1")?, "'[My Rates.xlsx]Curve'!A1");
+        assert_eq!(round_trip("'C:\\Models\\[Rates.xlsx]Curve'!A1")?, "'C:\\Models\\[Rates.xlsx]Curve'!A1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> Result<(), Error> {
+        assert_eq!(round_trip("SUM(Sales[Amount])")?, "SUM(Sales[Amount])");
+        assert_eq!(round_trip("[@Qty]*[@[Unit Price]]")?, "[@Qty]*[@[Unit Price]]");
+        assert_eq!(round_trip("Sales[[#This Row],[Qty]]")?, "Sales[@Qty]");
+        assert_eq!(round_trip("Sales[[#Totals], [Amount]]")?, "Sales[[#Totals],[Amount]]");
+        assert_eq!(round_trip("Sales[[Region]:[Amount]]")?, "Sales[[Region]:[Amount]]");
+        assert_eq!(round_trip("Sales[#all]")?, "Sales[#All]");
+        assert_eq!(round_trip("Sales[[Price '[$']]]")?, "Sales[[Price '[$']]]");
+        assert_eq!(round_trip("Sales[]")?, "Sales[]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_r1c1() -> Result<(), Error> {
+        let anchor = Cell::from("C3");
+        let expr = parse_str("C2+A3*SUM($A$1:C4,B:B,'My Sheet'!$2:$2)")?;
+        let printed = print_r1c1(&expr, anchor);
+        assert_eq!(printed, "R[-1]C+RC[-2]*SUM(R1C1:R[1]C,C[-1],'My Sheet'!R2)");
+        assert_eq!(parse_r1c1_str(&printed, anchor)?, expr);
+        let spill = parse_str("SUM(A1#)")?;
+        let printed = print_r1c1(&spill, Cell::from("E5"));
+        assert_eq!(printed, "SUM(R[-4]C[-4]#)");
+        assert_eq!(parse_r1c1_str(&printed, Cell::from("E5"))?, spill);
+        // A shared formula reads the same from every cell it is filled into.
+        for row in 2..6 {
+            let filled = adjust_formula(Reference::from("A2"), Reference::from((row, 1)), String::from("B1+$C$1"))?;
+            assert_eq!(print_r1c1(&parse_str(&filled)?, Cell::from((row, 1))), "R[-1]C[1]+R1C3");
+        }
+        Ok(())
+    }
+}
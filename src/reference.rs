@@ -190,6 +190,37 @@ impl Reference {
         Self::get_cells_from_dim(start_row, start_column, num_rows, num_cols)
     }
 
+    // R1C1 notation relative to `anchor`, see Cell::to_r1c1. 
+    // A single whole row or column (4:4) prints once, as R[1]. 
+    pub fn to_r1c1(&self, anchor: Cell) -> String {
+        let start = self.start_cell.to_r1c1(anchor); 
+        match self.end_cell {
+            Some(end_cell) => {
+                let end = end_cell.to_r1c1(anchor); 
+                if start == end && (self.is_hrange() || self.is_vrange()) {
+                    start
+                } else {
+                    format!("{}:{}", start, end)
+                }
+            }, 
+            None => start
+        }
+    }
+
+    pub fn from_r1c1(s: &str, anchor: Cell) -> Option<Reference> {
+        match s.split_once(':') {
+            Some((a, b)) => Some(Reference::from((Cell::from_r1c1(a, anchor)?, Some(Cell::from_r1c1(b, anchor)?)))), 
+            None => {
+                let cell = Cell::from_r1c1(s, anchor)?; 
+                if cell.is_hrange() || cell.is_vrange() {
+                    Some(Reference::from((cell, Some(cell))))
+                } else {
+                    Some(Reference::from(cell))
+                }
+            }
+        }
+    }
+
     pub fn offset(&mut self, offset: (i32, i32)) {
         if !self.start_cell.row.anchor && !self.start_cell.is_vrange() {
                 self.start_cell.row.index = (self.row() as i32 + offset.0) as usize;
@@ -47,7 +47,7 @@ pub fn remove_edge(tree: &mut Tree, edge_id: EdgeId) {
 mod tests {
 	use crate::tree::*; 
 
-	struct Node(u32); 
+	struct Node(#[allow(dead_code)] u32); 
 	// struct Edge(String); 
 	impl NodeTrait for Node {}
 	// impl EdgeTrait for Edge {} 
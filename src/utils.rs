@@ -1,4 +1,4 @@
-use chrono::{NaiveDate, Duration}; 
+use chrono::{NaiveDate, NaiveDateTime, Duration, Datelike}; 
 
 use crate::parser::{
     parse_str, 
@@ -7,14 +7,53 @@ use crate::parser::{
 use crate::reference::Reference; 
 use crate::errors::Error; 
 
-pub fn excel_to_date(serial: f64)  -> NaiveDate {
-    let start_date = NaiveDate::from_ymd(1899, 12, 30); 
-    let mut duration = Duration::days(serial as i64); 
-    let frac = (serial - serial.floor()) as i64; 
-    if frac > 0 {
-        duration = duration.checked_add(&Duration::hours(24 * frac)).expect("Could not add two dates together"); 
+/*
+ * Serial numbers count days from 1 for 1 January 1900, with the time of day as
+ * the fraction. Excel treats 1900 as a leap year, so serials from 61 (1 March
+ * 1900) on are one more than the days since 31 December 1899, and serial 60 is
+ * a 29 February that has no date.
+ */
+pub fn excel_to_date(serial: f64) -> NaiveDateTime {
+    let days = serial.floor(); 
+    let start_date = NaiveDate::from_ymd_opt(1899, 12, if days < 61.0 { 31 } else { 30 }).unwrap(); 
+    let seconds = ((serial - days) * 86400.0).round() as i64; 
+    let date = start_date.checked_add_signed(Duration::days(days as i64)).expect("Serial out of range for a date"); 
+    date.and_hms_opt(0, 0, 0).unwrap() + Duration::seconds(seconds)
+}
+
+pub fn date_to_excel(date: NaiveDate) -> f64 {
+    date_time_to_excel(date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+pub fn date_time_to_excel(date: NaiveDateTime) -> f64 {
+    let start_date = NaiveDate::from_ymd_opt(1899, 12, 30).unwrap().and_hms_opt(0, 0, 0).unwrap(); 
+    let serial = NaiveDateTime::signed_duration_since(date, start_date).num_milliseconds() as f64 / 86_400_000.0; 
+    if date.date() < NaiveDate::from_ymd_opt(1900, 3, 1).unwrap() {
+        serial - 1.0
+    } else {
+        serial
     }
-    start_date.checked_add_signed(duration).unwrap()
+}
+
+// Year, month and day of the serial for a whole day, including 0 (0 January 1900) and 
+// 60 (29 February 1900). 
+pub fn serial_to_ymd(days: i64) -> (i32, u32, u32) {
+    match days {
+        0 => (1900, 1, 0),
+        60 => (1900, 2, 29),
+        _ => {
+            let date = excel_to_date(days as f64); 
+            (date.year(), date.month(), date.day())
+        }
+    }
+}
+
+// Splits an external sheet name like [1]Inputs or C:\Models\[Rates.xlsx]Curve into the 
+// workbook ("1", "Rates.xlsx") and the sheet. Any path in front of the workbook is dropped. 
+pub fn split_external_sheet(sheet: &str) -> Option<(&str, &str)> {
+    let start = sheet.rfind('[')?; 
+    let end = start + sheet[start..].find(']')?; 
+    Some((&sheet[start+1..end], &sheet[end+1..]))
 }
 
 pub fn adjust_formula(
@@ -52,11 +91,17 @@ pub fn adjust_expression(
                 adjust_expression(row_offset, column_offset, arg)?; 
             } 
         }, 
-        Expr::Array(ref mut arr) => {
-            for a in arr.iter_mut() {
+        Expr::Array(ref mut rows) => {
+            for a in rows.iter_mut().flatten() {
                 adjust_expression(row_offset, column_offset, a)?; 
             }
         }
+        Expr::Call(ref mut f, ref mut args) => {
+            adjust_expression(row_offset, column_offset, f)?; 
+            for arg in args.iter_mut() {
+                adjust_expression(row_offset, column_offset, arg)?; 
+            }
+        }
         _ => {}
     }
     Ok(())
@@ -65,7 +110,7 @@ pub fn adjust_expression(
 #[cfg(test)]
 mod tests {
     use crate::reference::Reference; 
-    use crate::utils::adjust_formula; 
+    use crate::utils::{adjust_formula, split_external_sheet}; 
     use crate::errors::Error; 
     
     #[test]
@@ -73,9 +118,9 @@ mod tests {
        let base_reference = Reference::from((1, 1));
        let current_reference = Reference::from((2, 1));
        assert_eq!(&adjust_formula(base_reference, current_reference, String::from("Sheet1!A1"))?, &"Sheet1!A2"); 
-       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("A1+A2"))?, &"(A2+A3)"); 
-       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("SUM(A1+A2)"))?, &"SUM((A2+A3))"); 
-       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("{A1, A2}"))?, &"{A2, A3}"); 
+       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("A1+A2"))?, &"A2+A3"); 
+       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("SUM(A1+A2)"))?, &"SUM(A2+A3)"); 
+       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("{A1, A2}"))?, &"{A2,A3}"); 
         Ok(())
     }
 
@@ -84,9 +129,16 @@ mod tests {
        let base_reference = Reference::from((1, 1));
        let current_reference = Reference::from((2, 2));
        assert_eq!(&adjust_formula(base_reference, current_reference, String::from("Sheet1!$A$1"))?, &"Sheet1!$A$1"); 
-       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("$A$1+A2"))?, &"($A$1+B3)"); 
-       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("SUM(A$1+A$2)"))?, &"SUM((B$1+B$2))"); 
-       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("{A1, A2}"))?, &"{B2, B3}"); 
+       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("$A$1+A2"))?, &"$A$1+B3"); 
+       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("SUM(A$1+A$2)"))?, &"SUM(B$1+B$2)"); 
+       assert_eq!(&adjust_formula(base_reference, current_reference, String::from("{A1, A2}"))?, &"{B2,B3}"); 
         Ok(())
     }
+
+    #[test]
+    fn test_split_external_sheet() {
+        assert_eq!(split_external_sheet("[1]Inputs"), Some(("1", "Inputs"))); 
+        assert_eq!(split_external_sheet("C:\\Models\\[Rates.xlsx]Curve"), Some(("Rates.xlsx", "Curve"))); 
+        assert_eq!(split_external_sheet("Inputs"), None); 
+    }
 }
@@ -2,6 +2,8 @@ use quick_xml::events::BytesStart;
 use zip::read::{ZipArchive, ZipFile};
 use indicatif::ProgressBar; 
 use std::fs::File;
+use std::collections::{HashSet, HashMap}; 
+use std::cell::RefCell; 
 use std::fmt; 
 use std::io::BufReader; 
 use quick_xml::{
@@ -18,13 +20,15 @@ use crate::{
         evaluate_expr_with_context, 
         ensure_non_range
     }, 
-    utils::adjust_formula, 
+    utils::{adjust_formula, split_external_sheet}, 
     dependency::{CellId, DependencyTree}, 
     utils::excel_to_date, 
+    format::{NumberFormat, builtin_format}, 
+    function::{volatile::CalculationContext, registry::{FunctionRegistry, check_arities}}, 
     reference::Reference,
     parser::{
         parse_str, 
-        ast::Expr
+        ast::{Expr, TableRef, TableItem, Error as ExcelError}
     }, 
     cell::Cell, 
     errors::Error
@@ -32,21 +36,30 @@ use crate::{
 
 pub type ZipType = ZipArchive<File>; 
 
+static DEFAULT_FORMAT: std::sync::OnceLock<NumberFormat> = std::sync::OnceLock::new(); 
+
 pub struct Book {
     zip: Option<ZipType>, 
     pub sheets: Vec<Sheet>, 
+    pub tables: Vec<Table>, 
+    pub external_links: Vec<ExternalLink>, 
     shared_strings: Vec<SharedString>, 
     styles: Vec<Style>, 
     formulas: Vec<(CellId, String)>, // CellId, Formula Text
     pub current_sheet: usize, 
+    pub current_cell: Cell, // Formula being calculated, for ROW() and R1C1 INDIRECT 
     pub dependencies: DependencyTree, 
+    pub context: CalculationContext, // Clock and seed for TODAY, NOW and RAND 
+    pub defined_names: HashMap<(Option<usize>, String), String>, // (Sheet for local names, uppercase name) -> formula 
+    pub scope: RefCell<HashMap<String, Value>>, // LET names and LAMBDA parameters while their formula is evaluated 
+    pub functions: FunctionRegistry, // Functions added at runtime, checked before the built-in ones 
     // pub cells: HashMap<Sheet, Array2<Value>>
 }
 
 impl From<String> for Book {
     fn from(s: String) -> Self {
         let zip = Self::zip_from_path(&s); 
-        Book { zip: Some(zip), sheets: vec![], shared_strings: vec![], styles: vec![], current_sheet: 0, dependencies: DependencyTree::new(), formulas: vec![] }
+        Book { zip: Some(zip), sheets: vec![], tables: vec![], external_links: vec![], shared_strings: vec![], styles: vec![], current_sheet: 0, current_cell: Cell::from((1, 1)), dependencies: DependencyTree::new(), context: CalculationContext::default(), defined_names: HashMap::new(), scope: RefCell::new(HashMap::new()), functions: FunctionRegistry::new(), formulas: vec![] }
     }
 }
 
@@ -64,25 +77,100 @@ impl Default for Book {
 
 impl Book {
     pub fn new() -> Book {
-        Book { zip: None, sheets: vec![], shared_strings: vec![], styles: vec![], current_sheet: 0, dependencies: DependencyTree::new(), formulas: vec![] }
+        Book { zip: None, sheets: vec![], tables: vec![], external_links: vec![], shared_strings: vec![], styles: vec![], current_sheet: 0, current_cell: Cell::from((1, 1)), dependencies: DependencyTree::new(), context: CalculationContext::default(), defined_names: HashMap::new(), scope: RefCell::new(HashMap::new()), functions: FunctionRegistry::new(), formulas: vec![] }
     }
 
     pub fn load(&mut self, progress: bool) -> Result<(), Error> {
+        self.load_cells(progress)?; 
+        self.load_dependencies()?; 
+        Ok(())
+    }
+
+    // Everything but the dependency tree, which needs every formula to parse. 
+    pub fn load_cells(&mut self, progress: bool) -> Result<(), Error> {
         self.load_sheet_names()?; 
         self.load_shared_strings()?; 
         self.load_styles()?; 
         self.load_sheets(progress)?; 
-        self.load_dependencies()?; 
+        self.load_tables()?; 
+        self.load_external_links()?; 
         Ok(())
     }
 
+    // Every formula in the book with the cell it is in, as written in the file. 
+    pub fn formulas(&self) -> &[(CellId, String)] {
+        &self.formulas
+    }
+
     pub fn load_dependencies(&mut self) -> Result<(), Error> {
-        for (cell_id, formula_text) in self.formulas.iter() {
-            self.dependencies.add_formula(cell_id.clone(), &formula_text, &self.sheets)?; 
+        for (cell_id, formula_text) in self.formulas.clone().iter() {
+            parse_str(&formula_text[1..])
+                .and_then(|expr| {
+                    check_arities(&expr, &self.functions)?; 
+                    self.current_sheet = cell_id.sheet; 
+                    let expr = self.resolve_names(expr, &mut vec![]); 
+                    let expr = self.resolve_tables(expr, cell_id.sheet, cell_id.row, cell_id.column); 
+                    // Formulas without references, like =INDIRECT("A1"), still need calculating 
+                    self.dependencies.add_cell(*cell_id); 
+                    if cell_id.num_row * cell_id.num_col > 1 {
+                        self.dependencies.add_array(*cell_id); 
+                    }
+                    self.dependencies.add_expression(*cell_id, expr, &self.sheets)
+                })
+                .map_err(|e| Error::Formula(self.cell_name(cell_id.sheet, cell_id.row, cell_id.column), Box::new(e)))?; 
         }
         Ok(())
     }
 
+    // The formula behind a defined name, a name local to the current sheet first. 
+    pub fn defined_name(&self, name: &str) -> Option<&String> {
+        let name = name.to_uppercase(); 
+        self.defined_names.get(&(Some(self.current_sheet), name.clone()))
+            .or_else(|| self.defined_names.get(&(None, name)))
+    }
+
+    /*
+     * Replace defined names with their formulas so the cells they point at become 
+     * dependencies. A name already being expanded is left alone, a LAMBDA can call itself. 
+     */
+    pub fn resolve_names(&self, expr: Expr, expanding: &mut Vec<String>) -> Expr {
+        let expand = |name: &str, expanding: &mut Vec<String>| -> Option<Expr> {
+            let formula = self.defined_name(name)?; 
+            let key = name.to_uppercase(); 
+            if expanding.contains(&key) {
+                return None; 
+            }
+            let expr = parse_str(formula).ok()?; 
+            expanding.push(key); 
+            let expr = self.resolve_names(expr, expanding); 
+            expanding.pop(); 
+            Some(expr)
+        }; 
+        match expr {
+            Expr::Name(name) => expand(&name, expanding).unwrap_or(Expr::Name(name)), 
+            Expr::Prefix(p, a) => Expr::Prefix(p, Box::new(self.resolve_names(*a, expanding))), 
+            Expr::Infix(i, a, b) => Expr::Infix(i, Box::new(self.resolve_names(*a, expanding)), Box::new(self.resolve_names(*b, expanding))), 
+            Expr::Func { name, args } => {
+                let args: Vec<Expr> = args.into_iter().map(|a| self.resolve_names(a, expanding)).collect(); 
+                match expand(&name, expanding) {
+                    Some(f) => Expr::Call(Box::new(f), args), 
+                    None => Expr::Func { name, args }
+                }
+            }, 
+            Expr::Call(f, args) => Expr::Call(
+                Box::new(self.resolve_names(*f, expanding)), 
+                args.into_iter().map(|a| self.resolve_names(a, expanding)).collect()
+            ), 
+            Expr::Array(rows) => Expr::Array(rows.into_iter().map(|row| row.into_iter().map(|a| self.resolve_names(a, expanding)).collect()).collect()), 
+            e => e
+        }
+    }
+
+    // Sheet qualified address of a cell, f.ex. 'Sheet1'!B4. 
+    pub fn cell_name(&self, sheet_idx: usize, row: usize, column: usize) -> String {
+        format!("{}{}", self.get_sheet_by_idx(sheet_idx), Reference::from((row, column)))
+    }
+
     pub fn load_shared_strings(&mut self) -> Result<(), Error> {
         let mut buf = Vec::new(); 
         if let Ok(f) = self.zip.as_mut().unwrap().by_name("xl/sharedStrings.xml") {
@@ -93,14 +181,12 @@ impl Book {
                     Ok(Event::Start(ref e)) if e.name() == b"t" => {
                         is_shared_string = true;
                     }, 
-                    Ok(Event::Text(ref e)) => {
-                        if is_shared_string {
-                            let decoded_text: String = Self::decode_text_event(&reader, e);
-                            if !decoded_text.is_empty() {
-                                self.shared_strings.push(
-                                    SharedString(Self::decode_text_event(&reader, e))
-                                )
-                            }
+                    Ok(Event::Text(ref e)) if is_shared_string => {
+                        let decoded_text: String = Self::decode_text_event(&reader, e);
+                        if !decoded_text.is_empty() {
+                            self.shared_strings.push(
+                                SharedString(Self::decode_text_event(&reader, e))
+                            )
                         }
                     }, 
                     Ok(Event::Eof) => break, 
@@ -117,23 +203,33 @@ impl Book {
         if let Ok(f) = self.zip.as_mut().unwrap().by_name("xl/styles.xml") {
             let mut reader: Reader<BufReader<ZipFile>> = Reader::<BufReader<ZipFile>>::from_reader(BufReader::new(f)); 
             let mut is_cell_xfs: bool = false;
+            let mut custom_formats: Vec<(usize, String)> = vec![]; 
             loop {
                 match reader.read_event(&mut buf) {
+                    Ok(Event::Empty(ref e)) | Ok(Event::Start(ref e)) if e.name() == b"numFmt" => {
+                        let mut id: usize = 0; 
+                        let mut code = String::new(); 
+                        for a in e.attributes() {
+                            let a = a.unwrap(); 
+                            match a.key {
+                                b"numFmtId" => { id = Book::decode_attribute_usize(&reader, a); }, 
+                                b"formatCode" => { code = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                                _ => {}
+                            }
+                        }
+                        custom_formats.push((id, code)); 
+                    }, 
                     Ok(Event::Start(ref e)) if e.name() == b"cellXfs" || e.name() == b"xf" => {
                         match e.name() {
                             b"cellXfs" => { is_cell_xfs = true; },
-                            b"xf" => {
-                                if is_cell_xfs {
-                                    self.styles.push(Book::decode_style(&reader, e)); 
-                                }
+                            b"xf" if is_cell_xfs => {
+                                self.styles.push(Book::decode_style(&reader, e)); 
                             },
                             _ => {}
                         }
                     }, 
-                    Ok(Event::Empty(ref e)) if e.name() == b"xf" => {
-                        if is_cell_xfs {
-                            self.styles.push(Book::decode_style(&reader, e)); 
-                        }
+                    Ok(Event::Empty(ref e)) if e.name() == b"xf" && is_cell_xfs => {
+                        self.styles.push(Book::decode_style(&reader, e)); 
                     }, 
                     Ok(Event::End(ref e)) if e.name() == b"cellXfs" => { is_cell_xfs = false; }, 
                     Ok(Event::Eof) => break, 
@@ -141,6 +237,15 @@ impl Book {
                 }
                 buf.clear(); 
             }
+            // Custom codes (numFmtId 164 and up) are listed before the cell formats that use them 
+            for style in self.styles.iter_mut() {
+                let code = custom_formats.iter()
+                    .find(|(id, _)| *id == style.number_format_id)
+                    .map(|(_, code)| code.as_str())
+                    .or_else(|| builtin_format(style.number_format_id))
+                    .unwrap_or("General"); 
+                style.format = NumberFormat::from(code); 
+            }
         }
         Ok(())
     }
@@ -150,6 +255,7 @@ impl Book {
         if let Ok(f) = self.zip.as_mut().unwrap().by_name("xl/workbook.xml") {
             let mut reader: Reader<BufReader<ZipFile>> = Reader::<BufReader<ZipFile>>::from_reader(BufReader::new(f)); 
             let mut sheet_idx: usize = 0; 
+            let mut defined_name: Option<(Option<usize>, String)> = None; 
             loop {
                 match reader.read_event(&mut buf) {
                     Ok(Event::Empty(ref e)) if e.local_name() == b"sheet" => {
@@ -162,6 +268,30 @@ impl Book {
                             }
                         }
                     }, 
+                    // <definedName name="Rate" localSheetId="0">Sheet1!$B$1</definedName> 
+                    Ok(Event::Start(ref e)) if e.local_name() == b"definedName" => {
+                        let mut name = String::new(); 
+                        let mut local_sheet: Option<usize> = None; 
+                        for a in e.attributes() {
+                            let a = a.unwrap(); 
+                            match a.key {
+                                b"name" => { name = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                                b"localSheetId" => { local_sheet = a.unescape_and_decode_value(&reader).unwrap().parse().ok(); }, 
+                                _ => {}
+                            }
+                        }
+                        // Print areas, filters and the like are Excel's own 
+                        if !name.starts_with("_xl") {
+                            defined_name = Some((local_sheet, name.to_uppercase())); 
+                        }
+                    }, 
+                    Ok(Event::Text(ref e)) if defined_name.is_some() => {
+                        let formula = Self::decode_text_event(&reader, e).replace("_xlfn.", "").replace("_xlws.", ""); 
+                        self.defined_names.insert(defined_name.take().unwrap(), formula); 
+                    }, 
+                    Ok(Event::End(ref e)) if e.local_name() == b"definedName" => {
+                        defined_name = None; 
+                    }, 
                     Ok(Event::Eof) => break, 
                     _ => {}
                 }
@@ -171,6 +301,260 @@ impl Book {
         Ok(())
     }
 
+    // Tables are linked from each sheet's relationships, f.ex. xl/worksheets/_rels/sheet1.xml.rels -> ../tables/table1.xml 
+    pub fn load_tables(&mut self) -> Result<(), Error> {
+        for sheet_idx in 0..self.sheets.len() {
+            let mut targets: Vec<String> = vec![]; 
+            let mut buf = Vec::new();
+            if let Ok(f) = self.zip.as_mut().unwrap().by_name(&format!("xl/worksheets/_rels/sheet{}.xml.rels", sheet_idx + 1)) {
+                let mut reader: Reader<BufReader<ZipFile>> = Reader::<BufReader<ZipFile>>::from_reader(BufReader::new(f)); 
+                loop {
+                    match reader.read_event(&mut buf) {
+                        Ok(Event::Empty(ref e)) if e.local_name() == b"Relationship" => {
+                            let mut is_table = false; 
+                            let mut target = String::new(); 
+                            for a in e.attributes() {
+                                let a = a.unwrap(); 
+                                match a.key {
+                                    b"Type" => { is_table = a.unescape_and_decode_value(&reader).unwrap().ends_with("/table"); }, 
+                                    b"Target" => { target = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                                    _ => {}
+                                }
+                            }
+                            if is_table {
+                                targets.push(match target.strip_prefix('/') {
+                                    Some(absolute) => absolute.to_string(), 
+                                    None => format!("xl/worksheets/{}", target).replace("worksheets/../", "")
+                                }); 
+                            }
+                        }, 
+                        Ok(Event::Eof) => break, 
+                        _ => {}
+                    }
+                    buf.clear(); 
+                }
+            }
+            for target in targets {
+                self.load_table(&target, sheet_idx)?; 
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_table(&mut self, path: &str, sheet_idx: usize) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        if let Ok(f) = self.zip.as_mut().unwrap().by_name(path) {
+            let mut reader: Reader<BufReader<ZipFile>> = Reader::<BufReader<ZipFile>>::from_reader(BufReader::new(f)); 
+            let mut table = Table::new(sheet_idx); 
+            loop {
+                match reader.read_event(&mut buf) {
+                    Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) if e.local_name() == b"table" => {
+                        for a in e.attributes() {
+                            let a = a.unwrap(); 
+                            match a.key {
+                                b"name" if table.name.is_empty() => { table.name = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                                b"displayName" => { table.name = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                                b"ref" => { table.reference = Reference::from(a.unescape_and_decode_value(&reader).unwrap()); }, 
+                                b"headerRowCount" => { table.header_rows = Book::decode_attribute_usize(&reader, a); }, 
+                                b"totalsRowCount" => { table.totals_rows = Book::decode_attribute_usize(&reader, a); }, 
+                                _ => {}
+                            }
+                        }
+                    }, 
+                    Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) if e.local_name() == b"tableColumn" => {
+                        for a in e.attributes() {
+                            let a = a.unwrap(); 
+                            if let b"name" = a.key {
+                                table.columns.push(a.unescape_and_decode_value(&reader).unwrap()); 
+                            }
+                        }
+                    }, 
+                    Ok(Event::Eof) => break, 
+                    _ => {}
+                }
+                buf.clear(); 
+            }
+            self.tables.push(table); 
+        }
+        Ok(())
+    }
+
+    /*
+     * Linked workbooks are numbered by their order in the workbook's <externalReferences>, 
+     * [1] is the first. Each one names its part by relationship id, f.ex. rId3 -> 
+     * externalLinks/externalLink2.xml in xl/_rels/workbook.xml.rels. 
+     */
+    pub fn load_external_links(&mut self) -> Result<(), Error> {
+        let mut ids: Vec<String> = vec![]; 
+        let mut buf = Vec::new();
+        if let Ok(f) = self.zip.as_mut().unwrap().by_name("xl/workbook.xml") {
+            let mut reader: Reader<BufReader<ZipFile>> = Reader::<BufReader<ZipFile>>::from_reader(BufReader::new(f)); 
+            loop {
+                match reader.read_event(&mut buf) {
+                    Ok(Event::Empty(ref e)) if e.local_name() == b"externalReference" => {
+                        for a in e.attributes() {
+                            let a = a.unwrap(); 
+                            if let b"r:id" = a.key {
+                                ids.push(a.unescape_and_decode_value(&reader).unwrap()); 
+                            }
+                        }
+                    }, 
+                    Ok(Event::Eof) => break, 
+                    _ => {}
+                }
+                buf.clear(); 
+            }
+        }
+        let parts = self.workbook_relationships(); 
+        for (k, id) in ids.iter().enumerate() {
+            if let Some(link) = parts.get(id).and_then(|part| self.load_external_link(k + 1, part)) {
+                self.external_links.push(link); 
+            }
+        }
+        Ok(())
+    }
+
+    // Parts the workbook links to by relationship id, as paths in the zip. 
+    fn workbook_relationships(&mut self) -> HashMap<String, String> {
+        let mut parts: HashMap<String, String> = HashMap::new(); 
+        let mut buf = Vec::new();
+        if let Ok(f) = self.zip.as_mut().unwrap().by_name("xl/_rels/workbook.xml.rels") {
+            let mut reader: Reader<BufReader<ZipFile>> = Reader::<BufReader<ZipFile>>::from_reader(BufReader::new(f)); 
+            loop {
+                match reader.read_event(&mut buf) {
+                    Ok(Event::Empty(ref e)) if e.local_name() == b"Relationship" => {
+                        let mut id = String::new(); 
+                        let mut target = String::new(); 
+                        for a in e.attributes() {
+                            let a = a.unwrap(); 
+                            match a.key {
+                                b"Id" => { id = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                                b"Target" => { target = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                                _ => {}
+                            }
+                        }
+                        parts.insert(id, match target.strip_prefix('/') {
+                            Some(absolute) => absolute.to_string(), 
+                            None => format!("xl/{}", target)
+                        }); 
+                    }, 
+                    Ok(Event::Eof) => break, 
+                    _ => {}
+                }
+                buf.clear(); 
+            }
+        }
+        parts
+    }
+
+    fn load_external_link(&mut self, index: usize, part: &str) -> Option<ExternalLink> {
+        let mut buf = Vec::new();
+        let mut link = ExternalLink::new(index); 
+        // xl/externalLinks/externalLink1.xml -> xl/externalLinks/_rels/externalLink1.xml.rels 
+        let (folder, file) = part.rsplit_once('/').unwrap_or(("", part)); 
+        if let Ok(f) = self.zip.as_mut().unwrap().by_name(&format!("{}/_rels/{}.rels", folder, file)) {
+            let mut reader: Reader<BufReader<ZipFile>> = Reader::<BufReader<ZipFile>>::from_reader(BufReader::new(f)); 
+            loop {
+                match reader.read_event(&mut buf) {
+                    Ok(Event::Empty(ref e)) if e.local_name() == b"Relationship" => {
+                        for a in e.attributes() {
+                            let a = a.unwrap(); 
+                            if let b"Target" = a.key {
+                                link.target = a.unescape_and_decode_value(&reader).unwrap(); 
+                            }
+                        }
+                    }, 
+                    Ok(Event::Eof) => break, 
+                    _ => {}
+                }
+                buf.clear(); 
+            }
+        }
+        let f = self.zip.as_mut().unwrap().by_name(part).ok()?; 
+        let mut reader: Reader<BufReader<ZipFile>> = Reader::<BufReader<ZipFile>>::from_reader(BufReader::new(f)); 
+        let mut sheet_idx: usize = 0; 
+        let mut cell_reference = String::new(); 
+        let mut cell_type = String::new(); 
+        let mut is_value = false; 
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok(Event::Empty(ref e)) if e.local_name() == b"sheetName" => {
+                    for a in e.attributes() {
+                        let a = a.unwrap(); 
+                        if let b"val" = a.key {
+                            let name = a.unescape_and_decode_value(&reader).unwrap(); 
+                            link.sheets.push(Sheet::from((name, link.sheets.len()))); 
+                        }
+                    }
+                }, 
+                Ok(Event::Start(ref e)) if e.local_name() == b"sheetData" => {
+                    for a in e.attributes() {
+                        let a = a.unwrap(); 
+                        if let b"sheetId" = a.key {
+                            sheet_idx = Book::decode_attribute_usize(&reader, a); 
+                        }
+                    }
+                }, 
+                Ok(Event::Start(ref e)) if e.local_name() == b"cell" => {
+                    cell_type = String::new(); 
+                    for a in e.attributes() {
+                        let a = a.unwrap(); 
+                        match a.key {
+                            b"r" => { cell_reference = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                            b"t" => { cell_type = a.unescape_and_decode_value(&reader).unwrap(); }, 
+                            _ => {}
+                        }
+                    }
+                }, 
+                Ok(Event::Start(ref e)) if e.local_name() == b"v" => is_value = true, 
+                Ok(Event::End(ref e)) if e.local_name() == b"v" => is_value = false, 
+                Ok(Event::Text(ref e)) if is_value => {
+                    let cell_text = Book::decode_text_event(&reader, e); 
+                    // Cached strings are stored inline, not in the shared strings 
+                    let value = match cell_type.as_str() {
+                        "s" | "str" => Value::from(cell_text), 
+                        "b" => Value::Bool(cell_text == "1"), 
+                        "e" => match parse_str(&cell_text) {
+                            Ok(Expr::Error(e)) => Value::Error(e), 
+                            _ => Value::Error(ExcelError::NA)
+                        }, 
+                        _ => cell_text.parse::<f64>().map(Value::Num).unwrap_or(Value::Empty)
+                    }; 
+                    if let Some(sheet) = link.sheets.get_mut(sheet_idx) {
+                        let (row, column) = Cell::from(cell_reference.clone()).as_tuple(); 
+                        sheet.resize(row, column); 
+                        sheet.values[[row-1, column-1]] = SheetValue { value: value.clone(), calculated: value, dirty: false, style: 0 }; 
+                    }
+                }, 
+                Ok(Event::Eof) => break, 
+                _ => {}
+            }
+            buf.clear(); 
+        }
+        Some(link)
+    }
+
+    // Use a loaded workbook instead of the cached values for [index]Sheet!A1 references. 
+    // It is recalculated together with this book. 
+    pub fn link_book(&mut self, index: usize, book: Book) {
+        match self.external_links.iter_mut().find(|l| l.index == index) {
+            Some(link) => link.book = Some(Box::new(book)), 
+            None => {
+                let mut link = ExternalLink::new(index); 
+                link.book = Some(Box::new(book)); 
+                self.external_links.push(link); 
+            }
+        }
+    }
+
+    pub fn get_linked_book(&self, index: usize) -> Option<&Book> {
+        self.external_links.iter().find(|l| l.index == index).and_then(|l| l.book.as_deref())
+    }
+
+    pub fn get_mut_linked_book(&mut self, index: usize) -> Option<&mut Book> {
+        self.external_links.iter_mut().find(|l| l.index == index).and_then(|l| l.book.as_deref_mut())
+    }
+
     pub fn load_sheets(&mut self, progress: bool) -> Result<(), Error> { 
         for sheet_id in 0..self.sheets.len() {
             self.load_sheet(sheet_id, progress)?; 
@@ -180,8 +564,8 @@ impl Book {
 
     pub fn load_sheet(&mut self, sheet_idx: usize, progress: bool) -> Result<(), Error> {
         let mut buf = Vec::new();
-        let max_rows = self.get_sheet_by_idx(sheet_idx).max_rows.clone(); 
-        let max_columns = self.get_sheet_by_idx(sheet_idx).max_columns.clone(); 
+        let max_rows = self.get_sheet_by_idx(sheet_idx).max_rows; 
+        let max_columns = self.get_sheet_by_idx(sheet_idx).max_columns; 
         let pb = match progress {
             true => ProgressBar::new((max_rows * max_columns) as u64), 
             false => ProgressBar::hidden()
@@ -211,21 +595,45 @@ impl Book {
                                     // Cell style / date
                                     let cell_style_idx: usize = a.unescape_and_decode_value(&reader).unwrap().parse::<usize>().unwrap(); 
                                     let style: &Style = self.styles.get(cell_style_idx).expect("Could not find style index");
-                                    if style.number_format_id >= 14 && style.number_format_id <= 22 && style.apply_number_format {
+                                    let is_date_format = (14..=22).contains(&style.number_format_id) || (style.number_format_id >= 164 && style.format.is_date()); 
+                                    if is_date_format && style.apply_number_format {
                                         flags.is_date = true;
                                     }
+                                    flags.style = cell_style_idx; 
                                 }, 
+                                b"cm" => {
+                                    // Cell metadata, only dynamic arrays have any 
+                                    flags.is_dynamic_array = true; 
+                                }, 
+                                _ => {}
+                            }
+                        }
+                    }, 
+                    Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) if e.name() == b"row" => {
+                        // Hidden rows, for SUBTOTAL and AGGREGATE 
+                        let (mut row, mut hidden) = (0, false); 
+                        for a in e.attributes() {
+                            let a = a.unwrap(); 
+                            match (a.key, a.unescape_and_decode_value(&reader).unwrap().as_str()) {
+                                (b"r", r) => row = r.parse::<usize>().unwrap_or(0), 
+                                (b"hidden", "1" | "true") => hidden = true, 
                                 _ => {}
                             }
                         }
+                        if hidden {
+                            self.sheets[sheet_idx].hidden_rows.insert(row); 
+                        }
                     }, 
                     Ok(Event::Start(ref e)) if e.name() == b"f" => {
                         // Formula flag
                         flags.is_formula = true;
                         for a in e.attributes() {
                             let a = a.unwrap();
-                            if a.key == b"ref" {
-                                flags.is_shared_formula = true; 
+                            match (a.key, a.unescape_and_decode_value(&reader).unwrap().as_str()) {
+                                (b"t", "shared") => flags.is_shared_formula = true, 
+                                (b"t", "array") => flags.is_array_formula = true, 
+                                (b"ref", r) => flags.formula_ref = r.to_string(), 
+                                _ => {}
                             }
                         }
                     }, 
@@ -239,11 +647,15 @@ impl Book {
                                 let base_reference = Reference::from(start_cell.as_tuple()); 
                                 let current_cell = Cell::from(flags.current_cell_reference.clone()); 
                                 let current_reference = Reference::from(current_cell.as_tuple());
-                                let adjusted_formula: Value = Value::Formula(format!("={}", adjust_formula(base_reference, current_reference, formula_text.clone())?)); 
+                                let adjusted_formula_text = adjust_formula(base_reference, current_reference, formula_text.clone())
+                                    .map_err(|e| Error::Formula(format!("{}{}", self.sheets[sheet_idx], flags.current_cell_reference), Box::new(e)))?; 
+                                let adjusted_formula: Value = Value::Formula(format!("={}", adjusted_formula_text)); 
                                 let sheet = self.sheets.get_mut(sheet_idx).unwrap(); 
                                 let (row, column): (usize, usize) = current_cell.as_tuple(); 
                                 sheet.resize(row, column); 
                                 sheet.values[[row-1, column-1]].value = adjusted_formula.clone(); 
+                                sheet.values[[row-1, column-1]].style = flags.style; 
+                                sheet.values[[row-1, column-1]].dirty = true; 
                                 let cell_id = CellId::from((sheet_idx, row, column, 1, 1, true)); 
                                 self.formulas.push((cell_id, adjusted_formula.to_string())); 
                                 flags.reset(); 
@@ -260,7 +672,7 @@ impl Book {
                             let cell_text = Book::decode_text_event(&reader, e); 
                             let value: Value; 
                             if flags.is_formula {
-                                value = Value::Formula(format!("={}", &cell_text.replace("_xlfn.", "").to_owned()));
+                                value = Value::Formula(format!("={}", &cell_text.replace("_xlfn.", "").replace("_xlws.", "").to_owned()));
                                 if flags.is_shared_formula {
                                     flags.shared_formulas.push(
                                         (Cell::from(flags.current_cell_reference.clone()), cell_text.clone())
@@ -286,14 +698,39 @@ impl Book {
                             let cell = Cell::from(flags.current_cell_reference.clone()); 
                             let (row, column): (usize, usize) = cell.as_tuple(); 
  
+                            let (rows, cols) = match flags.is_array_formula {
+                                true => { 
+                                    let (_, _, rows, cols) = Reference::from(flags.formula_ref.clone()).get_dimensions(); 
+                                    (rows, cols)
+                                }, 
+                                false => (1, 1)
+                            }; 
                             if value.is_formula() {
-                                let cell_id = CellId::from((sheet_idx, row, column, 1, 1, true)); 
+                                // The block of a Ctrl+Shift+Enter formula calculates as one 
+                                let cell_id = match flags.is_array_formula && !flags.is_dynamic_array {
+                                    true => CellId::from((sheet_idx, row, column, rows, cols, true)), 
+                                    false => CellId::from((sheet_idx, row, column, 1, 1, true))
+                                }; 
                                 self.formulas.push((cell_id, value.to_string())); 
                             }
 
                             let sheet = self.sheets.get_mut(sheet_idx).unwrap(); 
                             sheet.resize(row, column); 
-                            sheet.values[[row-1, column-1]] = SheetValue { value: value.clone(), calculated: value, dirty: false }; 
+                            if flags.is_array_formula && flags.is_dynamic_array {
+                                sheet.dynamic.insert((row, column)); 
+                            }
+                            if flags.is_array_formula && flags.is_dynamic_array && rows * cols > 1 {
+                                sheet.spills.insert((row, column), (rows, cols)); 
+                            } else if flags.is_array_formula && !flags.is_dynamic_array {
+                                sheet.arrays.insert((row, column), (rows, cols)); 
+                            }
+                            let anchor = sheet.spill_anchor(row, column).or_else(|| sheet.array_anchor(row, column)); 
+                            if !value.is_formula() && anchor.is_some_and(|a| a != (row, column)) {
+                                // Cached result of an array formula, the anchor's formula writes it again 
+                                sheet.values[[row-1, column-1]] = SheetValue { value: Value::Empty, calculated: value, dirty: false, style: flags.style }; 
+                            } else {
+                                sheet.values[[row-1, column-1]] = SheetValue { dirty: value.is_formula(), value: value.clone(), calculated: value, style: flags.style }; 
+                            }
                             pb.set_position((row * max_columns + column) as u64); 
                             flags.reset(); 
                         }
@@ -338,7 +775,7 @@ impl Book {
                 _ => {}
             }
         }
-        Style { number_format_id, apply_number_format }
+        Style { number_format_id, apply_number_format, format: NumberFormat::default() }
     }
 
     pub fn get_mut_sheet_by_name<'a>(&'a mut self, s: &'a str) -> &'a mut Sheet {
@@ -370,45 +807,302 @@ impl Book {
 
     pub fn resolve_ref(&self, expr: Expr) -> Result<Array2<Value>, Error> {
         if let Expr::Reference {sheet, reference} = expr {
-            let (mut row, mut col, mut num_rows, mut num_cols) = Reference::from(reference).get_dimensions();
+            if let Some((book, sheet_name)) = sheet.as_deref().and_then(split_external_sheet) {
+                return self.resolve_external_ref(book, sheet_name, reference); 
+            }
             let sheet: &Sheet = match sheet {
                 Some(s) => self.get_sheet_by_name(s), 
                 None => self.get_sheet_by_idx(self.current_sheet)
             };
-            if num_rows == usize::MAX { 
-                num_rows = sheet.values.dim().0; 
-                row = 1; // To avoid subtract overflow on row_idx_start
-            }
-            if num_cols == usize::MAX { 
-                num_cols = sheet.values.dim().0; 
-                col = 1; // To avoid subtract overflow on col_idx_start
-            }
-            let row_idx_start: usize = sheet.values.dim().0.min(row-1);
-            let row_idx_end: usize = sheet.values.dim().0.min(row+num_rows-1);
-            let rows_append: usize = num_rows - (row_idx_end - row_idx_start);
-            let col_idx_start: usize = sheet.values.dim().1.min(col-1);
-            let col_idx_end: usize = sheet.values.dim().1.min(col+num_cols-1);
-            let cols_append: usize = num_cols - (col_idx_end - col_idx_start);
-            let mut output: Array2<SheetValue> = sheet.values.slice(s![row_idx_start..row_idx_end, col_idx_start..col_idx_end]).into_owned(); 
-            if rows_append > 0 {
-                for _ in 0..rows_append {
-                    output.push(Axis(0), ArrayView::from(&Array::from_elem(output.dim().1, SheetValue::new()))).unwrap(); 
-                }
+            Ok(sheet.get_range(reference))
+        } else {
+            panic!("Can only resolve a reference expression.")
+        }
+    }
+
+    // Number format of a cell, General for unformatted cells and cells outside the sheet. 
+    pub fn get_format(&self, sheet_idx: usize, row: usize, column: usize) -> &NumberFormat {
+        let sheet = self.get_sheet_by_idx(sheet_idx); 
+        let style = match sheet.values.get((row.wrapping_sub(1), column.wrapping_sub(1))) {
+            Some(v) => v.style, 
+            None => 0
+        }; 
+        match self.styles.get(style) {
+            Some(s) => &s.format, 
+            None => DEFAULT_FORMAT.get_or_init(NumberFormat::default)
+        }
+    }
+
+    // Values of a range as Excel displays them, using each cell's number format. 
+    pub fn resolve_formatted_ref(&self, expr: Expr) -> Result<Array2<String>, Error> {
+        let values = self.resolve_ref(expr.clone())?; 
+        let (sheet, reference) = match expr {
+            Expr::Reference { sheet, reference } => (sheet, reference), 
+            _ => unreachable!()
+        }; 
+        let sheet_idx = match sheet {
+            Some(s) if split_external_sheet(&s).is_some() => None, 
+            Some(s) => self.sheets.iter().position(|x| x.name == s), 
+            None => Some(self.current_sheet)
+        }; 
+        let (mut row, mut column, num_rows, num_cols) = Reference::from(reference).get_dimensions(); 
+        if num_rows == usize::MAX { row = 1; }
+        if num_cols == usize::MAX { column = 1; }
+        Ok(Array2::from_shape_fn(values.dim(), |(i, j)| {
+            let format = match sheet_idx {
+                Some(idx) => self.get_format(idx, row + i, column + j), 
+                None => DEFAULT_FORMAT.get_or_init(NumberFormat::default)
+            }; 
+            match format.format(&values[[i, j]]) {
+                Ok(s) => s, 
+                Err(ExcelError::Value) if !values[[i, j]].is_err() => String::from("#######"), // Dates out of range 
+                Err(e) => e.to_string()
             }
-            if cols_append > 0 {
-                for _ in 0..cols_append {
-                    output.push(Axis(1), ArrayView::from(&Array::from_elem(output.dim().0, SheetValue::new()))).unwrap(); 
+        }))
+    }
+
+    // [1]Inputs!B4 reads from the linked book if one is attached, and from Excel's cached values otherwise. 
+    pub fn resolve_external_ref(&self, book: &str, sheet_name: &str, reference: String) -> Result<Array2<Value>, Error> {
+        let link = match self.external_links.iter().find(|l| l.matches(book)) {
+            Some(link) => link, 
+            None => return Ok(Array2::from_elem((1, 1), Value::Error(ExcelError::Ref)))
+        }; 
+        match &link.book {
+            Some(linked) => {
+                match linked.sheets.iter().find(|s| s.name.eq_ignore_ascii_case(sheet_name)) {
+                    Some(sheet) => linked.resolve_ref(Expr::Reference { sheet: Some(sheet.name.clone()), reference }), 
+                    None => Ok(Array2::from_elem((1, 1), Value::Error(ExcelError::Ref)))
+                }
+            }, 
+            None => {
+                match link.sheets.iter().find(|s| s.name.eq_ignore_ascii_case(sheet_name)) {
+                    Some(sheet) => Ok(sheet.get_range(reference)), 
+                    None => Ok(Array2::from_elem((1, 1), Value::Error(ExcelError::Ref)))
                 }
             }
-            Ok(output.map(|b| {
-                if b.is_calculated() {
-                    b.calculated.clone()
-                } else {
-                    b.value.clone()
+        }
+    }
+
+    // Replace structured references with the ranges they point at, 
+    // as seen from the formula in (sheet, row, column). 
+    pub fn resolve_tables(&self, expr: Expr, sheet: usize, row: usize, column: usize) -> Expr {
+        match expr {
+            Expr::Table(t) => self.resolve_table_ref(&t, sheet, row, column), 
+            Expr::Prefix(p, a) => Expr::Prefix(p, Box::new(self.resolve_tables(*a, sheet, row, column))), 
+            Expr::Infix(i, a, b) => Expr::Infix(
+                i, 
+                Box::new(self.resolve_tables(*a, sheet, row, column)), 
+                Box::new(self.resolve_tables(*b, sheet, row, column))
+            ), 
+            Expr::Func { name, args } => Expr::Func {
+                name, 
+                args: args.into_iter().map(|a| self.resolve_tables(a, sheet, row, column)).collect()
+            }, 
+            Expr::Array(rows) => Expr::Array(rows.into_iter().map(|r| r.into_iter().map(|a| self.resolve_tables(a, sheet, row, column)).collect()).collect()), 
+            Expr::Call(f, args) => Expr::Call(
+                Box::new(self.resolve_tables(*f, sheet, row, column)), 
+                args.into_iter().map(|a| self.resolve_tables(a, sheet, row, column)).collect()
+            ), 
+            e => e
+        }
+    }
+
+    // Replace A1# with the range the array in A1 spills over right now, #REF! when it does not spill. 
+    pub fn resolve_spills(&self, expr: Expr, sheet: usize) -> Expr {
+        match expr {
+            Expr::Spill { sheet: name, anchor } => {
+                let sheet_idx = match &name {
+                    Some(s) => self.sheets.iter().position(|x| x.name == *s), 
+                    None => Some(sheet)
+                }; 
+                let (row, column) = Reference::from(anchor).start_cell.as_tuple(); 
+                match sheet_idx.and_then(|idx| self.sheets[idx].spills.get(&(row, column))) {
+                    Some((rows, cols)) => Expr::Reference {
+                        sheet: name, 
+                        reference: Reference::from((row, column, row + rows - 1, column + cols - 1)).to_string() 
+                    }, 
+                    None => Expr::Error(ExcelError::Ref)
                 }
-           }))
+            }, 
+            Expr::Prefix(p, a) => Expr::Prefix(p, Box::new(self.resolve_spills(*a, sheet))), 
+            Expr::Infix(i, a, b) => Expr::Infix(i, Box::new(self.resolve_spills(*a, sheet)), Box::new(self.resolve_spills(*b, sheet))), 
+            Expr::Func { name, args } => Expr::Func { name, args: args.into_iter().map(|a| self.resolve_spills(a, sheet)).collect() }, 
+            Expr::Array(rows) => Expr::Array(rows.into_iter().map(|row| row.into_iter().map(|a| self.resolve_spills(a, sheet)).collect()).collect()), 
+            Expr::Call(f, args) => Expr::Call(Box::new(self.resolve_spills(*f, sheet)), args.into_iter().map(|a| self.resolve_spills(a, sheet)).collect()), 
+            e => e
+        }
+    }
+
+    /*
+     * Formulas written before dynamic arrays take the one cell of a range in their own row 
+     * or column where a single value is expected, so =A1:A3*2 in B2 is A2*2. A range 
+     * sharing neither is #VALUE!. 
+     */
+    pub fn implicit_intersection(&self, expr: Expr, row: usize, column: usize) -> Expr {
+        match expr {
+            Expr::Reference { sheet, reference } => {
+                let (start_row, start_column, rows, cols) = Reference::from(reference.clone()).get_dimensions(); 
+                if rows * cols == 1 {
+                    return Expr::Reference { sheet, reference }; 
+                }
+                let in_rows = (start_row..start_row + rows).contains(&row); 
+                let in_columns = (start_column..start_column + cols).contains(&column); 
+                match (rows, cols) {
+                    (_, 1) if in_rows => Expr::Reference { sheet, reference: Reference::from((row, start_column)).to_string() }, 
+                    (1, _) if in_columns => Expr::Reference { sheet, reference: Reference::from((start_row, column)).to_string() }, 
+                    _ => Expr::Error(ExcelError::Value)
+                }
+            }, 
+            // Function arguments can take whole ranges 
+            Expr::Prefix(p, a) => Expr::Prefix(p, Box::new(self.implicit_intersection(*a, row, column))), 
+            Expr::Infix(i, a, b) => Expr::Infix(i, Box::new(self.implicit_intersection(*a, row, column)), Box::new(self.implicit_intersection(*b, row, column))), 
+            e => e
+        }
+    }
+
+    // Marks the formula in range as a dynamic array that spills, as Excel does for formulas entered since dynamic arrays. 
+    pub fn set_dynamic(&mut self, range: &str) {
+        let expr: Expr = parse_str(range).unwrap(); 
+        if let Expr::Reference { sheet: Some(sheet), reference } = expr {
+            let reference = Reference::from(reference); 
+            let sheet = self.get_mut_sheet_by_name(&sheet); 
+            sheet.dynamic.insert((reference.row(), reference.column())); 
+            let cell_id = CellId::from((sheet.idx, reference.row(), reference.column(), 1, 1, true)); 
+            let dependents = self.dependencies.dependents(vec![cell_id]); 
+            self.mark_dirty(&dependents); 
         } else {
-            panic!("Can only resolve a reference expression.")
+            panic!("String must resolve to a reference"); 
+        }
+    }
+
+    /*
+     * Writes an array result into the cells right of and below its formula and 
+     * returns what the formula's own cell shows. When a value or another array's 
+     * spill range is in the way nothing is written and the formula is #SPILL!. 
+     */
+    fn spill(&mut self, sheet_idx: usize, row: usize, column: usize, value: Value) -> Value {
+        let sheet: &mut Sheet = self.get_mut_sheet_by_idx(sheet_idx); 
+        if let Some((rows, cols)) = sheet.spills.remove(&(row, column)) {
+            for (i, j) in Reference::get_cells_from_dim(row, column, rows, cols) {
+                if let Some(v) = sheet.values.get_mut((i - 1, j - 1)).filter(|v| v.value.is_empty()) {
+                    *v = SheetValue { style: v.style, ..SheetValue::new() }; 
+                }
+            }
+        }
+        let arr2: Array2<Value> = match value {
+            Value::Array2(arr2) => arr2, 
+            Value::Array(arr) => Array2::from_shape_vec((1, arr.len()), arr).unwrap(), 
+            v => return v
+        }; 
+        let (rows, cols) = arr2.dim(); 
+        match rows * cols {
+            0 => return Value::Error(ExcelError::Calc), 
+            1 => return arr2[[0, 0]].clone(), 
+            _ => {}
+        }
+        if row + rows - 1 > 1048576 || column + cols - 1 > 16384 {
+            return Value::Error(ExcelError::Spill); 
+        }
+        let obstacles: Vec<(usize, usize)> = Reference::get_cells_from_dim(row, column, rows, cols).into_iter()
+            .filter(|cell| *cell != (row, column))
+            .filter(|(i, j)| {
+                sheet.values.get((i - 1, j - 1)).is_some_and(|v| !v.value.is_empty()) 
+                    || sheet.spill_anchor(*i, *j).is_some() 
+                    || sheet.array_anchor(*i, *j).is_some()
+            })
+            .collect(); 
+        if !obstacles.is_empty() {
+            // Clearing an obstacle has to calculate the array again 
+            let anchor = CellId::from((sheet_idx, row, column, 1, 1, true)); 
+            for (i, j) in obstacles {
+                let cell = CellId::from((sheet_idx, i, j, 1, 1, true)); 
+                if !self.dependencies.dependents(vec![anchor]).contains(&cell) {
+                    self.dependencies.add_precedent(&cell, &anchor); 
+                }
+            }
+            return Value::Error(ExcelError::Spill); 
+        }
+        sheet.resize(row + rows - 1, column + cols - 1); 
+        for ((i, j), v) in arr2.indexed_iter().skip(1) {
+            let cell = &mut sheet.values[[row + i - 1, column + j - 1]]; 
+            cell.calculated = match v {
+                Value::Empty => Value::from(0.0), 
+                v => v.clone()
+            }; 
+            cell.dirty = false; 
+        }
+        sheet.spills.insert((row, column), (rows, cols)); 
+        arr2[[0, 0]].clone()
+    }
+
+    /*
+     * Writes the result of a Ctrl+Shift+Enter formula over its block and returns the anchor's 
+     * value. Scalars fill the block, single rows and columns repeat and cells past the end 
+     * of a smaller array are #N/A. 
+     */
+    fn fill_array(&mut self, sheet_idx: usize, row: usize, column: usize, (rows, cols): (usize, usize), value: Value) -> Value {
+        let grid: Array2<Value> = match value {
+            Value::Array2(arr2) => arr2, 
+            Value::Array(arr) => Array2::from_shape_vec((1, arr.len()), arr).unwrap(), 
+            v => Array2::from_elem((1, 1), v)
+        }; 
+        let block = Array2::from_shape_fn((rows, cols), |(i, j)| {
+            let i = if grid.nrows() == 1 { 0 } else { i }; 
+            let j = if grid.ncols() == 1 { 0 } else { j }; 
+            match grid.get((i, j)) {
+                Some(Value::Empty) => Value::from(0.0), 
+                Some(v) => v.clone(), 
+                None => Value::Error(ExcelError::NA)
+            }
+        }); 
+        let sheet: &mut Sheet = self.get_mut_sheet_by_idx(sheet_idx); 
+        sheet.resize(row + rows - 1, column + cols - 1); 
+        for ((i, j), v) in block.indexed_iter().skip(1) {
+            let cell = &mut sheet.values[[row + i - 1, column + j - 1]]; 
+            cell.calculated = v.clone(); 
+            cell.dirty = false; 
+        }
+        block[[0, 0]].clone()
+    }
+
+    /*
+     * Links the cells an anchor spilled into to it in the dependency tree so their 
+     * readers calculate after it. Returns the readers of cells that were not linked yet, 
+     * they may already have been calculated with what was there before. 
+     */
+    fn link_spill(&mut self, anchor: &CellId) -> Vec<CellId> {
+        let (rows, cols) = match self.sheets[anchor.sheet].spills.get(&(anchor.row, anchor.column)) {
+            Some(size) => *size, 
+            None => return vec![]
+        }; 
+        let anchor = CellId::from((anchor.sheet, anchor.row, anchor.column, 1, 1, true)); 
+        let mut linked: Vec<CellId> = vec![]; 
+        for (i, j) in Reference::get_cells_from_dim(anchor.row, anchor.column, rows, cols) {
+            let cell = CellId::from((anchor.sheet, i, j, 1, 1, true)); 
+            // An array reading its own spill range is circular, leave it to the order it has 
+            if cell != anchor && !self.dependencies.is_precedent_of(&anchor, &cell) && !self.dependencies.dependents(vec![cell]).contains(&anchor) {
+                self.dependencies.add_precedent(&anchor, &cell); 
+                linked.push(cell); 
+            }
+        }
+        self.dependencies.dependents(linked.clone()).into_iter()
+            .filter(|c| *c != anchor && !linked.contains(c))
+            .collect()
+    }
+
+    pub fn resolve_table_ref(&self, t: &TableRef, sheet: usize, row: usize, column: usize) -> Expr {
+        let table = match &t.table {
+            Some(name) => self.tables.iter().find(|x| x.name.eq_ignore_ascii_case(name)), 
+            None => self.tables.iter().find(|x| x.contains(sheet, row, column))
+        }; 
+        match table {
+            Some(table) => {
+                match table.resolve(t, row) {
+                    Ok(reference) => Expr::Reference { sheet: Some(self.sheets[table.sheet].name.clone()), reference: reference.to_string() }, 
+                    Err(e) => Expr::Error(e)
+                }
+            }, 
+            None => Expr::Error(ExcelError::Ref)
         }
     }
 
@@ -421,24 +1115,54 @@ impl Book {
             let cell_value = &sheet.values[[cell_id.row-1, cell_id.column-1]].value; 
             if let Value::Formula(formula_text) = cell_value.clone() {
                 self.current_sheet = cell_id.sheet; 
+                self.current_cell = Cell::from((cell_id.row, cell_id.column)); 
                 let mut chars = formula_text.chars(); // Remove = at beginning
                 chars.next();
                 let expr: Expr = parse_str(chars.as_str())?; 
+                let expr: Expr = self.resolve_tables(expr, cell_id.sheet, cell_id.row, cell_id.column); 
+                let expr: Expr = self.resolve_spills(expr, cell_id.sheet); 
+                let sheet: &Sheet = &self.sheets[cell_id.sheet]; 
+                let is_dynamic = sheet.dynamic.contains(&(cell_id.row, cell_id.column)); 
+                let is_array = sheet.arrays.contains_key(&(cell_id.row, cell_id.column)); 
+                let expr: Expr = match is_dynamic || is_array {
+                    true => expr, 
+                    false => self.implicit_intersection(expr, cell_id.row, cell_id.column)
+                }; 
                 let new_value_result = evaluate_expr_with_context(expr, self, debug);
                 match new_value_result {
                     Ok(new_value) => {
                         if debug {
                             println!("======= Calculated cell: {}.{} -> {}", cell_id.sheet, Reference::from((cell_id.row, cell_id.column)), ensure_non_range(new_value.clone())); 
                         }
+                        let new_value = match ensure_non_range(new_value) {
+                            // A LAMBDA has to be called to give a value 
+                            Value::Lambda { .. } => Value::Error(ExcelError::Calc), 
+                            v => v
+                        }; 
+                        let new_value = match self.sheets[cell_id.sheet].arrays.get(&(cell_id.row, cell_id.column)) {
+                            Some(size) => self.fill_array(cell_id.sheet, cell_id.row, cell_id.column, *size, new_value), 
+                            None if is_dynamic => self.spill(cell_id.sheet, cell_id.row, cell_id.column, new_value), 
+                            // Any other array shows its first value 
+                            None => match new_value {
+                                Value::Array2(ref arr2) if arr2.is_empty() => Value::Error(ExcelError::Calc), 
+                                Value::Array(ref arr) if arr.is_empty() => Value::Error(ExcelError::Calc), 
+                                v => v.ensure_single()
+                            }
+                        }; 
+                        // A formula pointing at a blank cell is 0, only cells with nothing in them are blank 
+                        let new_value = match new_value.ensure_single() {
+                            Value::Empty => Value::from(0.0), 
+                            v => v
+                        }; 
                         let sheet: &mut Sheet = self.get_mut_sheet_by_idx(cell_id.sheet); 
-                        sheet.values[[cell_id.row-1, cell_id.column-1]].calculated = ensure_non_range(new_value).ensure_single(); 
+                        sheet.values[[cell_id.row-1, cell_id.column-1]].calculated = new_value; 
                         sheet.values[[cell_id.row-1, cell_id.column-1]].dirty = false; 
                         return Ok(()); 
                     }, 
                     Err(e) => {
                         return match e {
                             Error::Volatile(_) => Err(e), 
-                            _ => Err(Error::Calculation(cell_id.clone(), Box::new(e)))
+                            _ => Err(Error::Calculation(*cell_id, Box::new(e)))
 
                         }; 
                     }
@@ -454,6 +1178,17 @@ impl Book {
     }
 
     pub fn calculate(&mut self, debug: bool, progress: bool) -> Result<(), Error> {
+        for link in self.external_links.iter_mut() {
+            if let Some(linked) = link.book.as_mut() {
+                linked.calculate(debug, progress)?; 
+            }
+        }
+        // Volatile cells and everything depending on them change on every calculation 
+        let volatile = self.dependencies.dependents(self.dependencies.volatile_cells()); 
+        self.mark_dirty(&volatile); 
+        // Cells already calculated keep their value when OFFSET or INDIRECT 
+        // adds a dependency and the order has to be rebuilt. 
+        let mut done: HashSet<(usize, usize, usize, usize, usize)> = HashSet::new(); 
         loop {
             let mut calculated = true; 
             let order: Vec<CellId> = self.dependencies.get_order(); 
@@ -463,9 +1198,22 @@ impl Book {
             };
             for cell_id in self.dependencies.get_order().iter_mut() {
                 pb.inc(1); 
+                let key = (cell_id.sheet, cell_id.row, cell_id.column, cell_id.num_row, cell_id.num_col); 
+                if done.contains(&key) || !self.is_dirty(cell_id) {
+                    continue; 
+                }
                 match self.calculate_cell(cell_id, debug) {
                     Ok(()) => {
                         cell_id.dirty = false; 
+                        done.insert(key); 
+                        let stale: Vec<CellId> = self.link_spill(cell_id).into_iter()
+                            .filter(|c| done.remove(&(c.sheet, c.row, c.column, c.num_row, c.num_col)))
+                            .collect(); 
+                        if !stale.is_empty() {
+                            self.mark_dirty(&stale); 
+                            calculated = false; 
+                            break // Recalculate with the spill range in the order 
+                        }
                     }, 
                     Err(err) => { 
                         match err {
@@ -486,6 +1234,19 @@ impl Book {
         Ok(())
     }
 
+    // Only formulas changed since the last calculation, or depending on a cell that did, are recalculated. 
+    fn is_dirty(&self, cell_id: &CellId) -> bool {
+        self.sheets[cell_id.sheet].values.get((cell_id.row-1, cell_id.column-1)).is_some_and(|v| v.dirty)
+    }
+
+    fn mark_dirty(&mut self, cells: &[CellId]) {
+        for cell_id in cells.iter() {
+            if let Some(v) = self.sheets[cell_id.sheet].values.get_mut((cell_id.row-1, cell_id.column-1)) {
+                v.dirty |= v.value.is_formula(); 
+            }
+        }
+    }
+
     pub fn set_value(&mut self, range: &str, value: Value) {
         let expr: Expr = parse_str(range).unwrap(); 
         if let Expr::Reference { sheet, reference } = expr {
@@ -493,8 +1254,14 @@ impl Book {
             let sheet = self.get_mut_sheet_by_name(&sheet_str); 
             let reference = Reference::from(reference); 
             sheet.set_value(reference, value); 
-            let cell_id = CellId::from((sheet.idx, reference.row(), reference.column(), 1, 1, false)); 
-            self.dependencies.mark_for_recalculation(&cell_id); // Dirty in dep tree
+            let cell_id = CellId::from((sheet.idx, reference.row(), reference.column(), 1, 1, true)); 
+            let mut changed = vec![cell_id]; 
+            // A value typed into a spill range blocks the array 
+            if let Some((row, column)) = sheet.spill_anchor(reference.row(), reference.column()) {
+                changed.push(CellId::from((sheet.idx, row, column, 1, 1, true))); 
+            }
+            let dependents = self.dependencies.dependents(changed); 
+            self.mark_dirty(&dependents); 
        } else {
             panic!("String must resolve to a reference"); 
         }
@@ -507,6 +1274,7 @@ pub struct SheetValue {
     pub value: Value, 
     pub calculated: Value, 
     pub dirty: bool, 
+    pub style: usize, // Index into the workbook's cell formats 
 }
 
 impl From<Value> for SheetValue {
@@ -515,6 +1283,7 @@ impl From<Value> for SheetValue {
             value: v, 
             calculated: Value::Empty, 
             dirty: true,
+            style: 0, 
         }
     }
 }
@@ -522,13 +1291,13 @@ impl From<Value> for SheetValue {
 impl From<(Value, Value)> for SheetValue {
     fn from(v: (Value, Value)) -> SheetValue {
         let (value, calculated) = v; 
-        SheetValue { value, calculated, dirty: true}
+        SheetValue { value, calculated, dirty: true, style: 0 }
     }
 }
 
 impl SheetValue {
     fn new() -> SheetValue {
-        SheetValue { value: Value::Empty, calculated: Value::Empty, dirty: true }
+        SheetValue { value: Value::Empty, calculated: Value::Empty, dirty: true, style: 0 }
     }
 
     fn is_calculated(&self) -> bool {
@@ -542,7 +1311,11 @@ pub struct Sheet {
     pub idx: usize, 
     pub max_rows: usize, 
     pub max_columns: usize, 
-    pub values: Array2<SheetValue>
+    pub values: Array2<SheetValue>, 
+    pub spills: HashMap<(usize, usize), (usize, usize)>, // Dynamic array anchors and the rows and columns they spill over 
+    pub arrays: HashMap<(usize, usize), (usize, usize)>, // Ctrl+Shift+Enter anchors and the rows and columns of their block 
+    pub dynamic: HashSet<(usize, usize)>, // Formulas that spill, the rest take one value by implicit intersection 
+    pub hidden_rows: HashSet<usize>, 
 }
 
 impl From<(&str, usize)> for Sheet {
@@ -558,21 +1331,78 @@ impl From<(String, usize)> for Sheet {
             idx: s.1, 
             max_rows: 0, 
             max_columns: 0, 
-            values: Array::from_elem((0, 0), SheetValue::new())
+            values: Array::from_elem((0, 0), SheetValue::new()), 
+            spills: HashMap::new(), 
+            arrays: HashMap::new(), 
+            dynamic: HashSet::new(), 
+            hidden_rows: HashSet::new()
         }
     }
 }
 
 impl Sheet {
+    // Values of a range, padded with empty cells beyond the used area. 
+    pub fn get_range(&self, reference: String) -> Array2<Value> {
+        let sheet = self; 
+        let (mut row, mut col, mut num_rows, mut num_cols) = Reference::from(reference).get_dimensions();
+        if num_rows == usize::MAX { 
+            num_rows = sheet.values.dim().0; 
+            row = 1; // To avoid subtract overflow on row_idx_start
+        }
+        if num_cols == usize::MAX { 
+            num_cols = sheet.values.dim().0; 
+            col = 1; // To avoid subtract overflow on col_idx_start
+        }
+        let row_idx_start: usize = sheet.values.dim().0.min(row-1);
+        let row_idx_end: usize = sheet.values.dim().0.min(row+num_rows-1);
+        let rows_append: usize = num_rows - (row_idx_end - row_idx_start);
+        let col_idx_start: usize = sheet.values.dim().1.min(col-1);
+        let col_idx_end: usize = sheet.values.dim().1.min(col+num_cols-1);
+        let cols_append: usize = num_cols - (col_idx_end - col_idx_start);
+        let mut output: Array2<SheetValue> = sheet.values.slice(s![row_idx_start..row_idx_end, col_idx_start..col_idx_end]).into_owned(); 
+        if rows_append > 0 {
+            for _ in 0..rows_append {
+                output.push(Axis(0), ArrayView::from(&Array::from_elem(output.dim().1, SheetValue::new()))).unwrap(); 
+            }
+        }
+        if cols_append > 0 {
+            for _ in 0..cols_append {
+                output.push(Axis(1), ArrayView::from(&Array::from_elem(output.dim().0, SheetValue::new()))).unwrap(); 
+            }
+        }
+        output.map(|b| {
+            if b.is_calculated() {
+                b.calculated.clone()
+            } else {
+                b.value.clone()
+            }
+        })
+    }
+
     pub fn set_value(&mut self, reference: Reference, value: Value) {
+        let style = self.values[[reference.row()-1,reference.column()-1]].style; 
         let sheet_value = if value.is_formula() {
-            SheetValue {value, calculated: Value::Empty, dirty: true }
+            SheetValue {value, calculated: Value::Empty, dirty: true, style }
         } else {
-            SheetValue {value: value.clone(), calculated: value, dirty: false}
+            SheetValue {value: value.clone(), calculated: value, dirty: false, style }
         }; 
         self.values[[reference.row()-1,reference.column()-1]] = sheet_value; 
     }
 
+    // Anchor of the spill range (row, column) is in, the anchor itself included. 
+    pub fn spill_anchor(&self, row: usize, column: usize) -> Option<(usize, usize)> {
+        self.spills.iter()
+            .find(|((r, c), (rows, cols))| (*r..r + rows).contains(&row) && (*c..c + cols).contains(&column))
+            .map(|(anchor, _)| *anchor)
+    }
+
+    // Anchor of the array formula block (row, column) is in, the anchor itself included. 
+    pub fn array_anchor(&self, row: usize, column: usize) -> Option<(usize, usize)> {
+        self.arrays.iter()
+            .find(|((r, c), (rows, cols))| (*r..r + rows).contains(&row) && (*c..c + cols).contains(&column))
+            .map(|(anchor, _)| *anchor)
+    }
+
     pub fn resize(&mut self, row: usize, column: usize) {
         if self.values.dim().0 == 0 && self.values.dim().1 == 0 {
             self.values = Array::from_elem((row, column), SheetValue::new()); 
@@ -601,13 +1431,113 @@ impl fmt::Display for Sheet {
     }
 }
 
+/*
+ * An Excel Table (ListObject). `reference` covers the header and totals rows too. 
+ */
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Table {
+    pub name: String, 
+    pub sheet: usize, 
+    pub reference: Reference, 
+    pub header_rows: usize, 
+    pub totals_rows: usize, 
+    pub columns: Vec<String>, 
+}
+
+impl Table {
+    pub fn new(sheet: usize) -> Table {
+        Table { name: String::new(), sheet, reference: Reference::from((1, 1)), header_rows: 1, totals_rows: 0, columns: vec![] }
+    }
+
+    fn bounds(&self) -> (usize, usize, usize, usize) {
+        let (row, column, num_rows, num_cols) = self.reference.get_dimensions(); 
+        (row, column, row + num_rows - 1, column + num_cols - 1)
+    }
+
+    pub fn contains(&self, sheet: usize, row: usize, column: usize) -> bool {
+        let (top, left, bottom, right) = self.bounds(); 
+        self.sheet == sheet && (top..=bottom).contains(&row) && (left..=right).contains(&column)
+    }
+
+    // The range a structured reference points at. `row` is the formula's row, for #This Row. 
+    pub fn resolve(&self, t: &TableRef, row: usize) -> Result<Reference, ExcelError> {
+        let (top, left, bottom, right) = self.bounds(); 
+        let data_top = top + self.header_rows; 
+        let data_bottom = bottom - self.totals_rows; 
+        let mut rows: Option<(usize, usize)> = None; 
+        let items = if t.items.is_empty() { vec![TableItem::Data] } else { t.items.clone() }; 
+        for item in items.iter() {
+            let (a, b) = match item {
+                TableItem::All => (top, bottom), 
+                TableItem::Data => (data_top, data_bottom), 
+                TableItem::Headers if self.header_rows > 0 => (top, data_top - 1), 
+                TableItem::Totals if self.totals_rows > 0 => (data_bottom + 1, bottom), 
+                TableItem::ThisRow if items.len() == 1 => {
+                    if !(data_top..=data_bottom).contains(&row) {
+                        return Err(ExcelError::Value); 
+                    }
+                    (row, row)
+                }, 
+                _ => return Err(ExcelError::Ref)
+            }; 
+            rows = Some(match rows {
+                Some((x, y)) => (x.min(a), y.max(b)), 
+                None => (a, b)
+            }); 
+        }
+        let (row_start, row_end) = rows.unwrap(); 
+        if row_start > row_end {
+            return Err(ExcelError::Ref); 
+        }
+        let (col_start, col_end) = match &t.columns {
+            Some((first, last)) => {
+                let position = |name: &str| self.columns.iter().position(|c| c.eq_ignore_ascii_case(name)); 
+                match (position(first), position(last)) {
+                    (Some(a), Some(b)) => (left + a.min(b), left + a.max(b)), 
+                    _ => return Err(ExcelError::Ref)
+                }
+            }, 
+            None => (left, right)
+        }; 
+        if row_start == row_end && col_start == col_end {
+            Ok(Reference::from((row_start, col_start)))
+        } else {
+            Ok(Reference::from((row_start, col_start, row_end, col_end)))
+        }
+    }
+}
+
+/*
+ * A workbook referenced as [index]Sheet!A1. `sheets` holds the values Excel cached 
+ * when the file was saved, `book` a live copy attached with Book::link_book. 
+ */
+pub struct ExternalLink {
+    pub index: usize, 
+    pub target: String, 
+    pub sheets: Vec<Sheet>, 
+    pub book: Option<Box<Book>>, 
+}
+
+impl ExternalLink {
+    pub fn new(index: usize) -> ExternalLink {
+        ExternalLink { index, target: String::new(), sheets: vec![], book: None }
+    }
+
+    // By index as saved by Excel ([1]) or by file name as typed ([Rates.xlsx]). 
+    pub fn matches(&self, book: &str) -> bool {
+        let file_name = self.target.rsplit(['/', '\\']).next().unwrap_or(""); 
+        book.parse::<usize>() == Ok(self.index) || (!file_name.is_empty() && file_name.eq_ignore_ascii_case(book))
+    }
+}
+
 #[derive(Debug)]
 pub struct SharedString(String); 
 
 #[derive(Default, Debug)]
 pub struct Style {
     pub number_format_id: usize, 
-    pub apply_number_format: bool 
+    pub apply_number_format: bool, 
+    pub format: NumberFormat, 
 }
 
 impl Style {
@@ -619,10 +1549,14 @@ impl Style {
 #[derive(Debug)]
 struct SheetFlags {
     is_shared_formula: bool, 
+    is_array_formula: bool, 
+    is_dynamic_array: bool, // Cell metadata marks arrays that spill, others are Ctrl+Shift+Enter blocks 
+    formula_ref: String, // Range a shared or array formula covers 
     is_date: bool, 
     is_formula: bool, 
     is_string: bool, 
     is_value: bool, 
+    style: usize, 
     current_cell_reference: String, 
     shared_formulas: Vec<(Cell, String)>, // Start Cell, Formula
 }
@@ -631,10 +1565,14 @@ impl SheetFlags {
     fn new() -> SheetFlags {
         SheetFlags {
             is_shared_formula: false, 
+            is_array_formula: false, 
+            is_dynamic_array: false, 
+            formula_ref: String::new(), 
             is_date: false, 
             is_formula: false, 
             is_string: false, 
             is_value: false, 
+            style: 0, 
             current_cell_reference: String::new(), 
             shared_formulas: vec![]
         }
@@ -642,20 +1580,27 @@ impl SheetFlags {
     
     fn reset(&mut self) {
         self.is_shared_formula = false; 
+        self.is_array_formula = false; 
+        self.is_dynamic_array = false; 
+        self.formula_ref = String::new(); 
         self.is_date = false; 
         self.is_formula = false;
         self.is_string = false; 
         self.is_value = false; 
+        self.style = 0; 
         self.current_cell_reference = String::new(); 
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::workbook::{Sheet, Book};
+    use crate::workbook::{Sheet, Book, Table};
     use crate::evaluate::value::Value;
-    use crate::parser::parse_str; 
+    use crate::parser::{parse_str, ast::{TableRef, TableItem, Error as ExcelError}}; 
+    use crate::reference::Reference; 
     use crate::errors::Error; 
+    use crate::function::volatile::CalculationContext; 
+    use chrono::NaiveDate; 
     use ndarray::arr2; 
 
     fn get_cell<'a>(book: &'a Book, sheet_name: &'a str, row: usize, column: usize) -> Value {
@@ -682,7 +1627,7 @@ mod tests {
         assert_eq!(get_cell(&book, "test 1", 3, 0), Value::from("c")); 
         assert_eq!(get_cell(&book, "test 1", 1, 4), Value::Formula(String::from("=B2+1"))); 
         assert_eq!(get_cell(&book, "test 1", 2, 4), Value::Formula(String::from("=B3+1"))); 
-        assert_eq!(get_cell(&book, "test 1", 3, 4), Value::Formula(String::from("=(B4+1)"))); 
+        assert_eq!(get_cell(&book, "test 1", 3, 4), Value::Formula(String::from("=B4+1"))); 
     }
 
     #[test]
@@ -714,8 +1659,161 @@ mod tests {
         book.calculate(false, false)?; 
         assert!(book.resolve_str_ref("Sheet1!H7").unwrap()[[0, 0]].as_num() - 7.657 < 0.01); 
         book.set_value("Sheet1!F11", Value::from(20.0)); 
-        assert!(book.resolve_str_ref("Sheet1!H7").unwrap()[[0, 0]].as_num() - 19.947 < 0.01); 
+        book.calculate(false, false)?; 
+        assert!((book.resolve_str_ref("Sheet1!H7").unwrap()[[0, 0]].as_num() - 19.947).abs() < 0.01); 
         Ok(())
     }
-}
 
+    #[test]
+    fn test_volatile() -> Result<(), Error> {
+        let now = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap().and_hms_opt(10, 30, 0).unwrap(); 
+        let mut book = Book::from("assets/volatile.xlsx"); 
+        book.load(false).expect("Could not load workbook"); 
+        book.context = CalculationContext::default().with_now(now).with_seed(7); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Sheet1!A1")?[[0, 0]], Value::from(now.date())); 
+        assert_eq!(book.resolve_str_ref("Sheet1!A2")?[[0, 0]], Value::from(now)); 
+        let first = book.resolve_str_ref("Sheet1!A3")?[[0, 0]].as_num(); 
+        assert_eq!(book.resolve_str_ref("Sheet1!A4")?[[0, 0]], Value::from(first * 10.0)); 
+        let roll = book.resolve_str_ref("Sheet1!A5")?[[0, 0]].as_num(); 
+        assert_eq!(book.resolve_str_ref("Sheet1!A6")?[[0, 0]], Value::from(2024.0 + roll)); 
+        // Volatile cells and their dependents change on every calculation, the rest is left alone 
+        book.calculate(false, false)?; 
+        let second = book.resolve_str_ref("Sheet1!A3")?[[0, 0]].as_num(); 
+        assert_ne!(first, second); 
+        assert_eq!(book.resolve_str_ref("Sheet1!A4")?[[0, 0]], Value::from(second * 10.0)); 
+        assert_eq!(book.resolve_str_ref("Sheet1!B2")?[[0, 0]], Value::from(10.0)); 
+        book.set_value("Sheet1!B1", Value::from(6.0)); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Sheet1!B2")?[[0, 0]], Value::from(12.0)); 
+        assert_eq!(book.resolve_str_ref("Sheet1!B3")?[[0, 0]], Value::from(13.0)); 
+        // The same seed draws the same numbers 
+        let mut other = Book::from("assets/volatile.xlsx"); 
+        other.load(false).expect("Could not load workbook"); 
+        other.context = CalculationContext::default().with_now(now).with_seed(7); 
+        other.calculate(false, false)?; 
+        assert_eq!(other.resolve_str_ref("Sheet1!A3")?[[0, 0]].as_num(), first); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> Result<(), Error> {
+        let mut book = Book::from("assets/tables.xlsx"); 
+        book.load(false).expect("Could not load workbook"); 
+        assert_eq!(book.tables.len(), 1); 
+        assert_eq!(book.tables[0].name, "Sales"); 
+        assert_eq!(book.tables[0].reference, Reference::from("B2:E6")); 
+        assert_eq!(book.tables[0].columns, vec!["Region", "Qty", "Unit Price", "Amount"]); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Orders!E3")?[[0, 0]], Value::from(20.0)); 
+        assert_eq!(book.resolve_str_ref("Orders!E4")?[[0, 0]], Value::from(60.0)); 
+        assert_eq!(book.resolve_str_ref("Orders!E5")?[[0, 0]], Value::from(7.5)); 
+        assert_eq!(book.resolve_str_ref("Orders!E6")?[[0, 0]], Value::from(87.5)); 
+        assert_eq!(book.resolve_str_ref("Summary!A1")?[[0, 0]], Value::from(87.5)); 
+        assert_eq!(book.resolve_str_ref("Summary!A2")?[[0, 0]], Value::from(87.5)); 
+        assert_eq!(book.resolve_str_ref("Summary!A3")?[[0, 0]], Value::from(4.0)); 
+        assert_eq!(book.resolve_str_ref("Summary!A4")?[[0, 0]], Value::from(41.5)); 
+        assert_eq!(book.resolve_str_ref("Summary!A5")?[[0, 0]], Value::from(129.0)); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_resolve() {
+        let table = Table { 
+            name: String::from("Sales"), sheet: 0, reference: Reference::from("B2:E6"), 
+            header_rows: 1, totals_rows: 1, 
+            columns: vec!["Region", "Qty", "Unit Price", "Amount"].into_iter().map(String::from).collect() 
+        }; 
+        let resolve = |items: Vec<TableItem>, columns: Option<(&str, &str)>, row: usize| {
+            table.resolve(&TableRef { 
+                table: Some(String::from("Sales")), items, 
+                columns: columns.map(|(a, b)| (a.to_string(), b.to_string())) 
+            }, row).map(|r| r.to_string())
+        }; 
+        assert_eq!(resolve(vec![], Some(("Amount", "Amount")), 1), Ok(String::from("E3:E5"))); 
+        assert_eq!(resolve(vec![TableItem::All], None, 1), Ok(String::from("B2:E6"))); 
+        assert_eq!(resolve(vec![TableItem::Headers, TableItem::Data], Some(("region", "region")), 1), Ok(String::from("B2:B5"))); 
+        assert_eq!(resolve(vec![TableItem::Totals], Some(("Amount", "Amount")), 1), Ok(String::from("E6"))); 
+        assert_eq!(resolve(vec![TableItem::ThisRow], Some(("Qty", "Qty")), 4), Ok(String::from("C4"))); 
+        assert_eq!(resolve(vec![TableItem::ThisRow], Some(("Qty", "Qty")), 7), Err(ExcelError::Value)); 
+        assert_eq!(resolve(vec![], Some(("Missing", "Missing")), 1), Err(ExcelError::Ref)); 
+    }
+
+    #[test]
+    fn test_external_links() -> Result<(), Error> {
+        let mut book = Book::from("assets/linked.xlsx"); 
+        book.load(false).expect("Could not load workbook"); 
+        // [1] is the first of the workbook's external references, saved as externalLink2.xml 
+        assert_eq!(book.external_links.len(), 2); 
+        assert_eq!((book.external_links[0].index, book.external_links[0].target.as_str()), (1, "Rates.xlsx")); 
+        assert_eq!((book.external_links[1].index, book.external_links[1].target.as_str()), (2, "Other.xlsx")); 
+        book.calculate(false, false)?; 
+        // Cached values 
+        assert_eq!(book.resolve_str_ref("Model!A1")?[[0, 0]], Value::from(200.0)); 
+        assert!((book.resolve_str_ref("Model!A2")?[[0, 0]].as_num() - 0.06).abs() < 1e-9); 
+        assert_eq!(book.resolve_str_ref("Model!A3")?[[0, 0]], Value::from(2.0)); 
+        assert_eq!(book.resolve_str_ref("Model!A4")?[[0, 0]], Value::from("Notional")); 
+        assert_eq!(book.resolve_str_ref("Model!B2")?[[0, 0]], Value::from(105.0)); 
+        assert_eq!(book.resolve_str_ref("'[Rates.xlsx]Curve'!A3")?[[0, 0]], Value::from(0.03)); 
+        assert_eq!(book.resolve_str_ref("[2]Misc!A1")?[[0, 0]], Value::from(7.0)); 
+        assert_eq!(book.resolve_str_ref("[2]Curve!A3")?[[0, 0]], Value::Error(ExcelError::Ref)); 
+        assert_eq!(book.resolve_str_ref("[3]Curve!A3")?[[0, 0]], Value::Error(ExcelError::Ref)); 
+        // Live values 
+        let mut rates = Book::from("assets/rates.xlsx"); 
+        rates.load(false).expect("Could not load workbook"); 
+        book.link_book(1, rates); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Model!A1")?[[0, 0]], Value::from(240.0)); 
+        assert_eq!(book.resolve_str_ref("Model!A3")?[[0, 0]], Value::from(2.4)); 
+        book.get_mut_linked_book(1).unwrap().set_value("Inputs!B4", Value::from(10.0)); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Model!A1")?[[0, 0]], Value::from(20.0)); 
+        assert_eq!(book.resolve_str_ref("Model!B2")?[[0, 0]], Value::from(15.0)); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_formats() -> Result<(), Error> {
+        let mut book = Book::from("assets/formats.xlsx"); 
+        book.load(false).expect("Could not load workbook"); 
+        book.calculate(false, false)?; 
+        let formatted = book.resolve_formatted_ref(parse_str("Sheet1!A1:B7")?)?; 
+        assert_eq!(formatted[[0, 0]], "1,234.57"); 
+        assert_eq!(formatted[[1, 0]], "25.60%"); 
+        assert_eq!(formatted[[2, 0]], "2024-03-05 00:00"); 
+        assert_eq!(formatted[[3, 0]], "(1,234.50)"); 
+        assert_eq!(formatted[[4, 0]], "Mar 5, 2024"); 
+        assert_eq!(formatted[[5, 0]], "plain"); 
+        assert_eq!(formatted[[6, 0]], "12.5"); 
+        assert_eq!(formatted[[0, 1]], "2,469.13"); 
+        assert_eq!(formatted[[1, 1]], "1,234.6"); 
+        assert_eq!(formatted[[2, 1]], ""); 
+        // Custom date formats load as dates 
+        assert!(book.resolve_str_ref("Sheet1!A5")?[[0, 0]].is_date()); 
+        Ok(())
+    }
+
+    #[test]
+    fn test_array_formulas() -> Result<(), Error> {
+        let mut book = Book::from("assets/array.xlsx"); 
+        book.load(false).expect("Could not load workbook"); 
+        // {=A1:A3*B1:B3} in C1:C3 is one formula, the other cells only hold its result 
+        assert_eq!(get_cell(&book, "Sheet1", 1, 2), Value::Empty); 
+        book.calculate(false, false)?; 
+        let col = |values: &[f64]| arr2(&[[Value::from(values[0])], [Value::from(values[1])], [Value::from(values[2])]]); 
+        assert_eq!(book.resolve_str_ref("Sheet1!C1:C3")?, col(&[4.0, 10.0, 18.0])); 
+        assert_eq!(book.resolve_str_ref("Sheet1!D1")?[[0, 0]], Value::from(32.0)); 
+        // Cells past the end of a smaller array are #N/A, scalars fill the block 
+        assert_eq!(book.resolve_str_ref("Sheet1!E4")?[[0, 0]], Value::Error(ExcelError::NA)); 
+        assert_eq!(book.resolve_str_ref("Sheet1!F1:G2")?, arr2(&[[Value::from(7.0), Value::from(7.0)], [Value::from(7.0), Value::from(7.0)]])); 
+        // IF picks from its branches element by element 
+        assert_eq!(book.resolve_str_ref("Sheet1!J1")?[[0, 0]], Value::from(11.0)); 
+        assert_eq!(book.resolve_str_ref("Sheet1!K1:L1")?, arr2(&[[Value::from(1.0), Value::from(4.0)]])); 
+        book.set_value("Sheet1!A2", Value::from(10.0)); 
+        book.calculate(false, false)?; 
+        assert_eq!(book.resolve_str_ref("Sheet1!C1:C3")?, col(&[4.0, 50.0, 18.0])); 
+        assert_eq!(book.resolve_str_ref("Sheet1!H1")?[[0, 0]], Value::from(72.0)); 
+        assert_eq!(book.resolve_str_ref("Sheet1!I1")?[[0, 0]], Value::from(51.0)); 
+        Ok(())
+    }
+}